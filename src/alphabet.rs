@@ -0,0 +1,120 @@
+use crate::complement_base;
+
+/// Set of characters accepted in input and inserted sequences, selected with
+/// `--alphabet`. The same policy decides how bases are complemented, so the
+/// validator and the reverse-complement code can never disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    Dna,
+    Rna,
+    Iupac,
+    Protein,
+    Any,
+}
+
+/// Maximum number of offending characters listed in a validation error.
+const MAX_REPORTED: usize = 10;
+
+impl Alphabet {
+    pub fn parse(name: &str) -> Result<Alphabet, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "dna" => Ok(Alphabet::Dna),
+            "rna" => Ok(Alphabet::Rna),
+            "iupac" => Ok(Alphabet::Iupac),
+            "protein" => Ok(Alphabet::Protein),
+            "any" => Ok(Alphabet::Any),
+            _ => Err(format!("Unknown alphabet '{}'. Use 'dna', 'rna', 'iupac', 'protein', or 'any'", name)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Alphabet::Dna => "dna",
+            Alphabet::Rna => "rna",
+            Alphabet::Iupac => "iupac",
+            Alphabet::Protein => "protein",
+            Alphabet::Any => "any",
+        }
+    }
+
+    fn allowed(self) -> &'static str {
+        match self {
+            Alphabet::Dna => "A, C, G, T, N",
+            Alphabet::Rna => "A, C, G, U, N",
+            Alphabet::Iupac => "A, C, G, T, U, R, Y, S, W, K, M, B, D, H, V, N",
+            Alphabet::Protein => "A-Z (20 amino acids plus B, J, O, U, X, Z) and *",
+            Alphabet::Any => "any character",
+        }
+    }
+
+    pub fn allows(self, base: char) -> bool {
+        match self {
+            Alphabet::Dna => matches!(base.to_ascii_uppercase(), 'A' | 'C' | 'G' | 'T' | 'N'),
+            Alphabet::Rna => matches!(base.to_ascii_uppercase(), 'A' | 'C' | 'G' | 'U' | 'N'),
+            Alphabet::Iupac => matches!(base.to_ascii_uppercase(),
+                'A' | 'C' | 'G' | 'T' | 'U' | 'R' | 'Y' | 'S' | 'W' | 'K' | 'M' | 'B' | 'D' | 'H' | 'V' | 'N'),
+            // The 20 standard residues plus B, J, O, U, X, Z cover every letter
+            Alphabet::Protein => base.is_ascii_alphabetic() || base == '*',
+            Alphabet::Any => true,
+        }
+    }
+
+    /// Checks every character of `sequence`, listing the offending characters
+    /// and their 1-based positions if any fall outside the alphabet.
+    pub fn validate(self, what: &str, sequence: &str) -> Result<(), String> {
+        let offending: Vec<(usize, char)> = sequence
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !self.allows(*c))
+            .map(|(i, c)| (i + 1, c))
+            .collect();
+
+        if offending.is_empty() {
+            return Ok(());
+        }
+
+        let mut listed: Vec<String> = offending
+            .iter()
+            .take(MAX_REPORTED)
+            .map(|(pos, c)| format!("'{}' at position {}", c, pos))
+            .collect();
+        if offending.len() > MAX_REPORTED {
+            listed.push(format!("and {} more", offending.len() - MAX_REPORTED));
+        }
+
+        Err(format!(
+            "{} contains characters outside the {} alphabet ({}): {}",
+            what,
+            self.name(),
+            self.allowed(),
+            listed.join(", ")
+        ))
+    }
+
+    pub fn is_protein(self) -> bool {
+        self == Alphabet::Protein
+    }
+
+    /// Unit used for lengths in header annotations.
+    pub fn unit(self) -> &'static str {
+        if self.is_protein() { "aa" } else { "bp" }
+    }
+
+    /// Unambiguous characters that random substitutions draw from.
+    pub fn substitution_bases(self) -> &'static [u8] {
+        match self {
+            Alphabet::Rna => b"ACGU",
+            Alphabet::Protein => b"ACDEFGHIKLMNPQRSTVWY",
+            Alphabet::Dna | Alphabet::Iupac | Alphabet::Any => b"ACGT",
+        }
+    }
+
+    /// Complements a base; RNA output uses U in place of T.
+    pub fn complement(self, base: char) -> char {
+        match (self, complement_base(base)) {
+            (Alphabet::Rna, 'T') => 'U',
+            (Alphabet::Rna, 't') => 'u',
+            (_, complemented) => complemented,
+        }
+    }
+}
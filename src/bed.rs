@@ -0,0 +1,59 @@
+use std::fs;
+
+/// One BED interval: 0-based, half-open coordinates on `chrom`, with the
+/// optional name (column 4) and strand (column 6).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedInterval {
+    pub chrom: String,
+    pub start: usize,
+    pub end: usize,
+    pub name: Option<String>,
+    pub reverse: bool,
+}
+
+impl BedInterval {
+    /// Whether the interval overlaps 1-based, inclusive `start..=end` on `chrom`.
+    pub fn overlaps(&self, chrom: &str, start: usize, end: usize) -> bool {
+        self.chrom == chrom && self.start < end && start - 1 < self.end
+    }
+}
+
+/// Reads the coordinates, name and strand columns of a BED file,
+/// skipping blank, `#`, `track` and `browser` lines.
+pub fn read_bed(path: &str) -> Result<Vec<BedInterval>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read BED file {}: {}", path, e))?;
+    let mut intervals = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("track") || trimmed.starts_with("browser") {
+            continue;
+        }
+        let fields: Vec<&str> = trimmed.split('\t').collect();
+        if fields.len() < 3 {
+            return Err(format!("{} line {}: expected at least 3 tab-separated columns", path, i + 1));
+        }
+        let coordinate = |value: &str| value.trim().parse::<usize>()
+            .map_err(|_| format!("{} line {}: '{}' is not a valid coordinate", path, i + 1, value));
+        let start = coordinate(fields[1])?;
+        let end = coordinate(fields[2])?;
+        if start > end {
+            return Err(format!("{} line {}: start {} is after end {}", path, i + 1, start, end));
+        }
+        let name = fields.get(3).map(|name| name.trim()).filter(|name| !name.is_empty() && *name != ".");
+        let reverse = match fields.get(5).map(|strand| strand.trim()) {
+            None | Some("+") | Some(".") | Some("") => false,
+            Some("-") => true,
+            Some(other) => return Err(format!("{} line {}: '{}' is not a valid strand", path, i + 1, other)),
+        };
+        intervals.push(BedInterval {
+            chrom: fields[0].to_string(),
+            start,
+            end,
+            name: name.map(str::to_string),
+            reverse,
+        });
+    }
+
+    Ok(intervals)
+}
@@ -0,0 +1,114 @@
+use serde::Serialize;
+
+use crate::alphabet::Alphabet;
+use crate::stem;
+
+/// Shortest terminal stem taken as evidence of a copyback, by default.
+pub const DEFAULT_MIN_STEM: usize = 10;
+
+/// What a molecule looks like, using the definitions sv_maker builds them by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+    /// The reference's 5' end followed by the reverse complement of its start
+    Copyback5,
+    /// The reverse complement of the reference's 3' end, then that end forward
+    Copyback3,
+    /// A copyback whose end cannot be told without a reference
+    Copyback,
+    /// A copyback whose two arms are the same length: a perfect hairpin
+    Snapback,
+    /// The reference with one internal stretch removed
+    Deletion,
+    /// As long as the reference, with no terminal stem
+    FullLength,
+    /// No terminal stem, and no reference (or none it can be explained by)
+    Linear,
+}
+
+/// The classification of one molecule and the evidence behind it.
+#[derive(Debug, Serialize)]
+pub struct Classification {
+    pub id: String,
+    pub length: usize,
+    pub class: Class,
+    /// Bases from the 5' end that pair with the bases read back from the 3' end
+    pub terminal_stem: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference_length: Option<usize>,
+    /// Copyback breakpoint and backstart, as `copyback` takes them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakpoint: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backstart: Option<usize>,
+    /// 1-based inclusive range of the reference a deletion DVG lacks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<(usize, usize)>,
+}
+
+/// Classifies `sequence` by its terminal self-complementarity and, given the
+/// genome it derives from, by which of its ends it keeps. Coordinates of
+/// chance matches next to a junction are ambiguous; the leftmost placement is
+/// reported.
+pub fn classify(id: &str, sequence: &str, reference: Option<&str>, alphabet: Alphabet,
+                min_stem: usize) -> Classification {
+    let sequence = sequence.to_ascii_uppercase();
+    let length = sequence.len();
+    let terminal_stem = stem::terminal_stem(&sequence, alphabet);
+    let mut classification = Classification {
+        id: id.to_string(),
+        length,
+        class: Class::Linear,
+        terminal_stem,
+        reference_length: reference.map(str::len),
+        breakpoint: None,
+        backstart: None,
+        deleted: None,
+    };
+
+    if terminal_stem >= min_stem.max(1) {
+        // A hairpin with nothing but (at most) one unpaired base at its tip
+        if 2 * terminal_stem + 1 >= length {
+            classification.class = Class::Snapback;
+            return classification;
+        }
+        classification.class = Class::Copyback;
+        if let Some(reference) = reference {
+            let reference = reference.to_ascii_uppercase();
+            let reverse: String = reference.chars().rev().map(|b| alphabet.complement(b)).collect();
+            let prefix5 = common_prefix(&sequence, &reference);
+            let prefix3 = common_prefix(&sequence, &reverse);
+            let kept = prefix5.max(prefix3);
+            if kept >= min_stem && kept < length {
+                classification.class = if prefix5 >= prefix3 { Class::Copyback5 } else { Class::Copyback3 };
+                classification.breakpoint = Some(kept);
+                classification.backstart = Some(length - kept);
+            }
+        }
+        return classification;
+    }
+
+    if let Some(reference) = reference {
+        let reference = reference.to_ascii_uppercase();
+        if length == reference.len() {
+            classification.class = Class::FullLength;
+        } else if length < reference.len() {
+            let prefix = common_prefix(&sequence, &reference);
+            let suffix = common_suffix(&sequence, &reference);
+            if prefix + suffix >= length {
+                let start = length.saturating_sub(suffix);
+                classification.class = Class::Deletion;
+                classification.deleted = Some((start + 1, start + reference.len() - length));
+            }
+        }
+    }
+    classification
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix(a: &str, b: &str) -> usize {
+    a.bytes().rev().zip(b.bytes().rev()).take_while(|(x, y)| x == y).count()
+}
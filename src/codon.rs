@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fs;
+
+/// The standard genetic code: the amino acid of every codon, in TCAG order
+/// of the first, second and third base (`*` for stops).
+const STANDARD_CODE: &str = "FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// Most frequent codon of each amino acid (and stop) in the Kazusa codon
+/// usage tables for well-studied expression hosts, as `residue, codon` pairs.
+const BUILT_IN: &[(&[&str], &str)] = &[
+    (&["e_coli", "ecoli"], "AGCG RCGC NAAC DGAT CTGC QCAG EGAA GGGC HCAT IATT LCTG KAAA MATG FTTT PCCG SAGC TACC WTGG YTAT VGTG *TAA"),
+    (&["s_cerevisiae", "yeast"], "AGCT RAGA NAAT DGAT CTGT QCAA EGAA GGGT HCAT IATT LTTG KAAA MATG FTTT PCCA STCT TACT WTGG YTAT VGTT *TAA"),
+    (&["h_sapiens", "human"], "AGCC RAGA NAAC DGAC CTGC QCAG EGAG GGGC HCAC IATC LCTG KAAG MATG FTTC PCCC SAGC TACC WTGG YTAC VGTG *TGA"),
+];
+
+/// The codon used for each amino acid when reverse-translating a protein.
+#[derive(Debug, Clone)]
+pub struct CodonTable {
+    codons: HashMap<char, String>,
+}
+
+/// Names of the built-in codon tables, for error messages and usage.
+pub fn built_in_names() -> Vec<&'static str> {
+    BUILT_IN.iter().map(|(names, _)| names[0]).collect()
+}
+
+fn amino_acid(codon: &str) -> Option<char> {
+    let index = |base: u8| b"TCAG".iter().position(|&b| b == base);
+    let bytes = codon.as_bytes();
+    let i = index(bytes[0])? * 16 + index(bytes[1])? * 4 + index(bytes[2])?;
+    STANDARD_CODE.chars().nth(i)
+}
+
+impl CodonTable {
+    /// A built-in table by organism name, or a codon usage file: pairs of a
+    /// codon and its frequency, separated by whitespace, as in the tables
+    /// the Kazusa database prints (a count in parentheses after each
+    /// frequency is ignored). Each amino acid gets its most frequent codon.
+    pub fn load(name: &str) -> Result<CodonTable, String> {
+        if let Some((_, codons)) = BUILT_IN.iter().find(|(names, _)| names.contains(&name.to_ascii_lowercase().as_str())) {
+            let codons = codons.split(' ').map(|pair| (pair.chars().next().unwrap_or('*'), pair[1..].to_string())).collect();
+            return Ok(CodonTable { codons });
+        }
+        let text = fs::read_to_string(name).map_err(|_| format!(
+            "Unknown codon table '{}': not a built-in table ({}) or a readable codon usage file",
+            name, built_in_names().join(", ")))?;
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let mut best: HashMap<char, (String, f64)> = HashMap::new();
+        for pair in tokens.windows(2) {
+            let codon = pair[0].to_ascii_uppercase().replace('U', "T");
+            if codon.len() != 3 {
+                continue;
+            }
+            let Some(residue) = amino_acid(&codon) else {
+                continue;
+            };
+            let frequency: f64 = pair[1].split('(').next().unwrap_or("").parse()
+                .map_err(|_| format!("Codon table {}: codon {} is not followed by a frequency", name, pair[0]))?;
+            if best.get(&residue).is_none_or(|(_, most)| frequency > *most) {
+                best.insert(residue, (codon, frequency));
+            }
+        }
+        if best.is_empty() {
+            return Err(format!("Codon table {} lists no codons", name));
+        }
+        Ok(CodonTable { codons: best.into_iter().map(|(residue, (codon, _))| (residue, codon)).collect() })
+    }
+
+    /// The DNA coding for `protein`, one codon per residue. Only the 20
+    /// standard amino acids and `*` (stop) can be reverse-translated.
+    pub fn reverse_translate(&self, protein: &str) -> Result<String, String> {
+        let mut coding = String::with_capacity(protein.len() * 3);
+        for (i, residue) in protein.chars().enumerate() {
+            let residue = residue.to_ascii_uppercase();
+            match self.codons.get(&residue) {
+                Some(codon) => coding.push_str(codon),
+                None if STANDARD_CODE.contains(residue) => {
+                    return Err(format!("The codon table has no codon for '{}' (position {} of the protein)", residue, i + 1));
+                },
+                None => {
+                    return Err(format!("'{}' at position {} of the protein is not one of the 20 standard amino acids or *",
+                                       residue, i + 1));
+                },
+            }
+        }
+        Ok(coding)
+    }
+}
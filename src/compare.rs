@@ -0,0 +1,48 @@
+use std::fmt;
+
+use crate::fasta::Record;
+
+/// Where two FASTA files first differ in sequence.
+#[derive(Debug)]
+pub enum Difference {
+    /// The `record`th records (1-based) differ at `position` (1-based); a
+    /// `None` base means that sequence ended there.
+    Base { record: usize, ids: (String, String), position: usize, bases: (Option<char>, Option<char>) },
+    /// Every shared record matches, but one file has more records.
+    Records(usize, usize),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let base = |base: Option<char>| base.map_or("end of sequence".to_string(), |base| base.to_string());
+        match self {
+            Difference::Base { record, ids, position, bases } => {
+                write!(f, "record {} ({} vs {}), position {}: {} vs {}",
+                       record, ids.0, ids.1, position, base(bases.0), base(bases.1))
+            },
+            Difference::Records(a, b) => write!(f, "record count {} vs {}", a, b),
+        }
+    }
+}
+
+/// The first difference between the sequences of two files, compared record
+/// by record in order. Headers, line wrapping and qualities are ignored.
+pub fn first_difference(a: &[Record], b: &[Record]) -> Option<Difference> {
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        let (xs, ys) = (x.sequence.as_bytes(), y.sequence.as_bytes());
+        let position = xs.iter().zip(ys).position(|(p, q)| p != q)
+            .or(if xs.len() == ys.len() { None } else { Some(xs.len().min(ys.len())) });
+        if let Some(position) = position {
+            return Some(Difference::Base {
+                record: i + 1,
+                ids: (x.id().to_string(), y.id().to_string()),
+                position: position + 1,
+                bases: (xs.get(position).map(|&c| c as char), ys.get(position).map(|&c| c as char)),
+            });
+        }
+    }
+    if a.len() != b.len() {
+        return Some(Difference::Records(a.len(), b.len()));
+    }
+    None
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Write};
+
+#[cfg(feature = "cli")]
+use flate2::write::{DeflateEncoder, GzEncoder};
+#[cfg(feature = "cli")]
+use flate2::Crc;
+
+/// A compressed stream format for the output sequences.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Gzip,
+    /// Blocked gzip, as bgzip writes: readable by any gzip reader, and
+    /// indexable by `samtools faidx`.
+    Bgzf,
+    Zstd,
+}
+
+/// Every compression, in the order `--compress` lists them.
+pub const COMPRESSIONS: &[Compression] = &[Compression::Gzip, Compression::Bgzf, Compression::Zstd];
+
+impl Compression {
+    /// The name `--compress` takes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Bgzf => "bgzf",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// File extensions that select the compression without `--compress`.
+    /// `.gz` gets BGZF, which plain gzip readers read too.
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Compression::Gzip => &[],
+            Compression::Bgzf => &["gz", "bgz"],
+            Compression::Zstd => &["zst"],
+        }
+    }
+
+    /// Writes everything `write` writes to `out`, compressed, and ends the
+    /// compressed stream.
+    #[cfg(feature = "cli")]
+    pub fn write_to(self, out: &mut dyn Write, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(out, flate2::Compression::default());
+                write(&mut encoder)?;
+                encoder.finish()?.flush()
+            },
+            Compression::Bgzf => {
+                let mut encoder = Bgzf { out, block: Vec::with_capacity(BGZF_BLOCK) };
+                write(&mut encoder)?;
+                encoder.finish()
+            },
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(out, 0)?;
+                write(&mut encoder)?;
+                encoder.finish()?.flush()
+            },
+        }
+    }
+
+    /// Without the `cli` feature there are no encoders, so every
+    /// compression is refused.
+    #[cfg(not(feature = "cli"))]
+    pub fn write_to(self, _out: &mut dyn Write, _write: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported,
+                           format!("{} output needs sv_maker built with the cli feature", self.name())))
+    }
+}
+
+/// The compression `--compress <name>` selects.
+pub fn by_name(name: &str) -> Result<Compression, String> {
+    COMPRESSIONS.iter().copied().find(|compression| compression.name() == name).ok_or_else(|| format!(
+        "Unknown compression '{}' (expected {})",
+        name, COMPRESSIONS.iter().map(|compression| compression.name()).collect::<Vec<_>>().join(", ")))
+}
+
+/// The compression whose extension `path` ends in, ignoring case.
+pub fn by_extension(path: &str) -> Option<Compression> {
+    let (_, extension) = path.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    COMPRESSIONS.iter().copied().find(|compression| compression.extensions().contains(&extension.as_str()))
+}
+
+/// `path` without a compression extension, so the one before it can name
+/// the format (`out.fq.gz` is FASTQ).
+pub fn strip_extension(path: &str) -> &str {
+    match (by_extension(path), path.rsplit_once('.')) {
+        (Some(_), Some((stem, _))) => stem,
+        _ => path,
+    }
+}
+
+/// Uncompressed bytes per BGZF block, as bgzip uses, so that each
+/// compressed block stays within the format's 64 KiB limit.
+#[cfg(feature = "cli")]
+const BGZF_BLOCK: usize = 0xff00;
+
+/// The empty block that marks the end of a BGZF file.
+#[cfg(feature = "cli")]
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0, 0x1b, 0, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Writes BGZF: a gzip member per block, each recording its own size in a
+/// `BC` extra field.
+#[cfg(feature = "cli")]
+struct Bgzf<'a> {
+    out: &'a mut dyn Write,
+    block: Vec<u8>,
+}
+
+#[cfg(feature = "cli")]
+impl Bgzf<'_> {
+    fn write_block(&mut self) -> io::Result<()> {
+        let mut deflate = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(&self.block)?;
+        let data = deflate.finish()?;
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+
+        // Header, extra field and trailer around the data, less one
+        let size = (18 + data.len() + 8 - 1) as u16;
+        let mut header = [0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 0x06, 0, b'B', b'C', 0x02, 0, 0, 0];
+        header[16..].copy_from_slice(&size.to_le_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(&data)?;
+        self.out.write_all(&crc.sum().to_le_bytes())?;
+        self.out.write_all(&(self.block.len() as u32).to_le_bytes())?;
+        self.block.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if !self.block.is_empty() {
+            self.write_block()?;
+        }
+        self.out.write_all(&BGZF_EOF)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Write for Bgzf<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(BGZF_BLOCK - self.block.len());
+        self.block.extend_from_slice(&buf[..taken]);
+        if self.block.len() == BGZF_BLOCK {
+            self.write_block()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
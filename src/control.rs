@@ -0,0 +1,141 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::bed::BedInterval;
+use crate::rng::Rng;
+use crate::Operation;
+use crate::staging;
+
+/// Number of random loci tried before giving up on a matched control.
+const MAX_ATTEMPTS: usize = 10_000;
+
+/// Input positions (1-based, inclusive) an operation depends on, or `None`
+/// for operations anchored to the sequence ends or placed at random, which
+/// have no alternative locus.
+pub fn span(operation: &Operation) -> Option<(usize, usize)> {
+    match operation {
+        Operation::Delete { start, end }
+        | Operation::Replace { start, end, .. }
+        | Operation::Invert { start, end, .. }
+        | Operation::TandemDuplicate { start, end }
+        | Operation::Keep { start, end }
+        | Operation::Chromothripsis { start, end, .. } => Some((*start, *end)),
+        Operation::Insert { position, .. } => Some((*position, *position)),
+        Operation::Duplicate { start, end, position } => Some(((*start).min(*position), (*end).max(*position))),
+        Operation::Mei { position: Some(position), tsd, .. } => Some((position.saturating_sub(*tsd).max(1), *position)),
+        _ => None,
+    }
+}
+
+/// The same operation moved so its span starts at `new_start`.
+fn relocate(operation: &Operation, new_start: usize) -> Operation {
+    let (start, _) = span(operation).expect("relocate is only called for operations with a span");
+    let shift = |position: usize| position + new_start - start;
+    let mut moved = operation.clone();
+    match &mut moved {
+        Operation::Delete { start, end }
+        | Operation::Replace { start, end, .. }
+        | Operation::Invert { start, end, .. }
+        | Operation::TandemDuplicate { start, end }
+        | Operation::Keep { start, end }
+        | Operation::Chromothripsis { start, end, .. } => {
+            *start = shift(*start);
+            *end = shift(*end);
+        },
+        Operation::Insert { position, .. } | Operation::Mei { position: Some(position), .. } => {
+            *position = shift(*position);
+        },
+        Operation::Duplicate { start, end, position } => {
+            *start = shift(*start);
+            *end = shift(*end);
+            *position = shift(*position);
+        },
+        _ => unreachable!("operation without a span"),
+    }
+    moved
+}
+
+/// A size-matched control for one record.
+pub struct MatchedControl {
+    pub original: (usize, usize),
+    pub control: (usize, usize),
+    pub operation: Operation,
+}
+
+/// Draws a locus on `chrom` (of `length` bases) where the same operation can
+/// be applied without touching its original span or any excluded interval.
+pub fn matched_control(operation: &Operation, chrom: &str, length: usize, exclude: &[BedInterval],
+                       rng: &mut Rng) -> Result<MatchedControl, String> {
+    let (start, end) = span(operation)
+        .ok_or("Matched controls need an operation at a specific locus (not end-anchored or randomly placed)")?;
+    let width = end - start + 1;
+    // Insertions may sit just past the last base
+    let last_start = match operation {
+        Operation::Insert { .. } => length + 1,
+        _ => length.saturating_sub(width) + 1,
+    };
+    if last_start < 1 || width > length {
+        return Err(format!("Sequence '{}' is too short for a matched control", chrom));
+    }
+
+    for _ in 0..MAX_ATTEMPTS {
+        let candidate = rng.between(1, last_start);
+        let candidate_end = candidate + width - 1;
+        let overlaps_original = candidate <= end && start <= candidate_end;
+        if overlaps_original || exclude.iter().any(|interval| interval.overlaps(chrom, candidate, candidate_end)) {
+            continue;
+        }
+        return Ok(MatchedControl {
+            original: (start, end),
+            control: (candidate, candidate_end),
+            operation: relocate(operation, candidate),
+        });
+    }
+    Err(format!("No locus on '{}' is free for a matched control of {} bases", chrom, width))
+}
+
+/// Writes the matched controls as TSV, one row per edited record.
+pub fn write_control_manifest(path: &str, controls: &[(&str, MatchedControl)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "sequence\toriginal_start\toriginal_end\tcontrol_start\tcontrol_end\tcontrol_operation")?;
+    for (id, control) in controls {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", id, control.original.0, control.original.1, control.control.0,
+                 control.control.1, serde_json::to_string(&control.operation)?)?;
+    }
+    out.flush()
+}
+
+/// Whether `operation` inverts or copies an interval, which a scrambled
+/// control can stand in for.
+pub fn scrambles(operation: &Operation) -> bool {
+    matches!(operation, Operation::Invert { .. } | Operation::Duplicate { .. }
+                        | Operation::TandemDuplicate { .. } | Operation::DuplicateInto { .. })
+}
+
+/// The control for `operation` on `sequence`: the interval it inverts
+/// replaced by, or its copy inserted as, the same bases shuffled. Length and
+/// composition match the edit, while neither orientation nor sequence
+/// identity is kept.
+pub fn scramble_control(operation: &Operation, sequence: &str, rng: &mut Rng) -> Result<Operation, String> {
+    let mut shuffled = |bases: &str| {
+        let mut bases = bases.as_bytes().to_vec();
+        rng.shuffle(&mut bases);
+        String::from_utf8_lossy(&bases).into_owned()
+    };
+    let interval = |start: usize, end: usize| sequence.get(start - 1..end)
+        .ok_or_else(|| format!("Positions {}-{} are beyond sequence length {}", start, end, sequence.len()));
+    match operation {
+        Operation::Invert { start, end, .. } => {
+            Ok(Operation::Replace { start: *start, end: *end, sequence: shuffled(interval(*start, *end)?) })
+        },
+        Operation::Duplicate { start, end, position } => {
+            Ok(Operation::Insert { position: *position, sequence: shuffled(interval(*start, *end)?) })
+        },
+        Operation::TandemDuplicate { start, end } => {
+            Ok(Operation::Insert { position: end + 1, sequence: shuffled(interval(*start, *end)?) })
+        },
+        Operation::DuplicateInto { position, sequence: copy, .. } => {
+            Ok(Operation::Insert { position: *position, sequence: shuffled(copy) })
+        },
+        _ => Err("Scrambled controls need an invert or duplicate operation".to_string()),
+    }
+}
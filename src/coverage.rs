@@ -0,0 +1,110 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::layout::Segment;
+use crate::staging;
+use crate::vcf::Allele;
+
+/// Number of copies of each input base in a layout, as runs of equal copy
+/// number `(start, end, copies)` covering `0..length`.
+pub fn copy_number_runs(segments: &[Segment], length: usize) -> Vec<(usize, usize, usize)> {
+    // +1 where a copied stretch starts, -1 where it ends
+    let mut events: Vec<(usize, i64)> = vec![(0, 0), (length, 0)];
+    for segment in segments {
+        if let Segment::Source { start, end, .. } = *segment {
+            events.push((start, 1));
+            events.push((end, -1));
+        }
+    }
+    events.sort_unstable();
+
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    let mut copies = 0;
+    let mut position = 0;
+    for (at, change) in events {
+        if at > position {
+            match runs.last_mut() {
+                Some(last) if last.2 == copies as usize => last.1 = at,
+                _ => runs.push((position, at, copies as usize)),
+            }
+            position = at;
+        }
+        copies += change;
+    }
+    runs
+}
+
+/// Fraction of the input's bases with at least one copy in a layout.
+pub fn retained_fraction(segments: &[Segment], length: usize) -> f64 {
+    if length == 0 {
+        return 1.0;
+    }
+    let retained: usize = copy_number_runs(segments, length).iter()
+        .filter(|&&(_, _, copies)| copies > 0)
+        .map(|&(start, end, _)| end - start)
+        .sum();
+    retained as f64 / length as f64
+}
+
+/// Bases an edit touched: everything outside the longest run of input
+/// stretches the layout keeps forward and in their original order, counted on
+/// whichever of the input or output side has more of them.
+pub fn affected_bases(segments: &[Segment], length: usize) -> usize {
+    // Heaviest chain of forward copies in increasing input order
+    let forward: Vec<(usize, usize)> = segments.iter().filter_map(|segment| match *segment {
+        Segment::Source { start, end, reverse: false, complement: false } => Some((start, end)),
+        _ => None,
+    }).collect();
+    let mut best: Vec<usize> = Vec::with_capacity(forward.len());
+    for (i, &(start, end)) in forward.iter().enumerate() {
+        let before = (0..i).filter(|&j| forward[j].1 <= start).map(|j| best[j]).max().unwrap_or(0);
+        best.push(before + end - start);
+    }
+    let kept = best.into_iter().max().unwrap_or(0);
+    let output: usize = segments.iter().map(Segment::len).sum();
+    (output - kept).max(length - kept)
+}
+
+/// Fraction of the sample's copies that carry the edit: the allele fraction
+/// when given, else the share of non-reference alleles in the genotype, else
+/// all of them.
+pub fn edited_fraction(allele: &Allele) -> f64 {
+    if let Some(fraction) = allele.allele_fraction {
+        return fraction;
+    }
+    match &allele.genotype {
+        Some(genotype) => {
+            let alleles: Vec<&str> = genotype.split(['/', '|']).collect();
+            let alternate = alleles.iter().filter(|a| **a != "0" && **a != ".").count();
+            alternate as f64 / alleles.len() as f64
+        },
+        None => 1.0,
+    }
+}
+
+/// Writes the expected coverage of each record relative to an unedited
+/// genome as a bedGraph over input coordinates: a `fraction` of copies carry
+/// the record's layout and the rest are unchanged.
+pub fn write_coverage(path: &str, records: &[(&str, usize, &[Segment])], fraction: f64) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "track type=bedGraph name=expected_coverage")?;
+    for &(id, length, segments) in records {
+        let mut previous: Option<(usize, usize, f64)> = None;
+        for (start, end, copies) in copy_number_runs(segments, length) {
+            let relative = 1.0 - fraction + fraction * copies as f64;
+            // Round away floating-point noise so equal values merge
+            let relative = (relative * 1e6).round() / 1e6;
+            previous = match previous {
+                Some((from, _, value)) if value == relative => Some((from, end, value)),
+                Some((from, to, value)) => {
+                    writeln!(out, "{}\t{}\t{}\t{}", id, from, to, value)?;
+                    Some((start, end, relative))
+                },
+                None => Some((start, end, relative)),
+            };
+        }
+        if let Some((from, to, value)) = previous {
+            writeln!(out, "{}\t{}\t{}\t{}", id, from, to, value)?;
+        }
+    }
+    out.flush()
+}
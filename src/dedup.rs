@@ -0,0 +1,71 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::staging;
+
+/// k-mer length of the minimizer sketches.
+const KMER: usize = 15;
+
+/// Number of consecutive k-mers each minimizer is chosen from.
+const WINDOW: usize = 10;
+
+/// Hashes of the minimizers of `sequence`: the smallest k-mer hash in every
+/// window of `WINDOW` consecutive k-mers, sorted and without repeats. Case
+/// is ignored; k-mers are read on the given strand only, since the strand of
+/// a DVG matters.
+pub fn sketch(sequence: &str) -> Vec<u64> {
+    let bases = sequence.to_ascii_uppercase().into_bytes();
+    let hashes: Vec<u64> = bases.windows(KMER).map(fnv1a).collect();
+    let mut minimizers: Vec<u64> = if hashes.len() < WINDOW {
+        hashes.iter().min().into_iter().copied().collect()
+    } else {
+        hashes.windows(WINDOW).map(|window| *window.iter().min().expect("windows are not empty")).collect()
+    };
+    minimizers.sort_unstable();
+    minimizers.dedup();
+    minimizers
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Jaccard similarity of two sorted sketches.
+pub fn jaccard(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            },
+        }
+    }
+    shared as f64 / (a.len() + b.len() - shared) as f64
+}
+
+/// A record removed as a duplicate of a kept one.
+pub struct Collapsed {
+    pub id: String,
+    pub kept: String,
+    /// Sketch similarity for near-identical records, `None` for exact copies
+    pub similarity: Option<f64>,
+}
+
+/// Writes which kept record each removed one was collapsed into, as TSV.
+pub fn write_collapsed(path: &str, collapsed: &[Collapsed]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "id\tkept\tmatch\tsimilarity")?;
+    for record in collapsed {
+        match record.similarity {
+            Some(similarity) => writeln!(out, "{}\t{}\tnear\t{:.4}", record.id, record.kept, similarity)?,
+            None => writeln!(out, "{}\t{}\texact\t1", record.id, record.kept)?,
+        }
+    }
+    out.flush()
+}
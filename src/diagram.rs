@@ -0,0 +1,217 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::layout::Segment;
+use crate::staging;
+
+/// Width of the text diagram's bar, in characters.
+const TEXT_WIDTH: usize = 72;
+/// Width of the SVG drawing area and height of one molecule's row, in pixels.
+const SVG_WIDTH: f64 = 900.0;
+const SVG_ROW: f64 = 110.0;
+const SVG_MARGIN: f64 = 20.0;
+
+/// One molecule to draw: its ID, input length and segment layout.
+pub struct Molecule<'a> {
+    pub id: &'a str,
+    pub input_length: usize,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    Forward,
+    Reverse,
+    ReverseComplement,
+    Inserted,
+}
+
+impl Orientation {
+    fn name(self) -> &'static str {
+        match self {
+            Orientation::Forward => "forward",
+            Orientation::Reverse => "reverse",
+            Orientation::ReverseComplement => "reverse complement",
+            Orientation::Inserted => "inserted",
+        }
+    }
+}
+
+/// Drawable block of a molecule: adjacent segments that read the input
+/// contiguously are merged, so only real junctions separate blocks.
+struct Block {
+    label: String,
+    orientation: Orientation,
+    length: usize,
+    first: Option<(usize, bool)>,
+    last: Option<(usize, bool)>,
+}
+
+fn blocks(segments: &[Segment]) -> Vec<Block> {
+    let mut merged: Vec<Segment> = Vec::new();
+    for segment in segments {
+        if let (Some(Segment::Source { end: prev_end, start: prev_start, reverse: prev_reverse, complement: prev_complement }),
+                Segment::Source { start, end, reverse, complement }) = (merged.last_mut(), segment) {
+            if prev_reverse == reverse && prev_complement == complement {
+                if !reverse && *prev_end == *start {
+                    *prev_end = *end;
+                    continue;
+                }
+                if *reverse && *prev_start == *end {
+                    *prev_start = *start;
+                    continue;
+                }
+            }
+        }
+        merged.push(segment.clone());
+    }
+
+    merged.iter().map(|segment| match segment {
+        Segment::Source { start, end, reverse: false, .. } => Block {
+            label: format!("{}-{}", start + 1, end),
+            orientation: Orientation::Forward,
+            length: end - start,
+            first: Some((start + 1, false)),
+            last: Some((*end, false)),
+        },
+        Segment::Source { start, end, reverse: true, complement } => Block {
+            label: format!("{}-{}", end, start + 1),
+            orientation: if *complement { Orientation::ReverseComplement } else { Orientation::Reverse },
+            length: end - start,
+            first: Some((*end, true)),
+            last: Some((start + 1, true)),
+        },
+        Segment::Novel(novel) => Block {
+            label: format!("novel {}bp", novel.len()),
+            orientation: Orientation::Inserted,
+            length: novel.len(),
+            first: None,
+            last: None,
+        },
+    }).collect()
+}
+
+fn block_name(index: usize) -> String {
+    if index < 26 {
+        ((b'A' + index as u8) as char).to_string()
+    } else {
+        (index + 1).to_string()
+    }
+}
+
+fn breakend(end: Option<(usize, bool)>) -> String {
+    match end {
+        Some((position, reverse)) => format!("{}({})", position, if reverse { '-' } else { '+' }),
+        None => "novel".to_string(),
+    }
+}
+
+/// Splits `width` between blocks in proportion to their lengths, giving
+/// every block at least `minimum`.
+fn scale(blocks: &[Block], width: f64, minimum: f64) -> Vec<f64> {
+    let total: usize = blocks.iter().map(|b| b.length).sum();
+    let spare = (width - minimum * blocks.len() as f64).max(0.0);
+    blocks.iter().map(|b| minimum + spare * b.length as f64 / total.max(1) as f64).collect()
+}
+
+/// Writes the text diagram of each molecule to `out`.
+pub fn write_text<W: Write>(out: &mut W, molecules: &[Molecule]) -> io::Result<()> {
+    for (m, molecule) in molecules.iter().enumerate() {
+        if m > 0 {
+            writeln!(out)?;
+        }
+        let blocks = blocks(&molecule.segments);
+        let length: usize = blocks.iter().map(|b| b.length).sum();
+        writeln!(out, "{}: {}bp -> {}bp, {} block{}", molecule.id, molecule.input_length, length,
+                 blocks.len(), if blocks.len() == 1 { "" } else { "s" })?;
+
+        let widths: Vec<usize> = scale(&blocks, TEXT_WIDTH as f64, 3.0).iter().map(|w| w.round() as usize).collect();
+        let mut bar = String::from("|");
+        let mut names = String::from(" ");
+        for (i, (block, &width)) in blocks.iter().zip(&widths).enumerate() {
+            let arrow = match block.orientation {
+                Orientation::Forward => '>',
+                Orientation::Inserted => '~',
+                Orientation::Reverse | Orientation::ReverseComplement => '<',
+            };
+            bar.extend(std::iter::repeat_n(arrow, width));
+            bar.push('|');
+            names.push_str(&format!("{:<width$}", block_name(i), width = width + 1));
+        }
+        writeln!(out, "{}", bar)?;
+        writeln!(out, "{}", names.trim_end())?;
+
+        for (i, block) in blocks.iter().enumerate() {
+            writeln!(out, "  {:<3} {:<20} {:<19} {}bp", block_name(i), block.label, block.orientation.name(), block.length)?;
+        }
+        for (i, pair) in blocks.windows(2).enumerate() {
+            writeln!(out, "  junction {}|{}: {} -> {}", block_name(i), block_name(i + 1),
+                     breakend(pair[0].last), breakend(pair[1].first))?;
+        }
+    }
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn write_svg<W: Write>(out: &mut W, molecules: &[Molecule]) -> io::Result<()> {
+    let height = SVG_MARGIN * 2.0 + SVG_ROW * molecules.len() as f64;
+    writeln!(out, r##"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="11">"##,
+             SVG_WIDTH + SVG_MARGIN * 2.0, height)?;
+
+    for (m, molecule) in molecules.iter().enumerate() {
+        let blocks = blocks(&molecule.segments);
+        let length: usize = blocks.iter().map(|b| b.length).sum();
+        let top = SVG_MARGIN + SVG_ROW * m as f64;
+        let bar_y = top + 35.0;
+        writeln!(out, r##"  <text x="{}" y="{}" font-size="13" font-weight="bold">{} ({}bp -&gt; {}bp)</text>"##,
+                 SVG_MARGIN, top + 12.0, escape(molecule.id), molecule.input_length, length)?;
+
+        let mut x = SVG_MARGIN;
+        for (i, (block, width)) in blocks.iter().zip(scale(&blocks, SVG_WIDTH, 24.0)).enumerate() {
+            let fill = match block.orientation {
+                Orientation::Forward => "#4a7fc1",
+                Orientation::Inserted => "#9a9a9a",
+                Orientation::Reverse | Orientation::ReverseComplement => "#e08a2c",
+            };
+            writeln!(out, r##"  <rect x="{:.1}" y="{:.1}" width="{:.1}" height="20" fill="{}" stroke="white"/>"##,
+                     x, bar_y, width, fill)?;
+            // Orientation arrowhead at the block's downstream end
+            let arrow = match block.orientation {
+                Orientation::Forward => Some((x + width - 10.0, x + width - 2.0)),
+                Orientation::Inserted => None,
+                Orientation::Reverse | Orientation::ReverseComplement => Some((x + 10.0, x + 2.0)),
+            };
+            if let Some((base, tip)) = arrow {
+                writeln!(out, r##"  <polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="white"/>"##,
+                         base, bar_y + 4.0, tip, bar_y + 10.0, base, bar_y + 16.0)?;
+            }
+            writeln!(out, r##"  <text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"##,
+                     x + width / 2.0, bar_y + 34.0, block_name(i))?;
+            writeln!(out, r##"  <text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#555">{}</text>"##,
+                     x + width / 2.0, bar_y + 48.0, escape(&block.label))?;
+            if i > 0 {
+                writeln!(out, r##"  <line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#c0392b" stroke-width="2"/>"##,
+                         x, bar_y - 8.0, x, bar_y + 24.0)?;
+                writeln!(out, r##"  <text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#c0392b">{} -&gt; {}</text>"##,
+                         x, bar_y - 11.0, breakend(blocks[i - 1].last), breakend(block.first))?;
+            }
+            x += width;
+        }
+    }
+
+    writeln!(out, "</svg>")
+}
+
+/// Draws the structure of each molecule as SVG when `path` ends in `.svg`,
+/// otherwise as a text diagram.
+pub fn write_diagram(path: &str, molecules: &[Molecule]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    if path.ends_with(".svg") {
+        write_svg(&mut out, molecules)?;
+    } else {
+        write_text(&mut out, molecules)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,156 @@
+use std::io::{self, Write};
+
+/// Common type II enzymes and their recognition sites, with `^` marking
+/// where the top strand is cut.
+const ENZYMES: &[(&str, &str)] = &[
+    ("AluI", "AG^CT"),
+    ("ApaI", "GGGCC^C"),
+    ("BamHI", "G^GATCC"),
+    ("BglII", "A^GATCT"),
+    ("ClaI", "AT^CGAT"),
+    ("DpnII", "^GATC"),
+    ("EcoRI", "G^AATTC"),
+    ("EcoRV", "GAT^ATC"),
+    ("HaeIII", "GG^CC"),
+    ("HindIII", "A^AGCTT"),
+    ("HpaI", "GTT^AAC"),
+    ("KpnI", "GGTAC^C"),
+    ("MboI", "^GATC"),
+    ("MluI", "A^CGCGT"),
+    ("MspI", "C^CGG"),
+    ("NcoI", "C^CATGG"),
+    ("NdeI", "CA^TATG"),
+    ("NheI", "G^CTAGC"),
+    ("NotI", "GC^GGCCGC"),
+    ("PstI", "CTGCA^G"),
+    ("SacI", "GAGCT^C"),
+    ("SalI", "G^TCGAC"),
+    ("Sau3AI", "^GATC"),
+    ("SmaI", "CCC^GGG"),
+    ("SpeI", "A^CTAGT"),
+    ("TaqI", "T^CGA"),
+    ("XbaI", "T^CTAGA"),
+    ("XhoI", "C^TCGAG"),
+];
+
+/// A restriction enzyme: its recognition site (IUPAC codes allowed) and the
+/// offset of the top-strand cut from the start of the site.
+#[derive(Debug, Clone)]
+pub struct Enzyme {
+    pub name: String,
+    pub site: String,
+    pub cut: usize,
+}
+
+impl Enzyme {
+    /// An enzyme from the built-in table, matched case-insensitively, or a
+    /// custom site written with its cut, such as `GAAT^TC`.
+    pub fn parse(spec: &str) -> Result<Enzyme, String> {
+        if let Some((name, site)) = ENZYMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(spec)) {
+            return Ok(Enzyme::from_site(name, site));
+        }
+        let site = spec.to_ascii_uppercase();
+        if site.matches('^').count() == 1 && site.len() > 1 && site.chars().all(|b| b == '^' || iupac_bases(b).is_some()) {
+            return Ok(Enzyme::from_site(&site, &site));
+        }
+        let names: Vec<&str> = ENZYMES.iter().map(|(name, _)| *name).collect();
+        Err(format!("Unknown enzyme '{}'. Give a site with its cut (e.g. G^AATTC) or one of: {}", spec, names.join(", ")))
+    }
+
+    fn from_site(name: &str, site: &str) -> Enzyme {
+        Enzyme { name: name.to_string(), site: site.replace('^', ""), cut: site.find('^').unwrap_or(0) }
+    }
+
+    /// The site with its cut marked, as written in the enzyme table.
+    pub fn marked_site(&self) -> String {
+        format!("{}^{}", &self.site[..self.cut], &self.site[self.cut..])
+    }
+}
+
+/// Every base an IUPAC code stands for.
+fn iupac_bases(code: char) -> Option<&'static str> {
+    Some(match code {
+        'A' => "A", 'C' => "C", 'G' => "G", 'T' | 'U' => "T",
+        'R' => "AG", 'Y' => "CT", 'S' => "CG", 'W' => "AT", 'K' => "GT", 'M' => "AC",
+        'B' => "CGT", 'D' => "AGT", 'H' => "ACT", 'V' => "ACG", 'N' => "ACGT",
+        _ => return None,
+    })
+}
+
+fn complement_code(code: char) -> char {
+    match code {
+        'A' => 'T', 'T' => 'A', 'C' => 'G', 'G' => 'C',
+        'R' => 'Y', 'Y' => 'R', 'K' => 'M', 'M' => 'K',
+        'B' => 'V', 'V' => 'B', 'D' => 'H', 'H' => 'D',
+        other => other,
+    }
+}
+
+fn site_matches(site: &[u8], bases: &[u8]) -> bool {
+    site.iter().zip(bases).all(|(&code, &base)| {
+        let base = match base.to_ascii_uppercase() { b'U' => 'T', other => other as char };
+        iupac_bases(code as char).is_some_and(|set| set.contains(base))
+    })
+}
+
+/// Top-strand cut positions of `enzyme` in `sequence`, as the number of bases
+/// before each cut, in order. Sites on the bottom strand are cut at the
+/// mirrored offset. On a circular sequence sites may span the origin.
+pub fn cut_positions(sequence: &str, enzyme: &Enzyme, circular: bool) -> Vec<usize> {
+    let length = sequence.len();
+    let n = enzyme.site.len();
+    let reverse: String = enzyme.site.chars().rev().map(complement_code).collect();
+    let scanned = if circular {
+        format!("{}{}", sequence, &sequence[..(n - 1).min(length)])
+    } else {
+        sequence.to_string()
+    };
+    let bases = scanned.as_bytes();
+    let mut cuts = Vec::new();
+    for start in 0..(bases.len() + 1).saturating_sub(n) {
+        let window = &bases[start..start + n];
+        if site_matches(enzyme.site.as_bytes(), window) {
+            cuts.push(start + enzyme.cut);
+        }
+        if reverse != enzyme.site && site_matches(reverse.as_bytes(), window) {
+            cuts.push(start + n - enzyme.cut);
+        }
+    }
+    let mut cuts: Vec<usize> = cuts.into_iter()
+        .filter_map(|cut| if circular { Some(cut % length) } else { Some(cut).filter(|&c| c > 0 && c < length) })
+        .collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+    cuts
+}
+
+/// Writes the fragments of one record cut at `cuts` (position and the
+/// enzymes cutting there) as TSV rows: 1-based inclusive start and end,
+/// length, and the enzymes at either end. A circular fragment spanning the
+/// origin ends before it starts.
+pub fn write_fragments<W: Write>(out: &mut W, id: &str, length: usize, cuts: &[(usize, String)],
+                                 circular: bool) -> io::Result<()> {
+    let names = |i: Option<usize>| i.map_or(".".to_string(), |i| cuts[i].1.clone());
+    if circular {
+        if cuts.is_empty() {
+            return writeln!(out, "{}\t1\t{}\t{}\t.\t.", id, length, length);
+        }
+        for i in 0..cuts.len() {
+            let (start, next) = (cuts[i].0, (i + 1) % cuts.len());
+            let end = cuts[next].0;
+            let size = if end > start { end - start } else { length - start + end };
+            let end = if end == 0 { length } else { end };
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", id, start + 1, end, size, names(Some(i)), names(Some(next)))?;
+        }
+    } else {
+        let mut start = 0;
+        for i in 0..=cuts.len() {
+            let end = cuts.get(i).map_or(length, |cut| cut.0);
+            let left = if i == 0 { None } else { Some(i - 1) };
+            let right = if i < cuts.len() { Some(i) } else { None };
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", id, start + 1, end, end - start, names(left), names(right))?;
+            start = end;
+        }
+    }
+    Ok(())
+}
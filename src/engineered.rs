@@ -0,0 +1,131 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::layout::Segment;
+use crate::liftover;
+use crate::staging;
+
+/// What made a stretch of the edited sequence differ from the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Novel bases
+    Inserted,
+    /// A further copy of input bases read earlier in the sequence
+    Duplicated,
+    /// Input bases read backwards on the other strand
+    Inverted,
+    /// Input bases read backwards without complementing
+    Reversed,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Inserted => "inserted",
+            Kind::Duplicated => "duplicated",
+            Kind::Inverted => "inverted",
+            Kind::Reversed => "reversed",
+        }
+    }
+}
+
+/// An engineered segment of an edited sequence: 0-based, half-open
+/// coordinates on it, whether it reads on the reverse strand, and the
+/// operation (and plan line) that made it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Engineered {
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+    pub kind: Kind,
+    pub operation: String,
+    pub line: Option<usize>,
+}
+
+impl Engineered {
+    /// Short description for feature labels, e.g. `duplicated by duplicate`.
+    pub fn label(&self) -> String {
+        format!("{} by {}", self.kind.name(), self.operation)
+    }
+
+    /// Whitespace-free name for BED tracks, e.g. `duplicated:duplicate`, with
+    /// `:line3` appended for plan steps.
+    pub fn name(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}:line{}", self.kind.name(), self.operation, line),
+            None => format!("{}:{}", self.kind.name(), self.operation),
+        }
+    }
+}
+
+/// The engineered segments of one edit, in the coordinates of its output:
+/// novel bases, reversed input and, for input read more than once, every
+/// copy after the first.
+pub fn of_step(segments: &[Segment], operation: &str, line: Option<usize>) -> Vec<Engineered> {
+    let mut found = Vec::new();
+    let mut read: Vec<(usize, usize)> = Vec::new();
+    let mut offset = 0;
+    let mut add = |start: usize, end: usize, reverse: bool, kind: Kind| {
+        found.push(Engineered { start, end, reverse, kind, operation: operation.to_string(), line });
+    };
+    for segment in segments {
+        match *segment {
+            Segment::Novel(ref novel) => add(offset, offset + novel.len(), false, Kind::Inserted),
+            Segment::Source { start, end, reverse: true, complement } => {
+                add(offset, offset + end - start, true, if complement { Kind::Inverted } else { Kind::Reversed });
+                mark_read(&mut read, start, end);
+            },
+            Segment::Source { start, end, reverse: false, .. } => {
+                for &(read_start, read_end) in &read {
+                    let (from, to) = (start.max(read_start), end.min(read_end));
+                    if from < to {
+                        add(offset + from - start, offset + to - start, false, Kind::Duplicated);
+                    }
+                }
+                mark_read(&mut read, start, end);
+            },
+        }
+        offset += segment.len();
+    }
+    found
+}
+
+/// Adds `start..end` to the sorted, disjoint input ranges read so far.
+fn mark_read(read: &mut Vec<(usize, usize)>, start: usize, end: usize) {
+    read.push((start, end));
+    read.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for &(start, end) in read.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *read = merged;
+}
+
+/// Carries segments made by earlier edits through a later one with layout
+/// `segments`: each lands wherever its bases were copied to, flipping strand
+/// when read backwards, and is dropped when they were deleted.
+pub fn carry(engineered: &[Engineered], segments: &[Segment]) -> Vec<Engineered> {
+    engineered.iter().flat_map(|feature| {
+        liftover::map_interval(segments, feature.start, feature.end).into_iter().map(|mapped| Engineered {
+            start: mapped.start,
+            end: mapped.end,
+            reverse: feature.reverse != mapped.reverse,
+            ..feature.clone()
+        })
+    }).collect()
+}
+
+/// Writes the engineered segments of each record as BED6 on the edited
+/// sequence, named by what made them and stranded `-` when read backwards.
+pub fn write_segments_bed(path: &str, records: &[(&str, &[Engineered])]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    for &(id, features) in records {
+        for feature in features {
+            writeln!(out, "{}\t{}\t{}\t{}\t0\t{}", id, feature.start, feature.end, feature.name(),
+                     if feature.reverse { '-' } else { '+' })?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,116 @@
+use crate::units;
+
+/// A token of an arithmetic expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(char),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == ',') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &expression[start..end];
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(units::parse_bases(word)? as i64)
+            } else {
+                Token::Name(word.to_string())
+            });
+        } else {
+            return Err(format!("unexpected '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent evaluation over the tokens from `pos` on.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+impl Evaluator<'_> {
+    fn next_symbol(&mut self, symbols: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(c)) if symbols.contains(*c) => {
+                self.pos += 1;
+                Some(*c)
+            },
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut total = self.product()?;
+        while let Some(op) = self.next_symbol("+-") {
+            let term = self.product()?;
+            total = if op == '+' { total.checked_add(term) } else { total.checked_sub(term) }
+                .ok_or("the result is too large")?;
+        }
+        Ok(total)
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut total = self.factor()?;
+        while let Some(op) = self.next_symbol("*/") {
+            let factor = self.factor()?;
+            total = match op {
+                '*' => total.checked_mul(factor).ok_or("the result is too large")?,
+                _ if factor == 0 => return Err("division by zero".to_string()),
+                _ => total.div_euclid(factor),
+            };
+        }
+        Ok(total)
+    }
+
+    fn factor(&mut self) -> Result<i64, String> {
+        if self.next_symbol("-").is_some() {
+            return Ok(-self.factor()?);
+        }
+        if self.next_symbol("(").is_some() {
+            let value = self.sum()?;
+            return match self.next_symbol(")") {
+                Some(_) => Ok(value),
+                None => Err("missing ')'".to_string()),
+            };
+        }
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Name(name)) => (self.lookup)(&name).ok_or_else(|| format!("unknown name '{}'", name)),
+            Some(Token::Symbol(c)) => Err(format!("unexpected '{}'", c)),
+            None => Err("incomplete expression".to_string()),
+        }
+    }
+}
+
+/// Evaluates whole-number arithmetic: `+`, `-`, `*`, `/` (rounding down)
+/// and parentheses over numbers, written as for positions (`1.5k`), and
+/// names, whose values come from `lookup`.
+pub fn evaluate(expression: &str, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let mut evaluator = Evaluator { tokens: tokenize(expression)?, pos: 0, lookup };
+    let value = evaluator.sum()?;
+    match evaluator.tokens.get(evaluator.pos) {
+        Some(Token::Symbol(c)) => Err(format!("unexpected '{}'", c)),
+        Some(_) => Err("expected an operator between terms".to_string()),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::fasta;
+
+/// A `--region` selection: a record name and an optional 1-based, inclusive
+/// range within it, written samtools-style as `name` or `name:start-end`.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    pub range: Option<(usize, usize)>,
+}
+
+impl Region {
+    pub fn parse(text: &str) -> Result<Region, String> {
+        let (name, range) = match text.rsplit_once(':') {
+            Some((name, range)) => {
+                let (start, end) = range.split_once('-')
+                    .ok_or_else(|| format!("Region '{}' must be written as name:start-end", text))?;
+                let start = crate::units::parse_bases(start)
+                    .map_err(|_| format!("Region start '{}' must be a number", start))?;
+                let end = crate::units::parse_bases(end)
+                    .map_err(|_| format!("Region end '{}' must be a number", end))?;
+                if start == 0 || start > end {
+                    return Err(format!("Region '{}' must satisfy 1 <= start <= end", text));
+                }
+                (name, Some((start, end)))
+            },
+            None => (text, None),
+        };
+        if name.is_empty() {
+            return Err("Region must name a sequence".to_string());
+        }
+        Ok(Region { name: name.to_string(), range })
+    }
+
+    /// Header for the extracted sequence: the record's own header for whole
+    /// records, or `>name:start-end` for a sub-range.
+    pub fn header(&self, record_header: &str) -> String {
+        match self.range {
+            Some((start, end)) => format!(">{}:{}-{}", self.name, start, end),
+            None => record_header.to_string(),
+        }
+    }
+
+    /// Cuts the selected range out of a record of `length` bases, returning
+    /// 0-based, half-open bounds.
+    pub fn bounds(&self, length: usize) -> Result<(usize, usize), String> {
+        match self.range {
+            Some((_, end)) if end > length => Err(format!(
+                "Region end {} is beyond the length {} of sequence '{}'", end, length, self.name
+            )),
+            Some((start, end)) => Ok((start - 1, end)),
+            None => Ok((0, length)),
+        }
+    }
+}
+
+/// One line of a samtools `.fai` index.
+#[derive(Debug, Clone)]
+pub struct FaiEntry {
+    pub name: String,
+    pub length: usize,
+    pub offset: u64,
+    pub line_bases: usize,
+    pub line_width: usize,
+}
+
+/// Path of the index samtools would write for `fasta_path`, if it exists.
+/// Offsets into a compressed FASTA cannot be seeked to, so its index is
+/// not used.
+pub fn index_path(fasta_path: &str) -> Option<String> {
+    let path = format!("{}.fai", fasta_path);
+    if Path::new(&path).exists() && !fasta::is_compressed(fasta_path) { Some(path) } else { None }
+}
+
+pub fn read_fai(path: &str) -> io::Result<Vec<FaiEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let parsed = if fields.len() >= 5 {
+            match (fields[1].parse(), fields[2].parse(), fields[3].parse(), fields[4].parse()) {
+                (Ok(length), Ok(offset), Ok(line_bases), Ok(line_width)) => Some(FaiEntry {
+                    name: fields[0].to_string(),
+                    length,
+                    offset,
+                    line_bases,
+                    line_width,
+                }),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        match parsed {
+            Some(entry) => entries.push(entry),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid line {} in index {}", i + 1, path))),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Reads bases `start..end` (0-based, half-open) of an indexed record by
+/// seeking straight to them.
+pub fn fetch(fasta_path: &str, entry: &FaiEntry, start: usize, end: usize) -> io::Result<String> {
+    let mut file = File::open(fasta_path)?;
+    let file_offset = |pos: usize| {
+        entry.offset + ((pos / entry.line_bases) * entry.line_width + pos % entry.line_bases) as u64
+    };
+
+    let mut sequence = String::with_capacity(end - start);
+    if start == end {
+        return Ok(sequence);
+    }
+    let first = file_offset(start);
+    let last = file_offset(end - 1);
+    file.seek(SeekFrom::Start(first))?;
+    let mut raw = vec![0u8; (last - first + 1) as usize];
+    file.read_exact(&mut raw)?;
+
+    for &byte in &raw {
+        if !byte.is_ascii_whitespace() {
+            sequence.push(byte as char);
+        }
+    }
+    Ok(sequence)
+}
+
+/// Recovers the full header line of an indexed record, which ends right
+/// before the record's sequence offset.
+pub fn fetch_header(fasta_path: &str, entry: &FaiEntry) -> io::Result<String> {
+    let mut file = File::open(fasta_path)?;
+    let mut window = 4096u64;
+
+    loop {
+        let begin = entry.offset.saturating_sub(window);
+        file.seek(SeekFrom::Start(begin))?;
+        let mut raw = vec![0u8; (entry.offset - begin) as usize];
+        file.read_exact(&mut raw)?;
+
+        // Drop the line terminator that separates the header from the sequence
+        while raw.last().is_some_and(|b| *b == b'\n' || *b == b'\r') {
+            raw.pop();
+        }
+        if let Some(newline) = raw.iter().rposition(|b| *b == b'\n') {
+            return Ok(String::from_utf8_lossy(&raw[newline + 1..]).to_string());
+        }
+        if begin == 0 {
+            return Ok(String::from_utf8_lossy(&raw).to_string());
+        }
+        window *= 4;
+    }
+}
@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write, stdin};
+
+#[cfg(feature = "cli")]
+use flate2::bufread::MultiGzDecoder;
+
+use crate::fai::{self, Region};
+use crate::header;
+
+/// A single sequence record. `quality` is only present for FASTQ input and is
+/// carried through every edit alongside the bases it describes.
+///
+/// `line_width` is the wrapping detected in the input FASTA, so output can be
+/// written the same way; `None` means the sequence was on a single line.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub header: String,
+    pub sequence: String,
+    pub quality: Option<String>,
+    pub line_width: Option<usize>,
+}
+
+impl Record {
+    /// Sequence ID: the first word of the header.
+    pub fn id(&self) -> &str {
+        header::split_header(&self.header).1
+    }
+}
+
+/// An input that is not the FASTA or FASTQ it should be, as an I/O error.
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The first bytes of a gzip stream, and so of a bgzip file, which is a
+/// series of gzip members.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Opens `input` (`-` for stdin) for reading, decompressing it if it is
+/// gzip or bgzip compressed, whatever its name. Without the `cli` feature
+/// compressed input is an error.
+pub fn open_input(input: &str) -> io::Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(input)?))
+    };
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        #[cfg(feature = "cli")]
+        {
+            reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+        }
+        #[cfg(not(feature = "cli"))]
+        return Err(invalid(format!("{} is compressed, which needs sv_maker built with the cli feature", input)));
+    }
+    Ok(reader)
+}
+
+/// Whether the file at `path` is gzip or bgzip compressed, so its bytes are
+/// not the bases a `.fai` index or its size describe.
+pub fn is_compressed(path: &str) -> bool {
+    File::open(path)
+        .and_then(|file| Ok(BufReader::new(file).fill_buf()?.starts_with(&GZIP_MAGIC)))
+        .unwrap_or(false)
+}
+
+/// Reads every record in `input` (`-` for stdin), detecting FASTA or FASTQ
+/// from the first character and decompressing gzip or bgzip. Sequences are
+/// uppercased unless `preserve_case` is set.
+pub fn read_records(input: &str, preserve_case: bool) -> io::Result<Vec<Record>> {
+    let mut records = read_all(open_input(input)?, if input == "-" { "Input" } else { "File" })?;
+    if !preserve_case {
+        for record in records.iter_mut() {
+            record.sequence.make_ascii_uppercase();
+        }
+    }
+    Ok(records)
+}
+
+/// Reads every record of a FASTA or FASTQ file (`-` for stdin),
+/// uppercased as the command does without `--preserve-case`.
+pub fn read_fasta(input: &str) -> io::Result<Vec<Record>> {
+    read_records(input, false)
+}
+
+/// Reads only the record (or sub-range of a record) selected by `region`.
+/// With a `.fai` index next to the input this seeks straight to the bases;
+/// otherwise the file is scanned for the named record.
+pub fn read_region(input: &str, region: &Region, preserve_case: bool) -> io::Result<Record> {
+    let mut record = read_region_as_is(input, region)?;
+    if !preserve_case {
+        record.sequence.make_ascii_uppercase();
+    }
+    Ok(record)
+}
+
+fn read_region_as_is(input: &str, region: &Region) -> io::Result<Record> {
+    if input != "-" {
+        if let Some(index) = fai::index_path(input) {
+            let entries = fai::read_fai(&index)?;
+            let entry = entries.iter().find(|e| e.name == region.name)
+                .ok_or_else(|| invalid(format!("Sequence '{}' not found in index {}", region.name, index)))?;
+            let (start, end) = region.bounds(entry.length).map_err(invalid)?;
+            let sequence = fai::fetch(input, entry, start, end)?;
+            let header = region.header(&fai::fetch_header(input, entry)?);
+            return Ok(Record { header, sequence, quality: None, line_width: Some(entry.line_bases) });
+        }
+    }
+
+    let record = read_named_fasta(open_input(input)?, &region.name, if input == "-" { "Input" } else { "File" })?;
+    let (start, end) = region.bounds(record.sequence.len()).map_err(invalid)?;
+    Ok(Record {
+        header: region.header(&record.header),
+        sequence: record.sequence[start..end].to_string(),
+        quality: None,
+        line_width: record.line_width,
+    })
+}
+
+/// Scans a (possibly multi-record) FASTA for the record whose ID is `name`.
+fn read_named_fasta<R: BufRead>(reader: R, name: &str, source: &str) -> io::Result<Record> {
+    let mut header = None;
+    let mut sequence = String::new();
+    let mut wrap = WrapDetector::default();
+
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(rest) = line.strip_prefix('>') {
+            if header.is_some() {
+                break;
+            }
+            if rest.split_whitespace().next() == Some(name) {
+                header = Some(line);
+            }
+        } else if header.is_some() {
+            let line = line.trim();
+            wrap.observe(line);
+            sequence.push_str(line);
+        }
+    }
+
+    match header {
+        Some(header) => Ok(Record { header, sequence, quality: None, line_width: wrap.width() }),
+        None => Err(invalid(format!("Sequence '{}' not found in {}", name, source.to_lowercase()))),
+    }
+}
+
+fn read_all<R: BufRead>(mut reader: R, source: &str) -> io::Result<Vec<Record>> {
+    let is_fastq = reader.fill_buf()?.first() == Some(&b'@');
+    if is_fastq {
+        read_fastq(reader, source)
+    } else {
+        parse_fasta(reader, source)
+    }
+}
+
+fn parse_fasta<R: BufRead>(reader: R, source: &str) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut current: Option<(String, String, WrapDetector)> = None;
+    
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('>') {
+            if let Some((header, sequence, wrap)) = current.take() {
+                records.push(finish_fasta_record(header, sequence, wrap, source)?);
+            }
+            current = Some((line, String::new(), WrapDetector::default()));
+        } else if let Some((_, sequence, wrap)) = current.as_mut() {
+            // Remove surrounding whitespace
+            let line = line.trim();
+            wrap.observe(line);
+            sequence.push_str(line);
+        } else if !line.trim().is_empty() {
+            return Err(invalid(format!("{} does not appear to be a valid FASTA file (no header starting with '>')", source)));
+        }
+    }
+    
+    match current {
+        Some((header, sequence, wrap)) => records.push(finish_fasta_record(header, sequence, wrap, source)?),
+        None => return Err(invalid(format!("{} is empty", source))),
+    }
+    
+    Ok(records)
+}
+
+fn finish_fasta_record(header: String, sequence: String, wrap: WrapDetector, source: &str) -> io::Result<Record> {
+    if sequence.is_empty() {
+        return Err(invalid(format!("No sequence found for '{}' in {}", header, source.to_lowercase())));
+    }
+    Ok(Record { header, sequence, quality: None, line_width: wrap.width() })
+}
+
+/// Works out how a FASTA sequence was wrapped from the lengths of its lines:
+/// the first line's length, unless the whole sequence sat on one line.
+#[derive(Default)]
+struct WrapDetector {
+    first_line: Option<usize>,
+    lines: usize,
+}
+
+impl WrapDetector {
+    fn observe(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+        self.first_line.get_or_insert(line.len());
+        self.lines += 1;
+    }
+
+    fn width(&self) -> Option<usize> {
+        if self.lines > 1 { self.first_line } else { None }
+    }
+}
+
+fn read_fastq<R: BufRead>(reader: R, source: &str) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut lines = reader.lines();
+
+    while let Some(line) = lines.next() {
+        let header = line?.trim_end().to_string();
+        if header.is_empty() {
+            continue;
+        }
+        if !header.starts_with('@') {
+            return Err(invalid(format!("{} is not valid FASTQ (expected '@' header line, found '{}')", source, header)));
+        }
+        let mut next_line = |what: &str| -> io::Result<String> {
+            match lines.next() {
+                Some(line) => Ok(line?.trim_end().to_string()),
+                None => Err(invalid(format!("{} ends before the FASTQ {} line of '{}'", source, what, header))),
+            }
+        };
+
+        let sequence = next_line("sequence")?;
+        let separator = next_line("separator")?;
+        if !separator.starts_with('+') {
+            return Err(invalid(format!("{} is not valid FASTQ (expected '+' separator line)", source)));
+        }
+        let quality = next_line("quality")?;
+
+        if sequence.is_empty() {
+            return Err(invalid(format!("No sequence found for '{}' in {}", header, source.to_lowercase())));
+        }
+        if quality.len() != sequence.len() {
+            return Err(invalid(format!("FASTQ quality string of '{}' has {} values for {} bases", header, quality.len(), sequence.len())));
+        }
+
+        records.push(Record { header, sequence, quality: Some(quality), line_width: None });
+    }
+
+    if records.is_empty() {
+        return Err(invalid(format!("{} is empty", source)));
+    }
+
+    Ok(records)
+}
+
+/// Writes `records` to `output` (stdout when `None`), each as FASTQ if it
+/// carries qualities and as FASTA otherwise. FASTA sequence lines are wrapped
+/// at the record's `line_width`.
+pub fn write_records(records: &[Record], output: Option<&str>) -> io::Result<()> {
+    match output {
+        Some(filename) => {
+            let mut file = BufWriter::new(File::create(filename)?);
+            for record in records {
+                write_record(&mut file, record)?;
+            }
+            file.flush()
+        },
+        None => {
+            let stdout = io::stdout();
+            let mut handle = stdout.lock();
+            for record in records {
+                write_record(&mut handle, record)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> io::Result<()> {
+    match &record.quality {
+        Some(quality) => write_fastq_record(out, record, quality),
+        None => write_fasta_record(out, record),
+    }
+}
+
+/// Writes `record` as FASTA, its sequence lines wrapped at its `line_width`.
+pub fn write_fasta_record<W: Write + ?Sized>(out: &mut W, record: &Record) -> io::Result<()> {
+    // Headers keep the marker of the format they were read in
+    match record.header.strip_prefix('@') {
+        Some(rest) => writeln!(out, ">{}", rest)?,
+        None => writeln!(out, "{}", record.header)?,
+    }
+    match record.line_width {
+        Some(width) => {
+            for chunk in record.sequence.as_bytes().chunks(width) {
+                writeln!(out, "{}", std::str::from_utf8(chunk).unwrap())?;
+            }
+        },
+        None => writeln!(out, "{}", record.sequence)?,
+    }
+    Ok(())
+}
+
+/// Writes `record` as FASTQ with the given qualities.
+pub fn write_fastq_record<W: Write + ?Sized>(out: &mut W, record: &Record, quality: &str) -> io::Result<()> {
+    match record.header.strip_prefix('>') {
+        Some(rest) => writeln!(out, "@{}", rest)?,
+        None => writeln!(out, "{}", record.header)?,
+    }
+    writeln!(out, "{}", record.sequence)?;
+    writeln!(out, "+")?;
+    writeln!(out, "{}", quality)
+}
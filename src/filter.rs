@@ -0,0 +1,107 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+
+/// How a manifest field is compared with a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A `--where` condition on one manifest column, such as `gend=5` or
+/// `length>=1000`.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    field: String,
+    comparison: Comparison,
+    value: String,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        // Two-character operators first, so `>=` is not read as `>`
+        let operators = [
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("=", Comparison::Equal),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ];
+        for (symbol, comparison) in operators {
+            if let Some((field, value)) = text.split_once(symbol) {
+                if field.is_empty() || value.is_empty() {
+                    break;
+                }
+                return Ok(Condition { field: field.trim().to_string(), comparison, value: value.trim().to_string() });
+            }
+        }
+        Err(format!("--where must be <field><op><value> with op one of = != < <= > >=, got '{}'", text))
+    }
+
+    /// Whether `fields` satisfy the condition. Values compare as numbers when
+    /// both are numbers, and as text otherwise.
+    fn matches(&self, fields: &HashMap<String, String>) -> bool {
+        let Some(actual) = fields.get(&self.field) else {
+            return false;
+        };
+        let ordering = match (actual.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(actual), Ok(value)) => actual.partial_cmp(&value),
+            _ => Some(actual.as_str().cmp(self.value.as_str())),
+        };
+        let Some(ordering) = ordering else {
+            return false;
+        };
+        match self.comparison {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// The rows of a TSV manifest with a header line, such as `generate
+/// --manifest` writes, keyed by the first column (the record ID).
+pub struct Manifest {
+    pub columns: Vec<String>,
+    rows: HashMap<String, HashMap<String, String>>,
+}
+
+impl Manifest {
+    pub fn read(path: &str) -> Result<Manifest, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read manifest {}: {}", path, e))?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#'));
+        let columns: Vec<String> = lines.next()
+            .ok_or_else(|| format!("Manifest {} is empty", path))?
+            .split('\t').map(str::to_string).collect();
+        let mut rows = HashMap::new();
+        for (n, line) in lines.enumerate() {
+            let values: Vec<&str> = line.split('\t').collect();
+            if values.len() != columns.len() {
+                return Err(format!("{} row {}: {} fields, but the header has {}", path, n + 2, values.len(), columns.len()));
+            }
+            let fields = columns.iter().cloned().zip(values.iter().map(|value| value.to_string())).collect();
+            rows.insert(values[0].to_string(), fields);
+        }
+        Ok(Manifest { columns, rows })
+    }
+
+    /// Whether the manifest row for `id` meets every condition. Records the
+    /// manifest does not list meet none.
+    pub fn matches(&self, id: &str, conditions: &[Condition]) -> bool {
+        self.rows.get(id).is_some_and(|fields| conditions.iter().all(|condition| condition.matches(fields)))
+    }
+
+    /// The first condition on a column the manifest does not have.
+    pub fn unknown_field<'a>(&self, conditions: &'a [Condition]) -> Option<&'a str> {
+        conditions.iter().map(|condition| condition.field.as_str()).find(|field| !self.columns.iter().any(|c| c == field))
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::fasta::Record;
+use crate::staging;
+
+/// Characters alignment tools write for a gap column.
+pub fn is_gap(c: char) -> bool {
+    c == '-' || c == '.'
+}
+
+/// A run of bases with no gap between them: 1-based, inclusive ranges on
+/// the aligned and on the ungapped sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    pub aligned_start: usize,
+    pub aligned_end: usize,
+    pub ungapped_start: usize,
+    pub ungapped_end: usize,
+}
+
+/// One record's mapping from its aligned to its ungapped coordinates.
+#[derive(Debug, Clone)]
+pub struct GapMap {
+    pub id: String,
+    pub aligned_length: usize,
+    pub blocks: Vec<Block>,
+}
+
+impl GapMap {
+    pub fn gaps(&self) -> usize {
+        self.aligned_length - self.blocks.iter().map(|block| block.ungapped_end + 1 - block.ungapped_start).sum::<usize>()
+    }
+}
+
+/// Removes the gaps from `record`, and from its qualities for FASTQ, and
+/// returns the mapping from the aligned positions it had.
+pub fn strip(record: &mut Record) -> GapMap {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut ungapped = 0;
+    let mut previous_gap = true;
+    for (i, c) in record.sequence.chars().enumerate() {
+        if is_gap(c) {
+            previous_gap = true;
+            continue;
+        }
+        ungapped += 1;
+        match blocks.last_mut() {
+            Some(block) if !previous_gap => {
+                block.aligned_end = i + 1;
+                block.ungapped_end = ungapped;
+            },
+            _ => blocks.push(Block { aligned_start: i + 1, aligned_end: i + 1, ungapped_start: ungapped, ungapped_end: ungapped }),
+        }
+        previous_gap = false;
+    }
+    let map = GapMap { id: record.id().to_string(), aligned_length: record.sequence.len(), blocks };
+    if map.gaps() > 0 {
+        if let Some(quality) = &record.quality {
+            record.quality = Some(record.sequence.chars().zip(quality.chars())
+                .filter(|(c, _)| !is_gap(*c)).map(|(_, q)| q).collect());
+        }
+        record.sequence.retain(|c| !is_gap(c));
+    }
+    map
+}
+
+/// Lengths of the runs of `N` (either case), the mark of an assembly gap,
+/// at the start and at the end of `sequence`.
+pub fn terminal_ns(sequence: &str) -> (usize, usize) {
+    let is_n = |c: &u8| c.eq_ignore_ascii_case(&b'N');
+    let leading = sequence.bytes().take_while(is_n).count();
+    if leading == sequence.len() {
+        return (leading, 0);
+    }
+    (leading, sequence.bytes().rev().take_while(is_n).count())
+}
+
+/// Removes the runs of `N` from both ends of `record`, and from its
+/// qualities for FASTQ, noting in its header how many went. Returns the
+/// number removed from the start and from the end.
+pub fn trim_terminal_ns(record: &mut Record) -> (usize, usize) {
+    let (leading, trailing) = terminal_ns(&record.sequence);
+    if leading + trailing > 0 {
+        let end = record.sequence.len() - trailing;
+        record.sequence = record.sequence[leading..end].to_string();
+        if let Some(quality) = &mut record.quality {
+            *quality = quality[leading..end].to_string();
+        }
+        record.header = format!("{} [trimmed {} leading and {} trailing Ns]", record.header, leading, trailing);
+    }
+    (leading, trailing)
+}
+
+/// Writes the `--gap-map` TSV: one line per ungapped block of each record.
+pub fn write_map(path: &str, maps: &[GapMap]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "record\taligned_start\taligned_end\tungapped_start\tungapped_end")?;
+    for map in maps {
+        for block in &map.blocks {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}", map.id, block.aligned_start, block.aligned_end,
+                     block.ungapped_start, block.ungapped_end)?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,82 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::engineered::Engineered;
+use crate::staging;
+use crate::warnings::{self, Severity, Warning};
+
+/// An edited record and the engineered segments on it.
+pub struct Construct<'a> {
+    pub id: &'a str,
+    pub description: &'a str,
+    pub sequence: &'a str,
+    pub molecule: &'static str,
+    pub features: &'a [Engineered],
+}
+
+/// Fixed LOCUS date, as other tools use when a file has no real one, so the
+/// same edits always give the same file.
+const LOCUS_DATE: &str = "01-JAN-1980";
+
+/// Widest name the LOCUS line's fixed columns hold.
+const LOCUS_NAME_WIDTH: usize = 16;
+
+/// Writes the constructs as GenBank to the file `path`.
+pub fn write_genbank(path: &str, constructs: &[Construct]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    emit_genbank(&mut out, constructs)?;
+    out.flush()
+}
+
+/// Writes the records as GenBank flat files, one entry each, with every
+/// engineered segment as a `misc_feature` labelled with what made it, so
+/// constructs open in SnapGene and similar viewers with a map.
+pub fn emit_genbank(out: &mut dyn Write, constructs: &[Construct]) -> io::Result<()> {
+    for construct in constructs {
+        let length = construct.sequence.len();
+        let name = match construct.id.char_indices().nth(LOCUS_NAME_WIDTH) {
+            Some((cut, _)) => {
+                warnings::warn(Warning {
+                    code: "header_truncated",
+                    severity: Severity::Low,
+                    record: Some(construct.id.to_string()),
+                    position: None,
+                    message: format!("{} is longer than the {} characters of a GenBank LOCUS name; written as {}",
+                                     construct.id, LOCUS_NAME_WIDTH, &construct.id[..cut]),
+                });
+                &construct.id[..cut]
+            },
+            None => construct.id,
+        };
+        writeln!(out, "LOCUS       {:<16} {:>11} bp    {:<6}  linear   SYN {}",
+                 name, length, construct.molecule, LOCUS_DATE)?;
+        let description = if construct.description.is_empty() { construct.id } else { construct.description };
+        writeln!(out, "DEFINITION  {}.", description)?;
+        writeln!(out, "ACCESSION   .")?;
+        writeln!(out, "VERSION     .")?;
+        writeln!(out, "KEYWORDS    .")?;
+        writeln!(out, "SOURCE      synthetic construct")?;
+        writeln!(out, "  ORGANISM  synthetic construct")?;
+        writeln!(out, "FEATURES             Location/Qualifiers")?;
+        writeln!(out, "     source          1..{}", length)?;
+        writeln!(out, "                     /mol_type=\"other {}\"", construct.molecule)?;
+        for feature in construct.features {
+            let span = format!("{}..{}", feature.start + 1, feature.end);
+            let location = if feature.reverse { format!("complement({})", span) } else { span };
+            writeln!(out, "     misc_feature    {}", location)?;
+            writeln!(out, "                     /label=\"{}\"", feature.label())?;
+            if let Some(line) = feature.line {
+                writeln!(out, "                     /note=\"plan line {}\"", line)?;
+            }
+        }
+        writeln!(out, "ORIGIN")?;
+        for (row, chunk) in construct.sequence.as_bytes().chunks(60).enumerate() {
+            write!(out, "{:>9}", row * 60 + 1)?;
+            for block in chunk.chunks(10) {
+                write!(out, " {}", String::from_utf8_lossy(block).to_ascii_lowercase())?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(out, "//")?;
+    }
+    Ok(())
+}
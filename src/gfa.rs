@@ -0,0 +1,144 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::layout::Segment;
+use crate::staging;
+
+/// One input record and the layout of its edited version.
+pub struct GraphRecord<'a> {
+    pub id: &'a str,
+    pub sequence: &'a str,
+    pub segments: Vec<Segment>,
+}
+
+/// A GFA segment: a stretch of the backbone (rank 0, at an input offset) or
+/// material only found on the edited path (rank 1, at a derived offset).
+struct GraphSegment {
+    name: String,
+    sequence: String,
+    stable_name: String,
+    offset: usize,
+    rank: u8,
+}
+
+/// An oriented step through the graph, by segment index.
+type Step = (usize, bool);
+
+/// Writes the rGFA graph of `records` to the file `path`.
+pub fn write_gfa(path: &str, records: &[GraphRecord]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    emit_gfa(&mut out, records)?;
+    out.flush()
+}
+
+/// Writes an rGFA graph (GFA 1.1 with SN/SO/SR tags) in which each input
+/// record is the backbone and its edited version an alternate path. The
+/// backbone is cut at every breakpoint an edit uses, so both paths reuse its
+/// segments wherever they share bases; inserted material, and stretches
+/// reversed without complementing, become rank-1 segments. Segment names
+/// depend only on the input and the edits, so repeated runs agree.
+pub fn emit_gfa(out: &mut dyn Write, records: &[GraphRecord]) -> io::Result<()> {
+    let mut segments: Vec<GraphSegment> = Vec::new();
+    let mut links: Vec<(Step, Step)> = Vec::new();
+    let mut paths: Vec<(String, Vec<Step>)> = Vec::new();
+
+    for record in records {
+        let length = record.sequence.len();
+        let mut cuts = vec![0, length];
+        for segment in &record.segments {
+            if let Segment::Source { start, end, .. } = *segment {
+                cuts.push(start);
+                cuts.push(end);
+            }
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        // Backbone pieces between consecutive cuts, in input order
+        let first = segments.len();
+        for window in cuts.windows(2) {
+            segments.push(GraphSegment {
+                name: format!("s{}", segments.len() + 1),
+                sequence: record.sequence[window[0]..window[1]].to_string(),
+                stable_name: record.id.to_string(),
+                offset: window[0],
+                rank: 0,
+            });
+        }
+        let piece = |position: usize| first + cuts.binary_search(&position).unwrap_or_else(|i| i);
+        let backbone: Vec<Step> = (first..segments.len()).map(|i| (i, true)).collect();
+        add_links(&mut links, &backbone);
+        paths.push((record.id.to_string(), backbone));
+
+        let identity = record.segments.len() == 1
+            && record.segments[0] == Segment::Source { start: 0, end: length, reverse: false, complement: false };
+        if !identity {
+            let alt_name = format!("{}_alt", record.id);
+            let mut alt = Vec::new();
+            let mut offset = 0;
+            for segment in record.segments.iter().filter(|segment| !segment.is_empty()) {
+                match *segment {
+                    Segment::Source { start, end, reverse: false, .. } => {
+                        alt.extend((piece(start)..piece(end)).map(|i| (i, true)));
+                    },
+                    Segment::Source { start, end, reverse: true, complement: true } => {
+                        alt.extend((piece(start)..piece(end)).rev().map(|i| (i, false)));
+                    },
+                    // A reversal without complementing is not a reading of
+                    // either strand, so its bases are new to the graph
+                    Segment::Source { start, end, reverse: true, complement: false } => {
+                        let reversed = record.sequence[start..end].chars().rev().collect();
+                        alt.push((segments.len(), true));
+                        segments.push(alt_segment(segments.len(), reversed, &alt_name, offset));
+                    },
+                    Segment::Novel(ref inserted) => {
+                        alt.push((segments.len(), true));
+                        segments.push(alt_segment(segments.len(), inserted.clone(), &alt_name, offset));
+                    },
+                }
+                offset += segment.len();
+            }
+            add_links(&mut links, &alt);
+            paths.push((alt_name, alt));
+        }
+    }
+
+    let orientation = |forward: bool| if forward { '+' } else { '-' };
+    writeln!(out, "H\tVN:Z:1.1")?;
+    for segment in &segments {
+        writeln!(out, "S\t{}\t{}\tLN:i:{}\tSN:Z:{}\tSO:i:{}\tSR:i:{}", segment.name, segment.sequence,
+                 segment.sequence.len(), segment.stable_name, segment.offset, segment.rank)?;
+    }
+    for ((from, from_forward), (to, to_forward)) in &links {
+        writeln!(out, "L\t{}\t{}\t{}\t{}\t0M", segments[*from].name, orientation(*from_forward),
+                 segments[*to].name, orientation(*to_forward))?;
+    }
+    for (name, steps) in &paths {
+        let walk: Vec<String> = steps.iter()
+            .map(|&(i, forward)| format!("{}{}", segments[i].name, orientation(forward)))
+            .collect();
+        writeln!(out, "P\t{}\t{}\t*", name, walk.join(","))?;
+    }
+    Ok(())
+}
+
+fn alt_segment(index: usize, sequence: String, path_name: &str, offset: usize) -> GraphSegment {
+    GraphSegment {
+        name: format!("s{}", index + 1),
+        sequence,
+        stable_name: path_name.to_string(),
+        offset,
+        rank: 1,
+    }
+}
+
+/// Adds a link for each consecutive pair of steps, unless the graph already
+/// has it in either direction.
+fn add_links(links: &mut Vec<(Step, Step)>, steps: &[Step]) {
+    for pair in steps.windows(2) {
+        let link = (pair[0], pair[1]);
+        let flipped = ((pair[1].0, !pair[1].1), (pair[0].0, !pair[0].1));
+        if !links.contains(&link) && !links.contains(&flipped) {
+            links.push(link);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs;
+
+/// One GFF3 feature: 0-based, half-open coordinates on `seqid`.
+#[derive(Debug, Clone)]
+pub struct Feature {
+    pub seqid: String,
+    pub kind: String,
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+    /// The `ID` or `Name` attribute, or `<type>:<start>-<end>` without either
+    pub id: String,
+}
+
+/// Reads the features of a GFF3 file, stopping at its `##FASTA` section.
+pub fn read_gff(path: &str) -> Result<Vec<Feature>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read GFF file {}: {}", path, e))?;
+    let mut features = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.starts_with("##FASTA") {
+            break;
+        }
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return Err(format!("{} line {}: expected 9 tab-separated columns", path, i + 1));
+        }
+        let coordinate = |value: &str| value.trim().parse::<usize>()
+            .map_err(|_| format!("{} line {}: '{}' is not a valid coordinate", path, i + 1, value));
+        let (start, end) = (coordinate(fields[3])?, coordinate(fields[4])?);
+        if start == 0 || start > end {
+            return Err(format!("{} line {}: {}-{} is not a 1-based range", path, i + 1, start, end));
+        }
+        let attribute = |key: &str| fields[8].split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value.to_string());
+        let id = attribute("ID").or_else(|| attribute("Name"))
+            .unwrap_or_else(|| format!("{}:{}-{}", fields[2], start, end));
+        features.push(Feature {
+            seqid: fields[0].to_string(),
+            kind: fields[2].to_string(),
+            start: start - 1,
+            end,
+            reverse: fields[6] == "-",
+            id,
+        });
+    }
+
+    Ok(features)
+}
+
+/// The features edits are reported against: the genes when there are any,
+/// otherwise every feature except whole-sequence `region`, `chromosome` and
+/// `source` lines.
+pub fn reported(features: &[Feature]) -> Vec<&Feature> {
+    if features.iter().any(|feature| feature.kind == "gene") {
+        features.iter().filter(|feature| feature.kind == "gene").collect()
+    } else {
+        features.iter().filter(|feature| !matches!(feature.kind.as_str(), "region" | "chromosome" | "source")).collect()
+    }
+}
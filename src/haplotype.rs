@@ -0,0 +1,233 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::bed;
+use crate::layout::Junction;
+use crate::staging;
+
+/// Sex-chromosome complement of a diploid genome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    XX,
+    XY,
+}
+
+impl Sex {
+    pub fn parse(value: &str) -> Result<Sex, String> {
+        match value.to_ascii_uppercase().as_str() {
+            "XX" => Ok(Sex::XX),
+            "XY" => Ok(Sex::XY),
+            _ => Err(format!("--sex must be XX or XY, got '{}'", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Sex::XX => "XX",
+            Sex::XY => "XY",
+        }
+    }
+}
+
+/// A pseudoautosomal region: the same sequence at `x` on chrX and `y` on
+/// chrY, 0-based and half-open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Par {
+    pub x: (usize, usize),
+    pub y: (usize, usize),
+}
+
+/// The PARs of the human assemblies, recognised by their chrX and chrY lengths.
+const KNOWN_PARS: &[(&str, usize, usize, [Par; 2])] = &[
+    ("GRCh38", 156_040_895, 57_227_415, [
+        Par { x: (10_000, 2_781_479), y: (10_000, 2_781_479) },
+        Par { x: (155_701_382, 156_030_895), y: (56_887_902, 57_217_415) },
+    ]),
+    ("GRCh37", 155_270_560, 59_373_566, [
+        Par { x: (60_000, 2_699_520), y: (10_000, 2_649_520) },
+        Par { x: (154_931_043, 155_260_560), y: (59_034_049, 59_363_566) },
+    ]),
+];
+
+pub fn is_x(id: &str) -> bool {
+    id == "chrX" || id == "X"
+}
+
+pub fn is_y(id: &str) -> bool {
+    id == "chrY" || id == "Y"
+}
+
+/// The PARs of a known assembly with these chrX and chrY lengths, and its name.
+pub fn known_pars(x_length: usize, y_length: usize) -> Option<(&'static str, Vec<Par>)> {
+    KNOWN_PARS.iter()
+        .find(|(_, x, y, _)| *x == x_length && *y == y_length)
+        .map(|(name, _, _, pars)| (*name, pars.to_vec()))
+}
+
+/// Reads PARs from a BED file: the chrX intervals paired, in file order,
+/// with the chrY intervals, which must be the same lengths.
+pub fn read_pars(path: &str) -> Result<Vec<Par>, String> {
+    let intervals = bed::read_bed(path)?;
+    let on = |test: fn(&str) -> bool| -> Vec<(usize, usize)> {
+        intervals.iter().filter(|i| test(&i.chrom)).map(|i| (i.start, i.end)).collect()
+    };
+    let (x, y) = (on(is_x), on(is_y));
+    if x.len() != y.len() {
+        return Err(format!("{} has {} chrX and {} chrY intervals; PARs come in pairs", path, x.len(), y.len()));
+    }
+    x.into_iter().zip(y).map(|(x, y)| {
+        if x.1 - x.0 != y.1 - y.0 {
+            Err(format!("{}: PAR chrX:{}-{} and chrY:{}-{} differ in length", path, x.0 + 1, x.1, y.0 + 1, y.1))
+        } else {
+            Ok(Par { x, y })
+        }
+    }).collect()
+}
+
+/// Whether every junction joins two chrX bases of the same PAR, so the edit
+/// stays within it.
+pub fn within_pars(junctions: &[Junction], pars: &[Par]) -> bool {
+    junctions.iter().all(|junction| match (junction.left, junction.right) {
+        (Some(left), Some(right)) => pars.iter().any(|par| {
+            let inside = |position: usize| par.x.0 < position && position <= par.x.1;
+            inside(left.position) && inside(right.position)
+        }),
+        _ => false,
+    })
+}
+
+/// The haplotypes (1 and 2) a variant with `genotype` goes on, given the
+/// haplotypes that carry a copy of its region. Phased genotypes place it as
+/// written and an unphased heterozygote on haplotype 1. A region with one
+/// copy takes only hemizygous or homozygous genotypes. Without a genotype
+/// every copy is edited.
+pub fn placement(genotype: Option<&str>, copies: [bool; 2]) -> Result<[bool; 2], String> {
+    let Some(genotype) = genotype else {
+        return Ok(copies);
+    };
+    let alleles: Vec<bool> = genotype.split(['/', '|']).map(|allele| allele != "0").collect();
+    let single = copies[0] != copies[1];
+    match alleles[..] {
+        [_] if single => Ok(copies),
+        [true, true] if single => Ok(copies),
+        [_] => Err(format!("Genotype {} is haploid, but the region has a copy on both haplotypes", genotype)),
+        [_, _] if single => Err(format!("Genotype {} needs two copies, but the region has one", genotype)),
+        [first, second] if genotype.contains('|') => Ok([first, second]),
+        [true, true] => Ok([true, true]),
+        [_, _] => Ok([true, false]),
+        _ => Err(format!("Genotype {} is not diploid", genotype)),
+    }
+}
+
+/// One row of the ploidy manifest: a record on one haplotype.
+pub struct RecordCopy {
+    pub record: String,
+    pub haplotype: usize,
+    pub present: bool,
+    /// The PARs copied into the record, as `from>to` intervals
+    pub pars: Vec<String>,
+    /// The plan lines applied to this copy
+    pub edits: Vec<usize>,
+}
+
+/// Writes which haplotype carries each record, the PARs spliced into chrY
+/// and the plan lines applied to every copy as TSV.
+pub fn write_ploidy_manifest(path: &str, sex: Sex, copies: &[RecordCopy]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "# sex={}", sex.name())?;
+    writeln!(out, "record\thaplotype\tcopies\tpar\tedits")?;
+    for copy in copies {
+        let pars = if copy.pars.is_empty() { ".".to_string() } else { copy.pars.join(",") };
+        let edits = if copy.edits.is_empty() {
+            ".".to_string()
+        } else {
+            copy.edits.iter().map(|line| line.to_string()).collect::<Vec<_>>().join(",")
+        };
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", copy.record, copy.haplotype, u8::from(copy.present), pars, edits)?;
+    }
+    out.flush()
+}
+
+/// A germline variant of a phased VCF: `reference` at 0-based `position` of
+/// `chrom`, and the bases each haplotype of the sample carries instead
+/// (`None` where it has the reference). Haploid calls have one allele.
+pub struct PhasedSnp {
+    pub chrom: String,
+    pub position: usize,
+    pub reference: String,
+    pub alleles: Vec<Option<String>>,
+}
+
+/// Variants of a phased VCF that cannot be placed on the haplotypes.
+#[derive(Debug, Default)]
+pub struct SkippedSnps {
+    /// Heterozygous calls without phase (`0/1`)
+    pub unphased: usize,
+    /// Insertions, deletions and symbolic alleles, which would shift the
+    /// plan's coordinates
+    pub indels: usize,
+}
+
+/// Reads the SNVs (and same-length MNVs) of `sample`, or of the first
+/// sample, from a phased VCF.
+pub fn read_phased_snps(path: &str, sample: Option<&str>) -> Result<(Vec<PhasedSnp>, SkippedSnps), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read VCF {}: {}", path, e))?;
+    let mut column = None;
+    let mut snps = Vec::new();
+    let mut skipped = SkippedSnps::default();
+
+    for (i, line) in text.lines().enumerate() {
+        if let Some(header) = line.strip_prefix("#CHROM") {
+            let samples: Vec<&str> = header.split('\t').skip(9).collect();
+            column = Some(match sample {
+                Some(name) => 9 + samples.iter().position(|s| *s == name)
+                    .ok_or_else(|| format!("Sample '{}' is not in {}", name, path))?,
+                None if samples.is_empty() => return Err(format!("{} has no sample columns", path)),
+                None => 9,
+            });
+            continue;
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let column = column.ok_or_else(|| format!("{} line {}: variant before the #CHROM header", path, i + 1))?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() <= column {
+            return Err(format!("{} line {}: expected {} tab-separated columns", path, i + 1, column + 1));
+        }
+        let position: usize = fields[1].parse().ok().filter(|&p| p > 0)
+            .ok_or_else(|| format!("{} line {}: '{}' is not a valid position", path, i + 1, fields[1]))?;
+        let reference = fields[3].to_ascii_uppercase();
+        let alternates: Vec<&str> = fields[4].split(',').collect();
+        let gt_index = fields[8].split(':').position(|key| key == "GT")
+            .ok_or_else(|| format!("{} line {}: no GT field", path, i + 1))?;
+        let genotype = fields[column].split(':').nth(gt_index).unwrap_or(".");
+
+        let mut alleles = Vec::new();
+        for allele in genotype.split(['|', '/']) {
+            // Missing alleles are taken to be the reference
+            let index: usize = if allele == "." { 0 } else {
+                allele.parse().map_err(|_| format!("{} line {}: '{}' is not a genotype", path, i + 1, genotype))?
+            };
+            alleles.push(match index {
+                0 => None,
+                n => Some(alternates.get(n - 1)
+                    .ok_or_else(|| format!("{} line {}: genotype {} has no ALT allele {}", path, i + 1, genotype, n))?
+                    .to_ascii_uppercase()),
+            });
+        }
+        if alleles.iter().all(Option::is_none) {
+            continue;
+        }
+        if alleles.iter().flatten().any(|alt| alt.len() != reference.len() || alt.starts_with('<') || alt == "*") {
+            skipped.indels += 1;
+            continue;
+        }
+        if genotype.contains('/') && alleles.windows(2).any(|pair| pair[0] != pair[1]) {
+            skipped.unphased += 1;
+            continue;
+        }
+        snps.push(PhasedSnp { chrom: fields[0].to_string(), position: position - 1, reference, alleles });
+    }
+
+    Ok((snps, skipped))
+}
@@ -0,0 +1,79 @@
+/// How much of the input header's description to keep in the output header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepDescription {
+    /// Keep the description and every provenance annotation (the default)
+    Yes,
+    /// Write the sequence ID alone
+    No,
+    /// Drop the free-text description but keep the provenance annotations
+    Replace,
+}
+
+impl KeepDescription {
+    pub fn parse(value: &str) -> Result<KeepDescription, String> {
+        match value {
+            "yes" => Ok(KeepDescription::Yes),
+            "no" => Ok(KeepDescription::No),
+            "replace" => Ok(KeepDescription::Replace),
+            _ => Err(format!("Unknown --keep-description value '{}'. Use 'yes', 'no', or 'replace'", value)),
+        }
+    }
+}
+
+/// Splits a header line into its marker (`>` or `@`), sequence ID, and the
+/// description following the first whitespace.
+pub fn split_header(header: &str) -> (&str, &str, &str) {
+    let marker_len = if header.starts_with('>') || header.starts_with('@') { 1 } else { 0 };
+    let (marker, rest) = header.split_at(marker_len);
+    match rest.find(char::is_whitespace) {
+        Some(i) => (marker, &rest[..i], rest[i..].trim_start()),
+        None => (marker, rest, ""),
+    }
+}
+
+/// Returns the run of trailing `[...]` provenance annotations in `description`.
+fn trailing_annotations(description: &str) -> &str {
+    let mut start = description.len();
+    loop {
+        let head = description[..start].trim_end();
+        if !head.ends_with(']') {
+            break;
+        }
+        match head.rfind('[') {
+            Some(open) => start = open,
+            None => break,
+        }
+    }
+    description[start..].trim()
+}
+
+/// Rebuilds `header` with an optional new ID and the requested amount of
+/// description, leaving the marker character untouched.
+pub fn rewrite_header(header: &str, id: Option<&str>, keep: KeepDescription) -> String {
+    let (marker, old_id, description) = split_header(header);
+    let id = id.unwrap_or(old_id);
+    let description = match keep {
+        KeepDescription::Yes => description,
+        KeepDescription::No => "",
+        KeepDescription::Replace => trailing_annotations(description),
+    };
+
+    if description.is_empty() {
+        format!("{}{}", marker, id)
+    } else {
+        format!("{}{} {}", marker, id, description)
+    }
+}
+
+/// Normalizes a header for byte-stable output: the ID and description
+/// separated by single spaces, with any stray whitespace (tabs, carriage
+/// returns, repeated or trailing spaces) collapsed.
+pub fn canonical_header(header: &str) -> String {
+    let (marker, id, description) = split_header(header);
+    let mut canonical = format!("{}{}", marker, id);
+    for word in description.split_whitespace() {
+        canonical.push(' ');
+        canonical.push_str(word);
+    }
+    canonical
+}
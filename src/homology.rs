@@ -0,0 +1,112 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::staging;
+use crate::vcf::RecordJunctions;
+
+/// Sequence homology flanking a junction: the breakpoint can slide `left`
+/// bases towards the 5' end and `right` bases towards the 3' end without
+/// changing the edited sequence.
+pub struct HomologyTract {
+    pub id: String,
+    pub chrom: String,
+    /// Last input base before the junction, as reported (1-based)
+    pub left_position: usize,
+    /// First input base after the junction, as reported (1-based)
+    pub right_position: usize,
+    pub left: usize,
+    pub right: usize,
+    pub homology: String,
+    /// Confidence intervals declared for the left and right breakends
+    pub tolerance: [Option<(i64, i64)>; 2],
+}
+
+impl HomologyTract {
+    pub fn length(&self) -> usize {
+        self.left + self.right
+    }
+}
+
+/// Finds the homology tract of every deletion- or duplication-type junction,
+/// i.e. a forward-strand join with no inserted bases. IDs match the BND IDs
+/// of the truth VCF (`bnd<n>`), and tolerances its CIPOS, given the same
+/// default `confidence`.
+pub fn homology_tracts(records: &[RecordJunctions], confidence: Option<usize>) -> Vec<HomologyTract> {
+    let mut tracts = Vec::new();
+    let mut counter = 0;
+    for record in records {
+        let bases = record.sequence.as_bytes();
+        let base = |position: usize| bases[position - 1].to_ascii_uppercase();
+        for (junction, allele) in &record.junctions {
+            counter += 1;
+            let (Some(left), Some(right)) = (junction.left, junction.right) else { continue };
+            if left.reverse || right.reverse || !junction.inserted.is_empty() {
+                continue;
+            }
+            let (a, b) = (left.position, right.position);
+            // Sliding right keeps the sequence while the next base on the
+            // left matches the base the right side starts with, and vice versa
+            let mut slide_right = 0;
+            while a + slide_right < bases.len() && b + slide_right <= bases.len()
+                && base(a + slide_right + 1) == base(b + slide_right) {
+                slide_right += 1;
+            }
+            let mut slide_left = 0;
+            while slide_left < a && slide_left + 1 < b && base(a - slide_left) == base(b - slide_left - 1) {
+                slide_left += 1;
+            }
+            tracts.push(HomologyTract {
+                id: format!("bnd{}", counter),
+                chrom: record.chrom.to_string(),
+                left_position: a,
+                right_position: b,
+                left: slide_left,
+                right: slide_right,
+                homology: record.sequence[a - slide_left..a + slide_right].to_string(),
+                tolerance: [allele.interval(junction, left, confidence), allele.interval(junction, right, confidence)],
+            });
+        }
+    }
+    tracts
+}
+
+fn format_tolerance(tolerance: Option<(i64, i64)>) -> String {
+    tolerance.map_or(".".to_string(), |(before, after)| format!("{},{}", before, after))
+}
+
+/// Writes the homology tracts as BED when `path` ends in `.bed` (one
+/// interval per breakend, covering every position it could be reported at),
+/// and as a TSV with one row per junction otherwise. When any breakend has a
+/// confidence interval, BED lines gain a strand (`.`) and a tolerance column
+/// and the TSV a tolerance column per side.
+pub fn write_homology(path: &str, tracts: &[HomologyTract]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    let tolerant = tracts.iter().any(|tract| tract.tolerance.iter().any(Option::is_some));
+    if path.ends_with(".bed") {
+        for tract in tracts {
+            for (suffix, position, tolerance) in [("_1", tract.left_position, tract.tolerance[0]),
+                                                  ("_2", tract.right_position, tract.tolerance[1])] {
+                write!(out, "{}\t{}\t{}\t{}{}\t{}", tract.chrom, position - tract.left - 1,
+                       position + tract.right, tract.id, suffix, tract.length())?;
+                if tolerant {
+                    write!(out, "\t.\t{}", format_tolerance(tolerance))?;
+                }
+                writeln!(out)?;
+            }
+        }
+    } else {
+        write!(out, "id\tchrom\tleft\tright\thomology_length\thomology\tleft_window\tright_window")?;
+        writeln!(out, "{}", if tolerant { "\tleft_tolerance\tright_tolerance" } else { "" })?;
+        for tract in tracts {
+            write!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}-{}\t{}-{}", tract.id, tract.chrom,
+                   tract.left_position, tract.right_position, tract.length(),
+                   if tract.homology.is_empty() { "." } else { &tract.homology },
+                   tract.left_position - tract.left, tract.left_position + tract.right,
+                   tract.right_position - tract.left, tract.right_position + tract.right)?;
+            if tolerant {
+                write!(out, "\t{}\t{}", format_tolerance(tract.tolerance[0]), format_tolerance(tract.tolerance[1]))?;
+            }
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,124 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::gff::Feature;
+use crate::layout::Segment;
+use crate::liftover;
+use crate::staging;
+
+/// Default length of the upstream window treated as a feature's promoter.
+pub const DEFAULT_PROMOTER: usize = 500;
+
+/// How a layout holds the input bases `start..end`: the number of distinct
+/// bases kept, the total number of copies of them, how many pieces they are
+/// in and how many of those are reversed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Footprint {
+    kept: usize,
+    copied: usize,
+    pieces: usize,
+    reversed: usize,
+}
+
+impl Footprint {
+    fn of(segments: &[Segment], start: usize, end: usize) -> Footprint {
+        let mut overlaps: Vec<(usize, usize)> = segments.iter().filter_map(|segment| match *segment {
+            Segment::Source { start: source_start, end: source_end, .. } => {
+                Some((start.max(source_start), end.min(source_end))).filter(|(from, to)| from < to)
+            },
+            Segment::Novel(_) => None,
+        }).collect();
+        let copied = overlaps.iter().map(|(from, to)| to - from).sum();
+        overlaps.sort_unstable();
+        let mut kept = 0;
+        let mut covered_to = start;
+        for (from, to) in overlaps {
+            if to > covered_to {
+                kept += to - from.max(covered_to);
+                covered_to = to;
+            }
+        }
+        let mapped = liftover::map_interval(segments, start, end);
+        Footprint { kept, copied, pieces: mapped.len(), reversed: mapped.iter().filter(|piece| piece.reverse).count() }
+    }
+}
+
+/// The impacts of one edit on a feature.
+pub struct FeatureImpact<'a> {
+    pub feature: &'a Feature,
+    pub impacts: Vec<&'static str>,
+}
+
+/// What the edit taking the layout `before` to `after` (both relative to the
+/// input of `length` bases) did to each feature: `deleted`, `truncated`,
+/// `duplicated`, `inverted`, `interrupted` (split by a junction but otherwise
+/// intact, e.g. by an insertion) and `promoter_disrupted` (any change to the `promoter` bases
+/// upstream of a feature that survives). Features the edit left alone are
+/// not listed.
+pub fn feature_impacts<'a>(features: &[&'a Feature], before: &[Segment], after: &[Segment], promoter: usize,
+                           length: usize) -> Vec<FeatureImpact<'a>> {
+    let mut found = Vec::new();
+    for &feature in features {
+        let (start, end) = (feature.start, feature.end.min(length));
+        if start >= end {
+            continue;
+        }
+        let (was, now) = (Footprint::of(before, start, end), Footprint::of(after, start, end));
+        let mut impacts = Vec::new();
+        if now.kept == 0 && was.kept > 0 {
+            impacts.push("deleted");
+        } else {
+            if now.kept < was.kept {
+                impacts.push("truncated");
+            }
+            if now.copied > was.copied && now.copied > now.kept {
+                impacts.push("duplicated");
+            }
+            if now.reversed != was.reversed {
+                impacts.push("inverted");
+            }
+            // Split without losing, gaining or flipping bases, e.g. by an insertion
+            if now.pieces > was.pieces && impacts.is_empty() {
+                impacts.push("interrupted");
+            }
+            let upstream = if feature.reverse {
+                (end, (end + promoter).min(length))
+            } else {
+                (start.saturating_sub(promoter), start)
+            };
+            if upstream.0 < upstream.1
+                && Footprint::of(before, upstream.0, upstream.1) != Footprint::of(after, upstream.0, upstream.1) {
+                impacts.push("promoter_disrupted");
+            }
+        }
+        if !impacts.is_empty() {
+            found.push(FeatureImpact { feature, impacts });
+        }
+    }
+    found
+}
+
+/// One row group of the impact table: the step that made the edit (1 for a
+/// single operation), the operation's name and its impacts.
+pub struct StepImpacts<'a> {
+    pub step: usize,
+    pub operation: String,
+    pub impacts: Vec<FeatureImpact<'a>>,
+}
+
+/// Writes the impact table as TSV to `path`, or to stderr without one.
+pub fn write_impacts(path: Option<&str>, steps: &[StepImpacts]) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        Some(path) => Box::new(BufWriter::new(staging::create(path)?)),
+        None => Box::new(io::stderr()),
+    };
+    writeln!(out, "step\toperation\trecord\tfeature\ttype\tstart\tend\tstrand\timpact")?;
+    for step in steps {
+        for impact in &step.impacts {
+            let feature = impact.feature;
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", step.step, step.operation, feature.seqid,
+                     feature.id, feature.kind, feature.start + 1, feature.end,
+                     if feature.reverse { '-' } else { '+' }, impact.impacts.join(","))?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Number of worker threads for `--jobs <n>`, where 0 means one per CPU.
+pub fn threads(jobs: usize) -> usize {
+    match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Applies `f` to every item (with its index) on up to `jobs` threads and
+/// returns the results in item order, so the output does not depend on how
+/// the work was scheduled. Threads take the next unclaimed item as they
+/// finish, which keeps them busy when items take very different times.
+pub fn map_ordered<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(usize, &T) -> R + Sync) -> Vec<R> {
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().enumerate().map(|(i, item)| f(i, item)).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len())).map(|_| scope.spawn(|| {
+            let mut results = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    return results;
+                };
+                results.push((i, f(i, item)));
+            }
+        })).collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("generation worker panicked")).collect()
+    });
+    done.sort_unstable_by_key(|&(i, _)| i);
+    done.into_iter().map(|(_, result)| result).collect()
+}
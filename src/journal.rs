@@ -0,0 +1,102 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::schema;
+use crate::staging;
+use crate::{check_operation, Alphabet, Operation, Target};
+
+/// One line of an operation journal. Journals are JSON Lines files that are
+/// only ever appended to, so a chain of piped invocations sharing the same
+/// `--journal` path records the full edit history of the final genome.
+///
+/// Lengths are totals over all records of the input and output. Each entry
+/// carries the schema version it was written with, and older entries are
+/// migrated as they are read.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub schema_version: u32,
+    pub operation: Operation,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub all_records: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    pub input: String,
+    pub input_length: usize,
+    pub output_length: usize,
+    pub timestamp: u64,
+}
+
+impl JournalEntry {
+    pub fn new(operation: Operation, input: &str, input_length: usize, output_length: usize) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        JournalEntry {
+            schema_version: schema::SCHEMA_VERSION,
+            operation,
+            all_records: false,
+            record: None,
+            input: input.to_string(),
+            input_length,
+            output_length,
+            timestamp,
+        }
+    }
+
+    /// Records the entry's operation was applied to.
+    pub fn target(&self) -> Target {
+        if self.all_records {
+            Target::All
+        } else if let Some(id) = &self.record {
+            Target::Record(id.clone())
+        } else {
+            Target::Only
+        }
+    }
+}
+
+/// Appends `entry` to the journal at `path`, once the run's outputs are
+/// committed when they are staged, so a failed run leaves the journal as it
+/// was.
+pub fn append_entry(path: &str, entry: &JournalEntry) -> std::io::Result<()> {
+    let line = serde_json::to_string(entry)?;
+    staging::append(path, format!("{}\n", line))
+}
+
+pub fn read_journal(path: &str) -> std::io::Result<Vec<JournalEntry>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+    let mut entries = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = parse_entry(&line, &format!("Line {} of journal {}", i + 1, path))
+            .map_err(|msg| std::io::Error::new(std::io::ErrorKind::InvalidData, msg))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Parses one journal line, migrating it from the schema version it was
+/// written with.
+pub fn parse_entry(line: &str, source: &str) -> Result<JournalEntry, String> {
+    let invalid = |e: serde_json::Error| format!("{}: invalid journal entry: {}", source, e);
+    let mut entry: serde_json::Value = serde_json::from_str(line).map_err(invalid)?;
+    let version = schema::check_version(entry.get("schema_version"), source)?;
+    if let Some(operation) = entry.get_mut("operation") {
+        schema::migrate_json(operation, version);
+    }
+    entry["schema_version"] = schema::SCHEMA_VERSION.into();
+    let mut entry: JournalEntry = serde_json::from_value(entry).map_err(invalid)?;
+    entry.operation = check_operation(entry.operation, Alphabet::Any).map_err(|e| format!("{}: {}", source, e))?;
+    Ok(entry)
+}
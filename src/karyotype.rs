@@ -0,0 +1,122 @@
+use std::io::{self, BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::fasta::Record;
+use crate::staging;
+
+/// What happened to one record between the input and the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Unchanged,
+    Derived,
+    Gained,
+    Lost,
+}
+
+impl RecordStatus {
+    fn name(self) -> &'static str {
+        match self {
+            RecordStatus::Unchanged => "unchanged",
+            RecordStatus::Derived => "derived",
+            RecordStatus::Gained => "gained",
+            RecordStatus::Lost => "lost",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KaryotypeEntry {
+    pub id: String,
+    pub status: RecordStatus,
+    pub input_length: usize,
+    pub output_length: usize,
+    pub change: i64,
+}
+
+/// Karyotype-style comparison of the records before and after a run, matched
+/// by sequence ID. Records are listed in output order, followed by lost ones.
+#[derive(Debug, Serialize)]
+pub struct Karyotype {
+    pub records: Vec<KaryotypeEntry>,
+    pub input_length: usize,
+    pub output_length: usize,
+    pub derived: usize,
+    pub unchanged: usize,
+    pub gained: usize,
+    pub lost: usize,
+}
+
+impl Karyotype {
+    pub fn new(input: &[Record], output: &[Record]) -> Self {
+        let mut records = Vec::new();
+        for record in output {
+            let entry = match input.iter().find(|r| r.id() == record.id()) {
+                Some(original) => KaryotypeEntry {
+                    id: record.id().to_string(),
+                    status: if original.sequence == record.sequence { RecordStatus::Unchanged } else { RecordStatus::Derived },
+                    input_length: original.sequence.len(),
+                    output_length: record.sequence.len(),
+                    change: record.sequence.len() as i64 - original.sequence.len() as i64,
+                },
+                None => KaryotypeEntry {
+                    id: record.id().to_string(),
+                    status: RecordStatus::Gained,
+                    input_length: 0,
+                    output_length: record.sequence.len(),
+                    change: record.sequence.len() as i64,
+                },
+            };
+            records.push(entry);
+        }
+        for original in input {
+            if !output.iter().any(|r| r.id() == original.id()) {
+                records.push(KaryotypeEntry {
+                    id: original.id().to_string(),
+                    status: RecordStatus::Lost,
+                    input_length: original.sequence.len(),
+                    output_length: 0,
+                    change: -(original.sequence.len() as i64),
+                });
+            }
+        }
+
+        let count = |status| records.iter().filter(|e| e.status == status).count();
+        Karyotype {
+            input_length: input.iter().map(|r| r.sequence.len()).sum(),
+            output_length: output.iter().map(|r| r.sequence.len()).sum(),
+            derived: count(RecordStatus::Derived),
+            unchanged: count(RecordStatus::Unchanged),
+            gained: count(RecordStatus::Gained),
+            lost: count(RecordStatus::Lost),
+            records,
+        }
+    }
+
+    fn write_table<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let width = self.records.iter().map(|e| e.id.len()).max().unwrap_or(0).max("record".len());
+        writeln!(out, "{:<width$}  {:<9}  {:>12}  {:>12}  {:>10}", "record", "status", "input_bp", "output_bp", "change")?;
+        for entry in &self.records {
+            writeln!(out, "{:<width$}  {:<9}  {:>12}  {:>12}  {:>+10}", entry.id, entry.status.name(),
+                     entry.input_length, entry.output_length, entry.change)?;
+        }
+        let change = self.output_length as i64 - self.input_length as i64;
+        writeln!(out, "{:<width$}  {:<9}  {:>12}  {:>12}  {:>+10}", "total", "", self.input_length, self.output_length, change)?;
+        writeln!(out)?;
+        writeln!(out, "{} derived, {} unchanged, {} gained, {} lost",
+                 self.derived, self.unchanged, self.gained, self.lost)
+    }
+}
+
+/// Writes the karyotype as JSON when `path` ends in `.json`, otherwise as an
+/// aligned text table.
+pub fn write_karyotype(path: &str, karyotype: &Karyotype) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    if path.ends_with(".json") {
+        writeln!(out, "{}", serde_json::to_string_pretty(karyotype)?)?;
+    } else {
+        karyotype.write_table(&mut out)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,606 @@
+use crate::alphabet::Alphabet;
+use crate::{liftover, simulate};
+use crate::{CopybackCoords, Operation, TelomereEnd};
+
+/// One stretch of an edited sequence: either copied from the input sequence
+/// (0-based, half-open coordinates, optionally reversed and/or complemented)
+/// or novel material that did not exist in the input.
+///
+/// Every operation is expressed as a list of segments, so bases, qualities and
+/// anything else aligned to the sequence are edited in exactly the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Source { start: usize, end: usize, reverse: bool, complement: bool },
+    Novel(String),
+}
+
+impl Segment {
+    fn forward(start: usize, end: usize) -> Segment {
+        Segment::Source { start, end, reverse: false, complement: false }
+    }
+
+    fn reversed(start: usize, end: usize, complement: bool) -> Segment {
+        Segment::Source { start, end, reverse: true, complement }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Segment::Source { start, end, .. } => end - start,
+            Segment::Novel(sequence) => sequence.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Layout of an unedited sequence of `length` bases.
+pub fn identity(length: usize) -> Vec<Segment> {
+    vec![Segment::forward(0, length)]
+}
+
+/// Computes the segments making up the result of applying `operation` to
+/// `sequence`, or an error if its coordinates do not fit.
+pub fn operation_layout(operation: &Operation, sequence: &str) -> Result<Vec<Segment>, String> {
+    layout(operation, sequence.len(), Some(sequence))
+}
+
+/// Computes the layout of `operation` on a sequence of `length` bases
+/// without reading them, for streaming. Operations whose layout depends on
+/// the bases (drawn MEI sites, integrations with microhomology and repeat
+/// families) are an error.
+pub fn sized_layout(operation: &Operation, length: usize) -> Result<Vec<Segment>, String> {
+    layout(operation, length, None)
+}
+
+fn layout(operation: &Operation, length: usize, sequence: Option<&str>) -> Result<Vec<Segment>, String> {
+    let bases = || sequence.ok_or_else(|| format!("{} needs the sequence's bases to lay out", crate::operation_name(operation)));
+    let operation = &operation.resolve_end(length);
+    if let Operation::Delete { start, end }
+    | Operation::Replace { start, end, .. }
+    | Operation::Invert { start, end, .. }
+    | Operation::Duplicate { start, end, .. }
+    | Operation::TandemDuplicate { start, end }
+    | Operation::Keep { start, end }
+    | Operation::Case { start, end, .. } = operation {
+        // Only reachable once an open `end` has been fixed to a short sequence
+        if start > end {
+            return Err(format!("Start position {} is beyond sequence length {}", start, length));
+        }
+    }
+    let check_end = |end: usize| {
+        if end > length {
+            Err(format!("End position {} is beyond sequence length {}", end, length))
+        } else {
+            Ok(())
+        }
+    };
+    let check_insert = |position: usize| {
+        if position - 1 > length {
+            Err(format!("Insert position {} is beyond sequence length {}", position, length))
+        } else {
+            Ok(())
+        }
+    };
+
+    // Coordinates are 1-based and inclusive; start - 1 and end give the
+    // equivalent 0-based, half-open range.
+    let mut segments = match operation {
+        Operation::Delete { start, end } => {
+            check_end(*end)?;
+            vec![Segment::forward(0, start - 1), Segment::forward(*end, length)]
+        },
+        Operation::Insert { position, sequence } => {
+            check_insert(*position)?;
+            vec![
+                Segment::forward(0, position - 1),
+                Segment::Novel(sequence.clone()),
+                Segment::forward(position - 1, length),
+            ]
+        },
+        Operation::Replace { start, end, sequence } => {
+            check_end(*end)?;
+            vec![
+                Segment::forward(0, start - 1),
+                Segment::Novel(sequence.clone()),
+                Segment::forward(*end, length),
+            ]
+        },
+        Operation::Invert { start, end, complement } => {
+            check_end(*end)?;
+            vec![
+                Segment::forward(0, start - 1),
+                Segment::reversed(start - 1, *end, *complement),
+                Segment::forward(*end, length),
+            ]
+        },
+        Operation::Duplicate { start, end, position } => {
+            check_end(*end)?;
+            check_insert(*position)?;
+            vec![
+                Segment::forward(0, position - 1),
+                Segment::forward(start - 1, *end),
+                Segment::forward(position - 1, length),
+            ]
+        },
+        // Laid out on the receiving record, where the copy is novel
+        Operation::DuplicateInto { from, to, position, sequence, .. } => {
+            if sequence.is_empty() {
+                return Err(format!("The copy from {} to {} is only laid out once its bases are read", from, to));
+            }
+            check_insert(*position)?;
+            vec![
+                Segment::forward(0, position - 1),
+                Segment::Novel(sequence.clone()),
+                Segment::forward(position - 1, length),
+            ]
+        },
+        Operation::Keep { start, end } => {
+            check_end(*end)?;
+            vec![Segment::forward(start - 1, *end)]
+        },
+        Operation::TandemDuplicate { start, end } => {
+            check_end(*end)?;
+            vec![
+                Segment::forward(0, *end),
+                Segment::forward(start - 1, *end),
+                Segment::forward(*end, length),
+            ]
+        },
+        Operation::Copyback { gend, breakpoint, backstart, coords_on, .. } => {
+            if *breakpoint > length {
+                return Err(format!("Breakpoint {} is beyond sequence length {}", breakpoint, length));
+            }
+            if *backstart > length {
+                return Err(format!("Backstart {} is beyond sequence length {}", backstart, length));
+            }
+            // Plus-strand positions count from the other end
+            let (breakpoint, backstart) = match coords_on {
+                CopybackCoords::Reference => (length - breakpoint + 1, length - backstart + 1),
+                CopybackCoords::Revcomp => (*breakpoint, *backstart),
+            };
+            if *gend == 5 {
+                // Keep up to the breakpoint, then the reverse complement of
+                // everything from the backstart back to the 5' end
+                vec![
+                    Segment::forward(0, breakpoint),
+                    Segment::reversed(0, backstart, true),
+                ]
+            } else {
+                // Same as 5' on the reverse complemented genome: its first
+                // `breakpoint` bases are the reverse complement of the last
+                // `breakpoint` bases of the input, and the reverse complement
+                // of its first `backstart` bases is the input's last
+                // `backstart` bases in their original orientation
+                vec![
+                    Segment::reversed(length - breakpoint, length, true),
+                    Segment::forward(length - backstart, length),
+                ]
+            }
+        },
+        Operation::Chromothripsis { start, end, fragments, keep, seed } => {
+            check_end(*end)?;
+            let shattering = simulate::shatter(start - 1, *end, *fragments, *keep, *seed);
+            let mut segments = vec![Segment::forward(0, start - 1)];
+            segments.extend(shattering.kept.iter().map(|fragment| {
+                if fragment.reverse {
+                    Segment::reversed(fragment.start, fragment.end, true)
+                } else {
+                    Segment::forward(fragment.start, fragment.end)
+                }
+            }));
+            segments.push(Segment::forward(*end, length));
+            segments
+        },
+        Operation::Bfb { cycles, seed } => bfb_layout(length, &simulate::bfb_cycles(length, *cycles, *seed)?).0,
+        Operation::Mei { tsd, .. } => {
+            let position = match operation {
+                Operation::Mei { position: Some(position), .. } => *position,
+                _ => mei_position(operation, bases()?)?,
+            };
+            check_insert(position)?;
+            if *tsd > position - 1 {
+                return Err(format!("Target site duplication of {}bp does not fit before position {}", tsd, position));
+            }
+            // The element goes after the target site, which is then repeated
+            vec![
+                Segment::forward(0, position - 1),
+                Segment::Novel(mei_insert(operation)),
+                Segment::forward(position - 1 - tsd, length),
+            ]
+        },
+        Operation::Integrate { virus, microhomology, .. } => {
+            let mut segments = Vec::new();
+            let mut previous = 0;
+            for site in integration_sites(operation, length)? {
+                check_insert(site.position)?;
+                let cut = site.position - 1;
+                let mut inserted = integrant(virus, &site);
+                if *microhomology > 0 {
+                    // Overwrite the viral ends with the host bases they join,
+                    // so each breakpoint is ambiguous over that many bases
+                    if *microhomology > cut || cut + microhomology > length || inserted.len() < 2 * microhomology {
+                        return Err(format!("Microhomology of {}bp does not fit at integration site {} (position {})",
+                                           microhomology, site.site, site.position));
+                    }
+                    let sequence = bases()?;
+                    let n = inserted.len();
+                    inserted.replace_range(..*microhomology, &sequence[cut - microhomology..cut]);
+                    inserted.replace_range(n - microhomology.., &sequence[cut..cut + microhomology]);
+                }
+                segments.push(Segment::forward(previous, cut));
+                segments.push(Segment::Novel(inserted));
+                previous = cut;
+            }
+            segments.push(Segment::forward(previous, length));
+            segments
+        },
+        Operation::RepeatFamily { start, end, copies, divergence, seed } => {
+            check_end(*end)?;
+            let mut segments = Vec::new();
+            let mut previous = 0;
+            for copy in simulate::repeat_family(bases()?, start - 1, *end, *copies, *divergence, *seed) {
+                segments.push(Segment::forward(previous, copy.cut));
+                // Copies that escaped every substitution are still the input's bases
+                segments.push(match (copy.substitutions, copy.reverse) {
+                    (0, false) => Segment::forward(start - 1, *end),
+                    (0, true) => Segment::reversed(start - 1, *end, true),
+                    _ => Segment::Novel(copy.sequence),
+                });
+                previous = copy.cut;
+            }
+            segments.push(Segment::forward(previous, length));
+            segments
+        },
+        Operation::Polya { length: tail } => {
+            // RNA output keeps A, which is the same base in both alphabets
+            vec![Segment::forward(0, length), Segment::Novel("A".repeat(*tail))]
+        },
+        Operation::Telomere { repeat, length: array, end } => {
+            // The 3' array reads as the repeat; the 5' array is its reverse
+            // complement, i.e. the repeat on the other strand's 3' end
+            let tail: String = repeat.chars().cycle().take(*array).collect();
+            let head: String = crate::reverse_complement(&tail);
+            let mut segments = Vec::new();
+            if *end != TelomereEnd::Three {
+                segments.push(Segment::Novel(head));
+            }
+            segments.push(Segment::forward(0, length));
+            if *end != TelomereEnd::Five {
+                segments.push(Segment::Novel(tail));
+            }
+            segments
+        },
+        Operation::AddLeader { sequence: leader } => {
+            vec![Segment::Novel(leader.clone()), Segment::forward(0, length)]
+        },
+        // Only the letters change, so every base stays where it was
+        Operation::Case { end, .. } => {
+            check_end(*end)?;
+            vec![Segment::forward(0, length)]
+        },
+        Operation::Custom { name, params } => {
+            let segments = crate::registry::registered(name)?.layout(params, bases()?)?;
+            if let Some(Segment::Source { start, end, .. }) = segments.iter()
+                .find(|segment| matches!(segment, Segment::Source { start, end, .. } if start > end || *end > length)) {
+                return Err(format!("{} laid out bases {}-{} of a {}-base sequence", name, start + 1, end, length));
+            }
+            segments
+        },
+    };
+
+    segments.retain(|segment| !segment.is_empty());
+    Ok(segments)
+}
+
+/// Insertion position of a mobile element: the one given, or a site drawn
+/// from the operation's seed.
+pub fn mei_position(operation: &Operation, sequence: &str) -> Result<usize, String> {
+    match operation {
+        Operation::Mei { position: Some(position), .. } => Ok(*position),
+        Operation::Mei { tsd, seed, .. } => simulate::mei_site(sequence, *tsd, *seed),
+        _ => unreachable!("mei_position called for a non-MEI operation"),
+    }
+}
+
+/// Inserted bases of a mobile element, after 5' truncation, with its poly-A
+/// tail, in the orientation it is inserted.
+fn mei_insert(operation: &Operation) -> String {
+    let Operation::Mei { element, truncate, polya, reverse, .. } = operation else {
+        unreachable!("mei_insert called for a non-MEI operation");
+    };
+    let mut inserted = element[*truncate..].to_string();
+    inserted.extend(std::iter::repeat_n('A', *polya));
+    if *reverse {
+        crate::reverse_complement(&inserted)
+    } else {
+        inserted
+    }
+}
+
+/// The integrations an `integrate` operation makes into a host of `length`
+/// bases, drawn from its seed where they are not given.
+pub fn integration_sites(operation: &Operation, length: usize) -> Result<Vec<simulate::Integration>, String> {
+    let Operation::Integrate { virus, positions, sites, viral_range, partial, rearrange, seed, .. } = operation else {
+        unreachable!("integration_sites called for a non-integrate operation");
+    };
+    let spec = simulate::IntegrationSpec {
+        positions,
+        sites: *sites,
+        viral_range: *viral_range,
+        partial: *partial,
+        rearrange: *rearrange,
+        seed: *seed,
+    };
+    simulate::integrations(length, virus.len(), &spec)
+}
+
+/// Viral bases inserted at one integration site, in host orientation.
+fn integrant(virus: &str, site: &simulate::Integration) -> String {
+    let piece = &virus[site.viral_start - 1..site.viral_end];
+    let mut inserted = match site.rearranged_at {
+        Some(at) => {
+            let (first, second) = piece.split_at(at - site.viral_start);
+            let moved = if site.inverted_part {
+                crate::reverse_complement(first)
+            } else {
+                first.to_string()
+            };
+            format!("{}{}", second, moved)
+        },
+        None => piece.to_string(),
+    };
+    if site.reverse {
+        inserted = crate::reverse_complement(&inserted);
+    }
+    inserted
+}
+
+/// The part of a layout that produces bases `start..end` of its output.
+fn slice_layout(segments: &[Segment], start: usize, end: usize) -> Vec<Segment> {
+    let mut sliced = Vec::new();
+    let mut offset = 0;
+    for segment in segments {
+        let length = segment.len();
+        let (from, to) = (start.max(offset), end.min(offset + length));
+        if from < to {
+            let (a, b) = (from - offset, to - offset);
+            sliced.push(match segment {
+                Segment::Source { start: s, end: e, reverse, complement } => {
+                    // A reversed segment reads its source from the end backwards
+                    if *reverse {
+                        Segment::Source { start: e - b, end: e - a, reverse: true, complement: *complement }
+                    } else {
+                        Segment::Source { start: s + a, end: s + b, reverse: false, complement: *complement }
+                    }
+                },
+                Segment::Novel(novel) => Segment::Novel(novel[a..b].to_string()),
+            });
+        }
+        offset += length;
+    }
+    sliced
+}
+
+/// Expresses `outer`, a layout over the output of `inner`, directly as a
+/// layout over `inner`'s input. This is how multi-step edits are tracked
+/// back to the original coordinates.
+pub fn compose(inner: &[Segment], outer: &[Segment]) -> Vec<Segment> {
+    let mut composed = Vec::new();
+    for segment in outer {
+        match segment {
+            Segment::Source { start, end, reverse, complement } => {
+                let mut pieces = slice_layout(inner, *start, *end);
+                if *reverse {
+                    pieces.reverse();
+                }
+                for piece in pieces.iter_mut() {
+                    match piece {
+                        Segment::Source { reverse: r, complement: c, .. } => {
+                            *r ^= *reverse;
+                            *c ^= *complement;
+                        },
+                        Segment::Novel(novel) => {
+                            let mut bases: Vec<char> = novel.chars().collect();
+                            if *reverse {
+                                bases.reverse();
+                            }
+                            if *complement {
+                                bases = bases.into_iter().map(crate::complement_base).collect();
+                            }
+                            *novel = bases.into_iter().collect();
+                        },
+                    }
+                }
+                composed.extend(pieces);
+            },
+            Segment::Novel(novel) => composed.push(Segment::Novel(novel.clone())),
+        }
+    }
+    composed
+}
+
+/// Builds the edited sequence from `sequence` and its segment layout.
+pub fn render_sequence(sequence: &str, segments: &[Segment], alphabet: Alphabet) -> String {
+    let mut rendered = String::with_capacity(segments.iter().map(Segment::len).sum());
+    for segment in segments {
+        match segment {
+            Segment::Source { start, end, reverse, complement } => {
+                let slice = &sequence[*start..*end];
+                match (reverse, complement) {
+                    (false, false) => rendered.push_str(slice),
+                    (false, true) => rendered.extend(slice.chars().map(|b| alphabet.complement(b))),
+                    (true, false) => rendered.extend(slice.chars().rev()),
+                    (true, true) => rendered.extend(slice.chars().rev().map(|b| alphabet.complement(b))),
+                }
+            },
+            Segment::Novel(novel) => rendered.push_str(novel),
+        }
+    }
+    rendered
+}
+
+/// Builds the edited quality string. Qualities follow their bases through
+/// reversals, and novel bases are given `insert_qual`.
+pub fn render_quality(quality: &str, segments: &[Segment], insert_qual: char) -> String {
+    let mut rendered = String::with_capacity(segments.iter().map(Segment::len).sum());
+    for segment in segments {
+        match segment {
+            Segment::Source { start, end, reverse, .. } => {
+                let slice = &quality[*start..*end];
+                if *reverse {
+                    rendered.extend(slice.chars().rev());
+                } else {
+                    rendered.push_str(slice);
+                }
+            },
+            Segment::Novel(novel) => rendered.extend(std::iter::repeat_n(insert_qual, novel.len())),
+        }
+    }
+    rendered
+}
+
+/// A base of the input sequence at the edge of a segment, 1-based, and
+/// whether the segment reads through it on the reverse strand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakend {
+    pub position: usize,
+    pub reverse: bool,
+}
+
+/// A novel adjacency in the edited sequence: the input base read last
+/// before it, the input base read first after it, and any novel bases in
+/// between. `left` is `None` when novel bases start the molecule and
+/// `right` is `None` when they end it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Junction {
+    pub left: Option<Breakend>,
+    pub right: Option<Breakend>,
+    pub inserted: String,
+}
+
+/// The first stretch of the input, as 1-based positions, that `segments`
+/// read reversed but not complemented or complemented but not reversed.
+/// A breakend records only which strand is read, so no junction output can
+/// describe such a stretch.
+pub fn strand_mismatch(segments: &[Segment]) -> Option<(usize, usize)> {
+    segments.iter().find_map(|segment| match *segment {
+        Segment::Source { start, end, reverse, complement } if reverse != complement => Some((start + 1, end)),
+        _ => None,
+    })
+}
+
+/// Finds every junction in a layout, i.e. each point where the edited
+/// sequence stops reading the input contiguously.
+pub fn junctions(segments: &[Segment]) -> Vec<Junction> {
+    located_junctions(segments).into_iter().map(|(_, junction)| junction).collect()
+}
+
+/// The layout of breakage-fusion-bridge `cycles` on an input of `length`
+/// bases, and the breakends of each cycle on the input: the last base kept
+/// before the fold-back, and the base the molecule ends at after the bridge
+/// breaks, each read on the strand the molecule leaves it on.
+pub fn bfb_layout(length: usize, cycles: &[simulate::BfbCycle]) -> (Vec<Segment>, Vec<simulate::CycleEnds>) {
+    let mut segments = vec![Segment::forward(0, length)];
+    let mut ends = Vec::new();
+    for cycle in cycles {
+        let at = |segments: &[Segment], position: usize| match liftover::map_back(segments, position - 1, position).first() {
+            Some(&liftover::Origin::Original(mapped)) => Breakend { position: mapped.start + 1, reverse: mapped.reverse },
+            _ => unreachable!("breakage-fusion-bridge layouts have no novel bases"),
+        };
+        let fold = at(&segments, cycle.break_position);
+        let end = at(&segments, cycle.foldback_start);
+        ends.push((fold, Breakend { position: end.position, reverse: !end.reverse }));
+        let cycle_layout = [
+            Segment::forward(0, cycle.break_position),
+            Segment::reversed(cycle.foldback_start - 1, cycle.break_position, true),
+        ];
+        segments = compose(&segments, &cycle_layout);
+    }
+    (segments, ends)
+}
+
+/// The junctions of breakage-fusion-bridge `cycles` that a later cycle broke
+/// off again, so that they are not among the `kept` junctions of the final
+/// molecule: the fold-backs and molecule ends of earlier cycles.
+pub fn bfb_lost_junctions(length: usize, cycles: &[simulate::BfbCycle], kept: &[Junction]) -> Vec<Junction> {
+    let (_, ends) = bfb_layout(length, cycles);
+    ends.into_iter()
+        .flat_map(|(fold, end)| [
+            Junction { left: Some(fold), right: Some(Breakend { position: fold.position, reverse: !fold.reverse }),
+                       inserted: String::new() },
+            Junction { left: Some(end), right: None, inserted: String::new() },
+        ])
+        .filter(|junction| !kept.contains(junction))
+        .collect()
+}
+
+/// Every junction of a layout on an input of `length` bases, followed by its
+/// free ends: where the edited molecule starts or stops at an input base
+/// other than an end of the input, as a junction with no base on the other
+/// side and nothing inserted.
+pub fn junctions_with_ends(segments: &[Segment], length: usize) -> Vec<Junction> {
+    let mut found = junctions(segments);
+    let sources: Vec<(usize, usize, bool)> = segments.iter()
+        .filter_map(|segment| match *segment {
+            Segment::Source { start, end, reverse, .. } if end > start => Some((start, end, reverse)),
+            _ => None,
+        })
+        .collect();
+    if let (Some(Segment::Source { .. }), Some(&(start, end, reverse))) = (segments.first(), sources.first()) {
+        let first = if reverse { Breakend { position: end, reverse } } else { Breakend { position: start + 1, reverse } };
+        if first.position != if reverse { length } else { 1 } {
+            found.push(Junction { left: None, right: Some(first), inserted: String::new() });
+        }
+    }
+    if let (Some(Segment::Source { .. }), Some(&(start, end, reverse))) = (segments.last(), sources.last()) {
+        let last = if reverse { Breakend { position: start + 1, reverse } } else { Breakend { position: end, reverse } };
+        if last.position != if reverse { 1 } else { length } {
+            found.push(Junction { left: Some(last), right: None, inserted: String::new() });
+        }
+    }
+    found
+}
+
+/// Every junction of a layout with its 0-based offset in the edited
+/// sequence: where its inserted bases start, or the base after its left
+/// breakend when it has none.
+pub fn located_junctions(segments: &[Segment]) -> Vec<(usize, Junction)> {
+    let mut found = Vec::new();
+    let mut left: Option<Breakend> = None;
+    let mut inserted = String::new();
+    let mut offset = 0;
+
+    for segment in segments {
+        offset += segment.len();
+        match segment {
+            Segment::Novel(novel) => inserted.push_str(novel),
+            Segment::Source { start, end, reverse, .. } => {
+                let (first, last) = if *reverse {
+                    (Breakend { position: *end, reverse: true }, Breakend { position: start + 1, reverse: true })
+                } else {
+                    (Breakend { position: start + 1, reverse: false }, Breakend { position: *end, reverse: false })
+                };
+                let contiguous = inserted.is_empty() && match left {
+                    Some(l) if !l.reverse && !*reverse => l.position == *start,
+                    Some(l) if l.reverse && *reverse => l.position == end + 1,
+                    _ => false,
+                };
+                if !contiguous && (left.is_some() || !inserted.is_empty()) {
+                    found.push((offset - segment.len() - inserted.len(), Junction {
+                        left,
+                        right: Some(first),
+                        inserted: std::mem::take(&mut inserted),
+                    }));
+                }
+                left = Some(last);
+            },
+        }
+    }
+
+    if !inserted.is_empty() {
+        found.push((offset - inserted.len(), Junction { left, right: None, inserted }));
+    }
+    found
+}
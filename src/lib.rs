@@ -0,0 +1,1536 @@
+//! Structural-variant edits of FASTA and FASTQ records, as used by the
+//! `sv_maker` command. An [`Operation`] is parsed from command-line words
+//! with [`parse_operation`] or from journal JSON with [`operation_from_json`],
+//! and [`apply_operation`] returns the edited record:
+//!
+//! ```no_run
+//! use sv_maker::{apply_operation, parse_operation, read_fasta, Alphabet, EditSettings};
+//!
+//! let records = read_fasta("genome.fa").unwrap();
+//! let words: Vec<String> = ["delete", "100", "200"].iter().map(|w| w.to_string()).collect();
+//! let operation = parse_operation(&words, Alphabet::Dna, 1).unwrap();
+//! let settings = EditSettings { alphabet: Alphabet::Dna, insert_qual: 'I', disallow_nested: false, complete_termini: true };
+//! let edited = apply_operation(&records[0], &operation, &settings).unwrap();
+//! ```
+//!
+//! Nothing here prints or exits: malformed input is an `InvalidData` error,
+//! and notes on how words were rewritten come back from
+//! [`parse_operation_noted`]. Output files are written to the paths given,
+//! unless the program turns on [`staging`] to publish them together.
+
+use serde::{Deserialize, Serialize};
+
+pub use alphabet::Alphabet;
+pub use fasta::{read_fasta, Record};
+
+pub mod alphabet;
+pub mod bed;
+pub mod classify;
+pub mod codon;
+pub mod compare;
+pub mod compress;
+pub mod control;
+pub mod dedup;
+pub mod engineered;
+pub mod expr;
+pub mod coverage;
+pub mod diagram;
+pub mod digest;
+pub mod gaps;
+pub mod genbank;
+pub mod gfa;
+pub mod haplotype;
+pub mod gff;
+pub mod fai;
+pub mod fasta;
+pub mod filter;
+pub mod header;
+pub mod homology;
+pub mod impact;
+pub mod jobs;
+pub mod journal;
+pub mod karyotype;
+pub mod layout;
+pub mod liftover;
+pub mod methylation;
+pub mod orf;
+pub mod plan;
+pub mod recipe;
+pub mod registry;
+pub mod rng;
+pub mod schema;
+pub mod sgrna;
+pub mod signal;
+pub mod simconfig;
+pub mod simulate;
+pub mod spec;
+pub mod staging;
+pub mod stats;
+pub mod stem;
+pub mod stream;
+pub mod vcf;
+pub mod summary;
+pub mod telemetry;
+pub mod transcript;
+pub mod truth;
+pub mod units;
+pub mod warnings;
+pub mod writers;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Delete {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+    },
+    Insert { position: usize, sequence: String },
+    Replace {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        sequence: String,
+    },
+    Invert {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        complement: bool,
+    },
+    Duplicate {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        position: usize,
+    },
+    /// Bases `start`-`end` of record `from` copied to `position` of record
+    /// `to`: an interchromosomal (dispersed) duplication. It is applied to
+    /// `to` as an insertion of the copied bases, held in `sequence` once
+    /// they have been read from `from`.
+    DuplicateInto {
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        from: String,
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        to: String,
+        position: usize,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        sequence: String,
+    },
+    TandemDuplicate {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+    },
+    Keep {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+    },
+    Copyback {
+        gend: u8,
+        breakpoint: usize,
+        backstart: usize,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        min_stem: usize,
+        #[serde(default, skip_serializing_if = "CopybackCoords::is_revcomp")]
+        coords_on: CopybackCoords,
+    },
+    Chromothripsis {
+        start: usize,
+        end: usize,
+        fragments: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        keep: Option<usize>,
+        seed: u64,
+    },
+    Bfb { cycles: usize, seed: u64 },
+    Mei {
+        element: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        position: Option<usize>,
+        tsd: usize,
+        truncate: usize,
+        polya: usize,
+        reverse: bool,
+        seed: u64,
+    },
+    Integrate {
+        virus: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        positions: Vec<usize>,
+        sites: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        viral_range: Option<(usize, usize)>,
+        partial: bool,
+        rearrange: bool,
+        microhomology: usize,
+        seed: u64,
+    },
+    RepeatFamily {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        copies: usize,
+        divergence: f64,
+        seed: u64,
+    },
+    Polya { length: usize },
+    Telomere { repeat: String, length: usize, end: TelomereEnd },
+    AddLeader { sequence: String },
+    Case {
+        start: usize,
+        #[serde(with = "open_end")]
+        end: usize,
+        to: LetterCase,
+    },
+    /// An operation registered with [`registry::register`], by name.
+    Custom {
+        name: String,
+        #[serde(default)]
+        params: serde_json::Value,
+    },
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+/// Range end written as `end`: the last base of the sequence being edited,
+/// whatever its length turns out to be.
+pub const OPEN_END: usize = usize::MAX;
+
+/// Serializes open range ends as `"end"`, so journals and plans replay
+/// against sequences of any length.
+mod open_end {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use crate::OPEN_END;
+
+    pub fn serialize<S: Serializer>(end: &usize, serializer: S) -> Result<S::Ok, S::Error> {
+        if *end == OPEN_END {
+            serializer.serialize_str("end")
+        } else {
+            serializer.serialize_u64(*end as u64)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum End {
+            Position(usize),
+            Named(String),
+        }
+        match End::deserialize(deserializer)? {
+            End::Position(end) => Ok(end),
+            End::Named(name) if name == "end" => Ok(OPEN_END),
+            End::Named(name) => Err(de::Error::custom(format!("invalid range end '{}'", name))),
+        }
+    }
+}
+
+/// Which end(s) of a sequence a telomere operation caps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TelomereEnd {
+    #[serde(rename = "5")]
+    Five,
+    #[serde(rename = "3")]
+    Three,
+    #[serde(rename = "both")]
+    Both,
+}
+
+/// The case a `case` operation writes a range in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LetterCase {
+    Upper,
+    Lower,
+}
+
+/// What a 3' copyback's breakpoint and backstart count along: the reverse
+/// complemented genome, from its start (the input's 3' end), or the input's
+/// own plus-strand positions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopybackCoords {
+    #[default]
+    Revcomp,
+    Reference,
+}
+
+impl CopybackCoords {
+    pub fn is_revcomp(&self) -> bool {
+        *self == CopybackCoords::Revcomp
+    }
+}
+
+/// The name an operation has in journals and plan JSON.
+pub fn operation_name(operation: &Operation) -> String {
+    if let Operation::Custom { name, .. } = operation {
+        return name.clone();
+    }
+    serde_json::to_value(operation).ok()
+        .and_then(|value| value["op"].as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// The operation actually applied to record `index` of `length` bases. When
+/// one stochastic operation is broadcast over many records, each record gets
+/// its own seed derived from the operation's, so its result can be
+/// regenerated alone. Open range ends are fixed to the record's length.
+pub fn record_operation(operation: &Operation, index: usize, broadcast: bool, length: usize) -> Operation {
+    let operation = operation.resolve_end(length);
+    match operation.seed() {
+        Some(seed) if broadcast => operation.with_seed(rng::derive_seed(seed, index as u64)),
+        _ => operation,
+    }
+}
+
+impl Operation {
+    /// Seed of a stochastic operation.
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Operation::Chromothripsis { seed, .. }
+            | Operation::Bfb { seed, .. }
+            | Operation::Mei { seed, .. }
+            | Operation::Integrate { seed, .. }
+            | Operation::RepeatFamily { seed, .. } => Some(*seed),
+            _ => None,
+        }
+    }
+
+    /// The same operation with an `end` range end replaced by `length`.
+    pub fn resolve_end(&self, length: usize) -> Operation {
+        let mut resolved = self.clone();
+        match &mut resolved {
+            Operation::Delete { end, .. }
+            | Operation::Replace { end, .. }
+            | Operation::Invert { end, .. }
+            | Operation::Duplicate { end, .. }
+            | Operation::TandemDuplicate { end, .. }
+            | Operation::Keep { end, .. }
+            | Operation::Case { end, .. }
+            | Operation::RepeatFamily { end, .. } if *end == OPEN_END => *end = length,
+            _ => {},
+        }
+        resolved
+    }
+
+    /// Whether this is a duplication whose copy is inserted inside its own
+    /// source range, splitting the original around the copy.
+    pub fn nested_duplication(&self) -> bool {
+        matches!(*self, Operation::Duplicate { start, end, position } if start < position && position <= end)
+    }
+
+    pub fn with_seed(&self, new_seed: u64) -> Operation {
+        let mut reseeded = self.clone();
+        match &mut reseeded {
+            Operation::Chromothripsis { seed, .. }
+            | Operation::Bfb { seed, .. }
+            | Operation::Mei { seed, .. }
+            | Operation::Integrate { seed, .. }
+            | Operation::RepeatFamily { seed, .. } => *seed = new_seed,
+            _ => {},
+        }
+        reseeded
+    }
+}
+
+/// Which records of a (possibly multi-record) input an operation applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The input's only record
+    Only,
+    /// The record with this ID
+    Record(String),
+    /// Every record
+    All,
+}
+
+/// Indices of the records `target` refers to.
+pub fn select_targets(records: &[Record], target: &Target) -> Result<Vec<usize>, String> {
+    match target {
+        Target::All => Ok((0..records.len()).collect()),
+        Target::Record(id) => match records.iter().position(|r| r.id() == id) {
+            Some(i) => Ok(vec![i]),
+            None => Err(format!("Sequence '{}' not found in input", id)),
+        },
+        Target::Only if records.len() > 1 => Err(format!(
+            "Input contains {} sequences. Use --all to edit every record or --seq to select one.",
+            records.len()
+        )),
+        Target::Only => Ok(vec![0]),
+    }
+}
+
+/// Reads the bases a duplicate into another record copies from its source
+/// record, which is `--from` or else the record the operation targets, and
+/// retargets it to the receiving record. Other operations, and copies whose
+/// bases are already read (from a journal), only have their target set.
+pub fn resolve_duplicate_into(operation: Operation, target: Target, records: &[Record]) -> Result<(Operation, Target), String> {
+    let Operation::DuplicateInto { from, start, end, to, position, sequence } = operation else {
+        return Ok((operation, target));
+    };
+    if !sequence.is_empty() {
+        return Ok((Operation::DuplicateInto { from, start, end, to: to.clone(), position, sequence }, Target::Record(to)));
+    }
+    let from = match (from, target) {
+        (from, _) if !from.is_empty() => from,
+        (_, Target::Record(id)) => id,
+        (_, Target::Only) if records.len() == 1 => records[0].id().to_string(),
+        (_, Target::Only) => return Err(format!(
+            "Input contains {} sequences; name the record to copy from with --from", records.len())),
+        (_, Target::All) => return Err("A duplicate into another record copies from one record; \
+                                        name it with --from rather than --all".to_string()),
+    };
+    if from == to {
+        return Err(format!("The copy would stay on {}; use duplicate <start> <end> <position> within a record", to));
+    }
+    let source = records.iter().find(|r| r.id() == from).ok_or_else(|| format!("Sequence '{}' not found in input", from))?;
+    if !records.iter().any(|r| r.id() == to) {
+        return Err(format!("Sequence '{}' not found in input", to));
+    }
+    let end = if end == OPEN_END { source.sequence.len() } else { end };
+    if start > end || end > source.sequence.len() {
+        return Err(format!("End position {} is beyond the {} bases of {}", end, source.sequence.len(), from));
+    }
+    let sequence = source.sequence[start - 1..end].to_string();
+    Ok((Operation::DuplicateInto { from, start, end, to: to.clone(), position, sequence }, Target::Record(to)))
+}
+
+/// The input bases a duplicate into another record copied, given every
+/// record's layout relative to the input, when they are one forward piece.
+pub fn duplicate_donor<'a>(operation: &Operation, input_records: &'a [Record],
+                       layouts: &[Vec<layout::Segment>]) -> Option<vcf::Donor<'a>> {
+    let Operation::DuplicateInto { from, start, end, .. } = operation else {
+        return None;
+    };
+    let index = input_records.iter().position(|r| r.id() == from)?;
+    match liftover::map_back(&layouts[index], start - 1, *end).as_slice() {
+        [liftover::Origin::Original(mapped)] if !mapped.reverse => Some(vcf::Donor {
+            chrom: input_records[index].id(),
+            sequence: &input_records[index].sequence,
+            start: mapped.start + 1,
+            end: mapped.end,
+        }),
+        _ => None,
+    }
+}
+
+/// Applies `operation` to each target record in place. When broadcasting,
+/// records whose coordinates do not fit are left unchanged and reported as
+/// skipped; otherwise the first failure is returned.
+pub fn apply_to_records(records: &mut [Record], targets: &[usize], operation: &Operation,
+                    settings: &EditSettings, broadcast: bool) -> Result<Vec<summary::RecordSummary>, String> {
+    let mut record_summaries = Vec::new();
+    for &i in targets {
+        let record_op = record_operation(operation, i, broadcast, records[i].sequence.len());
+        match apply_operation(&records[i], &record_op, settings) {
+            Ok(edited) => {
+                let mut record_summary = summary::RecordSummary::applied(&records[i], &edited);
+                record_summary.seed = record_op.seed().filter(|_| broadcast);
+                record_summaries.push(record_summary);
+                records[i] = edited;
+            },
+            Err(msg) if broadcast => {
+                record_summaries.push(summary::RecordSummary::skipped(&records[i], msg));
+            },
+            Err(msg) if targets.len() > 1 || records.len() > 1 => {
+                return Err(format!("{} (sequence '{}')", msg, records[i].id()));
+            },
+            Err(msg) => return Err(msg),
+        }
+    }
+    Ok(record_summaries)
+}
+
+/// Total bases of `records`.
+pub fn total_length(records: &[Record]) -> usize {
+    records.iter().map(|r| r.sequence.len()).sum()
+}
+
+/// The complementary base, keeping case; characters with no complement are
+/// returned unchanged.
+pub fn complement_base(base: char) -> char {
+    // Lowercase bases, kept by `case` and --preserve-case, complement to lowercase
+    if base.is_ascii_lowercase() {
+        return complement_base(base.to_ascii_uppercase()).to_ascii_lowercase();
+    }
+    match base {
+        'A' => 'T',
+        'T' | 'U' => 'A',
+        'C' => 'G',
+        'G' => 'C',
+        'N' => 'N',
+        // IUPAC ambiguity codes complement to the code for the complementary set
+        'R' => 'Y',
+        'Y' => 'R',
+        'K' => 'M',
+        'M' => 'K',
+        'B' => 'V',
+        'V' => 'B',
+        'D' => 'H',
+        'H' => 'D',
+        'S' => 'S',
+        'W' => 'W',
+        _ => base, // Keep any other characters as-is
+    }
+}
+
+/// The reverse complement of `sequence`.
+pub fn reverse_complement(sequence: &str) -> String {
+    sequence.chars().rev().map(complement_base).collect()
+}
+
+/// Reads an operation written as JSON (the schema journals use) from a
+/// file, or from stdin when `path` is `-`, and checks it as strictly as the
+/// command-line parser would.
+pub fn read_operation_json(path: &str, alphabet: Alphabet) -> Result<Operation, String> {
+    let text = if path == "-" {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)
+            .map_err(|e| format!("Cannot read operation JSON from stdin: {}", e))?;
+        text
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("Cannot read operation JSON {}: {}", path, e))?
+    };
+    check_operation(operation_from_json(&text)?, alphabet)
+}
+
+/// Parses an operation's JSON, migrating it from the schema version given
+/// next to its `op`; operations without one predate versioning.
+pub fn operation_from_json(text: &str) -> Result<Operation, String> {
+    let mut operation: serde_json::Value = serde_json::from_str(text).map_err(|e| format!("Invalid operation JSON: {}", e))?;
+    let version = schema::check_version(operation.get("schema_version"), "Operation JSON")?;
+    if let Some(fields) = operation.as_object_mut() {
+        fields.remove("schema_version");
+    }
+    schema::migrate_json(&mut operation, version);
+    serde_json::from_value(operation).map_err(|e| format!("Invalid operation JSON: {}", e))
+}
+
+/// Checks the values of an operation, however it was built: every check
+/// `parse_operation` makes beyond reading its words is made here, so
+/// operations read from JSON are held to the same rules. Sequences it
+/// carries are uppercased.
+pub fn check_operation(mut operation: Operation, alphabet: Alphabet) -> Result<Operation, String> {
+    match &operation {
+        Operation::Delete { start, end }
+        | Operation::Replace { start, end, .. }
+        | Operation::Invert { start, end, .. }
+        | Operation::Duplicate { start, end, .. }
+        | Operation::DuplicateInto { start, end, .. }
+        | Operation::TandemDuplicate { start, end }
+        | Operation::Keep { start, end }
+        | Operation::Case { start, end, .. }
+        | Operation::Chromothripsis { start, end, .. }
+        | Operation::RepeatFamily { start, end, .. } => {
+            if *start == 0 || *end == 0 {
+                return Err("Positions must be 1-based (starting from 1)".to_string());
+            }
+            if start > end {
+                return Err("Start position must be <= end position".to_string());
+            }
+        },
+        _ => {},
+    }
+    match &mut operation {
+        Operation::Insert { position: 0, .. } | Operation::Duplicate { position: 0, .. }
+        | Operation::DuplicateInto { position: 0, .. } | Operation::Mei { position: Some(0), .. } => {
+            return Err("Position must be 1-based (starting from 1)".to_string());
+        },
+        Operation::Insert { sequence, .. } => {
+            alphabet.validate("Inserted sequence", sequence)?;
+            sequence.make_ascii_uppercase();
+        },
+        Operation::Replace { sequence, .. } => {
+            alphabet.validate("Replacement sequence", sequence)?;
+            sequence.make_ascii_uppercase();
+        },
+        Operation::AddLeader { sequence } => {
+            alphabet.validate("Leader sequence", sequence)?;
+            sequence.make_ascii_uppercase();
+        },
+        Operation::DuplicateInto { from, to, .. } if from == to => {
+            return Err(format!("The copy would stay on {}; use duplicate <start> <end> <position> within a record", to));
+        },
+        Operation::Invert { complement: true, .. } if alphabet.is_protein() => {
+            return Err("Reverse complement is not available for protein sequences".to_string());
+        },
+        Operation::Copyback { gend, breakpoint, backstart, coords_on, .. } => {
+            if alphabet.is_protein() {
+                return Err("Copyback is not available for protein sequences".to_string());
+            }
+            check_copyback(*gend, *breakpoint, *backstart, *coords_on)?;
+            // 5' positions read the same either way
+            if *gend == 5 {
+                *coords_on = CopybackCoords::Revcomp;
+            }
+        },
+        Operation::Chromothripsis { start, end, fragments, keep, .. } => {
+            if *fragments < 2 {
+                return Err("Chromothripsis requires at least 2 fragments".to_string());
+            }
+            if *fragments > *end - *start + 1 {
+                return Err("Region is shorter than the number of fragments".to_string());
+            }
+            if keep.is_some_and(|keep| keep == 0 || keep > *fragments) {
+                return Err("--keep must be between 1 and the number of fragments".to_string());
+            }
+        },
+        Operation::Bfb { cycles: 0, .. } => {
+            return Err("BFB requires at least one cycle".to_string());
+        },
+        Operation::Mei { element, truncate, .. } => {
+            if alphabet.is_protein() {
+                return Err("MEI cannot be used with protein sequences".to_string());
+            }
+            alphabet.validate("Element sequence", element)?;
+            element.make_ascii_uppercase();
+            if *truncate >= element.len() {
+                return Err(format!("--truncate must be shorter than the {}bp element", element.len()));
+            }
+        },
+        Operation::Integrate { virus, positions, sites, viral_range, .. } => {
+            if alphabet.is_protein() {
+                return Err("Integrate cannot be used with protein sequences".to_string());
+            }
+            if positions.contains(&0) {
+                return Err("Position must be 1-based (starting from 1)".to_string());
+            }
+            if *sites == 0 {
+                return Err("--sites must be at least 1".to_string());
+            }
+            alphabet.validate("Viral genome", virus)?;
+            virus.make_ascii_uppercase();
+            if let Some((start, end)) = *viral_range {
+                if start == 0 || start > end {
+                    return Err(format!("Invalid viral range {}-{} (expected start-end, 1-based)", start, end));
+                }
+                if end > virus.len() {
+                    return Err(format!("Viral range end {} is beyond viral genome length {}", end, virus.len()));
+                }
+            }
+        },
+        Operation::Polya { length } => {
+            if *length == 0 {
+                return Err("Poly-A length must be at least 1".to_string());
+            }
+            if alphabet.is_protein() {
+                return Err("Poly-A tails cannot be added to protein sequences".to_string());
+            }
+        },
+        Operation::Telomere { repeat, length, .. } => {
+            if *length == 0 || repeat.is_empty() {
+                return Err("Telomere length and repeat must not be empty".to_string());
+            }
+            if alphabet.is_protein() {
+                return Err("Telomeres cannot be added to protein sequences".to_string());
+            }
+            alphabet.validate("Telomere repeat", repeat)?;
+            repeat.make_ascii_uppercase();
+        },
+        Operation::Custom { name, params } => {
+            registry::registered(name)?.validate(params, alphabet)?;
+        },
+        Operation::RepeatFamily { copies, divergence, .. } => {
+            if *copies == 0 {
+                return Err("Repeat family requires at least one copy".to_string());
+            }
+            if !(0.0..=1.0).contains(divergence) {
+                return Err("--divergence must be between 0 and 1".to_string());
+            }
+            if alphabet.is_protein() {
+                return Err("Repeat family is not available for protein sequences".to_string());
+            }
+        },
+        _ => {},
+    }
+    Ok(operation)
+}
+
+/// Checks a copyback's positions. The backstart lies on the kept side of
+/// the breakpoint, or on it for a snapback.
+fn check_copyback(gend: u8, breakpoint: usize, backstart: usize, coords_on: CopybackCoords) -> Result<(), String> {
+    if gend != 5 && gend != 3 {
+        return Err("gend must be either 5 or 3".to_string());
+    }
+    if breakpoint == 0 || backstart == 0 {
+        return Err("Positions must be 1-based (starting from 1)".to_string());
+    }
+    if gend == 5 && backstart > breakpoint {
+        return Err("For 5' end, backstart must be less than breakpoint".to_string());
+    }
+    if gend == 3 && coords_on == CopybackCoords::Reference && backstart < breakpoint {
+        return Err("For 3' end on reference coordinates, backstart must be greater than breakpoint".to_string());
+    }
+    if gend == 3 && coords_on == CopybackCoords::Revcomp && backstart > breakpoint {
+        return Err("For 3' end, backstart must be less than breakpoint".to_string());
+    }
+    Ok(())
+}
+
+/// Parses the start of a range, where `start` stands for the first base.
+fn parse_range_start(value: &str) -> Result<usize, String> {
+    if value == "start" {
+        return Ok(1);
+    }
+    units::parse_bases(value).map_err(|_| "Start position must be a number".to_string())
+}
+
+/// Parses the end of a range, where `end` stands for the last base of
+/// whichever sequence the operation is eventually applied to.
+fn parse_range_end(value: &str) -> Result<usize, String> {
+    if value == "end" {
+        return Ok(OPEN_END);
+    }
+    units::parse_bases(value).map_err(|_| "End position must be a number".to_string())
+}
+
+/// Default window over which `--gc` bounds a random insert.
+const DEFAULT_GC_WINDOW: usize = 50;
+
+/// Reverse-translates the payload of `insert <position> --protein <residues>
+/// --codon-table <organism|file>`. The coding sequence is stored in the
+/// operation, so journals replay it without the table.
+fn parse_protein_insert(args: &[String], alphabet: Alphabet) -> Result<String, String> {
+    let mut protein = None;
+    let mut table = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).cloned();
+        match args[i].as_str() {
+            "--protein" => protein = Some(value.ok_or("--protein requires an amino-acid sequence")?),
+            "--codon-table" => table = Some(value.ok_or_else(|| format!(
+                "--codon-table requires an organism ({}) or a codon usage file", codon::built_in_names().join(", ")))?),
+            other => return Err(format!("Unknown insert option '{}'", other)),
+        }
+        i += 2;
+    }
+    let protein = protein.ok_or("--protein requires an amino-acid sequence")?;
+    let table = table.ok_or_else(|| format!(
+        "insert --protein requires --codon-table <organism|file> (built in: {})", codon::built_in_names().join(", ")))?;
+    if !matches!(alphabet, Alphabet::Dna | Alphabet::Rna | Alphabet::Iupac | Alphabet::Any) {
+        return Err("insert --protein reverse-translates into nucleotides and cannot be used with protein sequences".to_string());
+    }
+    let coding = codon::CodonTable::load(&table)?.reverse_translate(&protein)?;
+    Ok(if alphabet == Alphabet::Rna { coding.replace('T', "U") } else { coding })
+}
+
+/// Draws the sequence of `insert <position> --random <length> [constraints]`
+/// from `seed`. The drawn bases are stored in the operation, so journals
+/// replay them exactly.
+fn parse_random_insert(args: &[String], alphabet: Alphabet, seed: u64) -> Result<String, String> {
+    let length = units::parse_bases(&args[0]).map_err(|_| "--random length must be a number")?;
+    if length == 0 {
+        return Err("--random length must be at least 1".to_string());
+    }
+    let mut constraints = simulate::InsertConstraints {
+        max_homopolymer: None,
+        gc: None,
+        gc_window: DEFAULT_GC_WINDOW,
+        avoid: Vec::new(),
+    };
+    let mut i = 1;
+    while i < args.len() {
+        let value = |what: &str| args.get(i + 1).ok_or_else(|| format!("{} requires {}", args[i], what));
+        match args[i].as_str() {
+            "--max-homopolymer" => {
+                let max = units::parse_bases(value("a length")?).map_err(|_| "--max-homopolymer must be a number")?;
+                if max == 0 {
+                    return Err("--max-homopolymer must be at least 1".to_string());
+                }
+                constraints.max_homopolymer = Some(max);
+            },
+            "--gc" => {
+                let bounds = value("bounds such as 0.4-0.6")?;
+                let parsed = bounds.split_once('-')
+                    .and_then(|(low, high)| Some((low.trim().parse::<f64>().ok()?, high.trim().parse::<f64>().ok()?)));
+                match parsed {
+                    Some((low, high)) if (0.0..=1.0).contains(&low) && low <= high && high <= 1.0 => {
+                        constraints.gc = Some((low, high));
+                    },
+                    _ => return Err(format!("--gc must be two fractions such as 0.4-0.6, got '{}'", bounds)),
+                }
+            },
+            "--gc-window" => {
+                constraints.gc_window = units::parse_bases(value("a length")?).map_err(|_| "--gc-window must be a number")?;
+                if constraints.gc_window == 0 {
+                    return Err("--gc-window must be at least 1".to_string());
+                }
+            },
+            "--avoid" => {
+                let motif = value("a motif")?.to_uppercase();
+                alphabet.validate("Avoided motif", &motif)?;
+                constraints.avoid.push(motif);
+            },
+            other => return Err(format!("Unknown insert option '{}'", other)),
+        }
+        i += 2;
+    }
+    if constraints.gc.is_some() && alphabet.is_protein() {
+        return Err("--gc does not apply to protein sequences".to_string());
+    }
+    let mut rng = rng::Rng::stream(seed, rng::INSERT_STREAM);
+    simulate::random_insert(length, &constraints, alphabet.substitution_bases(), alphabet, &mut rng)
+}
+
+/// A way `parse_operation_noted` rewrote the words it was given, for the
+/// caller to report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseNote {
+    /// `--keep-leader`/`--keep-trailer` stand for this copyback
+    KeptEnd { gend: u8, kept: usize, copied: usize },
+    /// The copyback breakpoints were moved out to reach `min_stem`
+    StemAdjusted { breakpoint: usize, backstart: usize, min_stem: usize },
+}
+
+impl std::fmt::Display for ParseNote {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ParseNote::KeptEnd { gend, kept, copied } => {
+                let end_name = if gend == 5 { "leader" } else { "trailer" };
+                write!(f, "--keep-{} {} --reanneal {} is copyback {} {} {}", end_name, kept, copied, gend, kept, copied)
+            },
+            ParseNote::StemAdjusted { breakpoint, backstart, min_stem } => {
+                write!(f, "copyback adjusted to breakpoint {} and backstart {} for a {}bp terminal stem",
+                       breakpoint, backstart, min_stem)
+            },
+        }
+    }
+}
+
+/// Parses an operation from its command-line words. `seed` is recorded in
+/// stochastic operations so they can be replayed exactly.
+pub fn parse_operation(args: &[String], alphabet: Alphabet, seed: u64) -> Result<Operation, String> {
+    parse_operation_noted(args, alphabet, seed).map(|(operation, _)| operation)
+}
+
+/// Parses an operation as `parse_operation` does, along with how the words
+/// were rewritten on the way.
+pub fn parse_operation_noted(args: &[String], alphabet: Alphabet, seed: u64) -> Result<(Operation, Vec<ParseNote>), String> {
+    let mut notes = Vec::new();
+    let operation = check_operation(parse_words(args, alphabet, seed, &mut notes)?, alphabet)?;
+    Ok((operation, notes))
+}
+
+fn parse_words(args: &[String], alphabet: Alphabet, seed: u64, notes: &mut Vec<ParseNote>) -> Result<Operation, String> {
+    if args.is_empty() {
+        return Err("No operation specified".to_string());
+    }
+    
+    match args[0].as_str() {
+        "delete" => {
+            if args.len() != 3 {
+                return Err("Delete operation requires start and end positions".to_string());
+            }
+            let start = parse_range_start(&args[1])?;
+            let end = parse_range_end(&args[2])?;
+            
+            Ok(Operation::Delete { start, end })
+        },
+        "keep" => {
+            if args.len() != 3 {
+                return Err("Keep operation requires start and end positions".to_string());
+            }
+            let start = parse_range_start(&args[1])?;
+            let end = parse_range_end(&args[2])?;
+            
+            Ok(Operation::Keep { start, end })
+        },
+        "insert" => {
+            if args.len() == 3 && args[2] == "--random" {
+                return Err("--random requires a length".to_string());
+            }
+            if args.len() >= 4 && args[2] == "--random" {
+                let position: usize = units::parse_bases(&args[1]).map_err(|_| "Position must be a number")?;
+                let sequence = parse_random_insert(&args[3..], alphabet, seed)?;
+                return Ok(Operation::Insert { position, sequence });
+            }
+            if args.len() >= 3 && args[2..].iter().any(|arg| arg == "--protein") {
+                let position: usize = units::parse_bases(&args[1]).map_err(|_| "Position must be a number")?;
+                let sequence = parse_protein_insert(&args[2..], alphabet)?;
+                return Ok(Operation::Insert { position, sequence });
+            }
+            if args.len() != 3 {
+                return Err("Insert operation requires position and sequence".to_string());
+            }
+            let position: usize = units::parse_bases(&args[1]).map_err(|_| "Position must be a number")?;
+            let sequence = args[2].clone();
+            
+            Ok(Operation::Insert { position, sequence })
+        },
+        "replace" => {
+            if args.len() != 4 {
+                return Err("Replace operation requires start and end positions and a sequence".to_string());
+            }
+            let start = parse_range_start(&args[1])?;
+            let end = parse_range_end(&args[2])?;
+            let sequence = args[3].clone();
+            
+            Ok(Operation::Replace { start, end, sequence })
+        },
+        "invert" => {
+            let mut complement = false;
+            let mut pos_args = Vec::new();
+            
+            // Parse arguments, looking for --complement flag
+            for arg in &args[1..] {
+                if arg == "--complement" {
+                    complement = true;
+                } else {
+                    pos_args.push(arg);
+                }
+            }
+            
+            if pos_args.len() != 2 {
+                return Err("Invert operation requires start and end positions".to_string());
+            }
+            
+            let start = parse_range_start(pos_args[0])?;
+            let end = parse_range_end(pos_args[1])?;
+            
+            Ok(Operation::Invert { start, end, complement })
+        },
+        "duplicate" => {
+            let mut tandem = false;
+            let mut from = None;
+            let mut pos_args = Vec::new();
+            
+            // Parse arguments, looking for -td and --from
+            let mut i = 1;
+            while i < args.len() {
+                match args[i].as_str() {
+                    "-td" => tandem = true,
+                    "--from" => {
+                        from = Some(args.get(i + 1).ok_or("--from requires the record to copy from")?.clone());
+                        i += 1;
+                    },
+                    _ => pos_args.push(&args[i]),
+                }
+                i += 1;
+            }
+            if tandem && from.is_some() {
+                return Err("A tandem duplicate stays on its own record; --from applies to duplicate <start> <end> <record>:<position>".to_string());
+            }
+            
+            if tandem {
+                // Tandem duplication: duplicate <start> <end>
+                if pos_args.len() != 2 {
+                    return Err("Tandem duplicate operation requires start and end positions".to_string());
+                }
+                
+                let start = parse_range_start(pos_args[0])?;
+                let end = parse_range_end(pos_args[1])?;
+                
+                Ok(Operation::TandemDuplicate { start, end })
+            } else {
+                // Regular duplication: duplicate <start> <end> <position>
+                if pos_args.len() != 3 {
+                    return Err("Duplicate operation requires start, end, and insert positions".to_string());
+                }
+                
+                let start = parse_range_start(pos_args[0])?;
+                let end = parse_range_end(pos_args[1])?;
+                // A <record>:<position> destination copies into another record
+                let (to, position) = match pos_args[2].rsplit_once(':') {
+                    Some((to, position)) if !to.is_empty() => (Some(to.to_string()), position),
+                    _ => (None, pos_args[2].as_str()),
+                };
+                let position: usize = units::parse_bases(position).map_err(|_| "Insert position must be a number")?;
+                
+                match to {
+                    Some(to) => Ok(Operation::DuplicateInto {
+                        from: from.unwrap_or_default(),
+                        start,
+                        end,
+                        to,
+                        position,
+                        sequence: String::new(),
+                    }),
+                    None if from.is_some() => Err("--from needs a <record>:<position> destination".to_string()),
+                    None => Ok(Operation::Duplicate { start, end, position }),
+                }
+            }
+        },
+        "copyback" if alphabet.is_protein() => {
+            Err("Copyback is not available for protein sequences".to_string())
+        },
+        "copyback" => {
+            let mut snapback = false;
+            let mut min_stem = 0;
+            let mut require_perfect_stem = false;
+            let mut coords_on = CopybackCoords::Revcomp;
+            let mut kept_end: Option<(u8, usize)> = None;
+            let mut reanneal = None;
+            let mut pos_args = Vec::new();
+            let mut i = 1;
+            
+            // Parse arguments, looking for -sb and the stem and coordinate options
+            while i < args.len() {
+                match args[i].as_str() {
+                    "-sb" => snapback = true,
+                    "--require-perfect-stem" => require_perfect_stem = true,
+                    "--keep-leader" | "--keep-trailer" => {
+                        let value = args.get(i + 1).ok_or_else(|| format!("{} requires a length", args[i]))?;
+                        let kept = units::parse_bases(value).map_err(|_| format!("{} must be a number", args[i]))?;
+                        kept_end = Some((if args[i] == "--keep-leader" { 5 } else { 3 }, kept));
+                        i += 1;
+                    },
+                    "--reanneal" => {
+                        let value = args.get(i + 1).ok_or("--reanneal requires a length")?;
+                        reanneal = Some(units::parse_bases(value).map_err(|_| "--reanneal must be a number")?);
+                        i += 1;
+                    },
+                    "--coords-on" => {
+                        coords_on = match args.get(i + 1).map(String::as_str) {
+                            Some("revcomp") => CopybackCoords::Revcomp,
+                            Some("reference") => CopybackCoords::Reference,
+                            _ => return Err("--coords-on must be revcomp or reference".to_string()),
+                        };
+                        i += 1;
+                    },
+                    "--min-stem" => {
+                        let value = args.get(i + 1).ok_or("--min-stem requires a length")?;
+                        min_stem = units::parse_bases(value).map_err(|_| "--min-stem must be a number")?;
+                        i += 1;
+                    },
+                    _ => pos_args.push(&args[i]),
+                }
+                i += 1;
+            }
+            if require_perfect_stem && min_stem == 0 {
+                return Err("--require-perfect-stem requires --min-stem <n>".to_string());
+            }
+            
+            if reanneal.is_some() && kept_end.is_none() {
+                return Err("--reanneal requires --keep-leader or --keep-trailer".to_string());
+            }
+            let (gend, mut breakpoint, mut backstart) = if let Some((gend, kept)) = kept_end {
+                // Lengths kept from the leader (5') or trailer (3') end and
+                // copied back after reannealing: the breakpoint and backstart
+                // counted from that end
+                if !pos_args.is_empty() {
+                    return Err("--keep-leader/--keep-trailer replace the gend, breakpoint and backstart arguments".to_string());
+                }
+                if coords_on == CopybackCoords::Reference {
+                    return Err("--keep-leader/--keep-trailer take lengths, which --coords-on does not apply to".to_string());
+                }
+                let copied = match reanneal {
+                    Some(copied) => copied,
+                    None if snapback => kept,
+                    None => return Err("--keep-leader/--keep-trailer require --reanneal <n> (or -sb)".to_string()),
+                };
+                if kept == 0 || copied == 0 {
+                    return Err("Kept and reannealed lengths must be at least 1".to_string());
+                }
+                if copied > kept || (snapback && copied != kept) {
+                    return Err(format!("--reanneal {} must be at most the {} bases kept (equal for -sb)", copied, kept));
+                }
+                notes.push(ParseNote::KeptEnd { gend, kept, copied });
+                (gend, kept, copied)
+            } else if snapback {
+                // Snapback mode: copyback <gend> <breakpoint>
+                if pos_args.len() != 2 {
+                    return Err("Copyback with -sb flag requires gend and breakpoint".to_string());
+                }
+                
+                let gend_str = &pos_args[0];
+                let gend: u8 = match gend_str.as_str() {
+                    "5" => 5,
+                    "3" => 3,
+                    _ => return Err("gend must be either 5 or 3".to_string())
+                };
+                
+                let breakpoint: usize = units::parse_bases(pos_args[1]).map_err(|_| "Breakpoint must be a number")?;
+                
+                if breakpoint == 0 {
+                    return Err("Breakpoint must be 1-based (starting from 1)".to_string());
+                }
+                
+                (gend, breakpoint, breakpoint)
+            } else {
+                // Regular copyback: copyback <gend> <breakpoint> <backstart>
+                if pos_args.len() != 3 {
+                    return Err("Copyback operation requires gend, breakpoint, and backstart".to_string());
+                }
+                
+                let gend_str = &pos_args[0];
+                let gend: u8 = match gend_str.as_str() {
+                    "5" => 5,
+                    "3" => 3,
+                    _ => return Err("gend must be either 5 or 3".to_string())
+                };
+                
+                let breakpoint: usize = units::parse_bases(pos_args[1]).map_err(|_| "Breakpoint must be a number")?;
+                let backstart: usize = units::parse_bases(pos_args[2]).map_err(|_| "Backstart must be a number")?;
+                
+                if breakpoint == 0 || backstart == 0 {
+                    return Err("Positions must be 1-based (starting from 1)".to_string());
+                }
+                
+                // Validate backstart position relative to breakpoint based on gend
+                if gend == 5 && backstart >= breakpoint {
+                    return Err("For 5' end, backstart must be less than breakpoint".to_string());
+                }
+                if gend == 3 && coords_on == CopybackCoords::Reference && backstart <= breakpoint {
+                    return Err("For 3' end on reference coordinates, backstart must be greater than breakpoint".to_string());
+                }
+                if gend == 3 && coords_on == CopybackCoords::Revcomp && backstart >= breakpoint {
+                    return Err("For 3' end, backstart must be less than breakpoint".to_string());
+                }
+                
+                (gend, breakpoint, backstart)
+            };
+            
+            // 5' positions read the same either way
+            if gend == 5 {
+                coords_on = CopybackCoords::Revcomp;
+            }
+            // Plus-strand positions of a 3' copyback depend on the sequence
+            // length, so the stem can only be checked once it is applied
+            if coords_on == CopybackCoords::Reference && min_stem > 0 && !require_perfect_stem {
+                return Err("--min-stem with --coords-on reference needs --require-perfect-stem".to_string());
+            }
+
+            // The terminal stem pairs the copied-back stretch with the start of
+            // the genome, so it is as long as the shorter of the two; move the
+            // breakpoints out to reach the minimum unless asked only to check
+            if min_stem > backstart && !require_perfect_stem {
+                backstart = min_stem;
+                if snapback {
+                    breakpoint = min_stem;
+                } else if backstart >= breakpoint {
+                    breakpoint = backstart + 1;
+                }
+                notes.push(ParseNote::StemAdjusted { breakpoint, backstart, min_stem });
+            }
+            
+            Ok(Operation::Copyback { gend, breakpoint, backstart, min_stem, coords_on })
+        },
+        "chromothripsis" => {
+            let mut keep = None;
+            let mut pos_args = Vec::new();
+            let mut i = 1;
+            
+            // Parse arguments, looking for --keep <n>
+            while i < args.len() {
+                if args[i] == "--keep" {
+                    let value = args.get(i + 1).ok_or("--keep requires a number of fragments")?;
+                    keep = Some(value.parse::<usize>().map_err(|_| "--keep must be a number")?);
+                    i += 2;
+                } else {
+                    pos_args.push(&args[i]);
+                    i += 1;
+                }
+            }
+            
+            if pos_args.len() != 3 {
+                return Err("Chromothripsis requires start and end positions and a number of fragments".to_string());
+            }
+            
+            let start: usize = units::parse_bases(pos_args[0]).map_err(|_| "Start position must be a number")?;
+            let end: usize = units::parse_bases(pos_args[1]).map_err(|_| "End position must be a number")?;
+            let fragments: usize = pos_args[2].parse().map_err(|_| "Number of fragments must be a number")?;
+            
+            Ok(Operation::Chromothripsis { start, end, fragments, keep, seed })
+        },
+        "repeat-family" => {
+            let mut copies = None;
+            let mut divergence = 0.0;
+            let mut range = Vec::new();
+            let mut i = 1;
+            while i < args.len() {
+                let value = |what: &str| args.get(i + 1).ok_or(format!("{} requires {}", args[i], what));
+                match args[i].as_str() {
+                    "--copies" => {
+                        copies = Some(units::parse_bases(value("a number")?).map_err(|_| "--copies must be a number")?);
+                        i += 2;
+                    },
+                    "--divergence" => {
+                        divergence = value("a rate")?.parse::<f64>().map_err(|_| "--divergence must be a number")?;
+                        i += 2;
+                    },
+                    _ => {
+                        range.push(args[i].as_str());
+                        i += 1;
+                    }
+                }
+            }
+            let (&[start, end], Some(copies)) = (&range[..], copies) else {
+                return Err("Repeat family operation requires start and end positions and --copies <n>".to_string());
+            };
+            let start = parse_range_start(start)?;
+            let end = parse_range_end(end)?;
+            Ok(Operation::RepeatFamily { start, end, copies, divergence, seed })
+        },
+        "bfb" => {
+            let cycles = match &args[1..] {
+                [flag, n] if flag == "--cycles" => n,
+                [n] => n,
+                _ => return Err("BFB operation requires --cycles <n>".to_string()),
+            };
+            let cycles: usize = cycles.parse().map_err(|_| "Number of cycles must be a number")?;
+            Ok(Operation::Bfb { cycles, seed })
+        },
+        "mei" => {
+            let mut position = None;
+            let mut tsd = 15;
+            let mut truncate = 0;
+            let mut polya = 30;
+            let mut reverse = false;
+            let mut pos_args = Vec::new();
+            let mut i = 1;
+            while i < args.len() {
+                let value = |what: &str| args.get(i + 1).ok_or(format!("{} requires {}", args[i], what));
+                match args[i].as_str() {
+                    "--position" => {
+                        position = Some(units::parse_bases(value("a position")?).map_err(|_| "--position must be a number")?);
+                    },
+                    "--tsd" => tsd = units::parse_bases(value("a length")?).map_err(|_| "--tsd must be a number")?,
+                    "--truncate" => truncate = units::parse_bases(value("a length")?).map_err(|_| "--truncate must be a number")?,
+                    "--polya" => polya = units::parse_bases(value("a length")?).map_err(|_| "--polya must be a number")?,
+                    "--strand" => reverse = match value("+ or -")?.as_str() {
+                        "+" => false,
+                        "-" => true,
+                        _ => return Err("--strand must be + or -".to_string()),
+                    },
+                    _ => {
+                        pos_args.push(&args[i]);
+                        i += 1;
+                        continue;
+                    }
+                }
+                i += 2;
+            }
+            
+            if pos_args.len() != 1 {
+                return Err("MEI operation requires an element sequence or FASTA file".to_string());
+            }
+
+            // The element is either a FASTA file (its first record) or the bases themselves
+            let element = if std::path::Path::new(pos_args[0]).is_file() {
+                match fasta::read_records(pos_args[0], false) {
+                    Ok(records) => records[0].sequence.clone(),
+                    Err(e) => return Err(format!("Could not read element file '{}': {}", pos_args[0], e)),
+                }
+            } else {
+                pos_args[0].to_uppercase()
+            };
+            Ok(Operation::Mei { element, position, tsd, truncate, polya, reverse, seed })
+        },
+        "integrate" => {
+            let mut positions = Vec::new();
+            let mut sites = 1;
+            let mut viral_range = None;
+            let mut partial = false;
+            let mut rearrange = false;
+            let mut microhomology = 0;
+            let mut pos_args = Vec::new();
+            let mut i = 1;
+            while i < args.len() {
+                let value = |what: &str| args.get(i + 1).ok_or(format!("{} requires {}", args[i], what));
+                match args[i].as_str() {
+                    "--position" => {
+                        positions.push(units::parse_bases(value("a position")?).map_err(|_| "--position must be a number")?);
+                    },
+                    "--sites" => sites = value("a number")?.parse().map_err(|_| "--sites must be a number")?,
+                    "--viral-range" => {
+                        let range = value("a range")?;
+                        let parsed = range.split_once('-')
+                            .and_then(|(a, b)| Some((units::parse_bases(a).ok()?, units::parse_bases(b).ok()?)));
+                        match parsed {
+                            Some((a, b)) if a >= 1 && a <= b => viral_range = Some((a, b)),
+                            _ => return Err(format!("Invalid --viral-range '{}' (expected start-end, 1-based)", range)),
+                        }
+                    },
+                    "--microhomology" => {
+                        microhomology = units::parse_bases(value("a length")?).map_err(|_| "--microhomology must be a number")?;
+                    },
+                    "--partial" => {
+                        partial = true;
+                        i += 1;
+                        continue;
+                    },
+                    "--rearrange" => {
+                        rearrange = true;
+                        i += 1;
+                        continue;
+                    },
+                    _ => {
+                        pos_args.push(&args[i]);
+                        i += 1;
+                        continue;
+                    }
+                }
+                i += 2;
+            }
+            
+            if pos_args.len() != 1 {
+                return Err("Integrate operation requires a viral genome sequence or FASTA file".to_string());
+            }
+
+            let virus = if std::path::Path::new(pos_args[0]).is_file() {
+                match fasta::read_records(pos_args[0], false) {
+                    Ok(records) => records[0].sequence.clone(),
+                    Err(e) => return Err(format!("Could not read viral genome '{}': {}", pos_args[0], e)),
+                }
+            } else {
+                pos_args[0].to_uppercase()
+            };
+            Ok(Operation::Integrate { virus, positions, sites, viral_range, partial, rearrange, microhomology, seed })
+        },
+        "polya" => {
+            let length = match &args[1..] {
+                [flag, n] if flag == "--length" => n,
+                [n] => n,
+                _ => return Err("Poly-A operation requires --length <n>".to_string()),
+            };
+            let length: usize = units::parse_bases(length).map_err(|_| "Poly-A length must be a number")?;
+            Ok(Operation::Polya { length })
+        },
+        "telomere" => {
+            let mut repeat = "TTAGGG".to_string();
+            let mut length = None;
+            let mut end = TelomereEnd::Both;
+            let mut i = 1;
+            while i < args.len() {
+                let value = args.get(i + 1).ok_or(format!("{} requires a value", args[i]))?;
+                match args[i].as_str() {
+                    "--repeat" => repeat = value.clone(),
+                    "--length" => length = Some(units::parse_bases(value).map_err(|_| "--length must be a number")?),
+                    "--end" => end = match value.as_str() {
+                        "5" => TelomereEnd::Five,
+                        "3" => TelomereEnd::Three,
+                        "both" => TelomereEnd::Both,
+                        _ => return Err("--end must be 5, 3, or both".to_string()),
+                    },
+                    other => return Err(format!("Unknown telomere argument '{}'", other)),
+                }
+                i += 2;
+            }
+            
+            let length = length.ok_or("Telomere operation requires --length <n>")?;
+            Ok(Operation::Telomere { repeat, length, end })
+        },
+        "add-leader" => {
+            if args.len() != 2 {
+                return Err("Add-leader operation requires a leader sequence".to_string());
+            }
+            Ok(Operation::AddLeader { sequence: args[1].clone() })
+        },
+        "case" => {
+            if args.len() != 4 {
+                return Err("Case operation requires start and end positions and 'upper' or 'lower'".to_string());
+            }
+            let start = parse_range_start(&args[1])?;
+            let end = parse_range_end(&args[2])?;
+            let to = match args[3].as_str() {
+                "upper" => LetterCase::Upper,
+                "lower" => LetterCase::Lower,
+                other => return Err(format!("Case must be 'upper' or 'lower', got '{}'", other)),
+            };
+            Ok(Operation::Case { start, end, to })
+        },
+        name => match registry::lookup(name) {
+            Some(custom) => Ok(Operation::Custom { name: name.to_string(), params: custom.parse(&args[1..], alphabet, seed)? }),
+            None => {
+                let registered: String = registry::names().iter().map(|name| format!("'{}', ", name)).collect();
+                Err(format!("Unknown operation '{}'. Use 'delete', 'keep', 'insert', 'replace', 'invert', 'duplicate', 'copyback', 'chromothripsis', 'bfb', 'mei', 'integrate', 'polya', 'telomere', 'add-leader', {}or 'case'", name, registered))
+            },
+        },
+    }
+}
+
+/// Settings that affect how an operation is applied, independent of which
+/// file it is read from or written to.
+#[derive(Debug, Clone, Copy)]
+pub struct EditSettings {
+    pub alphabet: Alphabet,
+    /// Quality of inserted bases in FASTQ records
+    pub insert_qual: char,
+    /// Reject duplications whose copy lands inside their own source
+    pub disallow_nested: bool,
+    /// Whether the records end where the molecule does, which copybacks
+    /// depend on
+    pub complete_termini: bool,
+}
+
+/// `record` with `operation` applied, its header annotated with what was
+/// done, or why the operation does not fit the record.
+pub fn apply_operation(record: &Record, operation: &Operation, settings: &EditSettings) -> Result<Record, String> {
+    let operation = &operation.resolve_end(record.sequence.len());
+    check_nested(operation, settings.disallow_nested)?;
+    check_termini(operation, gaps::terminal_ns(&record.sequence), settings.complete_termini)?;
+    let segments = layout::operation_layout(operation, &record.sequence)?;
+    
+    let mut sequence = layout::render_sequence(&record.sequence, &segments, settings.alphabet);
+    if let Operation::Case { start, end, to } = operation {
+        let range = &mut sequence[start - 1..*end];
+        match to {
+            LetterCase::Upper => range.make_ascii_uppercase(),
+            LetterCase::Lower => range.make_ascii_lowercase(),
+        }
+    }
+    if let Operation::Copyback { min_stem, .. } = operation {
+        let stem = stem::terminal_stem(&sequence, settings.alphabet);
+        if stem < *min_stem {
+            return Err(format!("Copyback termini form a perfect stem of only {}{} (--min-stem {})",
+                               stem, settings.alphabet.unit(), min_stem));
+        }
+    }
+    let quality = record.quality.as_ref()
+        .map(|quality| layout::render_quality(quality, &segments, settings.insert_qual));
+    let header = format!("{} [{}]", record.header, describe_operation(operation, &record.sequence, settings.alphabet.unit()));
+    
+    Ok(Record { header, sequence, quality, line_width: record.line_width })
+}
+
+/// Rejects nested duplications under `--disallow-nested`.
+pub fn check_nested(operation: &Operation, disallow_nested: bool) -> Result<(), String> {
+    if disallow_nested && operation.nested_duplication() {
+        return Err("The duplicate's insert position lies inside the duplicated range (a nested duplication), \
+                    which --disallow-nested rejects".to_string());
+    }
+    Ok(())
+}
+
+/// Rejects copybacks, which join the true ends of the molecule, on a record
+/// not known to have them: with `--assume-complete-termini no`, or when
+/// either end is a run of Ns (`terminal_ns` long at the start and end).
+pub fn check_termini(operation: &Operation, terminal_ns: (usize, usize), complete_termini: bool) -> Result<(), String> {
+    if !matches!(operation, Operation::Copyback { .. }) {
+        return Ok(());
+    }
+    if !complete_termini {
+        return Err("A copyback joins the molecule's true ends, which --assume-complete-termini no says the reference lacks"
+            .to_string());
+    }
+    match terminal_ns {
+        (0, 0) => Ok(()),
+        (leading, trailing) => Err(format!(
+            "A copyback joins the molecule's true ends, but the record is padded with {} leading and {} trailing Ns \
+             (--trim-terminal-ns trims them first)", leading, trailing)),
+    }
+}
+
+/// Header annotation recording an applied operation to `sequence`.
+pub fn describe_operation(operation: &Operation, sequence: &str, unit: &str) -> String {
+    match operation {
+        Operation::Custom { name, params } => match registry::lookup(name) {
+            Some(custom) => custom.describe(params, sequence, unit),
+            None => format!("{} {}", name, params),
+        },
+        Operation::Delete { start, end } => {
+            format!("deleted {}{} at positions {}-{}", end - start + 1, unit, start, end)
+        },
+        Operation::Insert { position, sequence } => {
+            format!("inserted {}{} '{}' at position {}", sequence.len(), unit, sequence, position)
+        },
+        Operation::Replace { start, end, sequence } => {
+            format!("replaced {}{} at positions {}-{} with {}{} '{}'",
+                    end - start + 1, unit, start, end, sequence.len(), unit, sequence)
+        },
+        Operation::Invert { start, end, complement } => {
+            let operation_desc = if *complement {
+                "reverse complemented"
+            } else {
+                "inverted"
+            };
+            format!("{} {}{} at positions {}-{}", operation_desc, end - start + 1, unit, start, end)
+        },
+        Operation::Duplicate { start, end, position } => {
+            format!("duplicated {}{} from positions {}-{} to position {}{}", end - start + 1, unit, start, end, position,
+                    if operation.nested_duplication() { " (nested)" } else { "" })
+        },
+        Operation::DuplicateInto { from, start, end, position, .. } => {
+            format!("duplicated {}{} from {}:{}-{} to position {}", end - start + 1, unit, from, start, end, position)
+        },
+        Operation::Keep { start, end } => {
+            format!("kept {}{} at positions {}-{}", end - start + 1, unit, start, end)
+        },
+        Operation::TandemDuplicate { start, end } => {
+            format!("tandem duplicated {}{} at positions {}-{}", end - start + 1, unit, start, end)
+        },
+        Operation::Copyback { breakpoint, backstart, coords_on: CopybackCoords::Reference, .. } => {
+            if backstart == breakpoint {
+                format!("3' copyback (snapback) at position {} of reference plus strand", breakpoint)
+            } else {
+                format!("3' copyback from position {} of reference plus strand then reverse complement of position {} on",
+                        breakpoint, backstart)
+            }
+        },
+        Operation::Copyback { gend, breakpoint, backstart, .. } => {
+            if *gend == 5 {
+                if backstart == breakpoint {
+                    format!("5' copyback (snapback) at position {}", breakpoint)
+                } else {
+                    format!("5' copyback up to position {} then reverse complement of position {} on", breakpoint, backstart)
+                }
+            } else if backstart == breakpoint {
+                format!("3' copyback (snapback) at position {} of reference revcomp", breakpoint)
+            } else {
+                format!("3' copyback up to position {} of reference revcomp then reverse complement of position {} on", breakpoint, backstart)
+            }
+        },
+        Operation::Chromothripsis { start, end, fragments, keep, seed } => {
+            let kept = simulate::shatter(start - 1, *end, *fragments, *keep, *seed).kept.len();
+            format!("chromothripsis of positions {}-{}: {} fragments, {} reassembled (seed {})",
+                    start, end, fragments, kept, seed)
+        },
+        Operation::Bfb { cycles, seed } => {
+            format!("breakage-fusion-bridge, {} cycles (seed {})", cycles, seed)
+        },
+        Operation::Mei { element, position, tsd, truncate, polya, reverse, seed } => {
+            // Only called after a successful layout, so the site is valid
+            let site = layout::mei_position(operation, sequence).unwrap_or(0);
+            let strand = if *reverse { '-' } else { '+' };
+            let truncation = if *truncate > 0 {
+                format!(", 5' truncated by {}{}", truncate, unit)
+            } else {
+                String::new()
+            };
+            let placement = match position {
+                Some(_) => format!("at position {}", site),
+                None => format!("at position {} (seed {})", site, seed),
+            };
+            format!("mobile element insertion of {}{} ({} strand{}) with {}{} poly-A and {}{} TSD {}",
+                    element.len() - truncate, unit, strand, truncation, polya, unit, tsd, unit, placement)
+        },
+        Operation::Integrate { virus, seed, .. } => {
+            let sites = layout::integration_sites(operation, sequence.len()).unwrap_or_default();
+            let described: Vec<String> = sites.iter().map(|site| {
+                format!("{}-{}{} at {}", site.viral_start, site.viral_end,
+                        if site.reverse { "(-)" } else { "(+)" }, site.position)
+            }).collect();
+            format!("integrated {}{} viral genome: {} (seed {})", virus.len(), unit, described.join(", "), seed)
+        },
+        Operation::RepeatFamily { start, end, copies, divergence, seed } => {
+            format!("repeat family of {} copies of positions {}-{} at {} divergence (seed {})",
+                    copies, start, end, divergence, seed)
+        },
+        Operation::Polya { length } => format!("poly-A tail of {}{} added", length, unit),
+        Operation::Telomere { repeat, length, end } => {
+            let ends = match end {
+                TelomereEnd::Five => "5' end",
+                TelomereEnd::Three => "3' end",
+                TelomereEnd::Both => "both ends",
+            };
+            format!("{}{} ({})n telomere added at {}", length, unit, repeat, ends)
+        },
+        Operation::AddLeader { sequence } => {
+            format!("leader of {}{} '{}' added", sequence.len(), unit, sequence)
+        },
+        Operation::Case { start, end, to } => {
+            let case = match to {
+                LetterCase::Upper => "uppercased",
+                LetterCase::Lower => "lowercased",
+            };
+            format!("{} {}{} at positions {}-{}", case, end - start + 1, unit, start, end)
+        },
+    }
+}
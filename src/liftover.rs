@@ -0,0 +1,72 @@
+use crate::layout::Segment;
+
+/// A copy of an original base or interval in the derived sequence: 0-based,
+/// half-open coordinates, and whether it now reads on the reverse strand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapped {
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+}
+
+/// Every place the original bases `start..end` (0-based, half-open) were
+/// copied to in the derived sequence described by `segments`, in derived
+/// order. Empty when they were deleted.
+pub fn map_interval(segments: &[Segment], start: usize, end: usize) -> Vec<Mapped> {
+    let mut mapped = Vec::new();
+    let mut offset = 0;
+    for segment in segments {
+        if let Segment::Source { start: source_start, end: source_end, reverse, .. } = *segment {
+            let overlap_start = start.max(source_start);
+            let overlap_end = end.min(source_end);
+            if overlap_start < overlap_end {
+                // Reversed segments read their source from the end backwards
+                let (from, to) = if reverse {
+                    (source_end - overlap_end, source_end - overlap_start)
+                } else {
+                    (overlap_start - source_start, overlap_end - source_start)
+                };
+                mapped.push(Mapped { start: offset + from, end: offset + to, reverse });
+            }
+        }
+        offset += segment.len();
+    }
+    mapped
+}
+
+/// Where a piece of a derived sequence came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Original bases, 0-based and half-open, read on the reverse strand
+    /// when `reverse` is set
+    Original(Mapped),
+    /// Bases inserted by an edit, at these derived coordinates
+    Novel { start: usize, end: usize },
+}
+
+/// The origin of every piece of the derived bases `start..end` (0-based,
+/// half-open), in derived order.
+pub fn map_back(segments: &[Segment], start: usize, end: usize) -> Vec<Origin> {
+    let mut origins = Vec::new();
+    let mut offset = 0;
+    for segment in segments {
+        let overlap_start = start.max(offset);
+        let overlap_end = end.min(offset + segment.len());
+        if overlap_start < overlap_end {
+            let (from, to) = (overlap_start - offset, overlap_end - offset);
+            origins.push(match *segment {
+                Segment::Source { start: source_start, end: source_end, reverse, .. } => {
+                    let (original_start, original_end) = if reverse {
+                        (source_end - to, source_end - from)
+                    } else {
+                        (source_start + from, source_start + to)
+                    };
+                    Origin::Original(Mapped { start: original_start, end: original_end, reverse })
+                },
+                Segment::Novel(_) => Origin::Novel { start: overlap_start, end: overlap_end },
+            });
+        }
+        offset += segment.len();
+    }
+    origins
+}
@@ -1,75 +1,3654 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write, stdin};
 use std::env;
+use std::io::{BufRead, Write};
 
-#[derive(Debug)]
-enum Operation {
-    Delete { start: usize, end: usize },
-    Insert { position: usize, sequence: String },
-    Invert { start: usize, end: usize, complement: bool },
-    Duplicate { start: usize, end: usize, position: usize },
-    TandemDuplicate { start: usize, end: usize },
-    Copyback { gend: u8, breakpoint: usize, backstart: usize },
+use serde::Serialize;
+
+mod selftest;
+
+use sv_maker::{bed, classify, compare, compress, control, coverage, dedup, diagram, digest, engineered, fai, fasta, filter, gaps, genbank, gfa, gff, haplotype, header, homology, impact, jobs, journal, karyotype, layout, liftover, methylation, orf, plan, recipe, registry, rng, schema, sgrna, signal, simconfig, simulate, spec, staging, stats, stem, stream, summary, telemetry, transcript, truth, units, vcf, warnings, writers};
+use sv_maker::{
+    apply_operation, apply_to_records, check_nested, check_operation, check_termini, describe_operation, duplicate_donor, operation_from_json,
+    operation_name, parse_operation_noted, read_operation_json, record_operation, resolve_duplicate_into, select_targets,
+    total_length, Alphabet, CopybackCoords, EditSettings, Operation, ParseNote, Record, Target, OPEN_END,
+};
+
+/// Line width of `--canonical` FASTA output.
+const CANONICAL_WIDTH: usize = 60;
+
+#[derive(Clone)]
+struct GlobalOptions {
+    output: Option<String>,
+    journal: Option<String>,
+    summary: Option<String>,
+    expect_input_md5: Option<String>,
+    alphabet: Alphabet,
+    insert_qual: char,
+    region: Option<fai::Region>,
+    line_width: Option<usize>,
+    id: Option<String>,
+    keep_description: header::KeepDescription,
+    all: bool,
+    seq: Option<String>,
+    seed: u64,
+    truth_vcf: Option<String>,
+    allele: vcf::Allele,
+    homology: Option<String>,
+    signal_preview: Option<signal::Library>,
+    sq_header: Option<String>,
+    sim_config: Option<simconfig::Simulator>,
+    warnings: Option<String>,
+    fail_on_warn: Option<warnings::Severity>,
+    fuzz_breakpoints: Option<f64>,
+    place_in: Option<String>,
+    place_fraction: f64,
+    fuzz_ci: bool,
+    manifest: Option<String>,
+    karyotype: Option<String>,
+    stem_energy: bool,
+    diagram: Option<String>,
+    gfa: Option<String>,
+    coverage: Option<String>,
+    sequence_vcf: Option<String>,
+    vcf_style: vcf::VcfStyle,
+    methylation: Option<String>,
+    diploid: bool,
+    sex: Option<haplotype::Sex>,
+    par: Option<String>,
+    phased_snps: Option<String>,
+    snp_sample: Option<String>,
+    gff: Option<String>,
+    impact: Option<String>,
+    promoter: usize,
+    matched_control: Option<String>,
+    scramble_control: Option<String>,
+    control_manifest: Option<String>,
+    exclude: Option<String>,
+    canonical: bool,
+    preserve_case: bool,
+    strip_gaps: bool,
+    trim_terminal_ns: bool,
+    complete_termini: bool,
+    gap_map: Option<String>,
+    disallow_nested: bool,
+    rule_of_six: bool,
+    max_identity_change: Option<f64>,
+    max_affected_fraction: Option<f64>,
+    stats: Option<String>,
+    genbank: Option<String>,
+    format: Option<String>,
+    compress: Option<compress::Compression>,
+    segments_bed: Option<String>,
+    max_memory: Option<u64>,
+    gtf: Option<String>,
+    transcript: Option<String>,
+    cdna: bool,
+    op_json: Option<String>,
+    control_fd: Option<u32>,
+}
+
+impl GlobalOptions {
+    /// Whether any output needs the junctions the edits created.
+    fn wants_engineered(&self) -> bool {
+        self.genbank.is_some() || self.segments_bed.is_some() || self.writes_format("genbank")
+    }
+
+    fn wants_junctions(&self) -> bool {
+        self.truth_vcf.is_some() || self.homology.is_some() || self.signal_preview.is_some() || self.writes_format("vcf")
+    }
+
+    /// The output format named by `--format`, or else by the extension of
+    /// `--output`.
+    fn chosen_writer(&self) -> Option<&'static dyn writers::RecordWriter> {
+        match (&self.format, &self.output) {
+            (Some(name), _) => writers::by_name(name).ok(),
+            (None, Some(output)) => writers::by_extension(output),
+            (None, None) => None,
+        }
+    }
+
+    /// The compression chosen by `--compress` or the `--output` extension.
+    fn compression(&self) -> Option<compress::Compression> {
+        self.compress.or_else(|| self.output.as_deref().and_then(compress::by_extension))
+    }
+
+    fn writes_format(&self, name: &str) -> bool {
+        self.chosen_writer().is_some_and(|writer| writer.name() == name)
+    }
+
+    fn molecule(&self) -> &'static str {
+        if self.alphabet == Alphabet::Rna { "RNA" } else { "DNA" }
+    }
+
+    /// Reads the input, first checking that it fits within `--max-memory`.
+    /// The size of stdin is only known once it has been read.
+    fn read_records(&self, input: &str) -> std::io::Result<Vec<Record>> {
+        let bases = stream::input_bases(input, self.region.as_ref())?;
+        if let (Some(budget), Some(bases)) = (self.max_memory, bases) {
+            check_memory(input, stream::in_memory_estimate(bases), budget);
+        }
+        let mut records = match &self.region {
+            Some(region) => vec![fasta::read_region(input, region, self.preserve_case)?],
+            None => fasta::read_records(input, self.preserve_case)?,
+        };
+        if let (Some(budget), None) = (self.max_memory, bases) {
+            check_memory(input, stream::in_memory_estimate(total_length(&records) as u64), budget);
+        }
+        if self.strip_gaps {
+            self.strip_gaps(input, &mut records)?;
+        }
+        if self.trim_terminal_ns {
+            trim_terminal_ns(input, &mut records);
+        }
+        Ok(records)
+    }
+
+    /// Strips alignment gaps from the records read from `input`, so edits
+    /// take ungapped coordinates, and writes the `--gap-map` they came from.
+    fn strip_gaps(&self, input: &str, records: &mut [Record]) -> std::io::Result<()> {
+        let maps: Vec<gaps::GapMap> = records.iter_mut().map(gaps::strip).collect();
+        let gapped = maps.iter().filter(|map| map.gaps() > 0).count();
+        if gapped > 0 {
+            eprintln!("Stripped {} gap characters from {} of {} records of {}",
+                      maps.iter().map(gaps::GapMap::gaps).sum::<usize>(), gapped, maps.len(), input);
+        }
+        if let Some(path) = &self.gap_map {
+            gaps::write_map(path, &maps)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the output-only options: `--line-width` (0 meaning unwrapped)
+    /// over the detected wrapping, `--id`/`--keep-description` to the header,
+    /// and finally `--canonical`.
+    fn finish_output(&self, record: &mut Record) {
+        if let Some(width) = self.line_width {
+            record.line_width = if width == 0 { None } else { Some(width) };
+        }
+        if self.id.is_some() || self.keep_description != header::KeepDescription::Yes {
+            record.header = header::rewrite_header(&record.header, self.id.as_deref(), self.keep_description);
+        }
+        if self.canonical {
+            record.header = header::canonical_header(&record.header);
+            record.sequence.make_ascii_uppercase();
+            record.line_width = Some(CANONICAL_WIDTH);
+        }
+    }
+
+    /// The records an operation that names none applies to: the `--seq`
+    /// record, every record with `--all`, or else the input's only one.
+    fn target(&self) -> Target {
+        match &self.seq {
+            Some(id) => Target::Record(id.clone()),
+            None if self.all => Target::All,
+            None => Target::Only,
+        }
+    }
+
+    fn edit_settings(&self) -> EditSettings {
+        EditSettings {
+            alphabet: self.alphabet,
+            insert_qual: self.insert_qual,
+            disallow_nested: self.disallow_nested,
+            complete_termini: self.complete_termini,
+        }
+    }
+}
+
+/// Trims the Ns padding the ends of the records read from `input`, so edits
+/// take coordinates on the trimmed sequence, and reports what went.
+fn trim_terminal_ns(input: &str, records: &mut [Record]) {
+    for record in records.iter_mut() {
+        let (leading, trailing) = gaps::trim_terminal_ns(record);
+        if leading + trailing > 0 {
+            eprintln!("Trimmed {} leading and {} trailing Ns from {} of {}; positions are on the trimmed sequence",
+                      leading, trailing, record.id(), input);
+        }
+    }
+}
+
+/// Prints how an operation's words were rewritten as they were parsed.
+fn report_notes(notes: &[ParseNote]) {
+    for note in notes {
+        eprintln!("Note: {}", note);
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    staging::enable();
+
+    if args.len() < 3 {
+        print_usage(&args[0]);
+        staging::exit(1);
+    }
+
+    // Pull out global flags (--output, --journal) wherever they appear
+    let (options, remaining_args) = parse_global_options(&args[1..]);
+    let (warnings_path, fail_on_warn) = (options.warnings.clone(), options.fail_on_warn);
+    // Outputs are staged until every one of them has been written
+    let written = run(&args[0], options, remaining_args).and_then(|()| warnings::write(warnings_path.as_deref()));
+    if let Err(error) = written {
+        eprintln!("Error: {}", error);
+        staging::exit(1);
+    }
+    // A failing run publishes only its warnings report
+    if let Err(message) = warnings::check(fail_on_warn) {
+        staging::discard();
+        warnings::write(warnings_path.as_deref()).and_then(|()| staging::commit())?;
+        eprintln!("Error: {}", message);
+        staging::exit(1);
+    }
+    staging::commit()
+}
+
+/// Runs the command left once the global options are parsed.
+fn run(program_name: &str, options: GlobalOptions, remaining_args: Vec<String>) -> std::io::Result<()> {
+    // With --op-json or --control-fd the operations come from JSON and only
+    // the input is given
+    let min_args = if options.op_json.is_some() || options.control_fd.is_some() { 1 } else { 2 };
+    if remaining_args.len() < min_args {
+        print_usage(program_name);
+        staging::exit(1);
+    }
+
+    if remaining_args[0] == "replay" {
+        if remaining_args.len() != 3 {
+            eprintln!("Error: replay requires a journal file and a reference FASTA");
+            print_usage(program_name);
+            staging::exit(1);
+        }
+        return run_replay(&options, &remaining_args[1], &remaining_args[2]);
+    }
+
+    if remaining_args[0] == "map" {
+        return run_map(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "classify" {
+        return run_classify(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "explain" {
+        return run_explain(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "reassort" {
+        return run_reassort(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "filter" {
+        return run_filter(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "dedup" {
+        return run_dedup(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "check-truth" {
+        if remaining_args.len() != 4 {
+            eprintln!("Error: check-truth requires a reference FASTA, a derived FASTA and a truth VCF");
+            staging::exit(1);
+        }
+        return run_check_truth(&options, &remaining_args[1], &remaining_args[2], &remaining_args[3]);
+    }
+    if remaining_args[0] == "orfs" {
+        return run_orfs(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "cmp" {
+        return run_cmp(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "transcripts" {
+        return run_transcripts(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "migrate" {
+        return run_migrate(&options, &remaining_args[1..]);
+    }
+    if remaining_args[0] == "selftest" {
+        return selftest::run(&remaining_args[1..]);
+    }
+
+    let input_file = &remaining_args[0];
+    if options.diploid && remaining_args.get(1).is_none_or(|command| command != "plan") {
+        eprintln!("Error: --diploid applies to plan files, whose lines name the record and genotype of each edit");
+        staging::exit(1);
+    }
+    if options.gtf.is_some() && options.transcript.is_none() {
+        eprintln!("Error: Editing with --gtf requires --transcript to give the transcript the coordinates are on");
+        staging::exit(1);
+    }
+    if options.transcript.is_some() && remaining_args.get(1).is_some_and(|command|
+        registry::SUBCOMMANDS.contains(&command.as_str())) {
+        eprintln!("Error: --transcript applies to a single operation, not to {}", remaining_args[1]);
+        staging::exit(1);
+    }
+    if options.trim_terminal_ns && options.alphabet == Alphabet::Protein {
+        eprintln!("Error: --trim-terminal-ns trims assembly gaps from nucleotide sequences, where N is not a residue");
+        staging::exit(1);
+    }
+    if options.vcf_style != vcf::VcfStyle::Resolved && options.sequence_vcf.is_none() {
+        eprintln!("Error: --vcf-style sets how --vcf writes the changes, so it requires --vcf");
+        staging::exit(1);
+    }
+    if options.gap_map.is_some() && !options.strip_gaps {
+        eprintln!("Error: --gap-map reports the coordinates --strip-gaps changes, so it requires --strip-gaps");
+        staging::exit(1);
+    }
+    if options.sim_config.is_some() && options.output.is_none() {
+        eprintln!("Error: --emit-sim-config writes a script that reads the genomes back, so it requires --output");
+        staging::exit(1);
+    }
+    if options.stats.is_some() && remaining_args.get(1).is_none_or(|command| command != "generate") {
+        eprintln!("Error: --stats summarizes the genomes of a generate run");
+        staging::exit(1);
+    }
+    if let Some(fd) = options.control_fd {
+        if remaining_args.len() > 1 {
+            eprintln!("Error: --control-fd replaces the operation arguments; give only the input file");
+            staging::exit(1);
+        }
+        return run_session(&options, input_file, fd);
+    }
+    let operation = match &options.op_json {
+        Some(_) if remaining_args.len() > 1 => {
+            Err("--op-json replaces the operation arguments; give only the input file".to_string())
+        },
+        Some(_) if input_file == "-" => {
+            Err("--op-json cannot read the operation and the sequence both from stdin".to_string())
+        },
+        Some(json_path) => read_operation_json(json_path, options.alphabet),
+        None => {
+            if remaining_args[1] == "plan" {
+                if remaining_args.len() != 3 {
+                    eprintln!("Error: plan requires a plan file");
+                    print_usage(program_name);
+                    staging::exit(1);
+                }
+                return run_plan(&options, input_file, &remaining_args[2]);
+            }
+            if remaining_args[1] == "generate" {
+                if remaining_args.len() < 3 {
+                    eprintln!("Error: generate requires a spec file");
+                    print_usage(program_name);
+                    staging::exit(1);
+                }
+                return run_generate(&options, input_file, &remaining_args[2..]);
+            }
+            if remaining_args[1] == "recipe" {
+                return run_recipe(&options, input_file, &remaining_args[2..]);
+            }
+            if remaining_args[1] == "extract" {
+                return run_extract(&options, input_file, &remaining_args[2..]);
+            }
+            if remaining_args[1] == "digest" {
+                return run_digest(&options, input_file, &remaining_args[2..]);
+            }
+            if remaining_args[1] == "sgmrna" {
+                return run_sgmrna(&options, input_file, &remaining_args[2..]);
+            }
+            if remaining_args[1] == "quasispecies" {
+                return run_quasispecies(&options, input_file, &remaining_args[2..]);
+            }
+            parse_operation_noted(&remaining_args[1..], options.alphabet, options.seed).map(|(operation, notes)| {
+                report_notes(&notes);
+                operation
+            })
+        },
+    };
+
+    let operation = match operation {
+        Ok(op) => op,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            print_usage(program_name);
+            staging::exit(1);
+        }
+    };
+
+    if options.all && options.id.is_some() {
+        eprintln!("Error: --id cannot be combined with --all, since every record would get the same ID");
+        staging::exit(1);
+    }
+
+    if options.seq.is_some() && options.id.is_some() {
+        eprintln!("Error: --id cannot be combined with --seq, since the records written through would get the same ID");
+        staging::exit(1);
+    }
+
+    if options.place_fraction != 1.0 && options.place_in.is_none() {
+        eprintln!("Error: --fraction requires --place-in");
+        staging::exit(1);
+    }
+
+    if options.place_in.is_some() && options.all {
+        eprintln!("Error: --place-in draws sites for one sequence and cannot be combined with --all");
+        staging::exit(1);
+    }
+
+    if options.canonical && matches!(operation, Operation::Case { .. }) {
+        eprintln!("Error: --canonical uppercases the output, which would undo the case operation");
+        staging::exit(1);
+    }
+
+    if options.rule_of_six && options.all {
+        eprintln!("Error: --rule-of-six adjusts breakpoints for one sequence and cannot be combined with --all");
+        staging::exit(1);
+    }
+
+    if options.fuzz_ci && options.fuzz_breakpoints.is_none() {
+        eprintln!("Error: --fuzz-ci requires --fuzz-breakpoints");
+        staging::exit(1);
+    }
+
+    if options.matched_control.is_some() && control::span(&operation).is_none() {
+        eprintln!("Error: --matched-control needs an operation at a specific locus (not end-anchored or randomly placed)");
+        staging::exit(1);
+    }
+
+    if options.scramble_control.is_some() && !control::scrambles(&operation) {
+        eprintln!("Error: --scramble-control needs an invert or duplicate operation, whose interval it scrambles");
+        staging::exit(1);
+    }
+
+    if let Some(budget) = options.max_memory {
+        let bases = stream::input_bases(input_file, options.region.as_ref())?;
+        if bases.is_some_and(|bases| stream::in_memory_estimate(bases) > budget) {
+            return run_streamed(&options, input_file, &operation, budget);
+        }
+    }
+
+    let transcript = match (&options.gtf, &options.transcript) {
+        (Some(gtf_path), Some(id)) => Some(exit_on_error(transcript::read_transcript(gtf_path, id))),
+        _ => None,
+    };
+
+    let mut clock = telemetry::Clock::start();
+    let mut records = options.read_records(input_file)?;
+    check_input(&options, &records);
+    clock.lap(telemetry::Phase::Read);
+    let mut target = options.target();
+    let mut operation = operation;
+    if let Some(transcript) = &transcript {
+        let record = exit_on_error(records.iter().find(|r| r.id() == transcript.seqid).ok_or_else(|| format!(
+            "Sequence '{}' of transcript {} not found in input", transcript.seqid, transcript.id)));
+        if options.cdna {
+            records = vec![exit_on_error(transcript.cdna(record, options.alphabet, options.insert_qual))];
+        } else {
+            operation = exit_on_error(transcript.project(&operation, options.alphabet));
+            eprintln!("Transcript {} coordinates projected onto {}: {}", transcript.id, transcript.seqid,
+                      serde_json::to_string(&operation).unwrap_or_default());
+            target = Target::Record(transcript.seqid.clone());
+        }
+    }
+    let (operation, target) = exit_on_error(resolve_duplicate_into(operation, target, &records));
+    let input_records = records.clone();
+    let targets = exit_on_error(select_targets(&records, &target));
+    let operation = match &options.place_in {
+        Some(bed_path) => {
+            let repeats = exit_on_error(bed::read_bed(bed_path));
+            exit_on_error(place_in_repeats(&operation, &records[targets[0]], &repeats, options.place_fraction, options.seed))
+        },
+        None => operation,
+    };
+    let intended = options.fuzz_breakpoints.map(|_| operation.clone());
+    let operation = match options.fuzz_breakpoints {
+        Some(sd) => {
+            let realized = exit_on_error(fuzz_breakpoints(&operation, sd, &records, &targets, options.seed));
+            eprintln!("Breakpoints fuzzed (sd {}): intended {} realized {}", sd,
+                      serde_json::to_string(&operation).unwrap_or_default(),
+                      serde_json::to_string(&realized).unwrap_or_default());
+            realized
+        },
+        None => operation,
+    };
+    let operation = if options.rule_of_six {
+        exit_on_error(rule_of_six(&operation, &records[targets[0]]))
+    } else {
+        operation
+    };
+    let mut operation = operation;
+    let mut record_summaries = exit_on_error(
+        apply_to_records(&mut records, &targets, &operation, &options.edit_settings(), options.all));
+    if options.max_affected_fraction.is_some() {
+        let drawn_seed = operation.seed();
+        let mut redraws = 0;
+        loop {
+            let layouts = applied_layouts(&input_records, &targets, &record_summaries, &operation, options.all);
+            let Err(message) = check_affected_fraction(&options, &input_records, &layouts) else {
+                break;
+            };
+            let Some(seed) = drawn_seed.filter(|_| redraws < MAX_PLACEMENT_REDRAWS) else {
+                let tried = if redraws > 0 { format!(" after {} placements", redraws + 1) } else { String::new() };
+                eprintln!("Error: {}{}", message, tried);
+                staging::exit(1);
+            };
+            redraws += 1;
+            operation = operation.with_seed(rng::derive_seed(seed, redraws as u64));
+            records = input_records.clone();
+            record_summaries = exit_on_error(
+                apply_to_records(&mut records, &targets, &operation, &options.edit_settings(), options.all));
+        }
+        if redraws > 0 {
+            eprintln!("Redrew the placement {} time(s) to stay within --max-affected-fraction: {}", redraws,
+                      serde_json::to_string(&operation).unwrap_or_default());
+        }
+    }
+
+    if options.all {
+        for record_summary in &record_summaries {
+            eprintln!("{}", record_summary);
+        }
+        if record_summaries.iter().all(|r| r.skipped.is_some()) {
+            eprintln!("Error: Operation could not be applied to any record");
+            staging::exit(1);
+        }
+    }
+
+    let layouts = applied_layouts(&input_records, &targets, &record_summaries, &operation, options.all);
+    exit_on_error(check_junction_layouts(&options, &layouts));
+    check_identity_change(&options, &input_records, &layouts);
+    check_edits(&options, &input_records, &layouts, &records);
+    clock.lap(telemetry::Phase::Apply);
+
+    // Compare before --id renames anything, so records match by ID
+    if let Some(karyotype_path) = &options.karyotype {
+        karyotype::write_karyotype(karyotype_path, &karyotype::Karyotype::new(&input_records, &records))?;
+    }
+
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    let features = options.wants_engineered().then(|| {
+        let name = operation_name(&operation);
+        layouts.iter().map(|segments| engineered::of_step(segments, &name, None)).collect::<Vec<_>>()
+    });
+    let junctions = options.wants_junctions().then(|| truth_junctions(
+        &input_records, &targets, &record_summaries, &operation, options.all, &options.allele, &layouts));
+    let annotations = read_features(&options);
+    write_formatted(&options, &writers::Output {
+        edit: Some((&input_records, &layouts)),
+        engineered: features.as_deref(),
+        junctions: junctions.as_deref().map(|records| writers::Junctions {
+            records, confidence: breakpoint_confidence(&options), features: &annotations }),
+        ..writers::Output::new(&records, options.molecule())
+    })?;
+    if let Some(features) = &features {
+        write_engineered_outputs(&options, &records, features)?;
+    }
+    if let Some(junctions) = &junctions {
+        write_junction_outputs(&options, junctions)?;
+    }
+
+    if let Some(manifest_path) = &options.manifest {
+        write_manifest(manifest_path, &input_records, &targets, &record_summaries, &operation, options.all)?;
+    }
+
+    if let Some(control_path) = &options.matched_control {
+        write_matched_control(&options, control_path, &input_records, &targets, &record_summaries, &operation)?;
+    }
+
+    if let Some(control_path) = &options.scramble_control {
+        write_scramble_control(&options, control_path, &input_records, &targets, &record_summaries, &operation)?;
+    }
+
+    if let Some(diagram_path) = &options.diagram {
+        let mut molecules = Vec::new();
+        for (&i, record_summary) in targets.iter().zip(&record_summaries) {
+            if record_summary.skipped.is_none() {
+                let record = &input_records[i];
+                let record_op = record_operation(&operation, i, options.all, record.sequence.len());
+                let segments = exit_on_error(layout::operation_layout(&record_op, &record.sequence));
+                molecules.push(diagram::Molecule { id: record.id(), input_length: record.sequence.len(), segments });
+            }
+        }
+        diagram::write_diagram(diagram_path, &molecules)?;
+    }
+
+    if options.gff.is_some() {
+        let features = read_features(&options);
+        let mut impacts = Vec::new();
+        for (&i, record_summary) in targets.iter().zip(&record_summaries) {
+            let record = &input_records[i];
+            if record_summary.skipped.is_none() {
+                let record_op = record_operation(&operation, i, options.all, record.sequence.len());
+                let segments = exit_on_error(layout::operation_layout(&record_op, &record.sequence));
+                let on_record: Vec<&gff::Feature> = gff::reported(&features).into_iter().filter(|f| f.seqid == record.id()).collect();
+                impacts.extend(impact::feature_impacts(&on_record, &layout::identity(record.sequence.len()),
+                                                       &segments, options.promoter, record.sequence.len()));
+            }
+        }
+        let step = impact::StepImpacts { step: 1, operation: operation_name(&operation), impacts };
+        impact::write_impacts(options.impact.as_deref(), &[step])?;
+    }
+
+    write_layout_outputs(&options, &input_records, &layouts)?;
+    write_methylation(&options, &input_records, &layouts, &records)?;
+
+    if let Some(summary_path) = &options.summary {
+        if options.all {
+            let broadcast = summary::BroadcastSummary {
+                schema_version: schema::SCHEMA_VERSION,
+                operation: operation.clone(),
+                intended_operation: intended,
+                input: input_file.clone(),
+                records: record_summaries,
+                telemetry: Some(clock.telemetry(input_file, options.output.as_deref())),
+            };
+            summary::write_summary(summary_path, &broadcast)?;
+        } else {
+            let mut run_summary = summary::RunSummary::new(
+                operation.clone(), input_file, &input_records[targets[0]].sequence, &records[targets[0]].sequence);
+            run_summary.intended_operation = intended;
+            run_summary.telemetry = Some(clock.telemetry(input_file, options.output.as_deref()));
+            if options.stem_energy {
+                run_summary.terminal_stem = Some(stem::StemEnergy::of(&records[targets[0]].sequence, options.alphabet));
+            }
+            summary::write_summary(summary_path, &run_summary)?;
+        }
+    }
+
+    if let Some(journal_path) = &options.journal {
+        let mut entry = journal::JournalEntry::new(
+            operation, input_file, total_length(&input_records), total_length(&records));
+        entry.all_records = options.all;
+        if let Target::Record(id) = &target {
+            entry.record = Some(id.clone());
+        }
+        journal::append_entry(journal_path, &entry)?;
+    }
+    
+    Ok(())
+}
+
+/// Exits if an estimated `needed` bytes for editing `input` in memory is over
+/// the `--max-memory` budget.
+fn check_memory(input: &str, needed: u64, budget: u64) {
+    if needed > budget {
+        eprintln!("Error: Editing {} in memory needs an estimated {} MB, over the --max-memory budget of {} MB \
+                   (only single operations on a .fai-indexed FASTA can be streamed instead)",
+                  input, needed.div_ceil(stream::MEGABYTE), budget / stream::MEGABYTE);
+        staging::exit(1);
+    }
+}
+
+/// Applies a single operation by streaming the input from its `.fai` index a
+/// chunk at a time, for inputs too large to edit in memory within
+/// `--max-memory`. Outputs that need the whole sequence are not available.
+fn run_streamed(options: &GlobalOptions, input_file: &str, operation: &Operation, budget: u64) -> std::io::Result<()> {
+    let cannot_stream = |reason: &str| -> std::io::Result<()> {
+        eprintln!("Error: Editing {} in memory would exceed the --max-memory budget of {} MB, and it cannot be streamed: {}",
+                  input_file, budget / stream::MEGABYTE, reason);
+        staging::exit(1);
+    };
+    let unsupported = [
+        (options.all, "--all"), (options.seq.is_some(), "--seq"), (options.place_in.is_some(), "--place-in"),
+        (options.fuzz_breakpoints.is_some(), "--fuzz-breakpoints"), (options.rule_of_six, "--rule-of-six"),
+        (options.truth_vcf.is_some(), "--truth-vcf"), (options.homology.is_some(), "--homology"),
+        (options.signal_preview.is_some(), "--signal-preview"), (options.sq_header.is_some(), "--sq-header"),
+        (options.manifest.is_some(), "--manifest"), (options.karyotype.is_some(), "--karyotype"),
+        (options.stem_energy, "--stem-energy"), (options.diagram.is_some(), "--diagram"),
+        (options.gfa.is_some(), "--gfa"), (options.coverage.is_some(), "--coverage"),
+        (options.sequence_vcf.is_some(), "--vcf"), (options.methylation.is_some(), "--methylation"),
+        (options.gff.is_some(), "--gff"), (options.matched_control.is_some(), "--matched-control"),
+        (options.scramble_control.is_some(), "--scramble-control"),
+        (options.max_identity_change.is_some(), "--max-identity-change"), (options.genbank.is_some(), "--genbank"),
+        (options.segments_bed.is_some(), "--segments-bed"), (options.transcript.is_some(), "--transcript"),
+        (options.sim_config.is_some(), "--emit-sim-config"), (options.strip_gaps, "--strip-gaps"),
+        (options.trim_terminal_ns, "--trim-terminal-ns"),
+        (options.max_affected_fraction.is_some(), "--max-affected-fraction"),
+        (options.chosen_writer().is_some_and(|writer| writer.name() != "fasta"), "--format"),
+        (options.compression().is_some(), "--compress"),
+    ];
+    if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+        return cannot_stream(&format!("{} needs the sequence in memory", what));
+    }
+    // Operations whose layout and header depend only on the sequence length
+    // and whose bases are not changed after rendering
+    let streamable = match operation {
+        Operation::Copyback { min_stem, .. } => *min_stem == 0,
+        Operation::Mei { position, .. } => position.is_some(),
+        Operation::Delete { .. } | Operation::Keep { .. } | Operation::Insert { .. } | Operation::Replace { .. }
+        | Operation::Invert { .. } | Operation::Duplicate { .. } | Operation::TandemDuplicate { .. }
+        | Operation::Chromothripsis { .. } | Operation::Bfb { .. } | Operation::Polya { .. }
+        | Operation::Telomere { .. } | Operation::AddLeader { .. } => true,
+        _ => false,
+    };
+    if !streamable {
+        return cannot_stream(&format!("{} needs the sequence in memory", operation_name(operation)));
+    }
+    if input_file == "-" {
+        return cannot_stream("stdin cannot be read through an index");
+    }
+
+    let mut clock = telemetry::Clock::start();
+    let source = match &options.region {
+        Some(region) => stream::Source::open(input_file, region),
+        None => stream::Source::only(input_file),
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(msg) => return cannot_stream(&msg),
+    };
+    let input_md5 = exit_on_error(source.check(options.alphabet)?);
+    if let Some(expected) = &options.expect_input_md5 {
+        exit_on_error(summary::expect_md5(&input_md5, expected));
+    }
+    clock.lap(telemetry::Phase::Read);
+
+    let resolved = operation.resolve_end(source.length);
+    exit_on_error(check_nested(&resolved, options.disallow_nested));
+    if let Operation::Copyback { .. } = resolved {
+        exit_on_error(check_termini(&resolved, source.terminal_ns()?, options.complete_termini));
+    }
+    let segments = exit_on_error(layout::sized_layout(&resolved, source.length));
+    let needed = stream::streaming_estimate(&segments);
+    if needed > budget {
+        return cannot_stream(&format!("streaming needs an estimated {} MB", needed.div_ceil(stream::MEGABYTE)));
+    }
+    eprintln!("Note: Editing {} in memory would exceed --max-memory {} MB; streaming it from its .fai index",
+              input_file, budget / stream::MEGABYTE);
+    let header = format!("{} [{}]", source.header, describe_operation(&resolved, "", options.alphabet.unit()));
+    let mut record = Record { header, sequence: String::new(), quality: None, line_width: Some(source.line_bases()) };
+    options.finish_output(&mut record);
+    clock.lap(telemetry::Phase::Apply);
+
+    let (output_length, output_md5) = stream::write_streamed(&source, &segments, &record.header, record.line_width,
+                                                             options.preserve_case, options.alphabet,
+                                                             options.output.as_deref())?;
+    if let Some(summary_path) = &options.summary {
+        let run_summary = summary::RunSummary {
+            schema_version: schema::SCHEMA_VERSION,
+            operation: operation.clone(),
+            intended_operation: None,
+            input: input_file.to_string(),
+            input_length: source.length,
+            input_md5,
+            output_length,
+            output_md5,
+            terminal_stem: None,
+            telemetry: Some(clock.telemetry(input_file, options.output.as_deref())),
+        };
+        summary::write_summary(summary_path, &run_summary)?;
+    }
+    if let Some(journal_path) = &options.journal {
+        journal::append_entry(journal_path, &journal::JournalEntry::new(operation.clone(), input_file, source.length, output_length))?;
+    }
+    Ok(())
+}
+
+/// The layout of every input record after a single operation. Records the
+/// operation was not applied to keep their input layout.
+fn applied_layouts(input_records: &[Record], targets: &[usize], record_summaries: &[summary::RecordSummary],
+                   operation: &Operation, broadcast: bool) -> Vec<Vec<layout::Segment>> {
+    input_records.iter().enumerate().map(|(i, record)| {
+        let applied = targets.iter().zip(record_summaries)
+            .any(|(&target, record_summary)| target == i && record_summary.skipped.is_none());
+        if applied {
+            let record_op = record_operation(operation, i, broadcast, record.sequence.len());
+            exit_on_error(layout::operation_layout(&record_op, &record.sequence))
+        } else {
+            layout::identity(record.sequence.len())
+        }
+    }).collect()
+}
+
+/// Fails the run, before anything is written, if an edit left less of any
+/// record than `--max-identity-change` allows.
+fn check_identity_change(options: &GlobalOptions, input_records: &[Record], layouts: &[Vec<layout::Segment>]) {
+    let Some(max_change) = options.max_identity_change else {
+        return;
+    };
+    for (record, segments) in input_records.iter().zip(layouts) {
+        let change = 1.0 - coverage::retained_fraction(segments, record.sequence.len());
+        if change > max_change {
+            eprintln!("Error: {:.1}% of the {}bp of {} is not in the output, more than --max-identity-change {} allows",
+                      100.0 * change, record.sequence.len(), record.id(), max_change);
+            staging::exit(1);
+        }
+    }
+}
+
+/// Number of extra random placements tried to stay within
+/// `--max-affected-fraction`.
+const MAX_PLACEMENT_REDRAWS: usize = 100;
+
+/// Share of the genome's bases the edits in `layouts` touched, as an error
+/// naming the fraction when it is above `--max-affected-fraction`.
+fn check_affected_fraction(options: &GlobalOptions, input_records: &[Record],
+                           layouts: &[Vec<layout::Segment>]) -> Result<(), String> {
+    let Some(max_fraction) = options.max_affected_fraction else {
+        return Ok(());
+    };
+    let genome: usize = input_records.iter().map(|record| record.sequence.len()).sum();
+    let affected: usize = input_records.iter().zip(layouts)
+        .map(|(record, segments)| coverage::affected_bases(segments, record.sequence.len()))
+        .sum();
+    let fraction = if genome == 0 { 0.0 } else { affected as f64 / genome as f64 };
+    if fraction > max_fraction {
+        return Err(format!("The edit touches {}bp, {:.1}% of the {}bp genome, more than --max-affected-fraction {} allows",
+                           affected, 100.0 * fraction, genome, max_fraction));
+    }
+    Ok(())
+}
+
+/// Raises warnings about the junctions and bases of every edited record.
+fn check_edits(options: &GlobalOptions, input_records: &[Record], layouts: &[Vec<layout::Segment>], records: &[Record]) {
+    for ((input, segments), output) in input_records.iter().zip(layouts).zip(records) {
+        warnings::check_edit(input, segments, output, options.alphabet);
+    }
+}
+
+/// Refuses junction outputs for layouts that read part of the input
+/// reversed without complementing it, which no breakend can describe.
+fn check_junction_layouts(options: &GlobalOptions, layouts: &[Vec<layout::Segment>]) -> Result<(), String> {
+    if !options.wants_junctions() {
+        return Ok(());
+    }
+    match layouts.iter().find_map(|segments| layout::strand_mismatch(segments)) {
+        Some((start, end)) => Err(format!(
+            "Positions {}-{} are reversed without being complemented, which the breakends of --truth-vcf, VCF output, \
+             --homology and --signal-preview cannot describe; use invert --complement", start, end)),
+        None => Ok(()),
+    }
+}
+
+/// Every junction the operation created, on the coordinates of the input
+/// records it was applied to, and of the record a duplicate into another
+/// record copied from.
+fn truth_junctions<'a>(input_records: &'a [Record], targets: &[usize],
+                       record_summaries: &[summary::RecordSummary], operation: &Operation,
+                       broadcast: bool, allele: &vcf::Allele,
+                       layouts: &[Vec<layout::Segment>]) -> Vec<vcf::RecordJunctions<'a>> {
+    let donor = duplicate_donor(operation, input_records, layouts);
+    let mut per_record = Vec::new();
+    for (&i, record_summary) in targets.iter().zip(record_summaries) {
+        let record = &input_records[i];
+        let length = record.sequence.len();
+        let record_op = record_operation(operation, i, broadcast, length);
+        let junctions = if record_summary.skipped.is_none() {
+            layout::operation_layout(&record_op, &record.sequence)
+                .map(|segments| layout::junctions_with_ends(&segments, length))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let lost = match record_op {
+            Operation::Bfb { cycles, seed } if record_summary.skipped.is_none() => simulate::bfb_cycles(length, cycles, seed)
+                .map(|cycles| layout::bfb_lost_junctions(length, &cycles, &junctions))
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let donors = junctions.iter().filter_map(|junction| Some((junction.clone(), donor?))).collect();
+        let junctions = junctions.into_iter().map(|junction| (junction, allele.clone())).collect();
+        per_record.push((i, vcf::RecordJunctions { chrom: record.id(), sequence: &record.sequence, junctions, donors, lost }));
+    }
+    if let Some(donor) = donor {
+        if !per_record.iter().any(|(_, record)| record.chrom == donor.chrom) {
+            let index = input_records.iter().position(|r| r.id() == donor.chrom).unwrap_or(0);
+            let junctions = Vec::new();
+            per_record.push((index, vcf::RecordJunctions { chrom: donor.chrom, sequence: donor.sequence, junctions,
+                                                           donors: Vec::new(), lost: Vec::new() }));
+        }
+    }
+    // Contigs in input order
+    per_record.sort_by_key(|(i, _)| *i);
+    per_record.into_iter().map(|(_, record)| record).collect()
+}
+
+/// Writes the output records, and their `--sq-header` lines when requested.
+fn write_output(options: &GlobalOptions, records: &[Record]) -> std::io::Result<()> {
+    write_formatted(options, &writers::Output::new(records, options.molecule()))
+}
+
+/// Writes the output in the format chosen by `--format`, the `--output`
+/// extension or the input, and its `--sq-header` lines when requested.
+fn write_formatted(options: &GlobalOptions, output: &writers::Output) -> std::io::Result<()> {
+    let records = output.records;
+    let writer = options.chosen_writer().unwrap_or_else(|| writers::default_for(records));
+    exit_on_error(writer.check(output).map_err(|msg| format!("Cannot write {} output: {}", writer.name(), msg)));
+    if options.sim_config.is_some() && !["fasta", "fastq"].contains(&writer.name()) {
+        eprintln!("Error: --emit-sim-config simulates reads from sequence output, not {}", writer.name());
+        staging::exit(1);
+    }
+    if let (Some(_), Some(compression)) = (options.sim_config, options.compression()) {
+        eprintln!("Error: --emit-sim-config simulates reads from uncompressed output, not {}", compression.name());
+        staging::exit(1);
+    }
+    writers::write(writer, output, options.output.as_deref(), options.compression())?;
+    if let Some(sq_path) = &options.sq_header {
+        // Without --output the FASTA goes to stdout and has no location
+        let uri = match &options.output {
+            Some(output) => Some(format!("file:{}", staging::canonical(output)?.display())),
+            None => None,
+        };
+        summary::write_sq_header(sq_path, records, uri.as_deref())?;
+    }
+    write_sim_config(options, records, None)
+}
+
+/// Writes the `--emit-sim-config` script for the genomes just written to
+/// `--output`, next to them.
+fn write_sim_config(options: &GlobalOptions, records: &[Record], pool: Option<simconfig::Pool>) -> std::io::Result<()> {
+    if let (Some(simulator), Some(output)) = (options.sim_config, &options.output) {
+        let script = format!("{}.{}.sh", output, simulator.name());
+        simconfig::write_script(&script, simulator, output, records, pool, options.seed)?;
+        eprintln!("Wrote {} read simulation script {}", simulator.name(), script);
+    }
+    Ok(())
+}
+
+/// The `--fuzz-ci` interval around every breakpoint: 95% of the normally
+/// distributed offsets of `--fuzz-breakpoints`.
+fn breakpoint_confidence(options: &GlobalOptions) -> Option<usize> {
+    options.fuzz_breakpoints.filter(|_| options.fuzz_ci).map(|sd| (1.96 * sd).ceil() as usize)
+}
+
+/// Writes the `--truth-vcf` and `--homology` files and the `--signal-preview`
+/// for the junctions of a run.
+fn write_junction_outputs(options: &GlobalOptions, junctions: &[vcf::RecordJunctions]) -> std::io::Result<()> {
+    let confidence = breakpoint_confidence(options);
+    if let Some(vcf_path) = &options.truth_vcf {
+        vcf::write_breakend_vcf(vcf_path, junctions, confidence, &read_features(options))?;
+    }
+    if let Some(homology_path) = &options.homology {
+        homology::write_homology(homology_path, &homology::homology_tracts(junctions, confidence))?;
+    }
+    if let Some(library) = options.signal_preview {
+        signal::write_preview(&mut std::io::stderr(), junctions, library)?;
+    }
+    Ok(())
+}
+
+/// Writes the `--gfa` graph, `--coverage` profile and `--vcf` variants of
+/// the records' layouts relative to the input.
+fn write_layout_outputs(options: &GlobalOptions, input_records: &[Record],
+                        layouts: &[Vec<layout::Segment>]) -> std::io::Result<()> {
+    if let Some(gfa_path) = &options.gfa {
+        let graph: Vec<gfa::GraphRecord> = input_records.iter().zip(layouts)
+            .map(|(record, segments)| gfa::GraphRecord {
+                id: record.id(),
+                sequence: &record.sequence,
+                segments: segments.clone(),
+            })
+            .collect();
+        gfa::write_gfa(gfa_path, &graph)?;
+    }
+    if let Some(coverage_path) = &options.coverage {
+        let profiles: Vec<(&str, usize, &[layout::Segment])> = input_records.iter().zip(layouts)
+            .map(|(record, segments)| (record.id(), record.sequence.len(), segments.as_slice()))
+            .collect();
+        coverage::write_coverage(coverage_path, &profiles, coverage::edited_fraction(&options.allele))?;
+    }
+    if let Some(vcf_path) = &options.sequence_vcf {
+        let variants: Vec<vcf::RecordVariants> = input_records.iter().zip(layouts)
+            .map(|(record, segments)| vcf::RecordVariants { chrom: record.id(), sequence: &record.sequence, segments })
+            .collect();
+        vcf::write_sequence_vcf(vcf_path, &variants, options.vcf_style, options.alphabet, &options.allele)?;
+    }
+    Ok(())
+}
+
+/// Writes the `--methylation` track: levels drawn for the CpGs of the input,
+/// carried through the layouts onto the output `records`, and drawn afresh
+/// for the CpGs the edits created.
+fn write_methylation(options: &GlobalOptions, input_records: &[Record], layouts: &[Vec<layout::Segment>],
+                     records: &[Record]) -> std::io::Result<()> {
+    let Some(path) = &options.methylation else {
+        return Ok(());
+    };
+    let mut rng = rng::Rng::stream(options.seed, rng::METHYLATION_STREAM);
+    let tracks: Vec<(&str, methylation::Track)> = input_records.iter().zip(layouts).zip(records)
+        .map(|((input, segments), output)| {
+            let mut track = methylation::remap(&methylation::simulate(&input.sequence, &mut rng), segments);
+            methylation::fill_new_sites(&mut track, &output.sequence, &mut rng);
+            (output.id(), track)
+        })
+        .collect();
+    methylation::write_bedmethyl(path, &tracks)
+}
+
+/// Writes the `--genbank` map and `--segments-bed` track of the engineered
+/// segments of each output record.
+fn write_engineered_outputs(options: &GlobalOptions, records: &[Record],
+                            features: &[Vec<engineered::Engineered>]) -> std::io::Result<()> {
+    if let Some(genbank_path) = &options.genbank {
+        let molecule = options.molecule();
+        let constructs: Vec<genbank::Construct> = records.iter().zip(features)
+            .map(|(record, features)| genbank::Construct {
+                id: record.id(),
+                description: header::split_header(&record.header).2,
+                sequence: &record.sequence,
+                molecule,
+                features,
+            })
+            .collect();
+        genbank::write_genbank(genbank_path, &constructs)?;
+    }
+    if let Some(bed_path) = &options.segments_bed {
+        let tracks: Vec<(&str, &[engineered::Engineered])> = records.iter().zip(features)
+            .map(|(record, features)| (record.id(), features.as_slice()))
+            .collect();
+        engineered::write_segments_bed(bed_path, &tracks)?;
+    }
+    Ok(())
+}
+
+/// The `--gff` features, exiting if the file cannot be read.
+fn read_features(options: &GlobalOptions) -> Vec<gff::Feature> {
+    match &options.gff {
+        Some(path) => exit_on_error(gff::read_gff(path)),
+        None => Vec::new(),
+    }
+}
+
+/// Writes the `--manifest` of a stochastic operation: what was drawn for each
+/// edited record, with the seed that reproduces it.
+fn write_manifest(path: &str, input_records: &[Record], targets: &[usize],
+                  record_summaries: &[summary::RecordSummary], operation: &Operation,
+                  broadcast: bool) -> std::io::Result<()> {
+    let edited: Vec<(&Record, Operation)> = targets.iter().zip(record_summaries)
+        .filter(|(_, r)| r.skipped.is_none())
+        .map(|(&i, _)| (&input_records[i], record_operation(operation, i, broadcast, input_records[i].sequence.len())))
+        .collect();
+
+    match operation {
+        Operation::Chromothripsis { start, .. } => {
+            let shatterings: Vec<_> = edited.iter().map(|(record, op)| {
+                let Operation::Chromothripsis { start, end, fragments, keep, seed } = op else { unreachable!() };
+                (record.id(), *seed, simulate::shatter(start - 1, *end, *fragments, *keep, *seed))
+            }).collect();
+            simulate::write_fragment_manifest(path, &shatterings, start - 1)
+        },
+        Operation::Bfb { .. } => {
+            let cycles: Vec<_> = edited.iter().map(|(record, op)| {
+                let Operation::Bfb { cycles, seed } = op else { unreachable!() };
+                let cycles = exit_on_error(simulate::bfb_cycles(record.sequence.len(), *cycles, *seed));
+                let (_, ends) = layout::bfb_layout(record.sequence.len(), &cycles);
+                (record.id(), *seed, cycles, ends)
+            }).collect();
+            simulate::write_bfb_manifest(path, &cycles)
+        },
+        Operation::Integrate { .. } => {
+            let sites: Vec<_> = edited.iter().map(|(record, op)| {
+                let seed = op.seed().unwrap_or_default();
+                (record.id(), seed, exit_on_error(layout::integration_sites(op, record.sequence.len())))
+            }).collect();
+            simulate::write_integration_manifest(path, &sites)
+        },
+        Operation::RepeatFamily { .. } => {
+            let families: Vec<_> = edited.iter().map(|(record, op)| {
+                let Operation::RepeatFamily { start, end, copies, divergence, seed } = op else { unreachable!() };
+                (record.id(), *seed, simulate::repeat_family(&record.sequence, start - 1, *end, *copies, *divergence, *seed))
+            }).collect();
+            simulate::write_repeat_manifest(path, &families)
+        },
+        _ => {
+            eprintln!("Error: --manifest is only written for chromothripsis, bfb, integrate and repeat-family");
+            staging::exit(1);
+        }
+    }
+}
+
+/// `operation` with its randomly drawn sites made explicit: a `fraction` of
+/// them (rounded; for a single site, with that probability) inside the
+/// repeats annotated on `record`, the rest outside any repeat. The explicit
+/// sites are journaled, so replays do not need the BED file.
+fn place_in_repeats(operation: &Operation, record: &Record, repeats: &[bed::BedInterval], fraction: f64,
+                    seed: u64) -> Result<Operation, String> {
+    let mut rng = rng::Rng::stream(seed, rng::PLACE_STREAM);
+    let on_record: Vec<&bed::BedInterval> = repeats.iter().filter(|r| r.chrom == record.id()).collect();
+    let length = record.sequence.len();
+    let inside_count = |count: usize, rng: &mut rng::Rng| {
+        if count == 1 {
+            usize::from(rng.chance(fraction))
+        } else {
+            (fraction * count as f64).round() as usize
+        }
+    };
+
+    let mut placed = operation.clone();
+    let (count, inside) = match &mut placed {
+        Operation::Mei { position: position @ None, tsd, .. } => {
+            let inside = inside_count(1, &mut rng);
+            let drawn = simulate::repeat_placed_positions(length, *tsd + 1, &on_record, 1, inside, &mut rng)?;
+            *position = Some(drawn[0]);
+            (1, inside)
+        },
+        Operation::Integrate { positions, sites, .. } if positions.is_empty() => {
+            let inside = inside_count(*sites, &mut rng);
+            *positions = simulate::repeat_placed_positions(length, 2, &on_record, *sites, inside, &mut rng)?;
+            (*sites, inside)
+        },
+        _ => return Err("--place-in needs randomly placed sites: mei without --position or integrate without --position".to_string()),
+    };
+    eprintln!("Placed {} of {} sites inside annotated repeats", inside, count);
+    Ok(placed)
+}
+
+/// Largest breakpoint shift `--rule-of-six` tries in either direction.
+const RULE_OF_SIX_SHIFT: i64 = 5;
+
+/// Length of the sequence `operation` makes from `record`, if it applies.
+fn edited_length(operation: &Operation, record: &Record) -> Option<usize> {
+    let operation = operation.resolve_end(record.sequence.len());
+    let segments = layout::operation_layout(&operation, &record.sequence).ok()?;
+    Some(segments.iter().map(layout::Segment::len).sum())
+}
+
+/// `operation` with its breakpoint moved as little as possible so the
+/// result is a multiple of six long, as paramyxovirus genomes must be to
+/// replicate efficiently. Copyback breakpoints (both, for a snapback) and
+/// delete and keep ends are moved; other operations are only checked.
+fn rule_of_six(operation: &Operation, record: &Record) -> Result<Operation, String> {
+    let length = edited_length(operation, record);
+    if length.is_none_or(|length| length % 6 == 0) {
+        // Operations that do not apply fail with their own error
+        return Ok(operation.clone());
+    }
+    for shift in (1..=RULE_OF_SIX_SHIFT).flat_map(|distance| [distance, -distance]) {
+        let mut candidate = operation.resolve_end(record.sequence.len());
+        let moved = |position: &mut usize| match usize::try_from(*position as i64 + shift) {
+            Ok(shifted) if shifted > 0 => {
+                *position = shifted;
+                true
+            },
+            _ => false,
+        };
+        let valid = match &mut candidate {
+            Operation::Copyback { breakpoint, backstart, .. } if breakpoint == backstart => {
+                moved(breakpoint) && moved(backstart)
+            },
+            Operation::Copyback { breakpoint, backstart, coords_on, .. } => {
+                moved(breakpoint) && match coords_on {
+                    CopybackCoords::Revcomp => backstart < breakpoint,
+                    CopybackCoords::Reference => backstart > breakpoint,
+                }
+            },
+            Operation::Delete { start, end } | Operation::Keep { start, end } => moved(end) && start <= end,
+            _ => return Err(format!("--rule-of-six: the result would be {}nt, not a multiple of six, and only \
+                                     copyback, delete and keep can be adjusted", length.unwrap_or(0))),
+        };
+        let Some(adjusted) = edited_length(&candidate, record).filter(|length| valid && length % 6 == 0) else {
+            continue;
+        };
+        eprintln!("Note: --rule-of-six adjusted {} to {} for a {}nt genome",
+                  serde_json::to_string(operation).unwrap_or_default(),
+                  serde_json::to_string(&candidate).unwrap_or_default(), adjusted);
+        return Ok(candidate);
+    }
+    Err(format!("--rule-of-six: no breakpoint within {}nt gives a length that is a multiple of six", RULE_OF_SIX_SHIFT))
+}
+
+/// Number of jittered operations tried before giving up on fuzzing.
+const FUZZ_ATTEMPTS: usize = 1000;
+
+/// `operation` with each breakpoint moved by a normally distributed offset
+/// with standard deviation `sd`, redrawn until the result is still a valid
+/// operation that fits every target record the original fits.
+fn fuzz_breakpoints(operation: &Operation, sd: f64, records: &[Record], targets: &[usize],
+                    seed: u64) -> Result<Operation, String> {
+    let mut rng = rng::Rng::stream(seed, rng::FUZZ_STREAM);
+    let fits = |op: &Operation| -> Vec<bool> {
+        targets.iter().map(|&i| layout::operation_layout(op, &records[i].sequence).is_ok()).collect()
+    };
+    let intended_fits = fits(operation);
+
+    for _ in 0..FUZZ_ATTEMPTS {
+        let mut jitter = |position: &mut usize| {
+            // Open range ends stay anchored to the end of the sequence
+            if *position != OPEN_END {
+                let offset = (rng.normal() * sd).round() as i64;
+                *position = (*position as i64 + offset).max(1) as usize;
+            }
+        };
+        let mut candidate = operation.clone();
+        match &mut candidate {
+            Operation::Delete { start, end }
+            | Operation::Replace { start, end, .. }
+            | Operation::Invert { start, end, .. }
+            | Operation::TandemDuplicate { start, end }
+            | Operation::Keep { start, end }
+            | Operation::Case { start, end, .. }
+            | Operation::Chromothripsis { start, end, .. } => {
+                jitter(start);
+                jitter(end);
+            },
+            Operation::Duplicate { start, end, position } => {
+                jitter(start);
+                jitter(end);
+                jitter(position);
+            },
+            Operation::Insert { position, .. } => jitter(position),
+            Operation::Copyback { breakpoint, backstart, .. } => {
+                let snapback = breakpoint == backstart;
+                jitter(breakpoint);
+                if snapback {
+                    *backstart = *breakpoint;
+                } else {
+                    jitter(backstart);
+                }
+            },
+            _ => return Err("--fuzz-breakpoints needs an operation with explicit breakpoints".to_string()),
+        }
+        if let Operation::Copyback { breakpoint, backstart, coords_on, .. } = &candidate {
+            // Plus-strand positions of a 3' copyback count the other way
+            let reference = *coords_on == CopybackCoords::Reference;
+            if (!reference && backstart > breakpoint) || (reference && backstart < breakpoint) {
+                continue;
+            }
+        }
+        let Ok(candidate) = check_operation(candidate, Alphabet::Any) else { continue };
+        let candidate_fits = fits(&candidate);
+        if intended_fits.iter().zip(&candidate_fits).all(|(&before, &after)| !before || after) {
+            return Ok(candidate);
+        }
+    }
+    Err(format!("Could not place fuzzed breakpoints within the sequence after {} attempts", FUZZ_ATTEMPTS))
+}
+
+/// Applies a size-matched copy of `operation` at a random other locus of each
+/// edited record, writing the resulting control genome (and manifest).
+fn write_matched_control(options: &GlobalOptions, path: &str, input_records: &[Record], targets: &[usize],
+                         record_summaries: &[summary::RecordSummary], operation: &Operation) -> std::io::Result<()> {
+    let exclude = match &options.exclude {
+        Some(bed_path) => exit_on_error(bed::read_bed(bed_path)),
+        None => Vec::new(),
+    };
+    // Controls get their own stream so they never change the edit itself
+    let mut rng = rng::Rng::stream(options.seed, rng::CONTROL_STREAM);
+    let settings = options.edit_settings();
+
+    let mut control_records = input_records.to_vec();
+    let mut controls = Vec::new();
+    for (&i, record_summary) in targets.iter().zip(record_summaries) {
+        if record_summary.skipped.is_some() {
+            continue;
+        }
+        let record = &input_records[i];
+        let record_op = record_operation(operation, i, options.all, record.sequence.len());
+        let control = exit_on_error(control::matched_control(
+            &record_op, record.id(), record.sequence.len(), &exclude, &mut rng));
+        control_records[i] = exit_on_error(apply_operation(record, &control.operation, &settings));
+        controls.push((record.id(), control));
+    }
+
+    if let Some(manifest_path) = &options.control_manifest {
+        control::write_control_manifest(manifest_path, &controls)?;
+    }
+    write_control_genome(options, path, control_records)
+}
+
+/// Applies `operation` to each edited record with the interval it inverts or
+/// copies scrambled, writing the resulting control genome.
+fn write_scramble_control(options: &GlobalOptions, path: &str, input_records: &[Record], targets: &[usize],
+                          record_summaries: &[summary::RecordSummary], operation: &Operation) -> std::io::Result<()> {
+    let mut rng = rng::Rng::stream(options.seed, rng::SCRAMBLE_STREAM);
+    let settings = options.edit_settings();
+
+    let mut control_records = input_records.to_vec();
+    for (&i, record_summary) in targets.iter().zip(record_summaries) {
+        if record_summary.skipped.is_some() {
+            continue;
+        }
+        let record = &input_records[i];
+        let record_op = record_operation(operation, i, options.all, record.sequence.len());
+        let control = exit_on_error(control::scramble_control(&record_op, &record.sequence, &mut rng));
+        control_records[i] = exit_on_error(apply_operation(record, &control, &settings));
+    }
+    write_control_genome(options, path, control_records)
+}
+
+/// Writes a control genome to `path` the way the output is written.
+fn write_control_genome(options: &GlobalOptions, path: &str, mut records: Vec<Record>) -> std::io::Result<()> {
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    let writer = writers::default_for(&records);
+    writers::write(writer, &writers::Output::new(&records, options.molecule()), Some(path),
+                   options.compress.or_else(|| compress::by_extension(path)))
+}
+
+/// Validates the input against the checksum and alphabet requested on the
+/// command line, exiting on the first failure. For multi-record input the
+/// checksum covers the concatenated sequences in file order.
+fn check_input(options: &GlobalOptions, records: &[Record]) {
+    if let Some(expected) = &options.expect_input_md5 {
+        exit_on_error(summary::check_input_md5(records, expected));
+    }
+    for record in records {
+        let what = if records.len() == 1 {
+            "Input sequence".to_string()
+        } else {
+            format!("Input sequence '{}'", record.id())
+        };
+        if let Err(msg) = options.alphabet.validate(&what, &record.sequence) {
+            if !options.alphabet.allows('-') && record.sequence.contains(gaps::is_gap) {
+                eprintln!("Error: {}; for aligned input, --strip-gaps removes the gaps before editing", msg);
+            } else {
+                eprintln!("Error: {}", msg);
+            }
+            staging::exit(1);
+        }
+    }
+}
+
+/// Unwraps `result`, printing the error and exiting if there is one.
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            staging::exit(1);
+        }
+    }
+}
+
+/// Applies every operation of a plan file in order. All steps are applied in
+/// memory before anything is written, so a step whose record or coordinates
+/// are invalid (given the edits before it) aborts the run with no output.
+fn run_plan(options: &GlobalOptions, input_file: &str, plan_path: &str) -> std::io::Result<()> {
+    let mut clock = telemetry::Clock::start();
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    clock.lap(telemetry::Phase::Read);
+    let input = plan::PlanInput { records: &records, target: options.target(), diploid: options.diploid };
+    let steps = exit_on_error(plan::read_plan(plan_path, options.alphabet, options.seed, Some(&input)));
+    steps.iter().for_each(|step| report_notes(&step.notes));
+    if options.diploid {
+        return run_diploid(options, records, steps);
+    }
+    check_step_targets(&records, &steps);
+    if steps[0].layer.is_some() {
+        return run_layers(options, input_file, plan_path, records, steps, clock);
+    }
+    run_steps(options, input_file, plan_path, records, steps, None, clock)
+}
+
+/// Checks every step's sequence ID up front, so a typo fails before any
+/// editing.
+fn check_step_targets(records: &[Record], steps: &[plan::PlanStep]) {
+    for step in steps {
+        if let Target::Record(_) = step.target {
+            exit_on_error(select_targets(records, &step.target)
+                .map_err(|msg| format!("Plan line {}: {}", step.line, msg)));
+        }
+    }
+}
+
+/// Applies operations as a driver program sends them, one JSON object per
+/// line on file descriptor `fd`, printing each one's summary to stdout once
+/// it is applied. The genome and other outputs are written when the stream
+/// ends.
+fn run_session(options: &GlobalOptions, input_file: &str, fd: u32) -> std::io::Result<()> {
+    let conflicts = [
+        (options.op_json.is_some(), "--op-json"), (options.transcript.is_some(), "--transcript"),
+        (fd == 0 && input_file == "-", "reading the sequence from stdin"),
+    ];
+    if let Some((_, what)) = conflicts.iter().find(|(set, _)| *set) {
+        eprintln!("Error: --control-fd cannot be combined with {}", what);
+        staging::exit(1);
+    }
+    if options.output.is_none() {
+        eprintln!("Error: --control-fd prints a summary of each operation to stdout, so the genome needs --output");
+        staging::exit(1);
+    }
+    let control: Box<dyn BufRead> = if fd == 0 {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = exit_on_error(std::fs::File::open(format!("/dev/fd/{}", fd))
+            .map_err(|e| format!("Cannot read control file descriptor {}: {}", fd, e)));
+        Box::new(std::io::BufReader::new(file))
+    };
+
+    let mut clock = telemetry::Clock::start();
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    clock.lap(telemetry::Phase::Read);
+    let steps = control.lines().enumerate().filter_map(|(i, line)| {
+        let line = exit_on_error(line.map_err(|e| format!("Cannot read control line {}: {}", i + 1, e)));
+        if line.trim().is_empty() {
+            return None;
+        }
+        match plan::parse_control_line(&line, i + 1, options.alphabet) {
+            Ok(step) => Some(step),
+            Err(msg) => {
+                exit_on_error(skip_step(options, i + 1, &msg).map_err(|e| format!("Cannot report control line {}: {}", i + 1, e)));
+                None
+            },
+        }
+    });
+    run_steps(options, input_file, &format!("control fd {}", fd), records, steps, None, clock)
+}
+
+/// Handles a step that cannot be applied. It ends a plan, but a
+/// `--control-fd` session reports it to the driver as `{"line", "error"}`
+/// and carries on with the genome as it was.
+fn skip_step(options: &GlobalOptions, line: usize, message: &str) -> std::io::Result<()> {
+    if options.control_fd.is_none() {
+        eprintln!("Error: Plan line {}: {}", line, message);
+        staging::exit(1);
+    }
+    let error = serde_json::json!({ "line": line, "error": format!("Control line {}: {}", line, message) });
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", error)?;
+    stdout.flush()
+}
+
+/// Writes the genome of every layer of a layered plan: the input with the
+/// steps of the layer and all the layers it builds on applied. Each layer
+/// gets its own copy of every output file, named after it, and its truth
+/// files list only the junctions the layer itself adds.
+fn run_layers(options: &GlobalOptions, input_file: &str, plan_path: &str, records: Vec<Record>,
+              steps: Vec<plan::PlanStep>, clock: telemetry::Clock) -> std::io::Result<()> {
+    if options.output.is_none() {
+        eprintln!("Error: A layered plan writes one genome per layer and needs --output");
+        staging::exit(1);
+    }
+    let mut layers: Vec<&plan::Layer> = Vec::new();
+    for layer in steps.iter().filter_map(|step| step.layer.as_ref()) {
+        if !layers.contains(&layer) {
+            layers.push(layer);
+        }
+    }
+
+    for layer in &layers {
+        let mut lineage = vec![layer.name.as_str()];
+        let mut parent = layer.parent.as_deref();
+        while let Some(name) = parent {
+            lineage.push(name);
+            parent = layers.iter().find(|l| l.name == name).and_then(|l| l.parent.as_deref());
+        }
+        let layer_steps: Vec<plan::PlanStep> = steps.iter()
+            .filter(|step| step.layer.as_ref().is_some_and(|l| lineage.contains(&l.name.as_str())))
+            .cloned()
+            .collect();
+
+        let mut layer_options = options.clone();
+        for path in [&mut layer_options.output, &mut layer_options.journal, &mut layer_options.summary,
+                     &mut layer_options.truth_vcf, &mut layer_options.homology, &mut layer_options.sq_header,
+                     &mut layer_options.karyotype, &mut layer_options.diagram, &mut layer_options.gfa,
+                     &mut layer_options.coverage, &mut layer_options.sequence_vcf, &mut layer_options.methylation,
+                     &mut layer_options.impact].into_iter().flatten() {
+            *path = suffixed_path(path, &layer.name);
+        }
+        run_steps(&layer_options, input_file, plan_path, records.clone(), layer_steps, Some(&layer.name),
+                  clock.resumed())?;
+    }
+    Ok(())
+}
+
+/// `path` with `.<suffix>` inserted before its extension.
+fn suffixed_path(path: &str, suffix: &str) -> String {
+    let file_name_start = path.rfind('/').map_or(0, |i| i + 1);
+    match path[file_name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_name_start + dot;
+            format!("{}.{}{}", &path[..dot], suffix, &path[dot..])
+        },
+        _ => format!("{}.{}", path, suffix),
+    }
+}
+
+/// Why `generate` left a combination out.
+enum Filtered {
+    /// The edited genome's length is outside the limits.
+    Length(usize),
+    /// The edits touch more than `--max-affected-fraction` of the genome.
+    Affected,
+}
+
+/// Expands a generation spec into every combination of its parameters and
+/// writes one edited genome per combination that applies cleanly and, with
+/// `--min-length`/`--max-length`, whose length is in range (and, with
+/// `--rule-of-six`, a multiple of six; with `--max-affected-fraction`, edits
+/// little enough of the genome). Genomes are built on `--jobs` threads and
+/// written in combination order.
+fn run_generate(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    let spec_path = &args[0];
+    let mut min_length = 0;
+    let mut max_length = usize::MAX;
+    let mut threads = 1;
+    let mut sample = None;
+    let mut hotspots: Vec<String> = Vec::new();
+    let mut strength = None;
+    let mut dilution = None;
+    let mut ratios = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--sample" => {
+                let value = option_value(args, i, "--sample", "a number of genomes");
+                sample = Some(exit_on_error(value.parse::<usize>().ok().filter(|&n| n > 0)
+                    .ok_or_else(|| "--sample must be a positive whole number".to_string())));
+                i += 2;
+            },
+            "--hotspot" => {
+                let motif = option_value(args, i, "--hotspot", "a motif");
+                if motif.is_empty() || !motif.chars().all(|base| base.is_ascii_alphabetic()) {
+                    eprintln!("Error: --hotspot motif '{}' must be a run of bases", motif);
+                    staging::exit(1);
+                }
+                hotspots.push(motif);
+                i += 2;
+            },
+            "--hotspot-strength" => {
+                let value = option_value(args, i, "--hotspot-strength", "a weight");
+                strength = Some(exit_on_error(value.parse::<f64>().ok().filter(|w| w.is_finite() && *w > 0.0)
+                    .ok_or_else(|| "--hotspot-strength must be a positive number".to_string())));
+                i += 2;
+            },
+            "--dilution" => {
+                dilution = Some(option_value(args, i, "--dilution", "a file"));
+                i += 2;
+            },
+            "--ratios" => {
+                let value = option_value(args, i, "--ratios", "a list of ratios");
+                ratios = Some(exit_on_error(spec::parse_ratios(&value).map_err(|msg| format!("--ratios: {}", msg))));
+                i += 2;
+            },
+            "--min-length" | "--max-length" => {
+                let value = option_value(args, i, &args[i], "a length");
+                let length = exit_on_error(units::parse_bases(&value).map_err(|_| format!("{} must be a number", args[i])));
+                if args[i] == "--min-length" { min_length = length } else { max_length = length }
+                i += 2;
+            },
+            "--jobs" => {
+                let value = option_value(args, i, "--jobs", "a number of threads");
+                threads = jobs::threads(exit_on_error(value.parse::<usize>()
+                    .map_err(|_| "--jobs must be a whole number (0 for one thread per CPU)".to_string())));
+                i += 2;
+            },
+            other => {
+                eprintln!("Error: Unknown generate option '{}'", other);
+                staging::exit(1);
+            },
+        }
+    }
+    if min_length > max_length {
+        eprintln!("Error: --min-length {} is greater than --max-length {}", min_length, max_length);
+        staging::exit(1);
+    }
+    if strength.is_some() && hotspots.is_empty() {
+        eprintln!("Error: --hotspot-strength requires --hotspot");
+        staging::exit(1);
+    }
+    if !hotspots.is_empty() && sample.is_none() {
+        eprintln!("Error: --hotspot weights the combinations drawn by --sample, so it requires --sample <n>");
+        staging::exit(1);
+    }
+    if dilution.is_some() != ratios.is_some() {
+        eprintln!("Error: --dilution and --ratios must be given together");
+        staging::exit(1);
+    }
+    if dilution.is_some() && options.sim_config.is_some() && input_file == "-" {
+        eprintln!("Error: --emit-sim-config with --dilution simulates the input genome too, so it cannot be read from stdin");
+        staging::exit(1);
+    }
+    let strength = strength.unwrap_or(10.0);
+    let spec = exit_on_error(spec::read_spec(spec_path));
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    let targets = exit_on_error(select_targets(&records, &Target::Only));
+    let genome = &records[targets[0]];
+    let settings = options.edit_settings();
+
+    let mut outputs = Vec::new();
+    let mut generated = Vec::new();
+    let mut skipped = Vec::new();
+    let mut out_of_range = 0;
+    let mut not_six = 0;
+    let mut too_affected = 0;
+    let in_range = |length: usize| length >= min_length && length <= max_length;
+    // For --stats: the operation of every applied step and the input
+    // positions of every junction
+    let mut step_operations = Vec::new();
+    let mut breakends = Vec::new();
+    // Applies one step, composing its layout onto the genome's when --stats
+    // or --max-affected-fraction needs it
+    let apply_step = |(record, segments): (Record, Option<Vec<layout::Segment>>), step: &plan::PlanStep| {
+        let segments = segments.and_then(|segments| {
+            let operation = step.operation.resolve_end(record.sequence.len());
+            let step_layout = layout::operation_layout(&operation, &record.sequence).ok()?;
+            Some(layout::compose(&segments, &step_layout))
+        });
+        apply_operation(&record, &step.operation, &settings)
+            .map(|record| (record, segments))
+            .map_err(|msg| format!("Plan line {}: {}", step.line, msg))
+    };
+    // Each combination gets its own seed, so stochastic steps differ
+    // between genomes, and the result does not depend on which thread
+    // builds it.
+    let combination_seed = |n: usize| rng::derive_seed(options.seed, n as u64);
+    let plan_steps = |seed: u64, values: &spec::Combination| {
+        spec.template.expand(values, genome.sequence.len())
+            .and_then(|plan_text| plan::parse_plan(&plan_text, spec_path, options.alphabet, seed, None))
+    };
+    let build_seeded = |seed: u64, values: &spec::Combination| {
+        plan_steps(seed, values).and_then(|steps| {
+            let (last, before) = steps.split_last().expect("plans have at least one step");
+            let start = (options.stats.is_some() || options.max_affected_fraction.is_some())
+                .then(|| layout::identity(genome.sequence.len()));
+            let (record, segments) = before.iter().try_fold((genome.clone(), start), apply_step)?;
+            // The final length follows from the last step's layout, so
+            // genomes that would be filtered are never built
+            if let Some(length) = edited_length(&last.operation, &record) {
+                if !in_range(length) || (options.rule_of_six && length % 6 != 0) {
+                    return Ok(Err(Filtered::Length(length)));
+                }
+            }
+            let names: Vec<String> = steps.iter().map(|step| operation_name(&step.operation)).collect();
+            let (record, segments) = apply_step((record, segments), last)?;
+            if let (Some(max_fraction), Some(segments)) = (options.max_affected_fraction, &segments) {
+                let affected = coverage::affected_bases(segments, genome.sequence.len());
+                if affected as f64 > max_fraction * genome.sequence.len() as f64 {
+                    return Ok(Err(Filtered::Affected));
+                }
+            }
+            Ok(Ok((record, segments, names)))
+        })
+    };
+    // Builds the genome of combination n, redrawing the random placements of
+    // one that edits too much of the genome
+    let build = |_, (n, values): &(usize, spec::Combination)| {
+        let seed = combination_seed(*n);
+        let stochastic = plan_steps(seed, values)
+            .is_ok_and(|steps| steps.iter().any(|step| step.operation.seed().is_some()));
+        let mut built = build_seeded(seed, values);
+        for redraw in 1..=MAX_PLACEMENT_REDRAWS {
+            if !stochastic || !matches!(built, Ok(Err(Filtered::Affected))) {
+                break;
+            }
+            built = build_seeded(rng::derive_seed(seed, redraw as u64), values);
+        }
+        built
+    };
+    let mut combinations: Vec<(usize, spec::Combination)> = spec.combinations().into_iter().enumerate().collect();
+    if let Some(count) = sample {
+        // Each junction breakend on a hotspot base multiplies the chance of
+        // drawing its combination by the strength. Breakends are found from
+        // the layout alone; combinations whose layout needs the bases, or
+        // that do not apply, are drawn at the base rate.
+        let mask = spec::hotspot_mask(&genome.sequence, &hotspots);
+        let hits = |(n, values): &(usize, spec::Combination)| {
+            let Ok(steps) = plan_steps(combination_seed(*n), values) else {
+                return 0;
+            };
+            let mut segments = layout::identity(genome.sequence.len());
+            for step in &steps {
+                let length = segments.iter().map(layout::Segment::len).sum();
+                let Ok(step_layout) = layout::sized_layout(&step.operation.resolve_end(length), length) else {
+                    return 0;
+                };
+                segments = layout::compose(&segments, &step_layout);
+            }
+            layout::junctions(&segments).into_iter()
+                .flat_map(|junction| [junction.left, junction.right])
+                .flatten()
+                .filter(|end| mask[end.position - 1])
+                .count()
+        };
+        let hits = jobs::map_ordered(&combinations, threads, |_, combination| hits(combination));
+        let weights: Vec<f64> = hits.iter().map(|&hits| strength.powi(hits as i32)).collect();
+        let drawn = rng::Rng::new(options.seed).weighted_sample(&weights, count);
+        if hotspots.is_empty() {
+            eprintln!("Drew {} of {} combinations", drawn.len(), combinations.len());
+        } else {
+            let on_hotspot = |indices: &mut dyn Iterator<Item = usize>| indices.filter(|&i| hits[i] > 0).count();
+            eprintln!("Drew {} of {} combinations: {} with a breakend on a hotspot, against {} of {} swept",
+                      drawn.len(), combinations.len(), on_hotspot(&mut drawn.iter().copied()),
+                      on_hotspot(&mut (0..hits.len())), hits.len());
+        }
+        combinations = drawn.into_iter().map(|i| combinations[i].clone()).collect();
+    }
+    let built = jobs::map_ordered(&combinations, threads, build);
+    for ((n, values), edited) in combinations.into_iter().zip(built) {
+        let values_text = values.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join(" ");
+        match edited {
+            Ok(Err(Filtered::Length(length))) if in_range(length) => not_six += 1,
+            Ok(Err(Filtered::Length(_))) => out_of_range += 1,
+            Ok(Err(Filtered::Affected)) => too_affected += 1,
+            Ok(Ok((mut record, segments, names))) => {
+                step_operations.extend(names);
+                for junction in segments.as_deref().map(layout::junctions).unwrap_or_default() {
+                    breakends.extend([junction.left, junction.right].into_iter().flatten().map(|end| end.position));
+                }
+                let id = format!("{}_gen{}", genome.id(), n + 1);
+                let (_, _, description) = header::split_header(&record.header);
+                record.header = format!(">{} {}", id, description).trim_end().to_string();
+                generated.push((id, values, record.sequence.len()));
+                outputs.push(record);
+            },
+            Err(msg) => skipped.push(format!("{}: {}", values_text, msg)),
+        }
+    }
+
+    if !skipped.is_empty() {
+        eprintln!("Skipped {} of {} combinations that do not apply, e.g. {}",
+                  skipped.len(), skipped.len() + outputs.len() + out_of_range + not_six + too_affected, skipped[0]);
+    }
+    if out_of_range > 0 {
+        let range = match max_length {
+            usize::MAX => format!("at least {}", min_length),
+            _ if min_length == 0 => format!("at most {}", max_length),
+            _ => format!("{}-{}", min_length, max_length),
+        };
+        eprintln!("Filtered {} combinations whose genome length is not {}", out_of_range, range);
+    }
+    if not_six > 0 {
+        eprintln!("Filtered {} combinations whose genome length is not a multiple of six (--rule-of-six)", not_six);
+    }
+    if too_affected > 0 {
+        eprintln!("Filtered {} combinations that edit more than {} of the genome in every placement tried (--max-affected-fraction)",
+                  too_affected, options.max_affected_fraction.unwrap_or_default());
+    }
+    if outputs.is_empty() {
+        eprintln!("Error: No combination of the spec's parameters could be applied within the limits");
+        staging::exit(1);
+    }
+
+    if let Some(manifest_path) = &options.manifest {
+        spec::write_generation_manifest(manifest_path, &spec.params, &generated)?;
+    }
+    if let (Some(dilution_path), Some(ratios)) = (&dilution, &ratios) {
+        let ids: Vec<String> = generated.iter().map(|(id, _, _)| id.clone()).collect();
+        spec::write_dilution_manifest(dilution_path, genome.id(), &ids, ratios)?;
+    }
+    if let Some(stats_path) = &options.stats {
+        let operations: Vec<&str> = step_operations.iter().map(String::as_str).collect();
+        let lengths: Vec<usize> = outputs.iter().map(|record| record.sequence.len()).collect();
+        let mut stats = stats::BatchStats::new(&operations, &lengths, &breakends, genome.sequence.len());
+        stats.skipped = skipped.len();
+        stats.filtered = out_of_range + not_six + too_affected;
+        stats.combinations = outputs.len() + stats.skipped + stats.filtered;
+        summary::write_summary(stats_path, &stats)?;
+    }
+    for record in outputs.iter_mut() {
+        options.finish_output(record);
+    }
+    // A dilution series simulates the pool rather than the DVGs alone
+    let pool = dilution.as_deref().map(|dilution| simconfig::Pool { input: input_file, dilution });
+    write_output(&GlobalOptions { sim_config: None, ..options.clone() }, &outputs)?;
+    write_sim_config(options, &outputs, pool)?;
+
+    Ok(())
+}
+
+/// Expands a named recipe against the length of the input sequence and runs
+/// the resulting plan.
+fn run_recipe(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    if options.all {
+        eprintln!("Error: recipes are expanded against one sequence's length and cannot be combined with --all");
+        staging::exit(1);
+    }
+    let recipes = exit_on_error(recipe::load_recipes());
+    let Some(name) = args.first() else {
+        eprintln!("Error: recipe requires a recipe name. Available recipes:");
+        for recipe in &recipes {
+            eprintln!("  {} {}", recipe.name, recipe.usage());
+        }
+        staging::exit(1);
+    };
+    let recipe = exit_on_error(recipes.iter().find(|recipe| &recipe.name == name).ok_or_else(|| format!(
+        "Unknown recipe '{}'. Available recipes: {}",
+        name, recipes.iter().map(|recipe| recipe.name.as_str()).collect::<Vec<_>>().join(", "))));
+
+    let mut values = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        let Some(param) = args[i].strip_prefix("--") else {
+            eprintln!("Error: Recipe parameters are given as --<name> <n>, not '{}'", args[i]);
+            staging::exit(1);
+        };
+        let value = option_value(args, i, &args[i], "a number");
+        let value = exit_on_error(units::parse_bases(&value).map_err(|_| format!("{} must be a number", args[i])));
+        values.push((param.to_string(), value));
+        i += 2;
+    }
+
+    let mut clock = telemetry::Clock::start();
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    clock.lap(telemetry::Phase::Read);
+    let targets = exit_on_error(select_targets(&records, &options.target()));
+    let length = records[targets[0]].sequence.len();
+    let plan_text = exit_on_error(recipe.expand(&values, length));
+    let label = format!("recipe {}", recipe.name);
+    let steps = exit_on_error(plan::parse_plan(&plan_text, &label, options.alphabet, options.seed, None));
+    steps.iter().for_each(|step| report_notes(&step.notes));
+    check_step_targets(&records, &steps);
+    run_steps(options, input_file, &label, records, steps, None, clock)
+}
+
+/// Applies a plan to the two haplotypes of a diploid genome and writes them
+/// as `<output>.hap1.<ext>` and `<output>.hap2.<ext>`. Each step goes on the
+/// haplotypes its genotype names, with coordinates on each haplotype as left
+/// by the steps before. With `--sex XY`, chrX is on haplotype 1 and chrY on
+/// haplotype 2, except for the PARs: haplotype 2 keeps a copy of chrX for
+/// the edits within them, which is spliced into its chrY at the end.
+fn run_diploid(options: &GlobalOptions, records: Vec<Record>, steps: Vec<plan::PlanStep>) -> std::io::Result<()> {
+    let Some(output) = &options.output else {
+        eprintln!("Error: --diploid writes one FASTA per haplotype and needs --output");
+        staging::exit(1);
+    };
+    let unsupported = [
+        (options.summary.is_some(), "--summary"), (options.journal.is_some(), "--journal"),
+        (options.karyotype.is_some(), "--karyotype"), (options.diagram.is_some(), "--diagram"),
+        (options.gfa.is_some(), "--gfa"), (options.coverage.is_some(), "--coverage"),
+        (options.sequence_vcf.is_some(), "--vcf"), (options.methylation.is_some(), "--methylation"),
+        (options.gff.is_some(), "--gff"), (steps[0].layer.is_some(), "a layered plan"),
+    ];
+    if let Some((_, what)) = unsupported.iter().find(|(set, _)| *set) {
+        eprintln!("Error: --diploid cannot be combined with {}", what);
+        staging::exit(1);
+    }
+
+    let sex = options.sex.unwrap_or(haplotype::Sex::XX);
+    let x_index = records.iter().position(|record| haplotype::is_x(record.id()));
+    let y_index = records.iter().position(|record| haplotype::is_y(record.id()));
+    let pars = match (&options.par, x_index, y_index) {
+        (Some(path), _, _) => exit_on_error(haplotype::read_pars(path)),
+        (None, Some(x), Some(y)) => {
+            match haplotype::known_pars(records[x].sequence.len(), records[y].sequence.len()) {
+                Some((assembly, pars)) => {
+                    eprintln!("Using the {} PARs of chrX and chrY", assembly);
+                    pars
+                },
+                None => Vec::new(),
+            }
+        },
+        _ => Vec::new(),
+    };
+    if sex == haplotype::Sex::XY && x_index.is_some() && y_index.is_some() && pars.is_empty() {
+        warnings::warn(warnings::Warning {
+            code: "no_par",
+            severity: warnings::Severity::Medium,
+            record: None,
+            position: None,
+            message: "No PARs are known for chrX and chrY of these lengths; give them with --par".to_string(),
+        });
+    }
+    for par in &pars {
+        let fits = |index: Option<usize>, (start, end): (usize, usize)| index.is_some_and(|i| end <= records[i].sequence.len() && start < end);
+        if !fits(x_index, par.x) || !fits(y_index, par.y) {
+            eprintln!("Error: PAR chrX:{}-{} / chrY:{}-{} does not fit the input's chrX and chrY",
+                      par.x.0 + 1, par.x.1, par.y.0 + 1, par.y.1);
+            staging::exit(1);
+        }
+    }
+
+    // The haplotypes carrying each record
+    let carried = |i: usize| -> [bool; 2] {
+        let id = records[i].id();
+        match sex {
+            haplotype::Sex::XY if haplotype::is_x(id) => [true, false],
+            haplotype::Sex::XY if haplotype::is_y(id) => [false, true],
+            haplotype::Sex::XX if haplotype::is_y(id) => [false, false],
+            _ => [true, true],
+        }
+    };
+    let settings = options.edit_settings();
+    let mut haplotypes = [records.clone(), records.clone()];
+    let identity: Vec<Vec<layout::Segment>> = records.iter().map(|r| layout::identity(r.sequence.len())).collect();
+    let mut layouts = [identity.clone(), identity];
+    let mut added: [Vec<Vec<(layout::Junction, vcf::Allele)>>; 2] = [vec![Vec::new(); records.len()], vec![Vec::new(); records.len()]];
+    let mut edits: [Vec<Vec<usize>>; 2] = [vec![Vec::new(); records.len()], vec![Vec::new(); records.len()]];
+
+    // Germline variants go on first; they keep every length, so plan
+    // coordinates still refer to the input
+    if let Some(vcf_path) = &options.phased_snps {
+        let (snps, skipped) = exit_on_error(haplotype::read_phased_snps(vcf_path, options.snp_sample.as_deref()));
+        let mut placed = [0, 0];
+        let mut elsewhere = 0;
+        for snp in &snps {
+            let Some(i) = records.iter().position(|record| record.id() == snp.chrom) else {
+                elsewhere += 1;
+                continue;
+            };
+            let (start, end) = (snp.position, snp.position + snp.reference.len());
+            let input_bases = records[i].sequence.get(start..end);
+            if input_bases.is_none_or(|bases| !bases.eq_ignore_ascii_case(&snp.reference)) {
+                eprintln!("Error: {} has REF {} at {}:{}, but the input has {}", vcf_path, snp.reference, snp.chrom,
+                          start + 1, input_bases.unwrap_or("no base there"));
+                staging::exit(1);
+            }
+            // Haploid calls apply to every copy of the record; diploid ones
+            // follow their phase, including the chrX copy whose PARs chrY takes
+            let on: Vec<(usize, &Option<String>)> = match snp.alleles.as_slice() {
+                [allele] => (0..2).filter(|&h| carried(i)[h]).map(|h| (h, allele)).collect(),
+                alleles => alleles.iter().take(2).enumerate().collect(),
+            };
+            for (h, allele) in on {
+                if let Some(bases) = allele {
+                    haplotypes[h][i].sequence.replace_range(start..end, bases);
+                    placed[h] += 1;
+                }
+            }
+        }
+        eprintln!("Placed phased variants from {}: {} on haplotype 1, {} on haplotype 2", vcf_path, placed[0], placed[1]);
+        if skipped.unphased + skipped.indels + elsewhere > 0 {
+            eprintln!("Skipped {} unphased heterozygous calls, {} indels and {} variants on sequences not in the input",
+                      skipped.unphased, skipped.indels, elsewhere);
+        }
+    }
+
+    for step in &steps {
+        let allele = step.allele.or(&options.allele);
+        let target = match &step.target {
+            Target::Only => options.target(),
+            target => target.clone(),
+        };
+        let broadcast = target == Target::All;
+        let targets = exit_on_error(select_targets(&records, &target)
+            .map_err(|msg| format!("Plan line {}: {}", step.line, msg)));
+        for &i in &targets {
+            let id = records[i].id();
+            let context = |msg: String| format!("Plan line {}: {} (sequence '{}')", step.line, msg, id);
+            let mut copies = carried(i);
+            if copies == [false, false] {
+                if broadcast {
+                    continue;
+                }
+                exit_on_error::<()>(Err(context("chrY is absent from an XX genome".to_string())));
+            }
+            // Edits within the PARs of an XY chrX have a second copy, on chrY
+            let x_of_xy = sex == haplotype::Sex::XY && haplotype::is_x(id);
+            let record_op = record_operation(&step.operation, i, broadcast, haplotypes[0][i].sequence.len());
+            if x_of_xy && composed_layout(&haplotypes[0][i], &layouts[0][i], &record_op)
+                .is_some_and(|(_, new)| haplotype::within_pars(&new, &pars)) {
+                copies = [true, true];
+            }
+            let placed = exit_on_error(haplotype::placement(allele.genotype.as_deref(), copies).map_err(context));
+
+            for h in (0..2).filter(|&h| placed[h]) {
+                let record_op = record_operation(&step.operation, i, broadcast, haplotypes[h][i].sequence.len());
+                let edited = match apply_operation(&haplotypes[h][i], &record_op, &settings) {
+                    Ok(edited) => edited,
+                    Err(_) if broadcast => continue,
+                    Err(msg) => exit_on_error(Err(context(format!("{} on haplotype {}", msg, h + 1)))),
+                };
+                if let Some((composed, new)) = composed_layout(&haplotypes[h][i], &layouts[h][i], &record_op) {
+                    if h == 1 && x_of_xy && !haplotype::within_pars(&new, &pars) {
+                        exit_on_error::<()>(Err(context("edits chrX outside the PARs, which haplotype 2 of an XY genome has no copy of".to_string())));
+                    }
+                    let y_of_xy = sex == haplotype::Sex::XY && haplotype::is_y(id);
+                    if y_of_xy && pars.iter().any(|par| par_span(&composed, par.y).is_none()) {
+                        exit_on_error::<()>(Err(context("changes a PAR of chrY; edit the PARs through chrX".to_string())));
+                    }
+                    added[h][i].extend(new.into_iter().map(|junction| (junction, allele.clone())));
+                    layouts[h][i] = composed;
+                }
+                haplotypes[h][i] = edited;
+                edits[h][i].push(step.line);
+            }
+        }
+    }
+
+    // Haplotype 2's chrY takes the PARs of its copy of chrX
+    let mut spliced_pars = Vec::new();
+    if let (haplotype::Sex::XY, Some(x), Some(y)) = (sex, x_index, y_index) {
+        let mut spans: Vec<((usize, usize), (usize, usize))> = pars.iter().map(|par| {
+            let x_span = par_flanked_span(&layouts[1][x], par.x, records[x].sequence.len(), haplotypes[1][x].sequence.len());
+            (x_span, par_span(&layouts[1][y], par.y).unwrap_or(par.y))
+        }).collect();
+        for par in &pars {
+            spliced_pars.push(format!("{}:{}-{}>{}:{}-{}", records[x].id(), par.x.0 + 1, par.x.1,
+                                      records[y].id(), par.y.0 + 1, par.y.1));
+        }
+        // Last first, so the earlier spans stay in place
+        spans.sort_by_key(|&(_, y_span)| std::cmp::Reverse(y_span.0));
+        let x_copy = haplotypes[1][x].clone();
+        let chr_y = &mut haplotypes[1][y];
+        for ((x_start, x_end), (y_start, y_end)) in spans {
+            chr_y.sequence.replace_range(y_start..y_end, &x_copy.sequence[x_start..x_end]);
+            if let (Some(quality), Some(x_quality)) = (chr_y.quality.as_mut(), &x_copy.quality) {
+                quality.replace_range(y_start..y_end, &x_quality[x_start..x_end]);
+            }
+        }
+    }
+
+    for (h, records_h) in haplotypes.iter().enumerate() {
+        let mut outputs: Vec<Record> = records_h.iter().enumerate()
+            .filter(|&(i, _)| carried(i)[h])
+            .map(|(_, record)| record.clone())
+            .collect();
+        for record in outputs.iter_mut() {
+            options.finish_output(record);
+        }
+        let mut haplotype_options = options.clone();
+        let suffix = format!("hap{}", h + 1);
+        haplotype_options.output = Some(suffixed_path(output, &suffix));
+        haplotype_options.sq_header = options.sq_header.as_deref().map(|path| suffixed_path(path, &suffix));
+        write_output(&haplotype_options, &outputs)?;
+    }
+
+    for layouts_h in &layouts {
+        exit_on_error(check_junction_layouts(options, layouts_h));
+    }
+    if options.wants_junctions() {
+        // One phased call per junction, hemizygous where the region has one copy
+        let per_record: Vec<_> = records.iter().enumerate().filter(|&(i, _)| carried(i) != [false, false])
+            .map(|(i, record)| {
+                let mut calls: Vec<(layout::Junction, vcf::Allele)> = Vec::new();
+                for h in 0..2 {
+                    for junction in layout::junctions(&layouts[h][i]) {
+                        let Some((_, allele)) = added[h][i].iter().find(|(created, _)| *created == junction) else {
+                            continue;
+                        };
+                        if calls.iter().any(|(call, _)| *call == junction) {
+                            continue;
+                        }
+                        let on = |h: usize| layout::junctions(&layouts[h][i]).contains(&junction)
+                            && added[h][i].iter().any(|(created, _)| *created == junction);
+                        let diploid = carried(i) == [true, true]
+                            || (sex == haplotype::Sex::XY && haplotype::is_x(record.id())
+                                && haplotype::within_pars(std::slice::from_ref(&junction), &pars));
+                        let genotype = match (diploid, on(0), on(1)) {
+                            (false, _, _) => "1",
+                            (true, true, true) => "1|1",
+                            (true, true, false) => "1|0",
+                            (true, _, _) => "0|1",
+                        };
+                        let allele = vcf::Allele { genotype: Some(genotype.to_string()), ..allele.clone() };
+                        calls.push((junction, allele));
+                    }
+                }
+                vcf::RecordJunctions { chrom: record.id(), sequence: &record.sequence, junctions: calls, donors: Vec::new(), lost: Vec::new() }
+            })
+            .collect();
+        write_junction_outputs(options, &per_record)?;
+    }
+
+    if let Some(manifest_path) = &options.manifest {
+        let mut copies = Vec::new();
+        for (i, record) in records.iter().enumerate() {
+            for (h, haplotype_edits) in edits.iter().enumerate() {
+                let spliced = h == 1 && sex == haplotype::Sex::XY && haplotype::is_y(record.id());
+                copies.push(haplotype::RecordCopy {
+                    record: record.id().to_string(),
+                    haplotype: h + 1,
+                    present: carried(i)[h],
+                    pars: if spliced { spliced_pars.clone() } else { Vec::new() },
+                    edits: haplotype_edits[i].clone(),
+                });
+            }
+        }
+        haplotype::write_ploidy_manifest(manifest_path, sex, &copies)?;
+    }
+    Ok(())
+}
+
+/// The layout of `record` relative to the input after `operation`, given its
+/// current one, and the junctions the operation adds.
+fn composed_layout(record: &Record, current: &[layout::Segment], operation: &Operation)
+                   -> Option<(Vec<layout::Segment>, Vec<layout::Junction>)> {
+    let composed = layout::compose(current, &layout::operation_layout(operation, &record.sequence).ok()?);
+    let before = layout::junctions(current);
+    let new = layout::junctions(&composed).into_iter().filter(|junction| !before.contains(junction)).collect();
+    Some((composed, new))
+}
+
+/// Where the input bases `span` (0-based, half-open) are in a layout, if
+/// they were kept exactly once, forward and whole.
+fn par_span(segments: &[layout::Segment], span: (usize, usize)) -> Option<(usize, usize)> {
+    match liftover::map_interval(segments, span.0, span.1)[..] {
+        [mapped] if !mapped.reverse && mapped.end - mapped.start == span.1 - span.0 => Some((mapped.start, mapped.end)),
+        _ => None,
+    }
+}
+
+/// Where the edited version of the input bases `span` is in a layout that
+/// kept the bases either side of it in place: between the last base before
+/// it and the first base after it.
+fn par_flanked_span(segments: &[layout::Segment], span: (usize, usize), input_length: usize,
+                    length: usize) -> (usize, usize) {
+    let start = if span.0 == 0 { 0 } else { par_span(segments, (span.0 - 1, span.0)).map_or(0, |(_, end)| end) };
+    let end = if span.1 == input_length { length } else { par_span(segments, (span.1, span.1 + 1)).map_or(length, |(start, _)| start) };
+    (start, end)
+}
+
+/// Applies the steps of a plan in order, then writes the output and any
+/// requested summary, karyotype, diagram and journal. With a `layer`, the
+/// truth files only cover the junctions that layer's steps created.
+fn run_steps(options: &GlobalOptions, input_file: &str, plan_path: &str, mut records: Vec<Record>,
+             steps: impl IntoIterator<Item = plan::PlanStep>, layer: Option<&str>, mut clock: telemetry::Clock)
+             -> std::io::Result<()> {
+    if options.fuzz_breakpoints.is_some() {
+        eprintln!("Error: --fuzz-breakpoints applies to single operations, not plans");
+        staging::exit(1);
+    }
+    let input_records = records.clone();
+    let settings = options.edit_settings();
+
+    let mut step_summaries = Vec::new();
+    let mut journal_entries = Vec::new();
+    // Every record's layout relative to the input, for warnings, --diagram,
+    // --gfa and --truth-vcf, and the junctions each step added with its allele
+    let mut layouts: Vec<Vec<layout::Segment>> = records.iter().map(|r| layout::identity(r.sequence.len())).collect();
+    let mut step_junctions: Vec<Vec<(layout::Junction, vcf::Allele)>> = vec![Vec::new(); records.len()];
+    let mut step_donors: Vec<Vec<(layout::Junction, vcf::Donor)>> = vec![Vec::new(); records.len()];
+    // Engineered segments of every record, on its current sequence, for
+    // --genbank and --segments-bed
+    let mut engineered: Vec<Vec<engineered::Engineered>> = vec![Vec::new(); records.len()];
+    let features = read_features(options);
+    let mut step_impacts = Vec::new();
+    for step in steps {
+        if options.canonical && matches!(step.operation, Operation::Case { .. }) {
+            skip_step(options, step.line, "--canonical uppercases the output, which would undo the case operation")?;
+            continue;
+        }
+        let allele = step.allele.or(&options.allele);
+        let target = match step.target {
+            Target::Only => options.target(),
+            target => target,
+        };
+        let resolved = resolve_duplicate_into(step.operation, target, &records)
+            .and_then(|(operation, target)| Ok((select_targets(&records, &target)?, operation, target)));
+        let (targets, operation, target) = match resolved {
+            Ok(resolved) => resolved,
+            Err(msg) => {
+                skip_step(options, step.line, &msg)?;
+                continue;
+            },
+        };
+        let input_length = total_length(&records);
+        let donor = duplicate_donor(&operation, &input_records, &layouts);
+        // Laid out on the records as they were before the step
+        let step_layouts: Vec<_> = targets.iter().map(|&i| {
+            let record_op = record_operation(&operation, i, target == Target::All, records[i].sequence.len());
+            layout::operation_layout(&record_op, &records[i].sequence).ok()
+        }).collect();
+        let laid_out: Vec<_> = step_layouts.iter().flatten().cloned().collect();
+        if let Err(msg) = check_junction_layouts(options, &laid_out) {
+            skip_step(options, step.line, &msg)?;
+            continue;
+        }
+        let record_summaries = match apply_to_records(&mut records, &targets, &operation, &settings, target == Target::All) {
+            Ok(record_summaries) => record_summaries,
+            Err(msg) => {
+                skip_step(options, step.line, &msg)?;
+                continue;
+            },
+        };
+        let mut impacts = Vec::new();
+        for (&i, step_layout) in targets.iter().zip(step_layouts) {
+            if let Some(step_layout) = step_layout {
+                let before = layout::junctions_with_ends(&layouts[i], input_records[i].sequence.len());
+                let composed = layout::compose(&layouts[i], &step_layout);
+                if options.gff.is_some() {
+                    let record = &input_records[i];
+                    let on_record: Vec<&gff::Feature> = gff::reported(&features).into_iter().filter(|f| f.seqid == record.id()).collect();
+                    impacts.extend(impact::feature_impacts(&on_record, &layouts[i], &composed,
+                                                           options.promoter, record.sequence.len()));
+                }
+                layouts[i] = composed;
+                if options.wants_engineered() {
+                    let mut carried = engineered::carry(&engineered[i], &step_layout);
+                    carried.extend(engineered::of_step(&step_layout, &operation_name(&operation), Some(step.line)));
+                    carried.sort_by_key(|feature| (feature.start, feature.end));
+                    engineered[i] = carried;
+                }
+                let reported = layer.is_none_or(|name| step.layer.as_ref().is_some_and(|l| l.name == name));
+                for junction in layout::junctions_with_ends(&layouts[i], input_records[i].sequence.len()) {
+                    if reported && !before.contains(&junction) {
+                        if let Some(donor) = donor {
+                            step_donors[i].push((junction.clone(), donor));
+                        }
+                        step_junctions[i].push((junction, allele.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut entry = journal::JournalEntry::new(
+            operation.clone(), input_file, input_length, total_length(&records));
+        entry.all_records = target == Target::All;
+        if let Target::Record(id) = &target {
+            entry.record = Some(id.clone());
+        }
+        journal_entries.push(entry);
+        if options.gff.is_some() {
+            step_impacts.push(impact::StepImpacts { step: step.line, operation: operation_name(&operation), impacts });
+        }
+        let step_summary = summary::PlanStepSummary {
+            line: step.line,
+            operation,
+            allele,
+            records: record_summaries,
+        };
+        if options.control_fd.is_some() {
+            // The driver of a session reads each step's result as it is applied
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", serde_json::to_string(&step_summary)?)?;
+            stdout.flush()?;
+        }
+        step_summaries.push(step_summary);
+    }
+    if options.rule_of_six {
+        // Plans are not adjusted: a step's breakpoints may depend on earlier ones
+        if let Some(record) = records.iter().find(|record| record.sequence.len() % 6 != 0) {
+            eprintln!("Error: --rule-of-six: the plan makes {} {}nt long, not a multiple of six",
+                      record.id(), record.sequence.len());
+            staging::exit(1);
+        }
+    }
+    check_identity_change(options, &input_records, &layouts);
+    // A plan's steps are placed as written, so it is checked rather than redrawn
+    exit_on_error(check_affected_fraction(options, &input_records, &layouts));
+    check_edits(options, &input_records, &layouts, &records);
+    clock.lap(telemetry::Phase::Apply);
+
+    if let Some(karyotype_path) = &options.karyotype {
+        karyotype::write_karyotype(karyotype_path, &karyotype::Karyotype::new(&input_records, &records))?;
+    }
+    // Junctions of the final genome, each with the allele of the step that created it
+    let per_record = options.wants_junctions().then(|| {
+        input_records.iter().zip(&layouts).zip(&step_junctions).zip(&step_donors)
+            .map(|(((record, segments), added), donors)| {
+                let junctions = layout::junctions_with_ends(segments, record.sequence.len()).into_iter().filter_map(|junction| {
+                    let allele = match added.iter().find(|(created, _)| *created == junction) {
+                        Some((_, allele)) => allele.clone(),
+                        // Junctions of the layers a layer builds on are in their own truth files
+                        None if layer.is_some() => return None,
+                        None => options.allele.clone(),
+                    };
+                    Some((junction, allele))
+                }).collect();
+                vcf::RecordJunctions { chrom: record.id(), sequence: &record.sequence, junctions, donors: donors.clone(), lost: Vec::new() }
+            })
+            .collect::<Vec<_>>()
+    });
+    if let Some(per_record) = &per_record {
+        write_junction_outputs(options, per_record)?;
+    }
+    if options.gff.is_some() {
+        impact::write_impacts(options.impact.as_deref(), &step_impacts)?;
+    }
+    write_layout_outputs(options, &input_records, &layouts)?;
+    if let Some(diagram_path) = &options.diagram {
+        let molecules: Vec<diagram::Molecule> = input_records.iter().zip(&layouts)
+            .map(|(record, segments)| diagram::Molecule {
+                id: record.id(),
+                input_length: record.sequence.len(),
+                segments: segments.clone(),
+            })
+            .collect();
+        diagram::write_diagram(diagram_path, &molecules)?;
+    }
+
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    let annotations = read_features(options);
+    write_formatted(options, &writers::Output {
+        edit: Some((&input_records, &layouts)),
+        engineered: Some(&engineered),
+        junctions: per_record.as_deref().map(|records| writers::Junctions {
+            records, confidence: breakpoint_confidence(options), features: &annotations }),
+        ..writers::Output::new(&records, options.molecule())
+    })?;
+    write_methylation(options, &input_records, &layouts, &records)?;
+    write_engineered_outputs(options, &records, &engineered)?;
+
+    if let Some(summary_path) = &options.summary {
+        let plan_summary = summary::PlanSummary {
+            schema_version: schema::SCHEMA_VERSION,
+            input: input_file.to_string(),
+            plan: plan_path.to_string(),
+            steps: step_summaries,
+            telemetry: Some(clock.telemetry(input_file, options.output.as_deref())),
+        };
+        summary::write_summary(summary_path, &plan_summary)?;
+    }
+
+    if let Some(journal_path) = &options.journal {
+        for entry in &journal_entries {
+            journal::append_entry(journal_path, entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-applies every operation recorded in a journal to the original reference,
+/// checking each intermediate length against the one recorded at the time.
+fn run_replay(options: &GlobalOptions, journal_path: &str, reference: &str) -> std::io::Result<()> {
+    let entries = journal::read_journal(journal_path)?;
+    if entries.is_empty() {
+        eprintln!("Error: Journal {} contains no operations", journal_path);
+        staging::exit(1);
+    }
+
+    let mut records = options.read_records(reference)?;
+    check_input(options, &records);
+    let settings = options.edit_settings();
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        if total_length(&records) != entry.input_length {
+            eprintln!("Error: Journal entry {} expects an input of {}bp but the replayed sequence is {}bp",
+                      i + 1, entry.input_length, total_length(&records));
+            staging::exit(1);
+        }
+        let targets = exit_on_error(select_targets(&records, &entry.target()));
+        exit_on_error(apply_to_records(&mut records, &targets, &entry.operation, &settings, entry.all_records));
+        if total_length(&records) != entry.output_length {
+            eprintln!("Error: Journal entry {} recorded an output of {}bp but replay produced {}bp",
+                      i + 1, entry.output_length, total_length(&records));
+            staging::exit(1);
+        }
+    }
+
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    write_output(options, &records)?;
+
+    Ok(())
+}
+
+/// Translates original-genome positions or BED intervals to the genome
+/// derived from it by the operations of a journal, or with `--inverse`
+/// translates derived-genome ones back to the original.
+fn run_map(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut query = None;
+    let mut journal_path = None;
+    let mut reference = None;
+    let mut inverse = false;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--edits" => {
+                journal_path = Some(option_value(args, i, "--edits", "a journal file"));
+                i += 2;
+            },
+            "--reference" => {
+                reference = Some(option_value(args, i, "--reference", "the original FASTA"));
+                i += 2;
+            },
+            "--inverse" => {
+                inverse = true;
+                i += 1;
+            },
+            other if query.is_none() => {
+                query = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected map argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let (Some(query), Some(journal_path)) = (query, journal_path) else {
+        eprintln!("Error: map requires a position or BED file and --edits <journal>");
+        staging::exit(1);
+    };
+
+    let entries = journal::read_journal(&journal_path)?;
+    if entries.is_empty() {
+        eprintln!("Error: Journal {} contains no operations", journal_path);
+        staging::exit(1);
+    }
+    let (records, placeholder) = journal_reference(options, &entries, reference.as_deref())?;
+    let layouts = exit_on_error(journal_layouts(options, &entries, &records));
+    let strand = |reverse: bool| if reverse { '-' } else { '+' };
+    // Queries are on the original genome, or on the derived one with --inverse
+    let query_length = |index: usize| if inverse {
+        layouts[index].iter().map(layout::Segment::len).sum()
+    } else {
+        records[index].sequence.len()
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    // A BED file, or a single position written as <pos> or <chrom>:<pos>
+    if std::path::Path::new(&query).is_file() {
+        let intervals = exit_on_error(bed::read_bed(&query));
+        let mut unmapped = 0;
+        for interval in &intervals {
+            let index = exit_on_error(record_index(&records, &interval.chrom, placeholder));
+            let id = records[index].id();
+            let name = interval.name.as_deref().unwrap_or(".");
+            if inverse {
+                let origins = liftover::map_back(&layouts[index], interval.start, interval.end);
+                if origins.iter().all(|origin| matches!(origin, liftover::Origin::Novel { .. })) {
+                    unmapped += 1;
+                }
+                for origin in origins {
+                    match origin {
+                        liftover::Origin::Original(piece) => writeln!(out, "{}\t{}\t{}\t{}\t0\t{}", id, piece.start,
+                            piece.end, name, strand(piece.reverse != interval.reverse))?,
+                        // Inserted material has no original coordinates
+                        liftover::Origin::Novel { start, end } => {
+                            writeln!(out, "#novel\t{}\t{}\t{}\t{}", id, start, end, name)?
+                        },
+                    }
+                }
+            } else {
+                let mapped = liftover::map_interval(&layouts[index], interval.start, interval.end);
+                if mapped.is_empty() {
+                    unmapped += 1;
+                }
+                for piece in mapped {
+                    writeln!(out, "{}\t{}\t{}\t{}\t0\t{}", id, piece.start, piece.end, name,
+                             strand(piece.reverse != interval.reverse))?;
+                }
+            }
+        }
+        if unmapped > 0 {
+            let fate = if inverse { "entirely novel" } else { "deleted entirely" };
+            eprintln!("{} of {} intervals were {}", unmapped, intervals.len(), fate);
+        }
+    } else {
+        let (chrom, position) = match query.rsplit_once(':') {
+            Some((chrom, position)) => (Some(chrom), position),
+            None => (None, query.as_str()),
+        };
+        let position = exit_on_error(units::parse_bases(position)
+            .ok().filter(|&p| p > 0).ok_or_else(|| format!("'{}' is not a 1-based position or a BED file", query)));
+        let index = match chrom {
+            Some(chrom) => exit_on_error(record_index(&records, chrom, placeholder)),
+            None => exit_on_error(select_targets(&records, &Target::Only))[0],
+        };
+        if position > query_length(index) {
+            eprintln!("Error: Position {} is beyond sequence length {}", position, query_length(index));
+            staging::exit(1);
+        }
+        let id = records[index].id();
+        if inverse {
+            for origin in liftover::map_back(&layouts[index], position - 1, position) {
+                match origin {
+                    liftover::Origin::Original(piece) => {
+                        writeln!(out, "{}:{}\t{}:{}({})", id, position, id, piece.end, strand(piece.reverse))?
+                    },
+                    liftover::Origin::Novel { .. } => writeln!(out, "{}:{}\tnovel", id, position)?,
+                }
+            }
+        } else {
+            let mapped = liftover::map_interval(&layouts[index], position - 1, position);
+            if mapped.is_empty() {
+                writeln!(out, "{}:{}\tdeleted", id, position)?;
+            }
+            for piece in mapped {
+                writeln!(out, "{}:{}\t{}:{}({})", id, position, id, piece.end, strand(piece.reverse))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The original genome a journal was recorded against: `reference` if
+/// given, else the input of its first entry when that file still exists.
+/// Without either, single-sequence journals use a placeholder of the
+/// recorded length, which is enough unless a site was drawn from sequence;
+/// the flag returned says whether that placeholder is in use.
+fn journal_reference(options: &GlobalOptions, entries: &[journal::JournalEntry],
+                     reference: Option<&str>) -> std::io::Result<(Vec<Record>, bool)> {
+    let first = &entries[0];
+    let path = reference.or_else(|| {
+        Some(first.input.as_str()).filter(|input| *input != "-" && std::path::Path::new(input).is_file())
+    });
+    if let Some(path) = path {
+        let records = options.read_records(path)?;
+        if total_length(&records) != first.input_length {
+            eprintln!("Error: {} is {}bp but the journal was recorded against {}bp",
+                      path, total_length(&records), first.input_length);
+            staging::exit(1);
+        }
+        return Ok((records, false));
+    }
+    if first.all_records || first.record.is_some() {
+        eprintln!("Error: The journal edits a multi-record genome; give the original with --reference");
+        staging::exit(1);
+    }
+    if entries.iter().any(|entry| matches!(entry.operation, Operation::Mei { position: None, .. })) {
+        eprintln!("Error: The journal has mobile element insertions placed by sequence; give the original with --reference");
+        staging::exit(1);
+    }
+    let placeholder = Record {
+        header: ">sequence".to_string(),
+        sequence: "N".repeat(first.input_length),
+        quality: None,
+        line_width: None,
+    };
+    Ok((vec![placeholder], true))
+}
+
+/// Re-applies a journal to `records`, returning each record's final layout
+/// relative to the original.
+fn journal_layouts(options: &GlobalOptions, entries: &[journal::JournalEntry],
+                   records: &[Record]) -> Result<Vec<Vec<layout::Segment>>, String> {
+    let mut records = records.to_vec();
+    let mut layouts: Vec<Vec<layout::Segment>> = records.iter().map(|r| layout::identity(r.sequence.len())).collect();
+    let settings = EditSettings { alphabet: Alphabet::Any, ..options.edit_settings() };
+    for (n, entry) in entries.iter().enumerate() {
+        let targets = select_targets(&records, &entry.target())?;
+        for &i in &targets {
+            let record_op = record_operation(&entry.operation, i, entry.all_records, records[i].sequence.len());
+            match apply_operation(&records[i], &record_op, &settings) {
+                Ok(edited) => {
+                    let step_layout = layout::operation_layout(&record_op, &records[i].sequence)?;
+                    layouts[i] = layout::compose(&layouts[i], &step_layout);
+                    records[i] = edited;
+                },
+                // Broadcast operations leave records they do not fit unchanged
+                Err(_) if entry.all_records => {},
+                Err(msg) => return Err(format!("Journal entry {}: {}", n + 1, msg)),
+            }
+        }
+    }
+    Ok(layouts)
+}
+
+/// Index of the record called `id`. A placeholder stands in for whatever
+/// the single sequence was called.
+fn record_index(records: &[Record], id: &str, placeholder: bool) -> Result<usize, String> {
+    if placeholder {
+        return Ok(0);
+    }
+    select_targets(records, &Target::Record(id.to_string())).map(|targets| targets[0])
+}
+
+/// Describes what an operation would do to a sequence of a given length,
+/// without reading any input. Sequence-dependent choices (such as
+/// motif-biased insertion sites) are made on a placeholder sequence of Ns.
+/// Longest sequence `explain` builds a placeholder for, for the operations
+/// that read bases to be laid out or described.
+const MAX_EXPLAIN_PLACEHOLDER: usize = 100_000_000;
+
+fn run_explain(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    // The sequence length is the last --length, so operations with their
+    // own --length (polya, telomere) can still be explained
+    let length_flag = args.iter().rposition(|a| a == "--length");
+    let (length, operation_args) = match length_flag {
+        Some(i) if i + 1 < args.len() => {
+            let length: usize = exit_on_error(units::parse_bases(&args[i + 1]).map_err(|_| "--length must be a number".to_string()));
+            let mut rest = args.to_vec();
+            rest.drain(i..i + 2);
+            (length, rest)
+        },
+        _ => {
+            eprintln!("Error: explain requires --length <L>, the length of the sequence to edit");
+            staging::exit(1);
+        }
+    };
+    if operation_args.is_empty() {
+        eprintln!("Error: explain requires an operation");
+        staging::exit(1);
+    }
+
+    let (operation, notes) = exit_on_error(parse_operation_noted(&operation_args, options.alphabet, options.seed));
+    report_notes(&notes);
+    let mut operation = operation.resolve_end(length);
+    // --length is the receiving record's; the copied bases stand in as Ns
+    if let Operation::DuplicateInto { start, end, sequence, .. } = &mut operation {
+        if *end == OPEN_END {
+            eprintln!("Error: explain cannot resolve 'end' on the record the copy is read from; give its last base");
+            staging::exit(1);
+        }
+        *sequence = "N".repeat(*end - *start + 1);
+    }
+    // Most operations are laid out and described from the length alone;
+    // the rest read bases, which stand in as Ns up to a size worth allocating
+    let sized = layout::sized_layout(&operation, length);
+    let reads_bases = sized.is_err()
+        || matches!(operation, Operation::Custom { .. } | Operation::Mei { position: None, .. } | Operation::Integrate { .. });
+    if reads_bases && length > MAX_EXPLAIN_PLACEHOLDER {
+        eprintln!("Error: explain stands Ns in for the bases {} reads, so --length can be at most {} for it",
+                  operation_name(&operation), MAX_EXPLAIN_PLACEHOLDER);
+        staging::exit(1);
+    }
+    let placeholder = if reads_bases { "N".repeat(length) } else { String::new() };
+    let segments = exit_on_error(if reads_bases { layout::operation_layout(&operation, &placeholder) } else { sized });
+    let unit = options.alphabet.unit();
+    let output_length: usize = segments.iter().map(layout::Segment::len).sum();
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{}", explain_operation(&operation, length, unit))?;
+    writeln!(out)?;
+    writeln!(out, "Header annotation: [{}]", describe_operation(&operation, &placeholder, unit))?;
+    writeln!(out, "Output length: {}{} ({:+}{})", output_length, unit, output_length as i64 - length as i64, unit)?;
+    writeln!(out)?;
+    let molecule = diagram::Molecule { id: "input", input_length: length, segments };
+    diagram::write_text(&mut out, &[molecule])?;
+    Ok(())
+}
+
+/// Plain-English explanation of an operation on a sequence of `length`.
+fn explain_operation(operation: &Operation, length: usize, unit: &str) -> String {
+    match operation {
+        Operation::Copyback { gend: 5, breakpoint, backstart, .. } => format!(
+            "5' copyback: keeps positions 1-{bp} of the 5' end, then continues by copying back along the \
+             template, appending the reverse complement of positions {bs} down to 1. The new 3' end is \
+             complementary to the first {bs}{u} of the 5' end, so the termini can pair into a panhandle \
+             with a {bs}{u} stem. Positions {next}-{len} are lost. In leader terms: --keep-leader {bp} \
+             --reanneal {bs}.",
+            bp = breakpoint, bs = backstart, u = unit, next = breakpoint + 1, len = length),
+        Operation::Copyback { breakpoint, backstart, coords_on: CopybackCoords::Reference, .. } => format!(
+            "3' copyback on plus-strand coordinates: keeps input positions {bp}-{len} (the {kept}{u} at the \
+             3' end), reverse complemented, then appends positions {bs}-{len} in their original orientation. \
+             The termini can pair into a panhandle with a {stem}{u} stem. On the reverse complemented genome \
+             this is breakpoint {kept} and backstart {stem}; in trailer terms, --keep-trailer {kept} \
+             --reanneal {stem}.",
+            bp = breakpoint, bs = backstart, u = unit, len = length,
+            kept = (length + 1).saturating_sub(*breakpoint), stem = (length + 1).saturating_sub(*backstart)),
+        Operation::Copyback { breakpoint, backstart, .. } => format!(
+            "3' copyback: works on the reverse complement of the input, so positions count inwards from \
+             the 3' end (position 1 is input position {len}). Keeps the {bp}{u} at the 3' end (input \
+             positions {from}-{len}, reverse complemented), then appends the {bs}{u} closest to the 3' \
+             end in their original orientation. The termini can pair into a panhandle with a {bs}{u} stem. \
+             In plus-strand positions: --coords-on reference 3 {from} {plus_bs}; in trailer terms: \
+             --keep-trailer {bp} --reanneal {bs}.",
+            plus_bs = length.saturating_sub(*backstart) + 1,
+            bp = breakpoint, bs = backstart, u = unit, len = length, from = length.saturating_sub(*breakpoint) + 1),
+        Operation::Delete { start, end } => format!(
+            "Deletes positions {}-{} ({}{}); the bases either side are joined.", start, end, end - start + 1, unit),
+        Operation::Insert { position, sequence } => format!(
+            "Inserts {}{} of new sequence before position {}.", sequence.len(), unit, position),
+        Operation::Replace { start, end, sequence } => format!(
+            "Replaces positions {}-{} with {}{} of new sequence.", start, end, sequence.len(), unit),
+        Operation::Invert { start, end, complement } => format!(
+            "{} positions {}-{} in place, so the segment reads backwards{}.",
+            if *complement { "Reverse complements" } else { "Reverses" }, start, end,
+            if *complement { " on the other strand" } else { " without complementing" }),
+        Operation::Duplicate { start, end, position } if operation.nested_duplication() => format!(
+            "Copies positions {}-{} and inserts the copy before position {}, inside the original, which is split \
+             around it: {}-{}, the whole copy, then {}-{} (a nested duplication).",
+            start, end, position, start, position - 1, position, end),
+        Operation::Duplicate { start, end, position } => format!(
+            "Copies positions {}-{} and inserts the copy before position {}; the original stays in place.",
+            start, end, position),
+        Operation::DuplicateInto { from, start, end, to, position, .. } => format!(
+            "Copies positions {}-{} of {} and inserts the copy before position {} of {}, an interchromosomal \
+             duplication; {} is unchanged.",
+            start, end, if from.is_empty() { "the targeted record" } else { from }, position, to,
+            if from.is_empty() { "that record" } else { from }),
+        Operation::Keep { start, end } => format!(
+            "Keeps only positions {}-{} ({}{}); everything either side is discarded.", start, end, end - start + 1, unit),
+        Operation::TandemDuplicate { start, end } => format!(
+            "Duplicates positions {}-{} in tandem: the copy follows the original directly.", start, end),
+        _ => format!("{}.", capitalize(&describe_operation(operation, &"N".repeat(length), unit))),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Builds a reassortant of a segmented virus: segment `i` of the output is
+/// segment `i` of one of the parent genomes, by `--assign` or drawn from the
+/// seed. Segments are matched by their order in the parent files.
+/// Classifies each molecule of a FASTA as a copyback, snapback, deletion DVG
+/// or full-length genome, writing the classifications as JSON.
+/// Writes the records of a library that match every given criterion: ID and
+/// header regexes, a length range and conditions on manifest fields.
+/// Records are written as they are, provenance annotations included.
+fn run_filter(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut library = None;
+    let mut id_regex = None;
+    let mut header_regex = None;
+    let mut min_length = 0;
+    let mut max_length = usize::MAX;
+    let mut fields = None;
+    let mut conditions = Vec::new();
+    let regex = |flag: &str, pattern: String| {
+        exit_on_error(regex::Regex::new(&pattern).map_err(|e| format!("{} is not a valid regex: {}", flag, e)))
+    };
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--id-regex" => {
+                id_regex = Some(regex("--id-regex", option_value(args, i, "--id-regex", "a regex")));
+                i += 2;
+            },
+            "--header-regex" => {
+                header_regex = Some(regex("--header-regex", option_value(args, i, "--header-regex", "a regex")));
+                i += 2;
+            },
+            "--min-length" | "--max-length" => {
+                let value = option_value(args, i, &args[i], "a length");
+                let length = exit_on_error(units::parse_bases(&value).map_err(|_| format!("{} must be a number", args[i])));
+                if args[i] == "--min-length" { min_length = length } else { max_length = length }
+                i += 2;
+            },
+            "--fields" => {
+                let path = option_value(args, i, "--fields", "a manifest TSV");
+                fields = Some(exit_on_error(filter::Manifest::read(&path)));
+                i += 2;
+            },
+            "--where" => {
+                let condition = option_value(args, i, "--where", "a condition such as gend=5");
+                conditions.push(exit_on_error(filter::Condition::parse(&condition)));
+                i += 2;
+            },
+            other if library.is_none() => {
+                library = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected filter argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let Some(library) = library else {
+        eprintln!("Error: filter requires a FASTA library");
+        staging::exit(1);
+    };
+    if !conditions.is_empty() && fields.is_none() {
+        eprintln!("Error: --where needs --fields <manifest.tsv> to look the fields up in");
+        staging::exit(1);
+    }
+    if let Some(field) = fields.as_ref().and_then(|manifest| manifest.unknown_field(&conditions)) {
+        eprintln!("Error: The manifest has no '{}' column", field);
+        staging::exit(1);
+    }
+
+    let records = options.read_records(&library)?;
+    let total = records.len();
+    let kept: Vec<Record> = records.into_iter().filter(|record| {
+        let length = record.sequence.len();
+        id_regex.as_ref().is_none_or(|regex| regex.is_match(record.id()))
+            && header_regex.as_ref().is_none_or(|regex| regex.is_match(&record.header[1..]))
+            && length >= min_length && length <= max_length
+            && fields.as_ref().is_none_or(|manifest| manifest.matches(record.id(), &conditions))
+    }).collect();
+    eprintln!("Kept {} of {} records", kept.len(), total);
+    write_output(options, &kept)
+}
+
+/// Writes a library without duplicate records, keeping the first of each
+/// set: exact copies (ignoring case) and, with `--near`, records whose
+/// minimizer sketches are at least that similar. `--manifest` lists which
+/// record each removed one was collapsed into.
+fn run_dedup(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut library = None;
+    let mut near = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--near" => {
+                let value = option_value(args, i, "--near", "a similarity");
+                near = match value.parse::<f64>() {
+                    Ok(similarity) if similarity > 0.0 && similarity <= 1.0 => Some(similarity),
+                    _ => {
+                        eprintln!("Error: --near must be a similarity above 0 and at most 1");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            other if library.is_none() => {
+                library = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected dedup argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let Some(library) = library else {
+        eprintln!("Error: dedup requires a FASTA library");
+        staging::exit(1);
+    };
+
+    let records = options.read_records(&library)?;
+    let total = records.len();
+    let mut kept: Vec<Record> = Vec::new();
+    let mut by_sequence: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut sketches: Vec<Vec<u64>> = Vec::new();
+    let mut collapsed = Vec::new();
+    for record in records {
+        let sequence = record.sequence.to_ascii_uppercase();
+        if let Some(original) = by_sequence.get(&sequence) {
+            collapsed.push(dedup::Collapsed { id: record.id().to_string(), kept: original.clone(), similarity: None });
+            continue;
+        }
+        let sketch = if near.is_some() { dedup::sketch(&sequence) } else { Vec::new() };
+        if let Some(threshold) = near.filter(|_| !sketch.is_empty()) {
+            // Sequences shorter than a k-mer have no sketch and only match exactly
+            let best = kept.iter().zip(&sketches)
+                .filter(|(_, other)| !other.is_empty())
+                .map(|(original, other)| (original, dedup::jaccard(&sketch, other)))
+                .filter(|&(_, similarity)| similarity >= threshold)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((original, similarity)) = best {
+                collapsed.push(dedup::Collapsed {
+                    id: record.id().to_string(),
+                    kept: original.id().to_string(),
+                    similarity: Some(similarity),
+                });
+                continue;
+            }
+        }
+        by_sequence.insert(sequence, record.id().to_string());
+        sketches.push(sketch);
+        kept.push(record);
+    }
+
+    let near_count = collapsed.iter().filter(|record| record.similarity.is_some()).count();
+    eprintln!("Removed {} of {} records as duplicates ({} exact, {} near-identical)",
+              collapsed.len(), total, collapsed.len() - near_count, near_count);
+    if let Some(manifest_path) = &options.manifest {
+        dedup::write_collapsed(manifest_path, &collapsed)?;
+    }
+    write_output(options, &kept)
+}
+
+/// Checks that the truth VCF, applied to the reference, spells the derived
+/// genome byte for byte: each derived record must be its reference record
+/// read through every junction the VCF gives for it exactly once. Records
+/// pair up by ID, or as the only record of each file.
+fn run_check_truth(options: &GlobalOptions, reference_path: &str, derived_path: &str,
+                   vcf_path: &str) -> std::io::Result<()> {
+    let references = fasta::read_records(reference_path, options.preserve_case)?;
+    let derived = fasta::read_records(derived_path, options.preserve_case)?;
+    let mut truth = exit_on_error(truth::TruthJunctions::read(vcf_path, options.alphabet));
+    exit_on_error(truth.join_copies(|chrom| {
+        references.iter().find(|record| record.id() == chrom).map(|record| record.sequence.as_str())
+    }));
+    if let Some(chrom) = truth.chroms().find(|chrom| !references.iter().any(|record| record.id() == *chrom)) {
+        eprintln!("Error: The truth VCF has junctions on {}, which is not in {}", chrom, reference_path);
+        staging::exit(1);
+    }
+
+    let mut failures = 0;
+    for reference in &references {
+        let chrom = reference.id();
+        let junctions = truth.count(chrom);
+        let found = derived.iter().find(|record| record.id() == chrom)
+            .or(if references.len() == 1 && derived.len() == 1 { derived.first() } else { None });
+        let Some(record) = found else {
+            println!("{}	missing	no derived record", chrom);
+            failures += 1;
+            continue;
+        };
+        match truth.reproduces(chrom, &reference.sequence, &record.sequence, options.alphabet) {
+            Ok(()) => println!("{}	ok	{} junctions reproduce {}bp", chrom, junctions, record.sequence.len()),
+            Err(matched) => {
+                println!("{}	mismatch	no walk through the {} junctions spells the {}bp derived sequence \
+                          (the best matches its first {}bp)", chrom, junctions, record.sequence.len(), matched);
+                failures += 1;
+            },
+        }
+    }
+    for record in derived.iter().filter(|record| !references.iter().any(|r| r.id() == record.id())) {
+        if references.len() > 1 || derived.len() > 1 {
+            println!("{}	unexpected	not in {}", record.id(), reference_path);
+            failures += 1;
+        }
+    }
+    if failures > 0 {
+        eprintln!("Error: {} records of the derived genome are not reproduced by {}", failures, vcf_path);
+        staging::exit(1);
+    }
+    Ok(())
+}
+
+/// Exits with status 0 if two FASTA files hold the same sequences, in the
+/// same order, and 1 otherwise, ignoring headers and wrapping. Bases are
+/// compared after the usual uppercasing unless `--preserve-case` is given.
+fn run_cmp(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut paths = Vec::new();
+    let mut first_diff = false;
+    for arg in args {
+        match arg.as_str() {
+            "--first-diff" => first_diff = true,
+            other if other.starts_with("--") => {
+                eprintln!("Error: Unknown cmp option '{}'", other);
+                staging::exit(1);
+            },
+            path => paths.push(path),
+        }
+    }
+    let [a_path, b_path] = paths[..] else {
+        eprintln!("Error: cmp requires two FASTA files");
+        staging::exit(1);
+    };
+    if a_path == "-" && b_path == "-" {
+        eprintln!("Error: cmp can read only one of its files from stdin");
+        staging::exit(1);
+    }
+    let a = fasta::read_records(a_path, options.preserve_case)?;
+    let b = fasta::read_records(b_path, options.preserve_case)?;
+    if let Some(difference) = compare::first_difference(&a, &b) {
+        if first_diff {
+            println!("{} {} differ: {}", a_path, b_path, difference);
+        }
+        staging::exit(1);
+    }
+    Ok(())
+}
+
+fn run_classify(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut genome = None;
+    let mut reference = None;
+    let mut min_stem = classify::DEFAULT_MIN_STEM;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--reference" => {
+                reference = Some(option_value(args, i, "--reference", "the parental genome FASTA"));
+                i += 2;
+            },
+            "--min-stem" => {
+                let value = option_value(args, i, "--min-stem", "a length");
+                min_stem = exit_on_error(units::parse_bases(&value).map_err(|_| "--min-stem must be a number".to_string()));
+                i += 2;
+            },
+            other if genome.is_none() => {
+                genome = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected classify argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let Some(genome) = genome else {
+        eprintln!("Error: classify requires a genome FASTA");
+        staging::exit(1);
+    };
+
+    let records = fasta::read_records(&genome, false)?;
+    let references = match &reference {
+        Some(path) => fasta::read_records(path, false)?,
+        None => Vec::new(),
+    };
+    // Each molecule is compared with the reference record of the same ID,
+    // or with the only one
+    let classifications: Vec<classify::Classification> = records.iter().map(|record| {
+        let parent = references.iter().find(|parent| parent.id() == record.id())
+            .or(if references.len() == 1 { references.first() } else { None });
+        if reference.is_some() && parent.is_none() {
+            warnings::warn(warnings::Warning {
+                code: "no_reference_record",
+                severity: warnings::Severity::Low,
+                record: Some(record.id().to_string()),
+                position: None,
+                message: format!("No reference record for {}; classifying it without one", record.id()),
+            });
+        }
+        classify::classify(record.id(), &record.sequence, parent.map(|p| p.sequence.as_str()),
+                           options.alphabet, min_stem)
+    }).collect();
+
+    let json = serde_json::to_string_pretty(&classifications)?;
+    match &options.output {
+        Some(path) => staging::create(path)?.write_all((json + "\n").as_bytes())?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Rewrites a stored plan, journal or operation JSON file at the current
+/// schema version, stamped with it, so later releases read it the same way.
+fn run_migrate(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let [path] = args else {
+        eprintln!("Error: migrate requires one plan, journal or operation JSON file");
+        staging::exit(1);
+    };
+    let text = exit_on_error(std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e)));
+    let (kind, migrated) = exit_on_error(migrate_text(options, &text, path));
+    match &options.output {
+        Some(output) => staging::create(output)?.write_all(migrated.as_bytes())?,
+        None => std::io::stdout().lock().write_all(migrated.as_bytes())?,
+    }
+    eprintln!("Migrated {} {} to operation schema version {}", kind, path, schema::SCHEMA_VERSION);
+    Ok(())
+}
+
+/// The migrated form of a stored file and what kind of file it is. JSON
+/// with an `op` is a single operation, other JSON a journal, and anything
+/// else a plan, which is checked by parsing it after migration.
+fn migrate_text(options: &GlobalOptions, text: &str, path: &str) -> Result<(&'static str, String), String> {
+    #[derive(Serialize)]
+    struct Versioned {
+        schema_version: u32,
+        #[serde(flatten)]
+        operation: Operation,
+    }
+
+    if !text.trim_start().starts_with('{') {
+        let migrated = plan::migrate_plan(text, path)?;
+        plan::parse_plan(&migrated, path, options.alphabet, options.seed, None)?;
+        return Ok(("plan", migrated));
+    }
+    if serde_json::from_str::<serde_json::Value>(text).is_ok_and(|value| value.get("op").is_some()) {
+        let versioned = Versioned { schema_version: schema::SCHEMA_VERSION, operation: operation_from_json(text)? };
+        let json = serde_json::to_string_pretty(&versioned).map_err(|e| e.to_string())?;
+        return Ok(("operation", json + "\n"));
+    }
+    let mut migrated = String::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let entry = journal::parse_entry(line, &format!("Line {} of journal {}", i + 1, path))?;
+        migrated.push_str(&serde_json::to_string(&entry).map_err(|e| e.to_string())?);
+        migrated.push('\n');
+    }
+    Ok(("journal", migrated))
+}
+
+/// Writes the spliced transcripts of a GTF as FASTA. With `--edits`, the
+/// input is the genome a journal produced, and each exon is lifted through
+/// the journal's edits first.
+fn run_transcripts(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut genome = None;
+    let mut journal_path = None;
+    let mut reference = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--edits" => {
+                journal_path = Some(option_value(args, i, "--edits", "a journal file"));
+                i += 2;
+            },
+            "--reference" => {
+                reference = Some(option_value(args, i, "--reference", "the original FASTA"));
+                i += 2;
+            },
+            other if genome.is_none() => {
+                genome = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected transcripts argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let (Some(genome), Some(gtf_path)) = (genome, &options.gtf) else {
+        eprintln!("Error: transcripts requires a genome FASTA and --gtf <file>");
+        staging::exit(1);
+    };
+    if reference.is_some() && journal_path.is_none() {
+        eprintln!("Error: --reference gives the original genome of --edits");
+        staging::exit(1);
+    }
+
+    let mut transcripts = exit_on_error(transcript::read_gtf(gtf_path));
+    if let Some(id) = &options.transcript {
+        transcripts.retain(|transcript| &transcript.id == id);
+        if transcripts.is_empty() {
+            eprintln!("Error: Transcript '{}' has no exons in {}", id, gtf_path);
+            staging::exit(1);
+        }
+    }
+    let records = options.read_records(&genome)?;
+    check_input(options, &records);
+
+    // The original genome and each record's layout relative to it
+    let lifted = match &journal_path {
+        Some(journal_path) => {
+            let entries = journal::read_journal(journal_path)?;
+            if entries.is_empty() {
+                eprintln!("Error: Journal {} contains no operations", journal_path);
+                staging::exit(1);
+            }
+            let (originals, placeholder) = journal_reference(options, &entries, reference.as_deref())?;
+            let layouts = exit_on_error(journal_layouts(options, &entries, &originals));
+            let derived_lengths: Vec<usize> = layouts.iter().map(|segments| segments.iter().map(layout::Segment::len).sum()).collect();
+            if originals.len() != records.len()
+                || records.iter().zip(&derived_lengths).any(|(record, &length)| record.sequence.len() != length) {
+                eprintln!("Error: {} is not the genome the journal {} produced", genome, journal_path);
+                staging::exit(1);
+            }
+            Some((originals, placeholder, layouts))
+        },
+        None => None,
+    };
+
+    let mut outputs = Vec::new();
+    let (mut missing, mut deleted, mut changed) = (0, 0, 0);
+    for transcript in &transcripts {
+        let spliced = match &lifted {
+            Some((originals, placeholder, layouts)) => {
+                let Ok(index) = record_index(originals, &transcript.seqid, *placeholder) else {
+                    missing += 1;
+                    continue;
+                };
+                let spliced = transcript.lifted(&records[index], &layouts[index], options.alphabet, options.insert_qual);
+                if spliced.as_ref().is_some_and(|spliced| spliced.sequence.len() != transcript.length()) {
+                    changed += 1;
+                }
+                spliced
+            },
+            None => {
+                let Some(record) = records.iter().find(|record| record.id() == transcript.seqid) else {
+                    missing += 1;
+                    continue;
+                };
+                Some(exit_on_error(transcript.cdna(record, options.alphabet, options.insert_qual)))
+            },
+        };
+        match spliced {
+            Some(spliced) => outputs.push(spliced),
+            None => deleted += 1,
+        }
+    }
+
+    for record in outputs.iter_mut() {
+        options.finish_output(record);
+    }
+    write_output(options, &outputs)?;
+    if missing > 0 {
+        eprintln!("{} of {} transcripts are on sequences not in {} and were skipped", missing, transcripts.len(), genome);
+    }
+    if journal_path.is_some() {
+        eprintln!("{} transcripts written: {} changed length, {} deleted entirely", outputs.len(), changed, deleted);
+    }
+    Ok(())
+}
+
+/// Lists the ORFs of a genome on both strands as TSV and, with
+/// `--reference`, flags those the edits created or destroyed.
+fn run_orfs(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut genome = None;
+    let mut reference = None;
+    let mut min_codons = orf::DEFAULT_MIN_CODONS;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--reference" => {
+                reference = Some(option_value(args, i, "--reference", "the original genome FASTA"));
+                i += 2;
+            },
+            "--min-length" => {
+                let value = option_value(args, i, "--min-length", "a number of codons");
+                min_codons = exit_on_error(units::parse_bases(&value).map_err(|_| "--min-length must be a number".to_string()));
+                i += 2;
+            },
+            other if genome.is_none() => {
+                genome = Some(other.to_string());
+                i += 1;
+            },
+            other => {
+                eprintln!("Error: Unexpected orfs argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+    }
+    let Some(genome) = genome else {
+        eprintln!("Error: orfs requires a genome FASTA");
+        staging::exit(1);
+    };
+    if options.alphabet.is_protein() {
+        eprintln!("Error: orfs reads nucleotide sequences and cannot be used with protein sequences");
+        staging::exit(1);
+    }
+
+    let records = fasta::read_records(&genome, false)?;
+    let references = match &reference {
+        Some(path) => fasta::read_records(path, false)?,
+        None => Vec::new(),
+    };
+    let mut orfs = Vec::new();
+    for record in &records {
+        let found = orf::find_orfs(&record.sequence, min_codons);
+        // As in classify, each record is compared with the reference record
+        // of the same ID, or with the only one
+        let parent = references.iter().find(|parent| parent.id() == record.id())
+            .or(if references.len() == 1 { references.first() } else { None });
+        let compared = match parent {
+            Some(parent) => orf::compare(found, orf::find_orfs(&parent.sequence, min_codons)),
+            None => {
+                if reference.is_some() {
+                    warnings::warn(warnings::Warning {
+                        code: "no_reference_record",
+                        severity: warnings::Severity::Low,
+                        record: Some(record.id().to_string()),
+                        position: None,
+                        message: format!("No reference record for {}; listing its ORFs without comparing", record.id()),
+                    });
+                }
+                found.into_iter().map(|orf| (orf, orf::Status::Unknown)).collect()
+            },
+        };
+        orfs.extend(compared.into_iter().map(|(orf, status)| (record.id(), orf, status)));
+    }
+
+    match &options.output {
+        Some(path) => {
+            let mut out = std::io::BufWriter::new(staging::create(path)?);
+            orf::write_orfs(&mut out, &orfs)?;
+            out.flush()?;
+        },
+        None => orf::write_orfs(&mut std::io::stdout().lock(), &orfs)?,
+    }
+    let listed = orfs.len() - orf::count(&orfs, orf::Status::Destroyed);
+    match &reference {
+        Some(path) => eprintln!("{} ORFs of at least {} codons in {}: {} created, {} destroyed relative to {}",
+                                listed, min_codons, genome, orf::count(&orfs, orf::Status::Created),
+                                orf::count(&orfs, orf::Status::Destroyed), path),
+        None => eprintln!("{} ORFs of at least {} codons in {}", listed, min_codons, genome),
+    }
+    Ok(())
+}
+
+fn run_reassort(options: &GlobalOptions, args: &[String]) -> std::io::Result<()> {
+    let mut assign = None;
+    let mut parent_files = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--assign" {
+            assign = Some(option_value(args, i, "--assign", "a comma-separated parent number per segment"));
+            i += 2;
+        } else {
+            parent_files.push(args[i].as_str());
+            i += 1;
+        }
+    }
+
+    if parent_files.len() < 2 {
+        eprintln!("Error: reassort requires at least two parental genomes");
+        staging::exit(1);
+    }
+    if options.journal.is_some() || options.region.is_some() {
+        eprintln!("Error: --journal and --region cannot be used with reassort");
+        staging::exit(1);
+    }
+
+    let mut parents = Vec::new();
+    for file in &parent_files {
+        let records = fasta::read_records(file, options.preserve_case)?;
+        check_input(options, &records);
+        parents.push(records);
+    }
+    let segments = parents[0].len();
+    if let Some((file, records)) = parent_files.iter().zip(&parents).find(|(_, r)| r.len() != segments) {
+        eprintln!("Error: {} has {} segments but {} has {}", file, records.len(), parent_files[0], segments);
+        staging::exit(1);
+    }
+
+    let seeded = assign.is_none();
+    let assignment = match assign {
+        Some(spec) => {
+            let parsed: Result<Vec<usize>, _> = spec.split(',').map(|p| p.trim().parse::<usize>()).collect();
+            match parsed {
+                Ok(numbers) if numbers.len() == segments && numbers.iter().all(|&p| p >= 1 && p <= parents.len()) => {
+                    numbers.into_iter().map(|p| p - 1).collect()
+                },
+                _ => {
+                    eprintln!("Error: --assign must list {} parent numbers between 1 and {}", segments, parents.len());
+                    staging::exit(1);
+                }
+            }
+        },
+        None => simulate::reassortment(segments, parents.len(), options.seed),
+    };
+
+    let mut records = Vec::new();
+    let mut origins = Vec::new();
+    for (segment, &parent) in assignment.iter().enumerate() {
+        let source = &parents[parent][segment];
+        let mut record = source.clone();
+        let drawn = if seeded { format!(", seed {}", options.seed) } else { String::new() };
+        record.header = format!("{} [segment {} from parent {} ({}){}]", source.header, segment + 1, parent + 1,
+                                parent_files[parent], drawn);
+        records.push(record);
+        origins.push(simulate::SegmentOrigin {
+            segment: segment + 1,
+            parent: parent + 1,
+            parent_file: parent_files[parent],
+            source_id: source.id(),
+            length: source.sequence.len(),
+        });
+    }
+
+    if let Some(manifest_path) = &options.manifest {
+        simulate::write_reassortment_manifest(manifest_path, &origins)?;
+    }
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    write_output(options, &records)?;
+
+    Ok(())
+}
+
+/// Writes `count` variant genomes, each the input with random substitutions
+/// and indels. Variant `n` uses a seed derived from the run seed and `n`, so
+/// any single variant can be regenerated, and variants built on `--jobs`
+/// threads come out the same as on one.
+fn run_quasispecies(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    let mut rate = None;
+    let mut indel_rate = 0.0;
+    let mut threads = 1;
+    let mut pos_args = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--rate" => {
+                rate = Some(exit_on_error(parse_rate(&option_value(args, i, "--rate", "a per-base rate"), "--rate")));
+                i += 2;
+            },
+            "--indel-rate" => {
+                indel_rate = exit_on_error(parse_rate(&option_value(args, i, "--indel-rate", "a per-base rate"), "--indel-rate"));
+                i += 2;
+            },
+            "--jobs" => {
+                let value = option_value(args, i, "--jobs", "a number of threads");
+                threads = jobs::threads(exit_on_error(value.parse::<usize>()
+                    .map_err(|_| "--jobs must be a whole number (0 for one thread per CPU)".to_string())));
+                i += 2;
+            },
+            _ => {
+                pos_args.push(&args[i]);
+                i += 1;
+            }
+        }
+    }
+
+    let count: usize = match pos_args.as_slice() {
+        [count] => exit_on_error(count.parse().map_err(|_| "Number of variants must be a number".to_string())),
+        _ => {
+            eprintln!("Error: quasispecies requires a number of variants");
+            staging::exit(1);
+        }
+    };
+    let rate = match rate {
+        Some(rate) => rate,
+        None => {
+            eprintln!("Error: quasispecies requires --rate <per-base mutation rate>");
+            staging::exit(1);
+        }
+    };
+
+    let master = options.read_records(input_file)?;
+    check_input(options, &master);
+    let bases = options.alphabet.substitution_bases();
+
+    // Every record of every variant draws from its own stream
+    let variants: Vec<usize> = (1..=count).collect();
+    let built = jobs::map_ordered(&variants, threads, |_, &variant| {
+        let variant_seed = rng::derive_seed(options.seed, variant as u64);
+        master.iter().enumerate().map(|(index, record)| {
+            let seed = rng::derive_seed(variant_seed, index as u64);
+            let mut rng = rng::Rng::new(seed);
+            let (sequence, mutations) = simulate::mutate(&record.sequence, rate, indel_rate, bases, &mut rng);
+            let (_, id, description) = header::split_header(&record.header);
+            let variant_id = format!("{}_v{}", id, variant);
+            let mut header = format!(">{}", variant_id);
+            if !description.is_empty() {
+                header = format!("{} {}", header, description);
+            }
+            header = format!("{} [quasispecies variant {}: {} mutation{} (seed {})]",
+                             header, variant, mutations.len(), if mutations.len() == 1 { "" } else { "s" }, seed);
+            (Record { header, sequence, quality: None, line_width: record.line_width }, (variant_id, seed, mutations))
+        }).collect::<Vec<_>>()
+    });
+    let (mut records, tables): (Vec<Record>, Vec<_>) = built.into_iter().flatten().unzip();
+
+    if let Some(manifest_path) = &options.manifest {
+        simulate::write_mutation_table(manifest_path, &tables)?;
+    }
+    for record in records.iter_mut() {
+        options.finish_output(record);
+    }
+    write_output(options, &records)?;
+
+    Ok(())
+}
+
+/// Writes the subgenomic RNAs of a coronavirus-like genome, each the leader
+/// joined to one body site, as separate records.
+/// Cuts every record with the given restriction enzymes and writes the
+/// fragments as TSV, reporting the number of cuts of each enzyme.
+fn run_digest(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    let mut circular = false;
+    let mut enzymes = Vec::new();
+    for arg in args {
+        if arg == "--circular" {
+            circular = true;
+        } else {
+            enzymes.push(exit_on_error(digest::Enzyme::parse(arg)));
+        }
+    }
+    if enzymes.is_empty() {
+        eprintln!("Error: digest requires at least one enzyme");
+        staging::exit(1);
+    }
+    if options.alphabet.is_protein() {
+        eprintln!("Error: digest needs nucleotide sequences");
+        staging::exit(1);
+    }
+
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => Box::new(std::io::BufWriter::new(staging::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    writeln!(out, "record\tstart\tend\tlength\tleft_enzyme\tright_enzyme")?;
+    for record in records.iter().filter(|record| !record.sequence.is_empty()) {
+        // Cut positions, with every enzyme that cuts at each
+        let mut cuts: Vec<(usize, String)> = Vec::new();
+        for enzyme in &enzymes {
+            let positions = digest::cut_positions(&record.sequence, enzyme, circular);
+            eprintln!("{} ({}): {} cuts in {}", enzyme.name, enzyme.marked_site(), positions.len(), record.id());
+            for position in positions {
+                match cuts.iter_mut().find(|(cut, _)| *cut == position) {
+                    Some((_, names)) => {
+                        names.push(',');
+                        names.push_str(&enzyme.name);
+                    },
+                    None => cuts.push((position, enzyme.name.clone())),
+                }
+            }
+        }
+        cuts.sort_by_key(|(position, _)| *position);
+        digest::write_fragments(&mut out, record.id(), record.sequence.len(), &cuts, circular)?;
+    }
+    out.flush()
 }
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 3 {
-        print_usage(&args[0]);
-        std::process::exit(1);
+fn run_sgmrna(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    let mut leader_end = None;
+    let mut motif = None;
+    let mut bodies = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let position = |flag: &str| -> usize {
+            match units::parse_bases(&option_value(args, i, flag, "a position")) {
+                Ok(position) if position > 0 => position,
+                _ => {
+                    eprintln!("Error: {} must be a 1-based position", flag);
+                    staging::exit(1);
+                }
+            }
+        };
+        match args[i].as_str() {
+            "--leader" => leader_end = Some(position("--leader")),
+            "--body" => bodies.push(position("--body")),
+            "--motif" => motif = Some(option_value(args, i, "--motif", "a TRS core sequence")),
+            other => {
+                eprintln!("Error: Unknown sgmrna argument '{}'", other);
+                staging::exit(1);
+            }
+        }
+        i += 2;
     }
 
-    // Check for --output or -o flag
-    let (output_file, remaining_args) = parse_output_option(&args[1..]);
-    
-    if remaining_args.len() < 2 {
-        print_usage(&args[0]);
-        std::process::exit(1);
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+    let targets = exit_on_error(select_targets(&records, &Target::Only));
+    let genome = &records[targets[0]];
+    let rnas = exit_on_error(sgrna::subgenomic_rnas(&genome.sequence, leader_end, motif.as_deref(), &bodies));
+
+    let settings = options.edit_settings();
+    let mut outputs = Vec::new();
+    let mut listed = Vec::new();
+    let mut junctions = Vec::new();
+    for (n, rna) in rnas.into_iter().enumerate() {
+        // A subgenomic RNA is the genome with everything between the leader and the body removed
+        let operation = Operation::Delete { start: rna.leader_end + 1, end: rna.body_start - 1 };
+        let segments = exit_on_error(layout::operation_layout(&operation, &genome.sequence));
+        junctions.extend(layout::junctions(&segments));
+
+        let id = format!("{}_sg{}", genome.id(), n + 1);
+        let (_, _, description) = header::split_header(&genome.header);
+        let mut header = format!(">{}", id);
+        if !description.is_empty() {
+            header = format!("{} {}", header, description);
+        }
+        let kind = if rna.canonical { "canonical" } else { "non-canonical" };
+        header = format!("{} [{} subgenomic RNA: leader 1-{} joined to body {}-{}]",
+                         header, kind, rna.leader_end, rna.body_start, genome.sequence.len());
+        let sequence = layout::render_sequence(&genome.sequence, &segments, settings.alphabet);
+        listed.push((id, rna, sequence.len()));
+        outputs.push(Record { header, sequence, quality: None, line_width: genome.line_width });
     }
 
-    let input_file = &remaining_args[0];
-    let operation = parse_operation(&remaining_args[1..]);
-    
-    let operation = match operation {
-        Ok(op) => op,
-        Err(msg) => {
-            eprintln!("Error: {}", msg);
-            print_usage(&args[0]);
-            std::process::exit(1);
+    if let Some(manifest_path) = &options.manifest {
+        sgrna::write_sgrna_manifest(manifest_path, &listed)?;
+    }
+    let junction_records = options.wants_junctions().then(|| {
+        let junctions = junctions.into_iter().map(|junction| (junction, options.allele.clone())).collect();
+        vec![vcf::RecordJunctions { chrom: genome.id(), sequence: &genome.sequence, junctions, donors: Vec::new(), lost: Vec::new() }]
+    });
+    if let Some(junction_records) = &junction_records {
+        write_junction_outputs(options, junction_records)?;
+    }
+    for record in outputs.iter_mut() {
+        options.finish_output(record);
+    }
+    let annotations = read_features(options);
+    write_formatted(options, &writers::Output {
+        junctions: junction_records.as_deref().map(|records| writers::Junctions {
+            records, confidence: breakpoint_confidence(options), features: &annotations }),
+        ..writers::Output::new(&outputs, options.molecule())
+    })?;
+
+    Ok(())
+}
+
+/// Writes every region of a BED file as its own record, reverse complemented
+/// for intervals on the minus strand.
+fn run_extract(options: &GlobalOptions, input_file: &str, args: &[String]) -> std::io::Result<()> {
+    let bed_path = match args {
+        [flag, path] if flag == "--bed" => path,
+        _ => {
+            eprintln!("Error: extract requires --bed <regions.bed>");
+            staging::exit(1);
         }
     };
+    if options.region.is_some() {
+        eprintln!("Error: extract takes its regions from --bed and cannot be combined with --region");
+        staging::exit(1);
+    }
+    let intervals = exit_on_error(bed::read_bed(bed_path));
+    if intervals.iter().any(|interval| interval.reverse) && options.alphabet.is_protein() {
+        eprintln!("Error: Minus-strand regions cannot be extracted from protein sequences");
+        staging::exit(1);
+    }
 
-    let (header, sequence) = if input_file == "-" {
-        read_fasta_from_stdin()?
-    } else {
-        read_fasta(input_file)?
-    };
-    let (new_header, new_sequence) = apply_operation(&header, &sequence, operation);
-    
-    if let Some(output_path) = output_file {
-        write_fasta_to_file(&new_header, &new_sequence, &output_path)?;
-    } else {
-        write_fasta_to_stdout(&new_header, &new_sequence)?;
+    let records = options.read_records(input_file)?;
+    check_input(options, &records);
+
+    let mut outputs = Vec::new();
+    for interval in &intervals {
+        let record = exit_on_error(records.iter().find(|r| r.id() == interval.chrom)
+            .ok_or_else(|| format!("Sequence '{}' not found in input", interval.chrom)));
+        // BED is 0-based and half-open; headers use the same 1-based,
+        // inclusive positions as the edit operations
+        let (start, end) = (interval.start + 1, interval.end);
+        if interval.start >= interval.end || end > record.sequence.len() {
+            eprintln!("Error: BED region {}:{}-{} does not fit sequence '{}' of length {}",
+                      interval.chrom, interval.start, interval.end, record.id(), record.sequence.len());
+            staging::exit(1);
+        }
+
+        let segments = [layout::Segment::Source {
+            start: interval.start, end: interval.end, reverse: interval.reverse, complement: interval.reverse,
+        }];
+        let sequence = layout::render_sequence(&record.sequence, &segments, options.alphabet);
+        let quality = record.quality.as_ref()
+            .map(|quality| layout::render_quality(quality, &segments, options.insert_qual));
+        let strand = if interval.reverse { '-' } else { '+' };
+        let id = match &interval.name {
+            Some(name) => name.clone(),
+            None => format!("{}:{}-{}({})", interval.chrom, start, end, strand),
+        };
+        let header = format!(">{} [extracted {}{} at positions {}-{} of {} ({} strand)]",
+                             id, end - start + 1, options.alphabet.unit(), start, end, interval.chrom, strand);
+        outputs.push(Record { header, sequence, quality, line_width: record.line_width });
     }
-    
+
+    for record in outputs.iter_mut() {
+        options.finish_output(record);
+    }
+    write_output(options, &outputs)?;
+
     Ok(())
 }
 
+fn parse_rate(value: &str, flag: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("{} must be a number between 0 and 1", flag)),
+    }
+}
+
 fn print_usage(program_name: &str) {
     eprintln!("Usage:");
     eprintln!("  {} [--output|-o <file>] <input_file> delete <start> <end>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> keep <start> <end>", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> insert <position> <sequence>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> insert <position> --random <length> [--max-homopolymer <n>]", program_name);
+    eprintln!("      [--gc <min>-<max>] [--gc-window <n>] [--avoid <motif>]...");
+    eprintln!("  {} [--output|-o <file>] <input_file> insert <position> --protein <residues> --codon-table <organism|file>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> replace <start> <end> <sequence>", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> invert [--complement] <start> <end>", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> duplicate <start> <end> <position>", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> duplicate -td <start> <end>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> duplicate <start> <end> <record>:<position> [--from <record>]", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> copyback <gend> <breakpoint> <backstart>", program_name);
     eprintln!("  {} [--output|-o <file>] <input_file> copyback -sb <gend> <breakpoint>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> copyback --keep-leader|--keep-trailer <n> --reanneal <m>", program_name);
+    eprintln!("      copyback also accepts [--min-stem <n>] [--require-perfect-stem] [--coords-on revcomp|reference]");
+    eprintln!("  {} [--output|-o <file>] <input_file> chromothripsis <start> <end> <fragments> [--keep <n>]", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> repeat-family <start> <end> --copies <n> [--divergence <rate>]", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> bfb --cycles <n>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> mei <element> [--position <n>] [--tsd <n>] [--truncate <n>] [--polya <n>] [--strand +|-]", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> integrate <virus> [--position <n>]... [--sites <n>] [--viral-range <a-b>]", program_name);
+    eprintln!("      [--partial] [--rearrange] [--microhomology <n>]");
+    eprintln!("  {} [--output|-o <file>] <input_file> polya --length <n>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> add-leader <sequence>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> case <start> <end> upper|lower", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> telomere [--repeat <seq>] --length <n> [--end 5|3|both]", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> plan <plan_file>", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> recipe <name> [--<param> <n>]...", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> generate <spec_file> [--min-length <n>] [--max-length <n>] [--jobs <n>]", program_name);
+    eprintln!("      [--sample <n> [--hotspot <motif>]... [--hotspot-strength <w>]] [--dilution <file> --ratios <ratios>]");
+    eprintln!("      - One genome per parameter combination, optionally only those within a length range, on n threads;\n        --sample draws n combinations instead, weighted towards breakends on --hotspot motifs;\n        --dilution writes pool abundances at each DVG:full-length ratio");
+    eprintln!("  {} [--output|-o <file>] <input_file> extract --bed <regions.bed>", program_name);
+    eprintln!("  {} [--output|-o <file>] transcripts <genome.fa> --gtf <file> [--transcript <id>] [--edits <journal>] [--reference <fa>]", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> digest <enzyme|site>... [--circular] - Restriction fragments as TSV", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> sgmrna [--leader <n>] [--motif <trs>] [--body <n>]...", program_name);
+    eprintln!("  {} [--output|-o <file>] <input_file> quasispecies <n> --rate <r> [--indel-rate <r>] [--jobs <n>]", program_name);
     eprintln!("  {} [--output|-o <file>] - <operation> <args...> - Read from stdin", program_name);
-    eprintln!("");
+    eprintln!("  {} [--output|-o <file>] --op-json <file|-> <input_file> - Read the operation as JSON", program_name);
+    eprintln!("  {} --output|-o <file> --control-fd <n> <input_file> - Apply JSON operations streamed on a file descriptor", program_name);
+    eprintln!("  {} [--output|-o <file>] replay <journal> <reference> - Reproduce a genome from its journal", program_name);
+    eprintln!("  {} explain <operation> <args...> --length <L> - Describe an operation without running it", program_name);
+    eprintln!("  {} map <pos|chrom:pos|regions.bed> --edits <journal> [--reference <fa>] [--inverse]", program_name);
+    eprintln!("      - Where original positions ended up (--inverse: where derived positions came from)");
+    eprintln!("  {} [--output|-o <file>] classify <genome.fa> [--reference <fa>] [--min-stem <n>] - Classify DVGs as JSON", program_name);
+    eprintln!("  {} [--output|-o <file>] reassort <parent.fa> <parent.fa>... [--assign <p1,p2,...>] - Reassort virus segments", program_name);
+    eprintln!("  {} [--output|-o <file>] filter <library.fa> [--id-regex <re>] [--header-regex <re>] [--min-length <n>]", program_name);
+    eprintln!("      [--max-length <n>] [--fields <manifest.tsv> [--where <field><op><value>]...] - Select library records");
+    eprintln!("  {} check-truth <ref.fa> <derived.fa> <truth.vcf> - Check that the VCF applied to the reference gives the derived genome", program_name);
+    eprintln!("  {} [--output|-o <file>] orfs <genome.fa> [--reference <fa>] [--min-length <codons>]", program_name);
+    eprintln!("      - ORFs on both strands as TSV, flagging those created or destroyed relative to the reference");
+    eprintln!("  {} cmp <a.fa> <b.fa> [--first-diff] - Exit 0 if the sequences are identical, ignoring headers and wrapping", program_name);
+    eprintln!("  {} [--output|-o <file>] migrate <plan|journal|op.json> - Rewrite a stored file at the current schema version", program_name);
+    eprintln!("  {} selftest --outdir <dir>", program_name);
+    eprintln!("      - Write a reference and one output per operation with expected checksums, and check them");
+    eprintln!("  {} [--output|-o <file>] [--manifest <file>] dedup <library.fa> [--near <similarity>] - Remove duplicate records", program_name);
+    eprintln!();
     eprintln!("Examples:");
     eprintln!("  {} input.fa delete 10 20                     # Delete bases 10-20", program_name);
+    eprintln!("  {} input.fa delete 5000 end                  # Delete from base 5000 to the end", program_name);
     eprintln!("  {} input.fa insert 15 ATCG                   # Insert ATCG at position 15", program_name);
+    eprintln!("  {} input.fa replace 15 18 GGCC               # Replace bases 15-18 with GGCC", program_name);
     eprintln!("  {} input.fa invert 25 35                     # Invert bases 25-35", program_name);
     eprintln!("  {} input.fa invert --complement 25 35        # Reverse complement bases 25-35", program_name);
     eprintln!("  {} input.fa duplicate 10 20 50               # Duplicate bases 10-20 to position 50", program_name);
@@ -79,27 +3658,570 @@ fn print_usage(program_name: &str) {
     eprintln!("  {} input.fa copyback -sb 5 50                # 5' snapback: keep up to pos 50, append revcomp of pos 1-50", program_name);
     eprintln!("  {} -o output.fa input.fa delete 5 10         # Save result to file", program_name);
     eprintln!("  {} input.fa delete 5 10 | {} - insert 20 GGGG  # Chain operations", program_name, program_name);
-    eprintln!("");
+    eprintln!("  {} --journal ops.jsonl input.fa delete 5 10  # Append the operation to a journal", program_name);
+    eprintln!("  {} replay ops.jsonl input.fa                 # Re-apply all journaled operations", program_name);
+    eprintln!("  {} genome.fa plan edits.tsv                  # Apply a plan file (optionally per-record)", program_name);
+    eprintln!("  {} --seed 1 --manifest frags.tsv --truth-vcf truth.vcf input.fa chromothripsis 1000 9000 12", program_name);
+    eprintln!("  {} --summary run.json --expect-input-md5 <md5> input.fa delete 5 10  # Verify input, record checksums", program_name);
+    eprintln!();
     eprintln!("gend: 5 (5' end) or 3 (3' end)");
     eprintln!("For both 5' and 3' end: backstart < breakpoint");
     eprintln!("Without --output, result is written to stdout for piping.");
+    eprintln!("With --journal, each applied operation is appended to the given JSON Lines file.");
+    eprintln!("With --summary, a JSON summary including input and output sequence MD5s, per-phase timings,");
+    eprintln!("  peak memory and file sizes is written.");
+    eprintln!("With --stem-energy, the summary also reports the terminal stem length and a nearest-neighbor dG estimate.");
+    eprintln!("With --expect-input-md5, the run fails if the input sequence MD5 does not match.");
+    eprintln!("--alphabet dna|rna|iupac|protein|any selects the characters accepted in input and inserted sequences (default: dna).");
+    eprintln!("--region <name[:start-end]> edits only that record or range, seeking via <input>.fai when present.");
+    eprintln!("--gtf <file> --transcript <id> takes operation coordinates on that spliced transcript and applies the edit to");
+    eprintln!("  the genome; with --cdna the operation is applied to the extracted transcript instead.");
+    eprintln!("--max-memory <MB> streams single operations on an indexed FASTA that would not fit in memory, and fails");
+    eprintln!("  before reading anything else that would not.");
+    eprintln!("Positions and lengths accept separators and suffixes, e.g. 12_345, 1.5k, 2Mb.");
+    eprintln!("Range starts may be written as 'start' and range ends as 'end' (the last base of each sequence).");
+    eprintln!("Output keeps the input's line wrapping; --line-width <n> overrides it (0 for unwrapped).");
+    eprintln!("Output files are written under temporary names and moved into place together only if the run succeeds.");
+    eprintln!("--disallow-nested rejects duplications whose insert position lies inside the duplicated range.");
+    eprintln!("--max-identity-change <f> fails the run if more than that fraction of a record's bases are lost.");
+    eprintln!("--max-affected-fraction <f> keeps the edited bases below that fraction of the genome, redrawing random placements.");
+    eprintln!("--warnings <file> writes the run's warnings as JSON; --fail-on-warn low|medium|high exits 1 without writing");
+    eprintln!("  the outputs if any warning reaches that severity.");
+    eprintln!("--rule-of-six moves a copyback, delete or keep breakpoint by up to 5 bases so the result is a multiple");
+    eprintln!("  of six long; generate drops other combinations, and plans must already comply.");
+    eprintln!("--canonical writes byte-stable output: uppercase, 60-column lines, single-spaced headers.");
+    eprintln!("--strip-gaps removes alignment gaps (- and .) from the input before editing; --gap-map <file> writes");
+    eprintln!("  the aligned-to-ungapped coordinate blocks.");
+    eprintln!("--trim-terminal-ns trims N padding from the ends of the input records before editing;");
+    eprintln!("  --assume-complete-termini no refuses copybacks, which need the true ends (default: yes).");
+    eprintln!("--preserve-case keeps the case of input bases (by default they are uppercased), e.g. for case-marked regions.");
+    eprintln!("--seed <n> makes stochastic operations reproducible; the seed used is recorded in the header.");
+    eprintln!("--truth-vcf <file> writes BND records for every junction created; --manifest <file> writes the");
+    eprintln!("  chromothripsis fragment order, the breakage-fusion-bridge cycles, the viral integration sites,");
+    eprintln!("  the parent of each reassorted segment, the mutations of each quasispecies variant,");
+    eprintln!("  the copies planted by repeat-family,");
+    eprintln!("  or the junction of each subgenomic RNA.");
+    eprintln!("--vcf <file> writes each change to the input as a sequence-resolved VCF record (REF/ALT with the padding base);");
+    eprintln!("  --vcf-style symbolic writes <DEL>, <DUP>, <INV> and <INS> records with END, and BND pairs for other junctions.");
+    eprintln!("--allele-fraction <f> and --genotype <gt> add AF and GT to the truth VCF (per plan line: af=<f> gt=<gt>).");
+    eprintln!("Plan lines 'layer=<name> [from=<parent>]' build nested genomes, each written with its outputs as <file>.<name>.<ext>.");
+    eprintln!("--diploid applies a plan to two haplotypes (per line gt=1|0, 0|1, 1/1...), writing <file>.hap1/.hap2; --sex XX|XY");
+    eprintln!("  (default: XX) sets chrX/chrY ploidy, and --par <bed> the PARs (GRCh38/GRCh37 are recognised by length).");
+    eprintln!("  --phased-snps <vcf> [--snp-sample <name>] first puts that sample's phased SNVs on the haplotypes.");
+    eprintln!("--place-in <repeats.bed> [--fraction <f>] puts that fraction of mei/integrate sites inside repeats (default: all).");
+    eprintln!("--fuzz-breakpoints <sd> jitters the operation's breakpoints by a normal offset; --fuzz-ci adds CIPOS to the truth VCF.");
+    eprintln!("--cipos <interval> and --ciend <interval> (per plan line: cipos=-5,10 ciend=3) declare breakpoint confidence");
+    eprintln!("  intervals for the truth VCF's CIPOS and a tolerance column in --homology output.");
+    eprintln!("--signal-preview <insert>,<read> prints the discordant pairs and split reads each junction should produce.");
+    eprintln!("--homology <file> reports the flanking homology of deletion/duplication junctions (BED if <file> ends in .bed, TSV otherwise).");
+    eprintln!("--sq-header <file> writes @SQ lines (SN, LN, M5, UR) for the output, ready for aligners and read simulators.");
+    eprintln!("--emit-sim-config <art|badread|pbsim> writes <output>.<simulator>.sh to simulate reads from the output,");
+    eprintln!("  weighted by generate --dilution abundances when given.");
+    eprintln!("--diagram <file> draws the structure of the edited molecule (SVG if <file> ends in .svg, text otherwise).");
+    eprintln!("--gff <file> reports the features each operation deleted, truncated, duplicated, inverted or interrupted, or whose");
+    eprintln!("  --promoter <n> upstream bases (default: 500) it changed, to --impact <file> (default: stderr),");
+    eprintln!("  and adds gene and CDS consequences to the truth VCF.");
+    eprintln!("--methylation <file> writes simulated CpG methylation probabilities carried through the edits, as bedMethyl.");
+    eprintln!("--coverage <file> writes the expected relative coverage over input coordinates as a bedGraph,");
+    eprintln!("  for the edit at --allele-fraction/--genotype (default: on every copy).");
+    eprintln!("--format fasta|fastq|genbank|gfa|vcf writes the output in that format (default: by --output extension, else as read).");
+    eprintln!("--compress gz|bgzf|zst compresses the output (default: bgzf for a .gz or .bgz --output, zst for .zst).");
+    eprintln!("--genbank <file> writes the edited sequence as GenBank with each inserted, duplicated or inverted segment as a feature.");
+    eprintln!("--segments-bed <file> writes the same segments as a BED track on the edited sequence, named by operation.");
+    eprintln!("--gfa <file> writes an rGFA graph with each input record as the backbone and its edited version as an alternate path.");
+    eprintln!("--matched-control <file> writes a control genome with the same operation at a random other locus");
+    eprintln!("  (avoiding --exclude <bed> regions); --control-manifest <file> lists the control loci.");
+    eprintln!("--scramble-control <file> writes a control genome whose inverted or duplicated interval is shuffled instead.");
+    eprintln!("--stats <file> writes generate's operation counts, length distribution and junction position histogram as JSON.");
+    eprintln!("--karyotype <file> writes per-record length changes and gained/lost/derived records (JSON if <file> ends in .json).");
+    eprintln!("--all (or --all-records) applies the operation to every record of a multi-record input, skipping records it does not fit.");
+    eprintln!("--seq <id> applies it to that record only, writing the other records through unchanged.");
+    eprintln!("--id <id> renames the output sequence; --keep-description yes|no|replace keeps the description,");
+    eprintln!("  drops it, or replaces it with the provenance annotations only.");
+    eprintln!("FASTQ input is detected automatically; --insert-qual <char|score> sets the quality of inserted bases (default: I).");
+    eprintln!("--protein edits amino-acid sequences (same as --alphabet protein); complement-based operations are disabled.");
 }
 
-fn parse_output_option(args: &[String]) -> (Option<String>, Vec<String>) {
-    let mut output_file = None;
+fn parse_global_options(args: &[String]) -> (GlobalOptions, Vec<String>) {
+    let mut options = GlobalOptions {
+        output: None,
+        journal: None,
+        summary: None,
+        expect_input_md5: None,
+        alphabet: Alphabet::Dna,
+        insert_qual: 'I',
+        region: None,
+        line_width: None,
+        id: None,
+        keep_description: header::KeepDescription::Yes,
+        all: false,
+        seq: None,
+        seed: 0,
+        truth_vcf: None,
+        allele: vcf::Allele::default(),
+        homology: None,
+        signal_preview: None,
+        sq_header: None,
+        sim_config: None,
+        warnings: None,
+        fail_on_warn: None,
+        fuzz_breakpoints: None,
+        place_in: None,
+        place_fraction: 1.0,
+        fuzz_ci: false,
+        manifest: None,
+        karyotype: None,
+        stem_energy: false,
+        diagram: None,
+        gfa: None,
+        coverage: None,
+        sequence_vcf: None,
+        vcf_style: vcf::VcfStyle::Resolved,
+        methylation: None,
+        diploid: false,
+        sex: None,
+        par: None,
+        phased_snps: None,
+        snp_sample: None,
+        gff: None,
+        impact: None,
+        promoter: impact::DEFAULT_PROMOTER,
+        matched_control: None,
+        scramble_control: None,
+        control_manifest: None,
+        exclude: None,
+        canonical: false,
+        preserve_case: false,
+        strip_gaps: false,
+        trim_terminal_ns: false,
+        complete_termini: true,
+        gap_map: None,
+        disallow_nested: false,
+        rule_of_six: false,
+        max_identity_change: None,
+        max_affected_fraction: None,
+        stats: None,
+        genbank: None,
+        format: None,
+        compress: None,
+        segments_bed: None,
+        max_memory: None,
+        gtf: None,
+        transcript: None,
+        cdna: false,
+        op_json: None,
+        control_fd: None,
+    };
+    let mut seed = None;
     let mut remaining_args = Vec::new();
     let mut i = 0;
     
     while i < args.len() {
         match args[i].as_str() {
             "--output" | "-o" => {
-                if i + 1 < args.len() {
-                    output_file = Some(args[i + 1].clone());
-                    i += 2; // Skip both the flag and the filename
+                options.output = Some(option_value(args, i, "--output", "a filename"));
+                i += 2; // Skip both the flag and the filename
+            },
+            "--journal" => {
+                options.journal = Some(option_value(args, i, "--journal", "a filename"));
+                i += 2;
+            },
+            "--summary" => {
+                options.summary = Some(option_value(args, i, "--summary", "a filename"));
+                i += 2;
+            },
+            // `insert <position> --protein <residues>` names a payload to
+            // reverse-translate rather than the protein alphabet
+            "--protein" if remaining_args.get(1).is_some_and(|command| command == "insert") => {
+                remaining_args.extend(args[i..].iter().take(2).cloned());
+                i += 2;
+            },
+            "--protein" => {
+                options.alphabet = Alphabet::Protein;
+                i += 1;
+            },
+            "--alphabet" => {
+                let name = option_value(args, i, "--alphabet", "one of dna, rna, iupac, any");
+                options.alphabet = match Alphabet::parse(&name) {
+                    Ok(alphabet) => alphabet,
+                    Err(msg) => {
+                        eprintln!("Error: {}", msg);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--insert-qual" => {
+                let value = option_value(args, i, "--insert-qual", "a quality character or Phred score");
+                options.insert_qual = match parse_quality(&value) {
+                    Ok(qual) => qual,
+                    Err(msg) => {
+                        eprintln!("Error: {}", msg);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--region" => {
+                let value = option_value(args, i, "--region", "a region such as chr1 or chr1:1000-2000");
+                options.region = match fai::Region::parse(&value) {
+                    Ok(region) => Some(region),
+                    Err(msg) => {
+                        eprintln!("Error: {}", msg);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--line-width" => {
+                let value = option_value(args, i, "--line-width", "a number of bases per line (0 for unwrapped)");
+                options.line_width = match value.parse::<usize>() {
+                    Ok(width) => Some(width),
+                    Err(_) => {
+                        eprintln!("Error: --line-width must be a number");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--id" => {
+                let id = option_value(args, i, "--id", "a sequence ID");
+                if id.is_empty() || id.contains(char::is_whitespace) {
+                    eprintln!("Error: --id must be non-empty and contain no whitespace");
+                    staging::exit(1);
+                }
+                options.id = Some(id);
+                i += 2;
+            },
+            "--keep-description" => {
+                let value = option_value(args, i, "--keep-description", "one of yes, no, replace");
+                options.keep_description = match header::KeepDescription::parse(&value) {
+                    Ok(keep) => keep,
+                    Err(msg) => {
+                        eprintln!("Error: {}", msg);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--seed" => {
+                let value = option_value(args, i, "--seed", "a number");
+                seed = match value.parse::<u64>() {
+                    Ok(seed) => Some(seed),
+                    Err(_) => {
+                        eprintln!("Error: --seed must be a non-negative integer");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--vcf" => {
+                options.sequence_vcf = Some(option_value(args, i, "--vcf", "a filename"));
+                i += 2;
+            },
+            "--vcf-style" => {
+                let value = option_value(args, i, "--vcf-style", "resolved or symbolic");
+                options.vcf_style = exit_on_error(vcf::VcfStyle::parse(&value));
+                i += 2;
+            },
+            "--methylation" => {
+                options.methylation = Some(option_value(args, i, "--methylation", "a filename"));
+                i += 2;
+            },
+            "--truth-vcf" => {
+                options.truth_vcf = Some(option_value(args, i, "--truth-vcf", "a filename"));
+                i += 2;
+            },
+            "--fuzz-breakpoints" => {
+                let value = option_value(args, i, "--fuzz-breakpoints", "a standard deviation in bases");
+                options.fuzz_breakpoints = match value.parse::<f64>() {
+                    Ok(sd) if sd > 0.0 && sd.is_finite() => Some(sd),
+                    _ => {
+                        eprintln!("Error: --fuzz-breakpoints must be a positive number of bases");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--place-in" => {
+                options.place_in = Some(option_value(args, i, "--place-in", "a BED file of repeats"));
+                i += 2;
+            },
+            "--fraction" => {
+                let value = option_value(args, i, "--fraction", "a fraction");
+                options.place_fraction = match value.parse::<f64>() {
+                    Ok(fraction) if (0.0..=1.0).contains(&fraction) => fraction,
+                    _ => {
+                        eprintln!("Error: --fraction must be a number between 0 and 1");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--fuzz-ci" => {
+                options.fuzz_ci = true;
+                i += 1;
+            },
+            "--sq-header" => {
+                options.sq_header = Some(option_value(args, i, "--sq-header", "a filename"));
+                i += 2;
+            },
+            "--emit-sim-config" => {
+                let value = option_value(args, i, "--emit-sim-config", "a read simulator");
+                options.sim_config = Some(exit_on_error(simconfig::Simulator::parse(&value)));
+                i += 2;
+            },
+            "--warnings" => {
+                options.warnings = Some(option_value(args, i, "--warnings", "a filename"));
+                i += 2;
+            },
+            "--fail-on-warn" => {
+                let value = option_value(args, i, "--fail-on-warn", "a severity");
+                options.fail_on_warn = Some(exit_on_error(warnings::Severity::parse(&value)));
+                i += 2;
+            },
+            "--signal-preview" => {
+                let value = option_value(args, i, "--signal-preview", "<insert size>,<read length>");
+                options.signal_preview = Some(exit_on_error(signal::Library::parse(&value)));
+                i += 2;
+            },
+            "--homology" => {
+                options.homology = Some(option_value(args, i, "--homology", "a filename"));
+                i += 2;
+            },
+            "--allele-fraction" => {
+                let value = option_value(args, i, "--allele-fraction", "a fraction");
+                options.allele.allele_fraction = Some(exit_on_error(vcf::parse_allele_fraction(&value)));
+                i += 2;
+            },
+            "--genotype" => {
+                let value = option_value(args, i, "--genotype", "a genotype such as 0/1");
+                options.allele.genotype = Some(exit_on_error(vcf::parse_genotype(&value)));
+                i += 2;
+            },
+            "--phased-snps" => {
+                options.phased_snps = Some(option_value(args, i, "--phased-snps", "a phased VCF"));
+                i += 2;
+            },
+            "--snp-sample" => {
+                options.snp_sample = Some(option_value(args, i, "--snp-sample", "a VCF sample name"));
+                i += 2;
+            },
+            "--cipos" | "--ciend" => {
+                let value = option_value(args, i, &args[i], "a confidence interval such as -10,10");
+                let interval = Some(exit_on_error(vcf::parse_interval(&value)));
+                if args[i] == "--cipos" {
+                    options.allele.cipos = interval;
                 } else {
-                    eprintln!("Error: --output requires a filename");
-                    std::process::exit(1);
+                    options.allele.ciend = interval;
                 }
+                i += 2;
+            },
+            "--manifest" => {
+                options.manifest = Some(option_value(args, i, "--manifest", "a filename"));
+                i += 2;
+            },
+            "--karyotype" => {
+                options.karyotype = Some(option_value(args, i, "--karyotype", "a filename"));
+                i += 2;
+            },
+            "--gff" => {
+                options.gff = Some(option_value(args, i, "--gff", "a GFF3 file"));
+                i += 2;
+            },
+            "--impact" => {
+                options.impact = Some(option_value(args, i, "--impact", "a filename"));
+                i += 2;
+            },
+            "--promoter" => {
+                let value = option_value(args, i, "--promoter", "a length");
+                options.promoter = match units::parse_bases(&value) {
+                    Ok(length) => length,
+                    Err(_) => {
+                        eprintln!("Error: --promoter must be a length");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--coverage" => {
+                options.coverage = Some(option_value(args, i, "--coverage", "a filename"));
+                i += 2;
+            },
+            "--diploid" => {
+                options.diploid = true;
+                i += 1;
+            },
+            "--sex" => {
+                let value = option_value(args, i, "--sex", "XX or XY");
+                options.sex = Some(exit_on_error(haplotype::Sex::parse(&value)));
+                i += 2;
+            },
+            "--par" => {
+                options.par = Some(option_value(args, i, "--par", "a BED file"));
+                i += 2;
+            },
+            "--gfa" => {
+                options.gfa = Some(option_value(args, i, "--gfa", "a filename"));
+                i += 2;
+            },
+            "--diagram" => {
+                options.diagram = Some(option_value(args, i, "--diagram", "a filename"));
+                i += 2;
+            },
+            "--scramble-control" => {
+                options.scramble_control = Some(option_value(args, i, "--scramble-control", "a filename"));
+                i += 2;
+            },
+            "--matched-control" => {
+                options.matched_control = Some(option_value(args, i, "--matched-control", "a filename"));
+                i += 2;
+            },
+            "--control-manifest" => {
+                options.control_manifest = Some(option_value(args, i, "--control-manifest", "a filename"));
+                i += 2;
+            },
+            "--exclude" => {
+                options.exclude = Some(option_value(args, i, "--exclude", "a BED file"));
+                i += 2;
+            },
+            "--rule-of-six" => {
+                options.rule_of_six = true;
+                i += 1;
+            },
+            "--segments-bed" => {
+                options.segments_bed = Some(option_value(args, i, "--segments-bed", "a filename"));
+                i += 2;
+            },
+            "--format" => {
+                let name = option_value(args, i, "--format", "a format name");
+                exit_on_error(writers::by_name(&name));
+                options.format = Some(name);
+                i += 2;
+            },
+            "--compress" => {
+                let name = option_value(args, i, "--compress", "a compression");
+                options.compress = Some(exit_on_error(compress::by_name(&name)));
+                i += 2;
+            },
+            "--genbank" => {
+                options.genbank = Some(option_value(args, i, "--genbank", "a filename"));
+                i += 2;
+            },
+            "--stats" => {
+                options.stats = Some(option_value(args, i, "--stats", "a filename"));
+                i += 2;
+            },
+            "--max-identity-change" => {
+                let value = option_value(args, i, "--max-identity-change", "a fraction");
+                options.max_identity_change = match value.parse::<f64>() {
+                    Ok(fraction) if (0.0..=1.0).contains(&fraction) => Some(fraction),
+                    _ => {
+                        eprintln!("Error: --max-identity-change must be a number between 0 and 1");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--max-affected-fraction" => {
+                let value = option_value(args, i, "--max-affected-fraction", "a fraction");
+                options.max_affected_fraction = match value.parse::<f64>() {
+                    Ok(fraction) if (0.0..=1.0).contains(&fraction) => Some(fraction),
+                    _ => {
+                        eprintln!("Error: --max-affected-fraction must be a number between 0 and 1");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--max-memory" => {
+                let value = option_value(args, i, "--max-memory", "a size in MB");
+                options.max_memory = match value.parse::<u64>() {
+                    Ok(megabytes) if megabytes > 0 => Some(megabytes * stream::MEGABYTE),
+                    _ => {
+                        eprintln!("Error: --max-memory must be a positive whole number of MB");
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--gtf" => {
+                options.gtf = Some(option_value(args, i, "--gtf", "a GTF file"));
+                i += 2;
+            },
+            "--transcript" => {
+                options.transcript = Some(option_value(args, i, "--transcript", "a transcript ID"));
+                i += 2;
+            },
+            "--cdna" => {
+                options.cdna = true;
+                i += 1;
+            },
+            "--disallow-nested" => {
+                options.disallow_nested = true;
+                i += 1;
+            },
+            "--canonical" => {
+                options.canonical = true;
+                i += 1;
+            },
+            "--preserve-case" => {
+                options.preserve_case = true;
+                i += 1;
+            },
+            "--strip-gaps" => {
+                options.strip_gaps = true;
+                i += 1;
+            },
+            "--trim-terminal-ns" => {
+                options.trim_terminal_ns = true;
+                i += 1;
+            },
+            "--assume-complete-termini" => {
+                options.complete_termini = match option_value(args, i, "--assume-complete-termini", "yes or no").as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    value => {
+                        eprintln!("Error: Unknown --assume-complete-termini value '{}'. Use 'yes' or 'no'", value);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--gap-map" => {
+                options.gap_map = Some(option_value(args, i, "--gap-map", "a filename"));
+                i += 2;
+            },
+            "--op-json" => {
+                options.op_json = Some(option_value(args, i, "--op-json", "a JSON file, or - for stdin"));
+                i += 2;
+            },
+            "--control-fd" => {
+                let value = option_value(args, i, "--control-fd", "a file descriptor");
+                options.control_fd = match value.parse() {
+                    Ok(fd) => Some(fd),
+                    Err(_) => {
+                        eprintln!("Error: --control-fd requires a file descriptor number, not '{}'", value);
+                        staging::exit(1);
+                    }
+                };
+                i += 2;
+            },
+            "--stem-energy" => {
+                options.stem_energy = true;
+                i += 1;
+            },
+            "--all" | "--all-records" => {
+                options.all = true;
+                i += 1;
+            },
+            "--seq" => {
+                options.seq = Some(option_value(args, i, "--seq", "a sequence ID"));
+                i += 2;
+            },
+            "--expect-input-md5" => {
+                options.expect_input_md5 = Some(option_value(args, i, "--expect-input-md5", "an MD5 hash"));
+                i += 2;
             },
             _ => {
                 remaining_args.push(args[i].clone());
@@ -108,499 +4230,82 @@ fn parse_output_option(args: &[String]) -> (Option<String>, Vec<String>) {
         }
     }
     
-    (output_file, remaining_args)
-}
-
-fn complement_base(base: char) -> char {
-    match base.to_ascii_uppercase() {
-        'A' => 'T',
-        'T' => 'A',
-        'C' => 'G',
-        'G' => 'C',
-        'N' => 'N',
-        _ => base, // Keep any other characters as-is
+    options.seed = seed.unwrap_or_else(rng::random_seed);
+    if (options.sex.is_some() || options.par.is_some()) && !options.diploid {
+        eprintln!("Error: --sex and --par describe the haplotypes written by --diploid");
+        staging::exit(1);
     }
-}
-
-fn parse_operation(args: &[String]) -> Result<Operation, String> {
-    if args.is_empty() {
-        return Err("No operation specified".to_string());
+    if options.phased_snps.is_some() && !options.diploid {
+        eprintln!("Error: --phased-snps builds the haplotypes written by --diploid");
+        staging::exit(1);
     }
-    
-    match args[0].as_str() {
-        "delete" => {
-            if args.len() != 3 {
-                return Err("Delete operation requires start and end positions".to_string());
-            }
-            let start: usize = args[1].parse().map_err(|_| "Start position must be a number")?;
-            let end: usize = args[2].parse().map_err(|_| "End position must be a number")?;
-            
-            if start == 0 || end == 0 {
-                return Err("Positions must be 1-based (starting from 1)".to_string());
-            }
-            if start > end {
-                return Err("Start position must be <= end position".to_string());
-            }
-            
-            Ok(Operation::Delete { start, end })
-        },
-        "insert" => {
-            if args.len() != 3 {
-                return Err("Insert operation requires position and sequence".to_string());
-            }
-            let position: usize = args[1].parse().map_err(|_| "Position must be a number")?;
-            let sequence = args[2].clone();
-            
-            if position == 0 {
-                return Err("Position must be 1-based (starting from 1)".to_string());
-            }
-            
-            // Validate sequence contains only valid DNA bases
-            if !sequence.chars().all(|c| matches!(c.to_ascii_uppercase(), 'A' | 'T' | 'C' | 'G' | 'N')) {
-                return Err("Sequence must contain only valid DNA bases (A, T, C, G, N)".to_string());
-            }
-            
-            Ok(Operation::Insert { position, sequence: sequence.to_uppercase() })
-        },
-        "invert" => {
-            let mut complement = false;
-            let mut pos_args = Vec::new();
-            
-            // Parse arguments, looking for --complement flag
-            for arg in &args[1..] {
-                if arg == "--complement" {
-                    complement = true;
-                } else {
-                    pos_args.push(arg);
-                }
-            }
-            
-            if pos_args.len() != 2 {
-                return Err("Invert operation requires start and end positions".to_string());
-            }
-            
-            let start: usize = pos_args[0].parse().map_err(|_| "Start position must be a number")?;
-            let end: usize = pos_args[1].parse().map_err(|_| "End position must be a number")?;
-            
-            if start == 0 || end == 0 {
-                return Err("Positions must be 1-based (starting from 1)".to_string());
-            }
-            if start > end {
-                return Err("Start position must be <= end position".to_string());
-            }
-            
-            Ok(Operation::Invert { start, end, complement })
-        },
-        "duplicate" => {
-            let mut tandem = false;
-            let mut pos_args = Vec::new();
-            
-            // Parse arguments, looking for -td flag
-            for arg in &args[1..] {
-                if arg == "-td" {
-                    tandem = true;
-                } else {
-                    pos_args.push(arg);
-                }
-            }
-            
-            if tandem {
-                // Tandem duplication: duplicate <start> <end>
-                if pos_args.len() != 2 {
-                    return Err("Tandem duplicate operation requires start and end positions".to_string());
-                }
-                
-                let start: usize = pos_args[0].parse().map_err(|_| "Start position must be a number")?;
-                let end: usize = pos_args[1].parse().map_err(|_| "End position must be a number")?;
-                
-                if start == 0 || end == 0 {
-                    return Err("Positions must be 1-based (starting from 1)".to_string());
-                }
-                if start > end {
-                    return Err("Start position must be <= end position".to_string());
-                }
-                
-                Ok(Operation::TandemDuplicate { start, end })
-            } else {
-                // Regular duplication: duplicate <start> <end> <position>
-                if pos_args.len() != 3 {
-                    return Err("Duplicate operation requires start, end, and insert positions".to_string());
-                }
-                
-                let start: usize = pos_args[0].parse().map_err(|_| "Start position must be a number")?;
-                let end: usize = pos_args[1].parse().map_err(|_| "End position must be a number")?;
-                let position: usize = pos_args[2].parse().map_err(|_| "Insert position must be a number")?;
-                
-                if start == 0 || end == 0 || position == 0 {
-                    return Err("Positions must be 1-based (starting from 1)".to_string());
-                }
-                if start > end {
-                    return Err("Start position must be <= end position".to_string());
-                }
-                
-                Ok(Operation::Duplicate { start, end, position })
-            }
-        },
-        "copyback" => {
-            let mut snapback = false;
-            let mut pos_args = Vec::new();
-            
-            // Parse arguments, looking for -sb flag
-            for arg in &args[1..] {
-                if arg == "-sb" {
-                    snapback = true;
-                } else {
-                    pos_args.push(arg);
-                }
-            }
-            
-            if snapback {
-                // Snapback mode: copyback <gend> <breakpoint>
-                if pos_args.len() != 2 {
-                    return Err("Copyback with -sb flag requires gend and breakpoint".to_string());
-                }
-                
-                let gend_str = &pos_args[0];
-                let gend: u8 = match gend_str.as_str() {
-                    "5" => 5,
-                    "3" => 3,
-                    _ => return Err("gend must be either 5 or 3".to_string())
-                };
-                
-                let breakpoint: usize = pos_args[1].parse().map_err(|_| "Breakpoint must be a number")?;
-                
-                if breakpoint == 0 {
-                    return Err("Breakpoint must be 1-based (starting from 1)".to_string());
-                }
-                
-                Ok(Operation::Copyback { gend, breakpoint, backstart: breakpoint })
-            } else {
-                // Regular copyback: copyback <gend> <breakpoint> <backstart>
-                if pos_args.len() != 3 {
-                    return Err("Copyback operation requires gend, breakpoint, and backstart".to_string());
-                }
-                
-                let gend_str = &pos_args[0];
-                let gend: u8 = match gend_str.as_str() {
-                    "5" => 5,
-                    "3" => 3,
-                    _ => return Err("gend must be either 5 or 3".to_string())
-                };
-                
-                let breakpoint: usize = pos_args[1].parse().map_err(|_| "Breakpoint must be a number")?;
-                let backstart: usize = pos_args[2].parse().map_err(|_| "Backstart must be a number")?;
-                
-                if breakpoint == 0 || backstart == 0 {
-                    return Err("Positions must be 1-based (starting from 1)".to_string());
-                }
-                
-                // Validate backstart position relative to breakpoint based on gend
-                if gend == 5 && backstart >= breakpoint {
-                    return Err("For 5' end, backstart must be less than breakpoint".to_string());
-                }
-                if gend == 3 && backstart >= breakpoint {
-                    return Err("For 3' end, backstart must be less than breakpoint".to_string());
-                }
-                
-                Ok(Operation::Copyback { gend, breakpoint, backstart })
-            }
-        },
-        _ => Err(format!("Unknown operation '{}'. Use 'delete', 'insert', 'invert', 'duplicate', or 'copyback'", args[0]))
+    if options.snp_sample.is_some() && options.phased_snps.is_none() {
+        eprintln!("Error: --snp-sample selects the sample of --phased-snps");
+        staging::exit(1);
     }
-}
-
-fn read_fasta(filename: &str) -> std::io::Result<(String, String)> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
-    
-    // Read header
-    let header = match lines.next() {
-        Some(line) => {
-            let line = line?;
-            if !line.starts_with('>') {
-                eprintln!("Error: File does not appear to be a valid FASTA file (no header starting with '>')");
-                std::process::exit(1);
-            }
-            line
-        },
-        None => {
-            eprintln!("Error: File is empty");
-            std::process::exit(1);
-        }
-    };
-    
-    // Read sequence
-    let mut sequence = String::new();
-    let mut sequence_count = 0;
-    
-    for line in lines {
-        let line = line?;
-        if line.starts_with('>') {
-            sequence_count += 1;
-            if sequence_count > 0 {
-                eprintln!("Error: FASTA file contains multiple sequences. Only single-sequence files are supported.");
-                std::process::exit(1);
-            }
-        } else {
-            // Remove whitespace and convert to uppercase
-            sequence.push_str(&line.trim().to_uppercase());
-        }
+    if options.canonical && options.line_width.is_some() {
+        eprintln!("Error: --canonical always wraps at {} bases and cannot be combined with --line-width", CANONICAL_WIDTH);
+        staging::exit(1);
     }
-    
-    if sequence.is_empty() {
-        eprintln!("Error: No sequence found in FASTA file");
-        std::process::exit(1);
+    if options.genbank.is_some() && options.alphabet.is_protein() {
+        eprintln!("Error: --genbank writes nucleotide constructs and cannot be used with protein sequences");
+        staging::exit(1);
     }
-    
-    Ok((header, sequence))
-}
-
-fn read_fasta_from_stdin() -> std::io::Result<(String, String)> {
-    let stdin = stdin();
-    let reader = stdin.lock();
-    let mut lines = reader.lines();
-    
-    // Read header
-    let header = match lines.next() {
-        Some(line) => {
-            let line = line?;
-            if !line.starts_with('>') {
-                eprintln!("Error: Input does not appear to be a valid FASTA file (no header starting with '>')");
-                std::process::exit(1);
-            }
-            line
-        },
-        None => {
-            eprintln!("Error: No input provided");
-            std::process::exit(1);
-        }
-    };
-    
-    // Read sequence
-    let mut sequence = String::new();
-    let mut sequence_count = 0;
-    
-    for line in lines {
-        let line = line?;
-        if line.starts_with('>') {
-            sequence_count += 1;
-            if sequence_count > 0 {
-                eprintln!("Error: Input contains multiple sequences. Only single-sequence files are supported.");
-                std::process::exit(1);
-            }
-        } else {
-            // Remove whitespace and convert to uppercase
-            sequence.push_str(&line.trim().to_uppercase());
-        }
+    if options.canonical && options.preserve_case {
+        eprintln!("Error: --canonical uppercases the output and cannot be combined with --preserve-case");
+        staging::exit(1);
     }
-    
-    if sequence.is_empty() {
-        eprintln!("Error: No sequence found in input");
-        std::process::exit(1);
+    if options.seq.is_some() && (options.all || options.region.is_some()) {
+        eprintln!("Error: --seq selects one record to edit and cannot be combined with --all or --region");
+        staging::exit(1);
+    }
+    if options.transcript.is_some() && options.gtf.is_none() {
+        eprintln!("Error: --transcript requires --gtf");
+        staging::exit(1);
+    }
+    if options.cdna && options.transcript.is_none() {
+        eprintln!("Error: --cdna requires --gtf and --transcript");
+        staging::exit(1);
+    }
+    if options.transcript.is_some() {
+        let conflicts = [
+            (options.alphabet.is_protein(), "protein sequences"), (options.region.is_some(), "--region"),
+            (options.all, "--all"), (options.seq.is_some(), "--seq"),
+            (options.cdna && options.journal.is_some(), "--journal when editing the cDNA"),
+        ];
+        if let Some((_, what)) = conflicts.iter().find(|(set, _)| *set) {
+            eprintln!("Error: --transcript cannot be combined with {}", what);
+            staging::exit(1);
+        }
     }
     
-    Ok((header, sequence))
+    (options, remaining_args)
 }
 
-fn apply_operation(header: &str, sequence: &str, operation: Operation) -> (String, String) {
-    match operation {
-        Operation::Delete { start, end } => {
-            // Convert to 0-based indexing
-            let start_idx = start - 1;
-            let end_idx = end; // end is inclusive in 1-based, so end_idx is exclusive in 0-based
-            
-            if end_idx > sequence.len() {
-                eprintln!("Error: End position {} is beyond sequence length {}", end, sequence.len());
-                std::process::exit(1);
-            }
-            
-            let new_sequence = format!("{}{}", &sequence[..start_idx], &sequence[end_idx..]);
-            let deleted_length = end_idx - start_idx;
-            let new_header = format!("{} [deleted {}bp at positions {}-{}]", header, deleted_length, start, end);
-            
-            (new_header, new_sequence)
-        },
-        Operation::Insert { position, sequence: insert_seq } => {
-            // Convert to 0-based indexing
-            let insert_idx = position - 1;
-            
-            if insert_idx > sequence.len() {
-                eprintln!("Error: Insert position {} is beyond sequence length {}", position, sequence.len());
-                std::process::exit(1);
-            }
-            
-            let new_sequence = format!("{}{}{}", &sequence[..insert_idx], &insert_seq, &sequence[insert_idx..]);
-            let new_header = format!("{} [inserted {}bp '{}' at position {}]", header, insert_seq.len(), insert_seq, position);
-            
-            (new_header, new_sequence)
-        },
-        Operation::Invert { start, end, complement } => {
-            // Convert to 0-based indexing
-            let start_idx = start - 1;
-            let end_idx = end; // end is inclusive in 1-based, so end_idx is exclusive in 0-based
-            
-            if end_idx > sequence.len() {
-                eprintln!("Error: End position {} is beyond sequence length {}", end, sequence.len());
-                std::process::exit(1);
-            }
-            
-            // Extract the region to invert
-            let before = &sequence[..start_idx];
-            let to_invert = &sequence[start_idx..end_idx];
-            let after = &sequence[end_idx..];
-            
-            // Process the region based on complement flag
-            let processed: String = if complement {
-                // Reverse complement: reverse and complement each base
-                to_invert.chars().rev().map(complement_base).collect()
-            } else {
-                // Just reverse
-                to_invert.chars().rev().collect()
-            };
-            
-            let new_sequence = format!("{}{}{}", before, processed, after);
-            let inverted_length = end_idx - start_idx;
-            let operation_desc = if complement {
-                "reverse complemented"
-            } else {
-                "inverted"
-            };
-            let new_header = format!("{} [{} {}bp at positions {}-{}]", header, operation_desc, inverted_length, start, end);
-            
-            (new_header, new_sequence)
-        },
-        Operation::Duplicate { start, end, position } => {
-            // Convert to 0-based indexing
-            let start_idx = start - 1;
-            let end_idx = end; // end is inclusive in 1-based, so end_idx is exclusive in 0-based
-            let insert_idx = position - 1;
-            
-            if end_idx > sequence.len() {
-                eprintln!("Error: End position {} is beyond sequence length {}", end, sequence.len());
-                std::process::exit(1);
-            }
-            
-            if insert_idx > sequence.len() {
-                eprintln!("Error: Insert position {} is beyond sequence length {}", position, sequence.len());
-                std::process::exit(1);
-            }
-            
-            // Extract the segment to duplicate
-            let segment = &sequence[start_idx..end_idx];
-            
-            // Insert the duplicated segment at the specified position
-            let new_sequence = format!("{}{}{}", &sequence[..insert_idx], segment, &sequence[insert_idx..]);
-            let duplicated_length = end_idx - start_idx;
-            let new_header = format!("{} [duplicated {}bp from positions {}-{} to position {}]", header, duplicated_length, start, end, position);
-            
-            (new_header, new_sequence)
-        },
-        Operation::TandemDuplicate { start, end } => {
-            // Convert to 0-based indexing
-            let start_idx = start - 1;
-            let end_idx = end; // end is inclusive in 1-based, so end_idx is exclusive in 0-based
-            
-            if end_idx > sequence.len() {
-                eprintln!("Error: End position {} is beyond sequence length {}", end, sequence.len());
-                std::process::exit(1);
-            }
-            
-            // Extract the segment to duplicate
-            let segment = &sequence[start_idx..end_idx];
-            
-            // Insert the duplicated segment directly after the original segment
-            let new_sequence = format!("{}{}{}{}", &sequence[..start_idx], segment, segment, &sequence[end_idx..]);
-            let duplicated_length = end_idx - start_idx;
-            let new_header = format!("{} [tandem duplicated {}bp at positions {}-{}]", header, duplicated_length, start, end);
-            
-            (new_header, new_sequence)
-        },
-        Operation::Copyback { gend, breakpoint, backstart } => {
-            // Convert to 0-based indexing
-            let breakpoint_idx = breakpoint - 1;
-            let backstart_idx = backstart - 1;
-            
-            if breakpoint > sequence.len() {
-                eprintln!("Error: Breakpoint {} is beyond sequence length {}", breakpoint, sequence.len());
-                std::process::exit(1);
-            }
-            
-            if backstart > sequence.len() {
-                eprintln!("Error: Backstart {} is beyond sequence length {}", backstart, sequence.len());
-                std::process::exit(1);
-            }
-            
-            let new_sequence = if gend == 5 {
-                // 5' end processing
-                // Keep sequence up to breakpoint
-                let kept_part = &sequence[..breakpoint_idx + 1];
-                
-                // Get reverse complement from backstart to beginning (5' end)
-                let copyback_part = &sequence[..backstart_idx + 1];
-                let reverse_complement: String = copyback_part.chars().rev().map(complement_base).collect();
-                
-                format!("{}{}", kept_part, reverse_complement)
-            } else {
-                // 3' end processing
-                // First reverse complement the entire sequence
-                let rev_comp_sequence: String = sequence.chars().rev().map(complement_base).collect();
-                
-                // Now apply same logic as 5' end to the reverse complemented sequence
-                // Keep sequence up to breakpoint
-                let kept_part = &rev_comp_sequence[..breakpoint_idx + 1];
-                
-                // Get reverse complement from backstart to beginning
-                let copyback_part = &rev_comp_sequence[..backstart_idx + 1];
-                let reverse_complement: String = copyback_part.chars().rev().map(complement_base).collect();
-                
-                format!("{}{}", kept_part, reverse_complement)
-            };
-            
-            let operation_desc = if gend == 5 {
-                if backstart == breakpoint {
-                    format!("5' copyback (snapback) at position {}", breakpoint)
-                } else {
-                    format!("5' copyback up to position {} then reverse complement of position {} on", breakpoint, backstart)
-                }
-            } else {
-                if backstart == breakpoint {
-                    format!("3' copyback (snapback) at position {} of reference revcomp", breakpoint)
-                } else {
-                    format!("3' copyback up to position {} of reference revcomp then reverse complement of position {} on", breakpoint, backstart)
-                }
-            };
-            
-            let new_header = format!("{} [{}]", header, operation_desc);
-            
-            (new_header, new_sequence)
+/// Parses an `--insert-qual` value: either a single Phred+33 quality
+/// character or a numeric Phred score.
+fn parse_quality(value: &str) -> Result<char, String> {
+    if let Ok(score) = value.parse::<u8>() {
+        if score > 93 {
+            return Err("Phred quality score must be between 0 and 93".to_string());
         }
+        return Ok((score + 33) as char);
     }
-}
-
-fn write_fasta_to_stdout(header: &str, sequence: &str) -> std::io::Result<()> {
-    let stdout = io::stdout();
-    let mut handle = stdout.lock();
-    
-    writeln!(handle, "{}", header)?;
-    
-    // Write sequence in 70-character lines (standard FASTA format)
-    for chunk in sequence.as_bytes().chunks(70) {
-        writeln!(handle, "{}", std::str::from_utf8(chunk).unwrap())?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if ('!'..='~').contains(&c) => Ok(c),
+        _ => Err(format!("Invalid quality value '{}': use a single character from '!' to '~' or a Phred score", value)),
     }
-    
-    Ok(())
 }
 
-fn write_fasta_to_file(header: &str, sequence: &str, filename: &str) -> std::io::Result<()> {
-    let mut file = File::create(filename)?;
-    
-    writeln!(file, "{}", header)?;
-    
-    // Write sequence in 70-character lines (standard FASTA format)
-    for chunk in sequence.as_bytes().chunks(70) {
-        writeln!(file, "{}", std::str::from_utf8(chunk).unwrap())?;
+/// Returns the value following the flag at `args[i]`, exiting if it is missing.
+fn option_value(args: &[String], i: usize, flag: &str, what: &str) -> String {
+    match args.get(i + 1) {
+        Some(value) => value.clone(),
+        None => {
+            eprintln!("Error: {} requires {}", flag, what);
+            staging::exit(1);
+        }
     }
-    
-    Ok(())
 }
+
@@ -0,0 +1,146 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::layout::Segment;
+use crate::rng::Rng;
+use crate::staging;
+
+/// Bases either side of a CpG whose composition decides whether it lies in
+/// a CpG island.
+const ISLAND_FLANK: usize = 100;
+
+/// Reads per site the bedMethyl counts are given for, so that the percent
+/// modified is the site's probability.
+const NOMINAL_COVERAGE: usize = 100;
+
+/// Probability that each base is a modified (5mC) cytosine, on the forward
+/// strand and on the reverse strand, where it is one; `None` elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub forward: Vec<Option<f64>>,
+    pub reverse: Vec<Option<f64>>,
+}
+
+fn is_base(byte: u8, base: u8) -> bool {
+    byte.eq_ignore_ascii_case(&base)
+}
+
+/// Draws a methylation level for every CpG of `sequence`: 0-0.2 in CpG
+/// islands, 0.7-1 elsewhere, as in mammalian genomes. A CpG is in an island
+/// when the bases within `ISLAND_FLANK` of it are at least half G+C, with at
+/// least 0.6 of the CpGs that composition would give by chance. Both
+/// cytosines of a CpG get the same level.
+pub fn simulate(sequence: &str, rng: &mut Rng) -> Track {
+    let bases = sequence.as_bytes();
+    let mut track = Track { forward: vec![None; bases.len()], reverse: vec![None; bases.len()] };
+    // Counts of C, G and CpG (by the position of its C) over bases lo..hi
+    let (mut lo, mut hi) = (0, 0);
+    let (mut c, mut g, mut cpg) = (0usize, 0usize, 0usize);
+    let is_cpg = |i: usize| i + 1 < bases.len() && is_base(bases[i], b'C') && is_base(bases[i + 1], b'G');
+    for i in 0..bases.len().saturating_sub(1) {
+        if !is_cpg(i) {
+            continue;
+        }
+        let (start, end) = (i.saturating_sub(ISLAND_FLANK), (i + 2 + ISLAND_FLANK).min(bases.len()));
+        while hi < end {
+            c += is_base(bases[hi], b'C') as usize;
+            g += is_base(bases[hi], b'G') as usize;
+            if hi > lo && is_cpg(hi - 1) {
+                cpg += 1;
+            }
+            hi += 1;
+        }
+        while lo < start {
+            c -= is_base(bases[lo], b'C') as usize;
+            g -= is_base(bases[lo], b'G') as usize;
+            if lo + 1 < hi && is_cpg(lo) {
+                cpg -= 1;
+            }
+            lo += 1;
+        }
+        let length = (hi - lo) as f64;
+        let gc = (c + g) as f64 / length;
+        let observed_expected = cpg as f64 * length / (c * g).max(1) as f64;
+        let level = if gc >= 0.5 && observed_expected >= 0.6 {
+            0.2 * rng.unit()
+        } else {
+            0.7 + 0.3 * rng.unit()
+        };
+        track.forward[i] = Some(level);
+        track.reverse[i + 1] = Some(level);
+    }
+    track
+}
+
+/// The levels of `track` carried through `segments` onto the edited
+/// sequence. Each base keeps its own levels, swapped between the strands
+/// where it is read complemented; novel bases have none.
+pub fn remap(track: &Track, segments: &[Segment]) -> Track {
+    let mut remapped = Track::default();
+    for segment in segments {
+        match *segment {
+            Segment::Source { start, end, reverse, complement } => {
+                let positions: Box<dyn Iterator<Item = usize>> = if reverse {
+                    Box::new((start..end).rev())
+                } else {
+                    Box::new(start..end)
+                };
+                for j in positions {
+                    let (forward, reverse) = (track.forward[j], track.reverse[j]);
+                    let (forward, reverse) = if complement { (reverse, forward) } else { (forward, reverse) };
+                    remapped.forward.push(forward);
+                    remapped.reverse.push(reverse);
+                }
+            },
+            Segment::Novel(ref novel) => {
+                remapped.forward.extend(std::iter::repeat_n(None, novel.len()));
+                remapped.reverse.extend(std::iter::repeat_n(None, novel.len()));
+            },
+        }
+    }
+    remapped
+}
+
+/// Gives the CpGs of `sequence` that `track` has no level for, those the
+/// edits created, a level drawn from the model of `simulate`.
+pub fn fill_new_sites(track: &mut Track, sequence: &str, rng: &mut Rng) {
+    let bases = sequence.as_bytes();
+    let new_site = |i: usize| is_base(bases[i], b'C') && is_base(bases[i + 1], b'G')
+        && (track.forward[i].is_none() || track.reverse[i + 1].is_none());
+    if !(0..bases.len().saturating_sub(1)).any(new_site) {
+        return;
+    }
+    let drawn = simulate(sequence, rng);
+    for i in 0..bases.len().saturating_sub(1) {
+        if drawn.forward[i].is_some() {
+            track.forward[i] = track.forward[i].or(drawn.forward[i]);
+            track.reverse[i + 1] = track.reverse[i + 1].or(drawn.reverse[i + 1]);
+        }
+    }
+}
+
+/// Writes the `--methylation` bedMethyl file: one line per modified-base
+/// site of each `(chrom, track)`.
+pub fn write_bedmethyl(path: &str, tracks: &[(&str, Track)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    emit_bedmethyl(&mut out, tracks)?;
+    out.flush()
+}
+
+/// Writes 5mC (`m`) sites in the 18-column bedMethyl layout of modkit, in
+/// position order with the forward strand first. Counts are for
+/// `NOMINAL_COVERAGE` reads, so the percent modified is the site's level.
+pub fn emit_bedmethyl(out: &mut dyn Write, tracks: &[(&str, Track)]) -> io::Result<()> {
+    for (chrom, track) in tracks {
+        for (i, (forward, reverse)) in track.forward.iter().zip(&track.reverse).enumerate() {
+            for (level, strand) in [(forward, '+'), (reverse, '-')] {
+                if let Some(level) = level {
+                    let modified = (level * NOMINAL_COVERAGE as f64).round() as usize;
+                    writeln!(out, "{}\t{}\t{}\tm\t{}\t{}\t{}\t{}\t255,0,0\t{}\t{:.2}\t{}\t{}\t0\t0\t0\t0\t0", chrom, i, i + 1,
+                             NOMINAL_COVERAGE, strand, i, i + 1, NOMINAL_COVERAGE, level * 100.0, modified,
+                             NOMINAL_COVERAGE - modified)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Default minimum ORF length for `orfs`, in codons (stop excluded).
+pub const DEFAULT_MIN_CODONS: usize = 100;
+
+/// An open reading frame: an ATG and the codons up to and including the
+/// first in-frame stop.
+#[derive(Debug, Clone)]
+pub struct Orf {
+    /// 0-based, half-open bounds on the forward strand, stop included
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+    /// Offset of the frame from the 5' end of its strand (0, 1 or 2)
+    pub frame: usize,
+    /// Length in codons, without the stop
+    pub codons: usize,
+    /// The bases from the ATG through the stop, read on its strand
+    pub coding: String,
+}
+
+/// How an ORF of the derived genome compares with the original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// No reference was given
+    Unknown,
+    /// The same coding sequence is an ORF of the original
+    Unchanged,
+    /// Only in the derived genome
+    Created,
+    /// Only in the original (coordinates are on the original)
+    Destroyed,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Unknown => ".",
+            Status::Unchanged => "unchanged",
+            Status::Created => "created",
+            Status::Destroyed => "destroyed",
+        }
+    }
+}
+
+fn is_stop(codon: &[u8]) -> bool {
+    matches!(codon, b"TAA" | b"TAG" | b"TGA")
+}
+
+/// ORFs of at least `min_codons` codons on both strands. Each starts at the
+/// first ATG after the previous stop in its frame, so ORFs nested in a
+/// longer one are not listed; reading frames that run off the end without a
+/// stop are not ORFs.
+pub fn find_orfs(sequence: &str, min_codons: usize) -> Vec<Orf> {
+    let forward = sequence.to_ascii_uppercase().replace('U', "T");
+    let reverse: String = crate::reverse_complement(&forward);
+    let length = forward.len();
+    let mut orfs = Vec::new();
+    for (strand, is_reverse) in [(forward.as_bytes(), false), (reverse.as_bytes(), true)] {
+        for frame in 0..3 {
+            let mut open = None;
+            for at in (frame..length.saturating_sub(2)).step_by(3) {
+                let codon = &strand[at..at + 3];
+                if open.is_none() && codon == b"ATG" {
+                    open = Some(at);
+                } else if let (Some(begin), true) = (open, is_stop(codon)) {
+                    let codons = (at - begin) / 3;
+                    if codons >= min_codons {
+                        let (start, end) = if is_reverse { (length - at - 3, length - begin) } else { (begin, at + 3) };
+                        let coding = String::from_utf8_lossy(&strand[begin..at + 3]).to_string();
+                        orfs.push(Orf { start, end, reverse: is_reverse, frame, codons, coding });
+                    }
+                    open = None;
+                }
+            }
+        }
+    }
+    orfs.sort_by_key(|orf| (orf.start, orf.reverse));
+    orfs
+}
+
+/// Marks each derived ORF as unchanged or created, by whether an ORF of the
+/// original has the same coding sequence (wherever it now is), and lists the
+/// original's ORFs with no counterpart as destroyed.
+pub fn compare(derived: Vec<Orf>, original: Vec<Orf>) -> Vec<(Orf, Status)> {
+    let mut unmatched: HashMap<String, usize> = HashMap::new();
+    for orf in &original {
+        *unmatched.entry(orf.coding.clone()).or_default() += 1;
+    }
+    let mut compared: Vec<(Orf, Status)> = derived.into_iter().map(|orf| {
+        let status = match unmatched.get_mut(&orf.coding) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                Status::Unchanged
+            },
+            _ => Status::Created,
+        };
+        (orf, status)
+    }).collect();
+    for orf in original {
+        if let Some(count) = unmatched.get_mut(&orf.coding).filter(|count| **count > 0) {
+            *count -= 1;
+            compared.push((orf, Status::Destroyed));
+        }
+    }
+    compared
+}
+
+/// Writes ORFs as TSV, with 1-based, inclusive positions on the forward
+/// strand of the sequence they are on.
+pub fn write_orfs<W: Write>(out: &mut W, orfs: &[(&str, Orf, Status)]) -> io::Result<()> {
+    writeln!(out, "id\tstart\tend\tstrand\tframe\tcodons\tstatus")?;
+    for (id, orf, status) in orfs {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}", id, orf.start + 1, orf.end,
+                 if orf.reverse { '-' } else { '+' }, orf.frame + 1, orf.codons, status.name())?;
+    }
+    Ok(())
+}
+
+/// Number of ORFs with the given status.
+pub fn count(orfs: &[(&str, Orf, Status)], status: Status) -> usize {
+    orfs.iter().filter(|(_, _, s)| *s == status).count()
+}
@@ -0,0 +1,377 @@
+use std::borrow::Cow;
+use std::fs;
+
+use crate::alphabet::Alphabet;
+use crate::expr;
+use crate::fasta::Record;
+use crate::haplotype;
+use crate::layout;
+use crate::rng;
+use crate::schema;
+use crate::vcf::{self, Allele};
+use crate::{
+    check_operation, operation_from_json, operation_name, parse_operation_noted, record_operation, select_targets, Operation,
+    ParseNote, Target,
+};
+
+/// One operation of a plan file, with the plan line it came from.
+#[derive(Debug, Clone)]
+pub struct PlanStep {
+    pub line: usize,
+    pub target: Target,
+    pub operation: Operation,
+    pub allele: Allele,
+    /// The layer the step belongs to, in a layered plan
+    pub layer: Option<Layer>,
+    /// How the line's words were rewritten as they were parsed
+    pub notes: Vec<ParseNote>,
+}
+
+/// A named layer of a layered plan. Its genome is its parent's with the
+/// layer's own steps applied on top.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub parent: Option<String>,
+}
+
+/// The input a plan's `{...}` expressions are evaluated against: its
+/// records, the records a line that names none applies to, and whether the
+/// plan is applied to two haplotypes.
+pub struct PlanInput<'a> {
+    pub records: &'a [Record],
+    pub target: Target,
+    pub diploid: bool,
+}
+
+/// Each record's length as the plan has left it so far, or why it cannot
+/// be known before the plan runs.
+type Lengths = Vec<Result<usize, String>>;
+
+/// Reads a plan file: one operation per line, written exactly as on the
+/// command line (`delete 10 20`). Lines may start with a tab-separated
+/// sequence-ID column naming the record the operation applies to; `*`
+/// applies it to every record and an empty column to the input's only
+/// record. Trailing `af=<fraction>` and `gt=<genotype>` words set the
+/// allele the step is simulated at, and `cipos=<interval>` and
+/// `ciend=<interval>` the confidence of its breakpoints. Blank lines and `#`
+/// comments are ignored.
+///
+/// A `schema_version=<n>` line before the first operation pins the plan to
+/// that version of the operation syntax; plans without one are read as
+/// written before versioning. Either way, lines are migrated to the current
+/// syntax before they are parsed.
+///
+/// A `layer=<name>` line starts a layer: the steps after it build on the
+/// genome of the layer before, or of the layer named by `from=<name>`.
+///
+/// A `<name> = <expression>` line defines a variable, and `{<expression>}`
+/// in an operation is replaced by its value. Expressions are arithmetic on
+/// numbers, the variables defined above and `L` (or `len`), evaluated for
+/// each record the line applies to with `L` its length once the steps
+/// before have been applied (in a layered plan, those of the layer's
+/// lineage). A line on every record becomes one step per record. Without
+/// `input` such lines are only counted, to be checked when the plan runs.
+///
+/// Stochastic operations get a seed derived from `seed` and the line number.
+pub fn read_plan(path: &str, alphabet: Alphabet, seed: u64, input: Option<&PlanInput>) -> Result<Vec<PlanStep>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read plan {}: {}", path, e))?;
+    parse_plan(&text, path, alphabet, seed, input)
+}
+
+/// Parses one line of a `--control-fd` stream: an operation's JSON, as for
+/// `--op-json`, with an optional `"record"` naming the sequence it edits.
+pub fn parse_control_line(text: &str, line: usize, alphabet: Alphabet) -> Result<PlanStep, String> {
+    let mut fields: serde_json::Value = serde_json::from_str(text).map_err(|e| format!("Invalid operation JSON: {}", e))?;
+    let target = match fields.as_object_mut().and_then(|fields| fields.remove("record")) {
+        Some(serde_json::Value::String(id)) => Target::Record(id),
+        Some(_) => return Err("\"record\" must be a sequence ID".to_string()),
+        None => Target::Only,
+    };
+    let operation = check_operation(operation_from_json(&fields.to_string())?, alphabet)?;
+    Ok(PlanStep { line, target, operation, allele: Allele::default(), layer: None, notes: Vec::new() })
+}
+
+/// Parses plan text read from `source` (see `read_plan`).
+pub fn parse_plan(text: &str, source: &str, alphabet: Alphabet, seed: u64, input: Option<&PlanInput>)
+                  -> Result<Vec<PlanStep>, String> {
+    let mut steps = Vec::new();
+    let mut layers: Vec<Layer> = Vec::new();
+    let mut variables: Vec<(String, String)> = Vec::new();
+    let mut unevaluated = 0;
+    let input_lengths: Lengths = input.map_or_else(Vec::new, |input| {
+        input.records.iter().map(|record| Ok(record.sequence.len())).collect()
+    });
+    let mut lengths = input_lengths.clone();
+    // Lengths at the end of each layer, for the layers built on it
+    let mut layer_lengths: Vec<(String, Lengths)> = Vec::new();
+    let layered = text.lines()
+        .any(|line| line.split_whitespace().next().is_some_and(|word| word.starts_with("layer=")));
+    let version = plan_version(text, source)?;
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let content = line.split('#').next().unwrap_or("");
+        if content.trim().is_empty() || content.trim().starts_with("schema_version=") {
+            continue;
+        }
+        let migrated = schema::migrate_plan_line(content, version);
+        let content = migrated.as_str();
+        if let Some((name, expression)) = variable_definition(content) {
+            if name == "L" || name == "len" {
+                return Err(format!("Plan line {}: {} is the sequence length and cannot be redefined", line_number, name));
+            }
+            variables.push((name.to_string(), expression.to_string()));
+            continue;
+        }
+
+        let (target, op_text) = match content.split_once('\t') {
+            Some((id, rest)) => {
+                let target = match id.trim() {
+                    "" => Target::Only,
+                    "*" => Target::All,
+                    id => Target::Record(id.to_string()),
+                };
+                (target, rest)
+            },
+            None => (Target::Only, content),
+        };
+
+        // Lines with expressions are written out for each record they apply to
+        let expansions: Vec<(Target, Cow<str>)> = if !op_text.contains('{') {
+            vec![(target, Cow::Borrowed(op_text))]
+        } else if let Some(input) = input {
+            let selected = match &target {
+                Target::Only => &input.target,
+                target => target,
+            };
+            let indices = select_targets(input.records, selected)
+                .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?;
+            let mut expansions = Vec::new();
+            for &i in &indices {
+                let record = &input.records[i];
+                let expanded = expand_expressions(op_text, lengths[i].as_ref().copied(), &variables)
+                    .map_err(|msg| format!("Plan line {}: {} (sequence '{}')", line_number, msg, record.id()))?;
+                let target = if indices.len() > 1 { Target::Record(record.id().to_string()) } else { target.clone() };
+                expansions.push((target, Cow::Owned(expanded)));
+            }
+            expansions
+        } else {
+            unevaluated += 1;
+            Vec::new()
+        };
+
+        for (target, op_text) in expansions {
+            let mut words: Vec<String> = op_text.split_whitespace().map(str::to_string).collect();
+            if let Some(name) = words[0].strip_prefix("layer=") {
+                let layer = parse_layer(name, &words[1..], &layers)
+                    .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?;
+                if let Some(previous) = layers.last() {
+                    layer_lengths.push((previous.name.clone(), lengths.clone()));
+                }
+                lengths = match &layer.parent {
+                    Some(parent) => layer_lengths.iter().find(|(name, _)| name == parent)
+                        .map_or_else(|| input_lengths.clone(), |(_, lengths)| lengths.clone()),
+                    None => input_lengths.clone(),
+                };
+                layers.push(layer);
+                continue;
+            }
+            if words[0].starts_with("from=") {
+                return Err(format!("Plan line {}: from= goes after layer=<name>", line_number));
+            }
+            let mut allele = Allele::default();
+            while let Some(last) = words.last() {
+                if let Some(fraction) = last.strip_prefix("af=") {
+                    allele.allele_fraction = Some(vcf::parse_allele_fraction(fraction)
+                        .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?);
+                } else if let Some(genotype) = last.strip_prefix("gt=") {
+                    allele.genotype = Some(vcf::parse_genotype(genotype)
+                        .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?);
+                } else if let Some(interval) = last.strip_prefix("cipos=") {
+                    allele.cipos = Some(vcf::parse_interval(interval)
+                        .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?);
+                } else if let Some(interval) = last.strip_prefix("ciend=") {
+                    allele.ciend = Some(vcf::parse_interval(interval)
+                        .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?);
+                } else {
+                    break;
+                }
+                words.pop();
+            }
+            let (operation, notes) = parse_operation_noted(&words, alphabet, rng::derive_seed(seed, line_number as u64))
+                .map_err(|msg| format!("Plan line {}: {}", line_number, msg))?;
+            if layered && layers.is_empty() {
+                return Err(format!("Plan line {}: operations of a layered plan go after a layer= line", line_number));
+            }
+            if let Some(input) = input {
+                advance_lengths(&mut lengths, input, &target, &operation, &allele, line_number);
+            }
+            steps.push(PlanStep { line: line_number, target, operation, allele, layer: layers.last().cloned(), notes });
+
+        }
+    }
+
+    if steps.is_empty() && unevaluated == 0 {
+        return Err(format!("Plan {} contains no operations", source));
+    }
+    if let Some(empty) = layers.iter().find(|layer| !steps.iter().any(|step| step.layer.as_ref() == Some(layer))) {
+        return Err(format!("Layer '{}' in plan {} contains no operations", empty.name, source));
+    }
+    Ok(steps)
+}
+
+/// The name and expression of a `<name> = <expression>` line.
+fn variable_definition(content: &str) -> Option<(&str, &str)> {
+    let (name, expression) = content.split_once('=')?;
+    let name = name.trim();
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    // `layer=<name>`, `af=<fraction>` and the like are written without spaces
+    (valid && content.contains(" = ")).then(|| (name, expression.trim()))
+}
+
+/// Updates `lengths` for a step applying `operation` to `target`: each
+/// record it edits takes the length of its sized layout, and a duplicate
+/// into another record lengthens that record by the copy.
+fn advance_lengths(lengths: &mut Lengths, input: &PlanInput, target: &Target, operation: &Operation, allele: &Allele,
+                   line: usize) {
+    let selected = match target {
+        Target::Only => &input.target,
+        target => target,
+    };
+    let Ok(indices) = select_targets(input.records, selected) else {
+        // An unknown record fails when the plan runs
+        return;
+    };
+    let broadcast = *selected == Target::All;
+    if let Operation::DuplicateInto { from, start, end, to, .. } = operation {
+        let donor = if from.is_empty() { indices.first().copied() } else { input.records.iter().position(|r| r.id() == from) };
+        let Some(to) = input.records.iter().position(|record| record.id() == to) else {
+            return;
+        };
+        lengths[to] = match (donor.map(|donor| &lengths[donor]), &lengths[to]) {
+            (Some(Ok(donor_length)), Ok(length)) => Ok(length + (*end).min(*donor_length).saturating_sub(start - 1)),
+            (_, Err(reason)) | (Some(Err(reason)), _) => Err(reason.clone()),
+            (None, _) => return,
+        };
+        return;
+    }
+    for i in indices {
+        let Ok(length) = lengths[i] else {
+            continue;
+        };
+        let record_op = record_operation(operation, i, broadcast, length);
+        let edited = match layout::sized_layout(&record_op, length) {
+            Ok(segments) => segments.iter().map(layout::Segment::len).sum(),
+            Err(msg) => {
+                lengths[i] = Err(format!("the {} on plan line {} is not laid out from the length alone ({})",
+                                         operation_name(operation), line, msg));
+                continue;
+            },
+        };
+        let both = haplotype::placement(allele.genotype.as_deref(), [true, true]).is_ok_and(|placed| placed == [true, true]);
+        lengths[i] = if input.diploid && !both && edited != length {
+            Err(format!("plan line {} changes the length of one haplotype only", line))
+        } else {
+            Ok(edited)
+        };
+    }
+}
+
+/// `text` with each `{expression}` replaced by its value for a record of
+/// `length` bases, given the variables defined so far. When the length is
+/// not known, using `L` is an error giving the reason.
+fn expand_expressions(text: &str, length: Result<usize, &String>, variables: &[(String, String)]) -> Result<String, String> {
+    let mut values: Vec<(&str, i64)> = match length {
+        Ok(length) => vec![("L", length as i64), ("len", length as i64)],
+        Err(_) => Vec::new(),
+    };
+    let explain = |msg: String| match length {
+        Err(reason) if msg == "unknown name 'L'" || msg == "unknown name 'len'" => {
+            format!("L is not known before the plan runs: {}", reason)
+        },
+        _ => msg,
+    };
+    for (name, expression) in variables {
+        let lookup = |term: &str| values.iter().rev().find(|(name, _)| *name == term).map(|(_, value)| *value);
+        let value = expr::evaluate(expression, &lookup).map_err(|msg| format!("{} in {} = {}", explain(msg), name, expression))?;
+        values.push((name, value));
+    }
+    let lookup = |term: &str| values.iter().rev().find(|(name, _)| *name == term).map(|(_, value)| *value);
+
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..].find('}').ok_or_else(|| format!("unclosed '{{' in '{}'", text.trim()))?;
+        let expression = &rest[open + 1..open + close];
+        let value = expr::evaluate(expression, &lookup).map_err(|msg| format!("{} in {{{}}}", explain(msg), expression))?;
+        if value < 0 {
+            return Err(format!("{{{}}} is negative ({}) for a sequence of length {}", expression, value,
+                               length.map_or("unknown".to_string(), |length| length.to_string())));
+        }
+        expanded.push_str(&rest[..open]);
+        expanded.push_str(&value.to_string());
+        rest = &rest[open + close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The plan text rewritten at the current schema version: lines migrated,
+/// comments kept, and a `schema_version=<n>` line first.
+pub fn migrate_plan(text: &str, source: &str) -> Result<String, String> {
+    let version = plan_version(text, source)?;
+    let mut migrated = format!("schema_version={}\n", schema::SCHEMA_VERSION);
+    for line in text.lines() {
+        let (content, comment) = line.split_at(line.find('#').unwrap_or(line.len()));
+        if content.trim().starts_with("schema_version=") {
+            continue;
+        }
+        let content = if content.trim().is_empty() { content.to_string() } else { schema::migrate_plan_line(content, version) };
+        migrated.push_str(&content);
+        migrated.push_str(comment);
+        migrated.push('\n');
+    }
+    Ok(migrated)
+}
+
+/// The schema version a plan declares with a `schema_version=<n>` line
+/// before its first operation. Plans without one predate versioning.
+fn plan_version(text: &str, source: &str) -> Result<u32, String> {
+    let mut version = None;
+    let mut operations = false;
+    for (i, line) in text.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or("").trim();
+        match content.strip_prefix("schema_version=") {
+            Some(_) if version.is_some() => return Err(format!("Plan line {}: schema_version is declared twice", i + 1)),
+            Some(_) if operations => {
+                return Err(format!("Plan line {}: schema_version goes before the plan's first operation", i + 1));
+            },
+            Some(value) => version = Some(schema::check_plan_version(value, &format!("Plan {}", source))?),
+            None => operations |= !content.is_empty(),
+        }
+    }
+    Ok(version.unwrap_or(schema::UNVERSIONED))
+}
+
+/// Parses the rest of a `layer=<name> [from=<parent>]` line, given the
+/// layers defined before it.
+fn parse_layer(name: &str, words: &[String], layers: &[Layer]) -> Result<Layer, String> {
+    // Layer names end up in file names
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("Layer name '{}' must be letters, digits, '_' or '-'", name));
+    }
+    if layers.iter().any(|layer| layer.name == name) {
+        return Err(format!("Layer '{}' is defined twice", name));
+    }
+    let mut parent = layers.last().map(|layer| layer.name.clone());
+    for word in words {
+        match word.strip_prefix("from=") {
+            Some(from) if layers.iter().any(|layer| layer.name == from) => parent = Some(from.to_string()),
+            Some(from) => return Err(format!("Layer '{}' builds on '{}', which is not defined before it", name, from)),
+            None => return Err(format!("Unexpected '{}' after layer={}", word, name)),
+        }
+    }
+    Ok(Layer { name: name.to_string(), parent })
+}
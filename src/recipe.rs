@@ -0,0 +1,143 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::expr;
+
+/// Recipes that are always available. User recipes with the same name
+/// replace them.
+const BUILTIN_RECIPES: &str = "\
+# Deletion DVG: the first keep5 and last keep3 bases, joined
+[del-dvg keep5 keep3]
+delete {keep5 + 1} {len - keep3}
+
+# 5' copyback DVG: keep bases 1-keep, then a panhandle stem of stem bases
+[cb-dvg keep stem]
+copyback 5 {keep} {stem}
+
+# Inverted tandem duplication: start-end followed by its reverse complement
+[inverted-dup start end]
+duplicate -td {start} {end}
+invert --complement {end + 1} {end + end - start + 1}
+
+# Trim bases off either end
+[trim trim5 trim3]
+keep {trim5 + 1} {len - trim3}
+";
+
+/// A named, parameterized plan. Each line is a plan line whose `{...}`
+/// placeholders are arithmetic on numbers, parameters and `len`, the length
+/// of the sequence being edited.
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub name: String,
+    pub params: Vec<String>,
+    pub lines: Vec<String>,
+}
+
+impl Recipe {
+    /// The recipe's plan text for the given parameter values on a sequence
+    /// of `length` bases.
+    pub fn expand(&self, values: &[(String, usize)], length: usize) -> Result<String, String> {
+        for param in &self.params {
+            if !values.iter().any(|(name, _)| name == param) {
+                return Err(format!("Recipe '{}' requires --{}", self.name, param));
+            }
+        }
+        if let Some((name, _)) = values.iter().find(|(name, _)| !self.params.contains(name)) {
+            return Err(format!("Recipe '{}' has no parameter --{} (parameters: {})",
+                               self.name, name, self.usage()));
+        }
+
+        let mut plan = String::new();
+        for line in &self.lines {
+            let mut rest = line.as_str();
+            while let Some(open) = rest.find('{') {
+                let close = rest[open..].find('}')
+                    .ok_or_else(|| format!("Recipe '{}': unclosed '{{' in '{}'", self.name, line))?;
+                plan.push_str(&rest[..open]);
+                let value = self.evaluate(&rest[open + 1..open + close], values, length)?;
+                plan.push_str(&value.to_string());
+                rest = &rest[open + close + 1..];
+            }
+            plan.push_str(rest);
+            plan.push('\n');
+        }
+        Ok(plan)
+    }
+
+    /// Parameters as they are passed on the command line.
+    pub fn usage(&self) -> String {
+        self.params.iter().map(|param| format!("--{} <n>", param)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn evaluate(&self, expression: &str, values: &[(String, usize)], length: usize) -> Result<i64, String> {
+        let lookup = |name: &str| match name {
+            "len" => Some(length as i64),
+            name => values.iter().find(|(param, _)| param == name).map(|(_, value)| *value as i64),
+        };
+        let total = expr::evaluate(expression, &lookup)
+            .map_err(|msg| format!("Recipe '{}': {} in {{{}}}", self.name, msg, expression))?;
+        if total < 0 {
+            return Err(format!("Recipe '{}': {{{}}} is negative ({}) for a sequence of length {}",
+                               self.name, expression, total, length));
+        }
+        Ok(total)
+    }
+}
+
+/// Parses recipe definitions: a `[name param...]` header followed by the
+/// plan lines of that recipe. Blank lines and `#` comments are ignored.
+pub fn parse_recipes(text: &str, source: &str) -> Result<Vec<Recipe>, String> {
+    let mut recipes: Vec<Recipe> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(header) = content.strip_prefix('[') {
+            let header = header.strip_suffix(']')
+                .ok_or_else(|| format!("{} line {}: recipe header must end with ']'", source, i + 1))?;
+            let mut words = header.split_whitespace().map(str::to_string);
+            let name = words.next()
+                .ok_or_else(|| format!("{} line {}: recipe header needs a name", source, i + 1))?;
+            recipes.push(Recipe { name, params: words.collect(), lines: Vec::new() });
+        } else {
+            match recipes.last_mut() {
+                Some(recipe) => recipe.lines.push(content.to_string()),
+                None => return Err(format!("{} line {}: operation outside a [recipe] section", source, i + 1)),
+            }
+        }
+    }
+    if let Some(empty) = recipes.iter().find(|recipe| recipe.lines.is_empty()) {
+        return Err(format!("{}: recipe '{}' has no operations", source, empty.name));
+    }
+    Ok(recipes)
+}
+
+/// The user's recipe file: `$SV_MAKER_RECIPES`, or `sv_maker/recipes` in
+/// the XDG config directory (`~/.config` by default).
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("SV_MAKER_RECIPES") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("sv_maker").join("recipes"))
+}
+
+/// Built-in recipes followed by the user's, which take precedence.
+pub fn load_recipes() -> Result<Vec<Recipe>, String> {
+    let mut recipes = parse_recipes(BUILTIN_RECIPES, "built-in recipes")?;
+    if let Some(path) = config_path().filter(|path| path.exists()) {
+        let source = path.display().to_string();
+        let text = fs::read_to_string(&path).map_err(|e| format!("Cannot read recipes {}: {}", source, e))?;
+        for recipe in parse_recipes(&text, &source)? {
+            recipes.retain(|existing| existing.name != recipe.name);
+            recipes.push(recipe);
+        }
+    }
+    Ok(recipes)
+}
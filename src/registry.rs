@@ -0,0 +1,90 @@
+use std::sync::{Arc, RwLock};
+
+use serde_json::Value;
+
+use crate::alphabet::Alphabet;
+use crate::layout::Segment;
+
+/// An operation defined by a program using the library. It is named on the
+/// command line and in plan lines like a built-in one, and stored in
+/// journals, summaries and `--op-json` as `{"op": "custom", "name": ...,
+/// "params": ...}`. The edit is given as a layout, so truth VCFs, maps,
+/// graphs and every other layout-based output cover it without extra work.
+pub trait CustomOperation: Send + Sync {
+    /// The word that names the operation.
+    fn name(&self) -> &str;
+
+    /// Parses the words after the name into the operation's parameters.
+    /// Stochastic operations should draw from `seed` and record it in the
+    /// parameters, so journals replay them exactly.
+    fn parse(&self, args: &[String], alphabet: Alphabet, seed: u64) -> Result<Value, String>;
+
+    /// Checks parameters read from JSON as strictly as `parse` would.
+    fn validate(&self, _params: &Value, _alphabet: Alphabet) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// The edited sequence as pieces of `sequence` and novel bases.
+    fn layout(&self, params: &Value, sequence: &str) -> Result<Vec<Segment>, String>;
+
+    /// The header annotation recording the edit, e.g. "looped 40bp at
+    /// positions 100-140".
+    fn describe(&self, params: &Value, sequence: &str, unit: &str) -> String;
+}
+
+/// Words the command-line parser already uses for built-in operations.
+const BUILT_IN: &[&str] = &[
+    "delete", "keep", "insert", "replace", "invert", "duplicate", "copyback", "chromothripsis", "repeat-family",
+    "bfb", "mei", "integrate", "polya", "telomere", "add-leader", "case", "custom",
+];
+
+/// Subcommands that take the place of an operation after the input file,
+/// so an operation with one of these names could never be run.
+pub const SUBCOMMANDS: &[&str] = &["plan", "generate", "recipe", "extract", "digest", "sgmrna", "quasispecies"];
+
+static REGISTRY: RwLock<Vec<Arc<dyn CustomOperation>>> = RwLock::new(Vec::new());
+
+/// Makes `operation` available to every later parse, plan and replay in
+/// this process. Names must not clash with a built-in or registered one.
+pub fn register(operation: Arc<dyn CustomOperation>) -> Result<(), String> {
+    let name = operation.name().to_string();
+    if name.is_empty() || name.chars().any(char::is_whitespace) {
+        return Err(format!("Operation name '{}' must be a single word", name));
+    }
+    if BUILT_IN.contains(&name.as_str()) {
+        return Err(format!("'{}' is a built-in operation", name));
+    }
+    if SUBCOMMANDS.contains(&name.as_str()) {
+        return Err(format!("'{}' is a subcommand", name));
+    }
+    let mut registry = REGISTRY.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if registry.iter().any(|registered| registered.name() == name) {
+        return Err(format!("An operation named '{}' is already registered", name));
+    }
+    registry.push(operation);
+    Ok(())
+}
+
+/// Whether `word` names a built-in or registered operation.
+pub fn is_operation(word: &str) -> bool {
+    BUILT_IN.contains(&word) || lookup(word).is_some()
+}
+
+/// The registered operation called `name`.
+pub fn lookup(name: &str) -> Option<Arc<dyn CustomOperation>> {
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry.iter().find(|registered| registered.name() == name).cloned()
+}
+
+/// Names of the registered operations, in registration order.
+pub fn names() -> Vec<String> {
+    let registry = REGISTRY.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    registry.iter().map(|registered| registered.name().to_string()).collect()
+}
+
+/// The registered operation called `name`, or why a stored one cannot be
+/// used in this program.
+pub fn registered(name: &str) -> Result<Arc<dyn CustomOperation>, String> {
+    lookup(name).ok_or_else(|| format!(
+        "Operation '{}' is a custom operation that is not registered in this program", name))
+}
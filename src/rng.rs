@@ -0,0 +1,116 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Small deterministic PRNG (SplitMix64) used by every stochastic mode.
+/// Its output for a given seed is fixed by this file rather than by a
+/// dependency's version, so seeded runs stay reproducible across releases.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+// Seed indices of the named streams. Numbered streams (plan lines,
+// records, parallel tasks) count up from 0, so these count down from the
+// top; each must be used for one purpose only.
+
+/// Matched controls.
+pub const CONTROL_STREAM: u64 = u64::MAX;
+/// Breakpoint fuzzing.
+pub const FUZZ_STREAM: u64 = u64::MAX - 1;
+/// Repeat placement.
+pub const PLACE_STREAM: u64 = u64::MAX - 2;
+/// Random inserts.
+pub const INSERT_STREAM: u64 = u64::MAX - 3;
+/// Scrambled controls.
+pub const SCRAMBLE_STREAM: u64 = u64::MAX - 4;
+/// `--methylation` calls.
+pub const METHYLATION_STREAM: u64 = u64::MAX - 5;
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Stream number `index` of `seed`. A parallel task draws from the
+    /// stream of its own index, so what it draws depends only on the seed
+    /// and the index, never on the thread it runs on or the order tasks
+    /// finish in.
+    pub fn stream(seed: u64, index: u64) -> Rng {
+        Rng::new(derive_seed(seed, index))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    /// Uniform integer in `0..n`; `n` must be non-zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        // Multiply-shift keeps the bias negligible for the ranges used here
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Uniform integer in `low..=high`.
+    pub fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.below(high - low + 1)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal deviate (Box-Muller).
+    pub fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.unit();
+        let v = self.unit();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+
+    /// Draws `n` distinct indices of `weights` (all of them when there are
+    /// fewer), each in proportion to its weight among those not yet drawn,
+    /// returned in ascending order.
+    pub fn weighted_sample(&mut self, weights: &[f64], n: usize) -> Vec<usize> {
+        // Efraimidis-Spirakis: keep the n largest keys u^(1/w)
+        let mut keys: Vec<(f64, usize)> = weights.iter().enumerate()
+            .map(|(i, &weight)| ((1.0 - self.unit()).powf(1.0 / weight), i))
+            .collect();
+        keys.sort_unstable_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        let mut drawn: Vec<usize> = keys.into_iter().take(n).map(|(_, i)| i).collect();
+        drawn.sort_unstable();
+        drawn
+    }
+}
+
+/// Seed used when `--seed` is not given. It is recorded with the operation,
+/// so even unseeded runs can be reproduced from their header or journal.
+pub fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    mix(nanos ^ u64::from(std::process::id()))
+}
+
+/// Independent seed for the `index`-th item derived from a parent seed.
+pub fn derive_seed(seed: u64, index: u64) -> u64 {
+    mix(seed ^ mix(index.wrapping_add(1).wrapping_mul(GOLDEN_GAMMA)))
+}
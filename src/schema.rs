@@ -0,0 +1,68 @@
+use serde_json::Value;
+
+/// Version of the schema operations are stored in: their JSON in journals,
+/// `--op-json` files and summaries, and their plan-line syntax. It goes up
+/// whenever a stored operation would otherwise be read differently, with a
+/// migration below that rewrites the previous version's form.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Version of files written before the schema was versioned.
+pub const UNVERSIONED: u32 = 0;
+
+/// Rewrites an operation's JSON from version `n` to `n + 1`, at index `n`.
+const JSON_MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] = [unversioned_json];
+
+/// Rewrites a plan line from version `n` to `n + 1`, at index `n`.
+const PLAN_MIGRATIONS: [fn(&str) -> String; SCHEMA_VERSION as usize] = [unversioned_plan_line];
+
+/// Unversioned JSON already has the version 1 layout: every field added
+/// since the journal was introduced defaults to its earlier behaviour when
+/// missing (`coords_on` to `revcomp`, `min_stem` to 0).
+fn unversioned_json(_operation: &mut Value) {}
+
+/// Unversioned plan lines are read as version 1 lines, for the same reason.
+fn unversioned_plan_line(line: &str) -> String {
+    line.to_string()
+}
+
+/// Checks the `schema_version` a file was written with, `None` when it has
+/// none, and returns the version to migrate from.
+pub fn check_version(version: Option<&Value>, source: &str) -> Result<u32, String> {
+    let Some(version) = version else {
+        return Ok(UNVERSIONED);
+    };
+    let version = version.as_u64().and_then(|version| u32::try_from(version).ok())
+        .ok_or_else(|| format!("{}: schema_version must be a whole number, got {}", source, version))?;
+    checked(version, source)
+}
+
+/// Checks a `schema_version=<n>` plan directive's value.
+pub fn check_plan_version(value: &str, source: &str) -> Result<u32, String> {
+    let version = value.parse::<u32>()
+        .map_err(|_| format!("{}: schema_version must be a whole number, got '{}'", source, value))?;
+    checked(version, source)
+}
+
+fn checked(version: u32, source: &str) -> Result<u32, String> {
+    if version > SCHEMA_VERSION {
+        return Err(format!("{} was written with operation schema version {}, but this release of sv_maker reads \
+                            versions up to {}; upgrade sv_maker to read it", source, version, SCHEMA_VERSION));
+    }
+    if version == UNVERSIONED {
+        return Err(format!("{}: schema_version 0 is not a version; leave it out of files that predate versioning",
+                           source));
+    }
+    Ok(version)
+}
+
+/// Brings an operation's JSON from `version` up to the current schema.
+pub fn migrate_json(operation: &mut Value, version: u32) {
+    for migration in &JSON_MIGRATIONS[version as usize..] {
+        migration(operation);
+    }
+}
+
+/// Brings a plan line from `version` up to the current schema.
+pub fn migrate_plan_line(line: &str, version: u32) -> String {
+    PLAN_MIGRATIONS[version as usize..].iter().fold(line.to_string(), |line, migration| migration(&line))
+}
@@ -0,0 +1,120 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+
+use sv_maker::fasta::{self, Record};
+use sv_maker::rng::Rng;
+
+/// Seed of the reference's bases and of every stochastic fixture.
+const SEED: u64 = 1;
+const REFERENCE_LENGTH: usize = 600;
+const REFERENCE_MD5: &str = "3b765d681e1d43f8e63f02f636f28744";
+
+/// One golden fixture: the output of an operation on the reference, its
+/// arguments, and the MD5 of the FASTA file it must produce. A change to an
+/// operation's output or header shows up here first.
+struct Fixture {
+    name: &'static str,
+    args: &'static str,
+    md5: &'static str,
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture { name: "delete", args: "delete 101 150", md5: "aa538227ba170f4ea0335124a2842cbb" },
+    Fixture { name: "insert", args: "insert 200 ACGTTGCA", md5: "5789a9ad1be8d06078fd0dfd0e7eaa0d" },
+    Fixture { name: "replace", args: "replace 50 59 GGGCCC", md5: "9793cc08f82d4d0e705d3917875fbf82" },
+    Fixture { name: "invert", args: "invert 300 349", md5: "582d8ab09d249d1834bbd6a1339acc09" },
+    Fixture { name: "invert_complement", args: "invert 300 349 --complement", md5: "faaefe51690ab4723afc471a744b4647" },
+    Fixture { name: "duplicate", args: "duplicate 100 149 400", md5: "af712640f50323d7b8f057c34b0860af" },
+    Fixture { name: "tandem_duplicate", args: "duplicate -td 100 149", md5: "9b8699e6e7e78798bdddb6d0cfc7b72b" },
+    Fixture { name: "keep", args: "keep 51 550", md5: "2756e3ade547e00d5b4c1c6eff705cbc" },
+    Fixture { name: "copyback_5", args: "copyback 5 400 100", md5: "1628dd06b1d6d0dc4ce293c07d57bf50" },
+    Fixture { name: "copyback_3", args: "copyback 3 400 100", md5: "b80a35c204b605bc895cd050a9421b81" },
+    Fixture { name: "snapback", args: "copyback -sb 5 300", md5: "a66e4c408c5112712f7f16b51614985d" },
+    Fixture { name: "chromothripsis", args: "chromothripsis 200 400 5", md5: "623a14c4bdd14fe849a6139e0349aa1e" },
+    Fixture { name: "repeat_family", args: "repeat-family 100 139 --copies 3 --divergence 0.05", md5: "373e958f814ee0142ce54ac0f6179e8b" },
+    Fixture { name: "bfb", args: "bfb --cycles 2", md5: "7e1e05429d534918168ab04cd04925f7" },
+    Fixture { name: "mei", args: "mei GGCCGGGCGCGGTGGCTCACGCCTGTAATCCCAGCACTTTGGGAGGCCGAGGCGGGCGGA \
+                                 --position 250 --tsd 10 --polya 20", md5: "2ba7230c7563acc7c61cdc82206e1194" },
+    Fixture { name: "integrate", args: "integrate ATGCACCAAAAGAGAACTGCAATGTTTCAGGACCCACAGGAGCGACCCAGAAAGTTACCACAGTTATGCACAGAGCTGCAA \
+                                       --position 150", md5: "1f775ebc6a259f821292872d08f9275b" },
+    Fixture { name: "polya", args: "polya --length 30", md5: "766c2582961ab317ffd62bb0c17ce75a" },
+    Fixture { name: "telomere", args: "telomere --length 24", md5: "dd3ab114398791ef544b5d556eeb075f" },
+    Fixture { name: "add_leader", args: "add-leader ACGTACGTAC", md5: "b92caef7ec2c6a72133e3cffe893601d" },
+    Fixture { name: "case", args: "case 100 200 lower", md5: "43e482a7fa662a282fe3716e0e479f03" },
+];
+
+/// The fixtures' reference: bases drawn from a fixed seed, so it is the same
+/// on every installation.
+fn reference() -> Record {
+    let mut rng = Rng::new(SEED);
+    let sequence = (0..REFERENCE_LENGTH).map(|_| b"ACGT"[rng.below(4)] as char).collect();
+    Record { header: ">selftest".to_string(), sequence, quality: None, line_width: Some(60) }
+}
+
+fn file_md5(path: &Path) -> io::Result<String> {
+    Ok(format!("{:x}", md5::compute(fs::read(path)?)))
+}
+
+/// Writes the reference and one output per operation to `outdir`, made by
+/// running this executable as a pipeline would, with `MD5SUMS` giving the
+/// expected checksums and `commands.sh` the commands behind them. Returns
+/// whether every output matched.
+fn write_fixtures(outdir: &Path) -> io::Result<bool> {
+    fs::create_dir_all(outdir)?;
+    let reference_path = outdir.join("reference.fa");
+    fasta::write_records(&[reference()], reference_path.to_str())?;
+    let executable = std::env::current_exe()?;
+
+    let mut sums = BufWriter::new(File::create(outdir.join("MD5SUMS"))?);
+    let mut commands = BufWriter::new(File::create(outdir.join("commands.sh"))?);
+    writeln!(commands, "#!/bin/sh")?;
+    writeln!(commands, "# Regenerates the selftest outputs; run in this directory, then check them with md5sum -c MD5SUMS")?;
+    writeln!(commands, "set -eu")?;
+    writeln!(commands, "SV_MAKER=${{SV_MAKER:-sv_maker}}")?;
+    writeln!(sums, "{}  reference.fa", REFERENCE_MD5)?;
+    let mut passed = file_md5(&reference_path)? == REFERENCE_MD5;
+    if !passed {
+        eprintln!("MISMATCH reference: expected {}, got {}", REFERENCE_MD5, file_md5(&reference_path)?);
+    }
+    for fixture in FIXTURES {
+        let file_name = format!("{}.fa", fixture.name);
+        let output = outdir.join(&file_name);
+        writeln!(sums, "{}  {}", fixture.md5, file_name)?;
+        writeln!(commands, "\"$SV_MAKER\" --seed {} -o {} reference.fa {}", SEED, file_name, fixture.args)?;
+        let run = Command::new(&executable)
+            .args(["--seed", &SEED.to_string(), "-o"]).arg(&output).arg(&reference_path)
+            .args(fixture.args.split_whitespace())
+            .output()?;
+        if !run.status.success() {
+            let message = String::from_utf8_lossy(&run.stderr);
+            eprintln!("FAILED {}: {}", fixture.name, message.lines().last().unwrap_or("no error message"));
+            passed = false;
+            continue;
+        }
+        let md5 = file_md5(&output)?;
+        if md5 != fixture.md5 {
+            eprintln!("MISMATCH {}: expected {}, got {}", fixture.name, fixture.md5, md5);
+            passed = false;
+        }
+    }
+    sums.flush()?;
+    commands.flush()?;
+    Ok(passed)
+}
+
+/// Runs `selftest --outdir <dir>`.
+pub fn run(args: &[String]) -> io::Result<()> {
+    let outdir = match args {
+        [flag, dir] if flag == "--outdir" => dir,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "selftest requires --outdir <dir> and takes no other arguments")),
+    };
+    if write_fixtures(Path::new(outdir))? {
+        eprintln!("All {} selftest outputs in {} match their expected checksums", FIXTURES.len(), outdir);
+        Ok(())
+    } else {
+        Err(io::Error::other("selftest outputs differ from this release's; see the lines above"))
+    }
+}
@@ -0,0 +1,83 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::staging;
+
+/// One subgenomic RNA: the leader (bases `1..=leader_end`, ending in TRS-L)
+/// joined to the genome from `body_start` on. Positions are 1-based.
+#[derive(Debug, Clone)]
+pub struct SubgenomicRna {
+    pub leader_end: usize,
+    pub body_start: usize,
+    pub canonical: bool,
+}
+
+/// 1-based start positions of every occurrence of `motif` in `sequence`,
+/// treating T and U as the same base.
+pub fn find_motif(sequence: &str, motif: &str) -> Vec<usize> {
+    let normalize = |s: &str| s.to_ascii_uppercase().replace('U', "T");
+    let sequence = normalize(sequence);
+    let motif = normalize(motif);
+    if motif.is_empty() || motif.len() > sequence.len() {
+        return Vec::new();
+    }
+    (0..=sequence.len() - motif.len())
+        .filter(|&i| sequence[i..].starts_with(&motif))
+        .map(|i| i + 1)
+        .collect()
+}
+
+/// Works out the subgenomic RNAs of a genome. With a TRS motif, its first
+/// occurrence is TRS-L (unless `leader_end` is given) and every later one a
+/// canonical TRS-B; the body starts right after each TRS-B, since the TRS
+/// itself comes from the leader. `extra_bodies` are non-canonical body
+/// start positions.
+pub fn subgenomic_rnas(sequence: &str, leader_end: Option<usize>, motif: Option<&str>,
+                       extra_bodies: &[usize]) -> Result<Vec<SubgenomicRna>, String> {
+    let occurrences = motif.map(|m| find_motif(sequence, m)).unwrap_or_default();
+    let leader_end = match (leader_end, motif) {
+        (Some(end), _) => end,
+        (None, Some(m)) => match occurrences.first() {
+            Some(start) => start + m.len() - 1,
+            None => return Err(format!("TRS motif '{}' not found in the genome", m)),
+        },
+        (None, None) => return Err("sgmrna requires --leader or --motif to locate TRS-L".to_string()),
+    };
+    if leader_end == 0 || leader_end >= sequence.len() {
+        return Err(format!("Leader end {} is outside the {}bp genome", leader_end, sequence.len()));
+    }
+
+    let mut rnas = Vec::new();
+    if let Some(m) = motif {
+        for start in occurrences {
+            let body_start = start + m.len();
+            if start > leader_end && body_start <= sequence.len() {
+                rnas.push(SubgenomicRna { leader_end, body_start, canonical: true });
+            }
+        }
+    }
+    for &body_start in extra_bodies {
+        if body_start <= leader_end + 1 || body_start > sequence.len() {
+            return Err(format!("Body start {} must be after the leader (ends at {}) and within the {}bp genome",
+                               body_start, leader_end, sequence.len()));
+        }
+        rnas.push(SubgenomicRna { leader_end, body_start, canonical: false });
+    }
+    if rnas.is_empty() {
+        return Err("No TRS-B sites found; give --body positions for non-canonical junctions".to_string());
+    }
+
+    rnas.sort_by_key(|rna| rna.body_start);
+    rnas.dedup_by_key(|rna| rna.body_start);
+    Ok(rnas)
+}
+
+/// Writes one row per subgenomic RNA as TSV.
+pub fn write_sgrna_manifest(path: &str, rnas: &[(String, SubgenomicRna, usize)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "id\tleader_end\tbody_start\tlength\tjunction")?;
+    for (id, rna, length) in rnas {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", id, rna.leader_end, rna.body_start, length,
+                 if rna.canonical { "canonical" } else { "non-canonical" })?;
+    }
+    out.flush()
+}
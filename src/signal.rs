@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+use crate::layout::Breakend;
+use crate::vcf::RecordJunctions;
+
+/// Shortest part of a read aligners reliably place on either side of a split.
+const MIN_ANCHOR: usize = 20;
+
+/// Library parameters the read signal is previewed for.
+#[derive(Debug, Clone, Copy)]
+pub struct Library {
+    pub insert_size: usize,
+    pub read_length: usize,
+}
+
+impl Library {
+    /// Parses `<insert size>,<read length>`, e.g. `400,150`.
+    pub fn parse(value: &str) -> Result<Library, String> {
+        let invalid = || format!("--signal-preview must be <insert size>,<read length>, e.g. 400,150, got '{}'", value);
+        let (insert, read) = value.split_once(',').ok_or_else(invalid)?;
+        let insert_size = crate::units::parse_bases(insert.trim()).map_err(|_| invalid())?;
+        let read_length = crate::units::parse_bases(read.trim()).map_err(|_| invalid())?;
+        if read_length == 0 || insert_size < read_length {
+            return Err(format!("--signal-preview insert size {} must be at least the read length {}", insert_size, read_length));
+        }
+        Ok(Library { insert_size, read_length })
+    }
+}
+
+fn breakend(chrom: &str, end: Breakend) -> String {
+    format!("{}:{}({})", chrom, end.position, if end.reverse { '-' } else { '+' })
+}
+
+/// Describes the discordant read pairs and split reads each junction should
+/// produce with `library`, in reference coordinates, so an event can be
+/// checked for detectability before simulating reads.
+pub fn write_preview<W: Write>(out: &mut W, records: &[RecordJunctions], library: Library) -> io::Result<()> {
+    let Library { insert_size, read_length } = library;
+    writeln!(out, "Signal preview ({}bp inserts, {}bp reads):", insert_size, read_length)?;
+    let mut counter = 0;
+    for record in records {
+        for (junction, _) in &record.junctions {
+            counter += 1;
+            let inserted = junction.inserted.len();
+            let with_insert = if inserted > 0 { format!(" with {}bp inserted", inserted) } else { String::new() };
+            let (left, right) = match (junction.left, junction.right) {
+                (Some(left), Some(right)) => (left, right),
+                (Some(end), None) | (None, Some(end)) if inserted == 0 => {
+                    writeln!(out, "bnd{} {} where the molecule ends", counter, breakend(record.chrom, end))?;
+                    writeln!(out, "  coverage stops at {}; no read or pair spans it", end.position)?;
+                    continue;
+                },
+                (Some(end), None) | (None, Some(end)) => {
+                    writeln!(out, "bnd{} {}{} at the molecule end", counter, breakend(record.chrom, end), with_insert)?;
+                    writeln!(out, "  soft-clipped reads at {} and pairs whose mate does not map nearby", end.position)?;
+                    continue;
+                },
+                (None, None) => continue,
+            };
+            writeln!(out, "bnd{} {} -> {}{}", counter, breakend(record.chrom, left), breakend(record.chrom, right), with_insert)?;
+
+            // Pairs: the mate before the junction reads towards it, the mate
+            // after reads back towards it, each on its breakend's strand
+            let pair = match (left.reverse, right.reverse) {
+                (false, false) if right.position > left.position => {
+                    // Skipped reference bases stretch the pair, inserted ones shrink it
+                    let shift = (right.position - left.position - 1) as i64 - inserted as i64;
+                    format!("FR ({}-type), mapped span ~{}bp ({:+}bp, {:.0}% of the insert size)",
+                            if shift < 0 { "insertion" } else { "deletion" },
+                            insert_size as i64 + shift, shift, 100.0 * shift as f64 / insert_size as f64)
+                },
+                (false, false) => {
+                    format!("RF (duplication-type, outward-facing) over the {}bp repeated from {}",
+                            left.position - right.position + 1, right.position)
+                },
+                (false, true) => "FF (inversion-type, both mates on the forward strand)".to_string(),
+                (true, false) => "RR (inversion-type, both mates on the reverse strand)".to_string(),
+                (true, true) => "FR (joined on the reverse strand)".to_string(),
+            };
+            if 2 * read_length > insert_size {
+                writeln!(out, "  discordant pairs: none, the mates of {}bp inserts overlap; the junction shows in split reads", insert_size)?;
+            } else if inserted + 2 * read_length > insert_size {
+                writeln!(out, "  discordant pairs: none with both mates outside the {}bp inserted; \
+                               pairs with one mate inside it map elsewhere or not at all", inserted)?;
+            } else {
+                let spanning = insert_size - 2 * read_length - inserted + 1;
+                writeln!(out, "  discordant pairs: {}, from ~{} fragment positions", pair, spanning)?;
+            }
+
+            if inserted + 2 * MIN_ANCHOR > read_length {
+                writeln!(out, "  split reads: none anchored on both sides ({}bp inserted, {}bp anchors)", inserted, MIN_ANCHOR)?;
+            } else {
+                let positions = read_length - inserted - 2 * MIN_ANCHOR + 1;
+                writeln!(out, "  split reads: split between {} and {} from ~{} read positions", left.position, right.position, positions)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::fasta::Record;
+use crate::staging;
+
+/// An external read simulator that `--emit-sim-config` writes a run script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simulator {
+    Art,
+    Badread,
+    Pbsim,
+}
+
+impl Simulator {
+    pub fn parse(value: &str) -> Result<Simulator, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "art" | "art_illumina" => Ok(Simulator::Art),
+            "badread" => Ok(Simulator::Badread),
+            "pbsim" | "pbsim3" => Ok(Simulator::Pbsim),
+            _ => Err(format!("--emit-sim-config must be art, badread or pbsim, got '{}'", value)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Simulator::Art => "art",
+            Simulator::Badread => "badread",
+            Simulator::Pbsim => "pbsim",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Simulator::Art => "ART",
+            Simulator::Badread => "Badread",
+            Simulator::Pbsim => "PBSIM3",
+        }
+    }
+}
+
+/// A `generate --dilution` pool: the DVGs written with the full-length
+/// input genome, weighted by one dilution of the manifest.
+#[derive(Debug, Clone, Copy)]
+pub struct Pool<'a> {
+    pub input: &'a str,
+    pub dilution: &'a str,
+}
+
+/// Quotes `text` for a POSIX shell.
+fn quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn absolute(path: &str) -> io::Result<String> {
+    Ok(staging::canonical(path)?.display().to_string())
+}
+
+/// Writes a shell script at `path` that simulates reads with `simulator`
+/// from the genomes written to `output`. Each record is simulated at a depth
+/// proportional to its abundance, the most abundant at `$DEPTH`: equal
+/// abundances by default, or those of a dilution of `pool` (chosen by the
+/// script's first argument).
+pub fn write_script(path: &str, simulator: Simulator, output: &str, records: &[Record],
+                    pool: Option<Pool>, seed: u64) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    let output = absolute(output)?;
+    writeln!(out, "#!/bin/sh")?;
+    writeln!(out, "# Simulates {} reads from the genomes sv_maker wrote to {}.", simulator.label(), output)?;
+    writeln!(out, "# Settings can be overridden from the environment, e.g. DEPTH=100 sh {}", path)?;
+    writeln!(out, "set -eu")?;
+    writeln!(out, "DEPTH=${{DEPTH:-30}}")?;
+    // Kept small enough that adding record numbers stays a valid 32-bit seed
+    writeln!(out, "SEED=${{SEED:-{}}}", seed % (1 << 30))?;
+    writeln!(out, "OUT=${{OUT:-{}}}", quoted(&format!("{}.{}", output, simulator.name())))?;
+    match simulator {
+        Simulator::Art => {
+            writeln!(out, "ART_PROFILE=${{ART_PROFILE:-HS25}}")?;
+            writeln!(out, "READ_LENGTH=${{READ_LENGTH:-150}}")?;
+            writeln!(out, "FRAGMENT_MEAN=${{FRAGMENT_MEAN:-400}}")?;
+            writeln!(out, "FRAGMENT_SD=${{FRAGMENT_SD:-10}}")?;
+        },
+        Simulator::Badread => {},
+        Simulator::Pbsim => writeln!(out, "PBSIM_MODEL=${{PBSIM_MODEL:-QSHMM-RSII.model}}")?,
+    }
+    writeln!(out, "ABUNDANCE=\"$OUT.abundance.tsv\"")?;
+    writeln!(out)?;
+
+    // Abundances as `id<TAB>fraction` lines
+    match pool {
+        Some(pool) => {
+            writeln!(out, "# The full-length genome and its DVGs at dilution $1 (default 1) of the manifest")?;
+            writeln!(out, "DILUTION=${{1:-1}}")?;
+            writeln!(out, "REFERENCE=\"$OUT.pool.fa\"")?;
+            writeln!(out, "cat {} {} > \"$REFERENCE\"", quoted(&absolute(pool.input)?), quoted(&output))?;
+            writeln!(out, "awk -F'\\t' -v dilution=\"$DILUTION\" 'NR > 1 && $1 == dilution {{ print $3 \"\\t\" $4 }}' {} > \"$ABUNDANCE\"",
+                     quoted(&absolute(pool.dilution)?))?;
+            writeln!(out, "[ -s \"$ABUNDANCE\" ] || {{ echo \"No dilution $DILUTION in the manifest\" >&2; exit 1; }}")?;
+        },
+        None => {
+            writeln!(out, "REFERENCE={}", quoted(&output))?;
+            writeln!(out, "cat > \"$ABUNDANCE\" <<'END'")?;
+            for record in records {
+                writeln!(out, "{}\t{:.6e}", record.id(), 1.0 / records.len() as f64)?;
+            }
+            writeln!(out, "END")?;
+        },
+    }
+    writeln!(out)?;
+
+    match simulator {
+        Simulator::Badread => {
+            // Badread weights references by the depth= tag in their headers
+            writeln!(out, "awk -F'\\t' 'NR == FNR {{ depth[$1] = $2; next }} /^>/ {{ split(substr($0, 2), id, \" \"); \
+                           print $0 \" depth=\" depth[id[1]]; next }} {{ print }}' \"$ABUNDANCE\" \"$REFERENCE\" > \"$OUT.reference.fa\"")?;
+            writeln!(out, "badread simulate --reference \"$OUT.reference.fa\" --quantity \"${{DEPTH}}x\" --seed \"$SEED\" > \"$OUT.fastq\"")?;
+        },
+        Simulator::Art | Simulator::Pbsim => {
+            writeln!(out, "MAX=$(cut -f2 \"$ABUNDANCE\" | sort -g | tail -n 1)")?;
+            if simulator == Simulator::Art {
+                writeln!(out, ": > \"${{OUT}}_1.fq\"")?;
+                writeln!(out, ": > \"${{OUT}}_2.fq\"")?;
+            }
+            writeln!(out, "n=0")?;
+            writeln!(out, "while IFS=\"$(printf '\\t')\" read -r id abundance; do")?;
+            writeln!(out, "    n=$((n + 1))")?;
+            writeln!(out, "    depth=$(awk -v depth=\"$DEPTH\" -v abundance=\"$abundance\" -v max=\"$MAX\" 'BEGIN {{ print depth * abundance / max }}')")?;
+            writeln!(out, "    awk -v id=\"$id\" '/^>/ {{ keep = substr($1, 2) == id }} keep' \"$REFERENCE\" > \"$OUT.$id.fa\"")?;
+            if simulator == Simulator::Art {
+                writeln!(out, "    art_illumina -ss \"$ART_PROFILE\" -i \"$OUT.$id.fa\" -p -na -l \"$READ_LENGTH\" -f \"$depth\" \
+                               -m \"$FRAGMENT_MEAN\" -s \"$FRAGMENT_SD\" -rs \"$((SEED + n))\" -o \"$OUT.$id.\"")?;
+                writeln!(out, "    cat \"$OUT.$id.1.fq\" >> \"${{OUT}}_1.fq\"")?;
+                writeln!(out, "    cat \"$OUT.$id.2.fq\" >> \"${{OUT}}_2.fq\"")?;
+            } else {
+                writeln!(out, "    pbsim --strategy wgs --method qshmm --qshmm \"$PBSIM_MODEL\" --depth \"$depth\" \
+                               --genome \"$OUT.$id.fa\" --prefix \"$OUT.$id\" --seed \"$((SEED + n))\"")?;
+            }
+            writeln!(out, "done < \"$ABUNDANCE\"")?;
+        },
+    }
+    out.flush()
+}
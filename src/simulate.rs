@@ -0,0 +1,545 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::alphabet::Alphabet;
+use crate::bed::BedInterval;
+use crate::layout::Breakend;
+use crate::rng::Rng;
+use crate::staging;
+
+/// One fragment of a shattered region. Coordinates are 0-based, half-open
+/// positions in the input sequence; `index` counts fragments from the left.
+#[derive(Debug, Clone, Serialize)]
+pub struct Fragment {
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub reverse: bool,
+}
+
+/// Result of shattering a region: the fragments joined back together, in
+/// their new order and orientation, and those that were lost.
+#[derive(Debug, Clone)]
+pub struct Shattering {
+    pub kept: Vec<Fragment>,
+    pub lost: Vec<Fragment>,
+}
+
+/// Shatters `start..end` into `fragments` pieces at random breakpoints and
+/// reassembles `keep` of them (a random number when `None`) in random order
+/// and orientation. The result depends only on the arguments and `seed`.
+pub fn shatter(start: usize, end: usize, fragments: usize, keep: Option<usize>, seed: u64) -> Shattering {
+    let mut rng = Rng::new(seed);
+
+    // Pick fragments - 1 distinct cut points strictly inside the region
+    let mut cuts = BTreeSet::new();
+    while cuts.len() < fragments - 1 {
+        cuts.insert(rng.between(start + 1, end - 1));
+    }
+
+    let mut bounds = vec![start];
+    bounds.extend(cuts);
+    bounds.push(end);
+    let mut pieces: Vec<Fragment> = bounds
+        .windows(2)
+        .enumerate()
+        .map(|(index, w)| Fragment { index: index + 1, start: w[0], end: w[1], reverse: false })
+        .collect();
+
+    let keep = keep.unwrap_or_else(|| rng.between(1, fragments));
+    rng.shuffle(&mut pieces);
+    let mut lost = pieces.split_off(keep);
+    lost.sort_by_key(|f| f.index);
+    for fragment in pieces.iter_mut() {
+        fragment.reverse = rng.chance(0.5);
+    }
+
+    Shattering { kept: pieces, lost }
+}
+
+/// One breakage-fusion-bridge cycle. Positions are 1-based on the molecule
+/// entering the cycle: bases after `break_position` are lost, and the
+/// reverse complement of `foldback_start..=break_position` is fused on.
+#[derive(Debug, Clone, Serialize)]
+pub struct BfbCycle {
+    pub cycle: usize,
+    pub length_before: usize,
+    pub break_position: usize,
+    pub foldback_start: usize,
+    pub length_after: usize,
+}
+
+/// A cycle's break and fold-back end on the input, as `layout::bfb_layout`
+/// gives them.
+pub type CycleEnds = (Breakend, Breakend);
+
+/// Draws `cycles` breakage-fusion-bridge cycles on a molecule of `length`
+/// bases, anchored at the 5' (left) end. Each cycle loses the distal end
+/// beyond a break in the right half of the molecule, then the sister
+/// chromatid fuses as a fold-back inversion and the bridge breaks again,
+/// keeping up to half of the inverted copy.
+pub fn bfb_cycles(length: usize, cycles: usize, seed: u64) -> Result<Vec<BfbCycle>, String> {
+    let mut rng = Rng::new(seed);
+    let mut current = length;
+    let mut drawn = Vec::new();
+
+    for cycle in 1..=cycles {
+        if current < 4 {
+            return Err(format!("Sequence is too short ({}bp) for breakage-fusion-bridge cycle {}", current, cycle));
+        }
+        let break_position = rng.between(current.div_ceil(2), current - 1);
+        let foldback_start = rng.between(break_position / 2 + 1, break_position);
+        let length_after = break_position + (break_position - foldback_start + 1);
+        drawn.push(BfbCycle { cycle, length_before: current, break_position, foldback_start, length_after });
+        current = length_after;
+    }
+
+    Ok(drawn)
+}
+
+/// One viral integration. `position` is the 1-based host position the virus
+/// is inserted before; the viral coordinates are 1-based and inclusive. A
+/// rearranged integrant is split before `rearranged_at` and its two parts
+/// joined in swapped order, the moved part inverted when `inverted_part`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Integration {
+    pub site: usize,
+    pub position: usize,
+    pub viral_start: usize,
+    pub viral_end: usize,
+    pub reverse: bool,
+    pub rearranged_at: Option<usize>,
+    pub inverted_part: bool,
+}
+
+/// How viral integrations are placed; see `integrations`.
+#[derive(Debug, Clone)]
+pub struct IntegrationSpec<'a> {
+    pub positions: &'a [usize],
+    pub sites: usize,
+    pub viral_range: Option<(usize, usize)>,
+    pub partial: bool,
+    pub rearrange: bool,
+    pub seed: u64,
+}
+
+/// Draws the integrations of a `virus_length` genome into a host of
+/// `host_length` bases: at the given positions, or at `sites` distinct
+/// random ones. Orientation, partial ranges and rearrangements are drawn
+/// per site from the seed.
+pub fn integrations(host_length: usize, virus_length: usize, spec: &IntegrationSpec) -> Result<Vec<Integration>, String> {
+    let mut rng = Rng::new(spec.seed);
+
+    let positions: Vec<usize> = if spec.positions.is_empty() {
+        if spec.sites > host_length.saturating_sub(1) {
+            return Err(format!("Host sequence ({}bp) is too short for {} integration sites", host_length, spec.sites));
+        }
+        let mut picked = BTreeSet::new();
+        while picked.len() < spec.sites {
+            picked.insert(rng.between(2, host_length));
+        }
+        picked.into_iter().collect()
+    } else {
+        let mut given = spec.positions.to_vec();
+        given.sort_unstable();
+        given
+    };
+
+    let (range_start, range_end) = spec.viral_range.unwrap_or((1, virus_length));
+    if range_end > virus_length {
+        return Err(format!("Viral range end {} is beyond viral genome length {}", range_end, virus_length));
+    }
+
+    let mut drawn = Vec::new();
+    for (i, &position) in positions.iter().enumerate() {
+        let (viral_start, viral_end) = if spec.partial && range_end > range_start {
+            let a = rng.between(range_start, range_end);
+            let b = rng.between(range_start, range_end);
+            (a.min(b), a.max(b))
+        } else {
+            (range_start, range_end)
+        };
+        let reverse = rng.chance(0.5);
+        let (rearranged_at, inverted_part) = if spec.rearrange && viral_end > viral_start {
+            (Some(rng.between(viral_start + 1, viral_end)), rng.chance(0.5))
+        } else {
+            (None, false)
+        };
+        drawn.push(Integration { site: i + 1, position, viral_start, viral_end, reverse, rearranged_at, inverted_part });
+    }
+
+    Ok(drawn)
+}
+
+/// Writes one row per viral integration as TSV.
+pub fn write_integration_manifest(path: &str, records: &[(&str, u64, Vec<Integration>)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "sequence\tseed\tsite\tposition\tviral_start\tviral_end\torientation\trearranged_at\tinverted_part")?;
+    for (id, seed, sites) in records {
+        for site in sites {
+            let rearranged_at = site.rearranged_at.map_or(".".to_string(), |p| p.to_string());
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", id, seed, site.site, site.position, site.viral_start,
+                     site.viral_end, if site.reverse { '-' } else { '+' }, rearranged_at, site.inverted_part)?;
+        }
+    }
+    out.flush()
+}
+
+/// Draws which parent (0-based) each of `segments` segments comes from. With
+/// more than one segment the draw is repeated until at least two parents
+/// contribute, so the result is always a true reassortant.
+pub fn reassortment(segments: usize, parents: usize, seed: u64) -> Vec<usize> {
+    let mut rng = Rng::new(seed);
+    loop {
+        let assignment: Vec<usize> = (0..segments).map(|_| rng.below(parents)).collect();
+        if segments < 2 || assignment.iter().any(|&p| p != assignment[0]) {
+            return assignment;
+        }
+    }
+}
+
+/// One segment of a reassortant and the parent record it was taken from.
+pub struct SegmentOrigin<'a> {
+    pub segment: usize,
+    pub parent: usize,
+    pub parent_file: &'a str,
+    pub source_id: &'a str,
+    pub length: usize,
+}
+
+/// Writes the per-segment provenance of a reassortant as TSV.
+pub fn write_reassortment_manifest(path: &str, origins: &[SegmentOrigin]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "segment\tparent\tparent_file\tsource_id\tlength")?;
+    for origin in origins {
+        writeln!(out, "{}\t{}\t{}\t{}\t{}", origin.segment, origin.parent, origin.parent_file,
+                 origin.source_id, origin.length)?;
+    }
+    out.flush()
+}
+
+/// One point mutation of a quasispecies variant, on 1-based coordinates of
+/// the master sequence. Insertions follow the base at `position` and have
+/// `-` as `reference`; deletions have `-` as `alternate`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mutation {
+    pub position: usize,
+    pub kind: &'static str,
+    pub reference: String,
+    pub alternate: String,
+}
+
+/// Longest insertion or deletion drawn by `mutate`.
+const MAX_INDEL: usize = 3;
+
+/// Mutates `sequence` base by base: each base is substituted with
+/// probability `rate` and starts an indel of 1-3 bases (insertion or
+/// deletion with equal chance) with probability `indel_rate`.
+pub fn mutate(sequence: &str, rate: f64, indel_rate: f64, bases: &[u8], rng: &mut Rng) -> (String, Vec<Mutation>) {
+    let master = sequence.as_bytes();
+    let mut variant = String::with_capacity(sequence.len());
+    let mut mutations = Vec::new();
+    let mut i = 0;
+
+    while i < master.len() {
+        let base = master[i];
+        if rng.chance(indel_rate) {
+            let length = rng.between(1, MAX_INDEL);
+            if rng.chance(0.5) {
+                let deleted = &sequence[i..(i + length).min(master.len())];
+                mutations.push(Mutation {
+                    position: i + 1,
+                    kind: "deletion",
+                    reference: deleted.to_string(),
+                    alternate: "-".to_string(),
+                });
+                i += deleted.len();
+                continue;
+            }
+            let inserted: String = (0..length).map(|_| bases[rng.below(bases.len())] as char).collect();
+            variant.push(base as char);
+            variant.push_str(&inserted);
+            mutations.push(Mutation { position: i + 1, kind: "insertion", reference: "-".to_string(), alternate: inserted });
+        } else if rng.chance(rate) {
+            // Draw from the other bases so every substitution changes the base
+            let others: Vec<u8> = bases.iter().copied().filter(|&b| b != base).collect();
+            let alternate = others[rng.below(others.len())] as char;
+            variant.push(alternate);
+            mutations.push(Mutation {
+                position: i + 1,
+                kind: "substitution",
+                reference: (base as char).to_string(),
+                alternate: alternate.to_string(),
+            });
+        } else {
+            variant.push(base as char);
+        }
+        i += 1;
+    }
+
+    (variant, mutations)
+}
+
+/// One planted copy of a repeat family's seed segment.
+#[derive(Debug, Clone)]
+pub struct RepeatCopy {
+    /// Order in which the copy was drawn, from 1
+    pub index: usize,
+    /// Number of input bases before the copy
+    pub cut: usize,
+    pub reverse: bool,
+    /// The copy's bases, in the orientation they are inserted
+    pub sequence: String,
+    pub substitutions: usize,
+}
+
+/// Plants `copies` copies of `sequence[start..end]` (0-based, half-open) at
+/// random points outside the segment, each on a random strand and with each
+/// base substituted with probability `divergence`. Copies are returned in
+/// sequence order; the result depends only on the arguments and `seed`.
+pub fn repeat_family(sequence: &str, start: usize, end: usize, copies: usize, divergence: f64,
+                     seed: u64) -> Vec<RepeatCopy> {
+    let mut rng = Rng::new(seed);
+    let unit = &sequence[start..end];
+    let bases: &[u8] = if unit.contains('U') { b"ACGU" } else { b"ACGT" };
+    // Insertion points before, at either end of, or after the segment
+    let before = start + 1;
+    let points = before + sequence.len() + 1 - end;
+    let mut planted: Vec<RepeatCopy> = (1..=copies).map(|index| {
+        let pick = rng.below(points);
+        let cut = if pick < before { pick } else { end + pick - before };
+        let reverse = rng.chance(0.5);
+        let (diverged, mutations) = mutate(unit, divergence, 0.0, bases, &mut rng);
+        let sequence = if reverse {
+            crate::reverse_complement(&diverged)
+        } else {
+            diverged
+        };
+        RepeatCopy { index, cut, reverse, sequence, substitutions: mutations.len() }
+    }).collect();
+    planted.sort_by_key(|copy| copy.cut);
+    planted
+}
+
+/// Writes the copies planted in each record as TSV.
+pub fn write_repeat_manifest(path: &str, records: &[(&str, u64, Vec<RepeatCopy>)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "record\tseed\tcopy\tposition\tstrand\tlength\tsubstitutions")?;
+    for (id, seed, copies) in records {
+        for copy in copies {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}", id, seed, copy.index, copy.cut + 1,
+                     if copy.reverse { '-' } else { '+' }, copy.sequence.len(), copy.substitutions)?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes the mutations of every quasispecies variant as TSV.
+pub fn write_mutation_table(path: &str, variants: &[(String, u64, Vec<Mutation>)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "variant\tseed\tposition\ttype\treference\talternate")?;
+    for (id, seed, mutations) in variants {
+        for mutation in mutations {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", id, seed, mutation.position, mutation.kind, mutation.reference,
+                     mutation.alternate)?;
+        }
+    }
+    out.flush()
+}
+
+/// Relative weight of inserting before base `position` (1-based) given the
+/// bases around the nick. L1 endonuclease cuts 5'-TTTT/AA-3' on the bottom
+/// strand, i.e. TT/AAAA on the top strand, and tolerates weaker matches.
+fn endonuclease_weight(sequence: &[u8], position: usize) -> usize {
+    let cut = position - 1;
+    let left = &sequence[..cut];
+    let right = &sequence[cut..];
+    if (left.ends_with(b"TTTT") && right.starts_with(b"AA")) || (left.ends_with(b"TT") && right.starts_with(b"AAAA")) {
+        20
+    } else if left.ends_with(b"T") && right.starts_with(b"A") {
+        4
+    } else {
+        1
+    }
+}
+
+/// Picks a mobile-element insertion site, weighted towards endonuclease
+/// motifs. The returned position is 1-based and leaves room for a target
+/// site duplication of `tsd` bases to its left.
+pub fn mei_site(sequence: &str, tsd: usize, seed: u64) -> Result<usize, String> {
+    if tsd >= sequence.len() {
+        return Err(format!("Sequence is too short ({}bp) for a {}bp target site duplication", sequence.len(), tsd));
+    }
+    let bases = sequence.as_bytes();
+    let sites = (tsd + 1)..=sequence.len();
+    let total: usize = sites.clone().map(|p| endonuclease_weight(bases, p)).sum();
+    let mut pick = Rng::new(seed).below(total);
+    for position in sites {
+        let weight = endonuclease_weight(bases, position);
+        if pick < weight {
+            return Ok(position);
+        }
+        pick -= weight;
+    }
+    unreachable!("weighted pick is below the total weight")
+}
+
+/// Synthesis constraints on a randomly drawn insert.
+#[derive(Debug, Clone)]
+pub struct InsertConstraints {
+    /// Longest run of one base allowed
+    pub max_homopolymer: Option<usize>,
+    /// GC fraction bounds of every window of `gc_window` bases (of the whole
+    /// insert when it is shorter)
+    pub gc: Option<(f64, f64)>,
+    pub gc_window: usize,
+    /// Motifs that may not occur on either strand, such as restriction sites
+    pub avoid: Vec<String>,
+}
+
+/// Number of times a random insert is restarted after drawing itself into a
+/// corner before the constraints are declared unsatisfiable.
+const INSERT_ATTEMPTS: usize = 1000;
+
+/// Draws `length` random bases one at a time from `bases`, each from those
+/// that keep the insert within `constraints`.
+pub fn random_insert(length: usize, constraints: &InsertConstraints, bases: &[u8], alphabet: Alphabet,
+                     rng: &mut Rng) -> Result<String, String> {
+    // Avoided motifs on both strands
+    let mut motifs: Vec<Vec<u8>> = Vec::new();
+    for motif in &constraints.avoid {
+        let reverse: String = motif.chars().rev().map(|b| alphabet.complement(b)).collect();
+        motifs.push(motif.as_bytes().to_vec());
+        motifs.push(reverse.into_bytes());
+    }
+    let window = constraints.gc_window.min(length).max(1);
+    let gc_bounds = constraints.gc.map(|(low, high)| {
+        ((low * window as f64).ceil() as usize, (high * window as f64).floor() as usize)
+    });
+    let is_gc = |b: u8| b == b'G' || b == b'C';
+
+    for _ in 0..INSERT_ATTEMPTS {
+        let mut drawn: Vec<u8> = Vec::with_capacity(length);
+        while drawn.len() < length {
+            let allowed: Vec<u8> = bases.iter().copied().filter(|&base| {
+                drawn.push(base);
+                let n = drawn.len();
+                let run = drawn.iter().rev().take_while(|&&b| b == base).count();
+                let homopolymer_ok = constraints.max_homopolymer.is_none_or(|max| run <= max);
+                let motif_ok = motifs.iter().all(|motif| !drawn.ends_with(motif));
+                // The window ending here must still be able to land in bounds
+                let gc_ok = gc_bounds.is_none_or(|(low, high)| {
+                    let filled = n.min(window);
+                    let gc = drawn[n - filled..].iter().filter(|&&b| is_gc(b)).count();
+                    gc <= high && gc + (window - filled) >= low
+                });
+                drawn.pop();
+                homopolymer_ok && motif_ok && gc_ok
+            }).collect();
+            if allowed.is_empty() {
+                break;
+            }
+            drawn.push(allowed[rng.below(allowed.len())]);
+        }
+        if drawn.len() == length {
+            return Ok(String::from_utf8(drawn).expect("bases are ASCII"));
+        }
+    }
+    Err(format!("Could not draw a {}bp insert satisfying the constraints in {} attempts", length, INSERT_ATTEMPTS))
+}
+
+/// Number of uniform draws tried when looking for an insertion point outside
+/// every repeat.
+const OUTSIDE_ATTEMPTS: usize = 100_000;
+
+/// Whether inserting before base `position` (1-based) breaks a repeat, i.e.
+/// both bases either side of the insertion point lie in the same interval.
+fn inside_repeat(repeats: &[&BedInterval], position: usize) -> bool {
+    repeats.iter().any(|r| r.start + 2 <= position && position <= r.end)
+}
+
+/// Draws `count` distinct insertion points in `lowest..=length` (inserting
+/// before that base), `inside` of them within the given repeats of this
+/// sequence and the rest outside every repeat.
+pub fn repeat_placed_positions(length: usize, lowest: usize, repeats: &[&BedInterval], count: usize,
+                               inside: usize, rng: &mut Rng) -> Result<Vec<usize>, String> {
+    // Insertion points inside each repeat, clipped to the allowed range
+    let spans: Vec<(usize, usize)> = repeats.iter()
+        .map(|r| ((r.start + 2).max(lowest), r.end.min(length)))
+        .filter(|(low, high)| low <= high)
+        .collect();
+    let inside_total: usize = spans.iter().map(|(low, high)| high - low + 1).sum();
+    if inside > inside_total {
+        return Err(format!("Annotated repeats only offer {} insertion points, {} needed", inside_total, inside));
+    }
+    if lowest > length || count - inside > (length - lowest + 1).saturating_sub(inside_total) {
+        return Err(format!("Sequence ({}bp) is too short for {} insertions outside repeats", length, count - inside));
+    }
+
+    let mut picked = BTreeSet::new();
+    while picked.len() < inside {
+        let mut pick = rng.below(inside_total);
+        for &(low, high) in &spans {
+            if pick <= high - low {
+                picked.insert(low + pick);
+                break;
+            }
+            pick -= high - low + 1;
+        }
+    }
+    let mut attempts = 0;
+    while picked.len() < count {
+        let position = rng.between(lowest, length);
+        if !inside_repeat(repeats, position) {
+            picked.insert(position);
+        }
+        attempts += 1;
+        if attempts > OUTSIDE_ATTEMPTS {
+            return Err("Could not find enough insertion points outside the annotated repeats".to_string());
+        }
+    }
+    Ok(picked.into_iter().collect())
+}
+
+/// Writes the per-cycle breakpoints of a breakage-fusion-bridge run as TSV,
+/// with each cycle's break and fold-back end also as breakends of the input
+/// (`layout::bfb_layout`), the strand the molecule leaves them on appended.
+pub fn write_bfb_manifest(path: &str, records: &[(&str, u64, Vec<BfbCycle>, Vec<CycleEnds>)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "sequence\tseed\tcycle\tlength_before\tbreak_position\tfoldback_start\tlength_after\tbreak_input\tfoldback_start_input")?;
+    let breakend = |end: &Breakend| format!("{}{}", end.position, if end.reverse { '-' } else { '+' });
+    for (id, seed, cycles, ends) in records {
+        for (c, (fold, end)) in cycles.iter().zip(ends) {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", id, seed, c.cycle, c.length_before, c.break_position,
+                     c.foldback_start, c.length_after, breakend(fold), breakend(end))?;
+        }
+    }
+    out.flush()
+}
+
+/// Writes the fragment-order manifest of a chromothripsis event as TSV: the
+/// reassembled fragments in their derived order with 1-based coordinates on
+/// the input and on the derived sequence, followed by the lost fragments.
+/// `region_start` is the 0-based start of the shattered region, which is also
+/// where the reassembled fragments begin in the derived sequence.
+pub fn write_fragment_manifest(path: &str, records: &[(&str, u64, Shattering)], region_start: usize) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "sequence\tseed\tfragment\tstart\tend\tlength\torientation\tderived_start\tderived_end")?;
+
+    for (id, seed, shattering) in records {
+        let mut offset = region_start;
+        for fragment in &shattering.kept {
+            let length = fragment.end - fragment.start;
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", id, seed, fragment.index, fragment.start + 1,
+                     fragment.end, length, if fragment.reverse { "-" } else { "+" }, offset + 1, offset + length)?;
+            offset += length;
+        }
+        for fragment in &shattering.lost {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\tlost\t.\t.", id, seed, fragment.index, fragment.start + 1,
+                     fragment.end, fragment.end - fragment.start)?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use crate::recipe::Recipe;
+use crate::registry;
+use crate::sgrna;
+use crate::staging;
+use crate::units;
+
+/// Values of every parameter for one generated genome, in spec order.
+pub type Combination = Vec<(String, usize)>;
+
+/// One swept parameter of a generation spec and the values it takes.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub values: Vec<usize>,
+}
+
+/// A generation spec: parameter sweeps and the plan template they fill in.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    pub params: Vec<Parameter>,
+    pub template: Recipe,
+}
+
+/// Reads a generation spec. Lines of the form `name = values` declare a
+/// parameter, where the values are a comma-separated list of numbers and
+/// inclusive `start..end [step n]` ranges; every other line is a plan line
+/// using `{...}` placeholders as in recipes. Blank lines and `#` comments
+/// are ignored.
+pub fn read_spec(path: &str) -> Result<Spec, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read spec {}: {}", path, e))?;
+    let mut params: Vec<Parameter> = Vec::new();
+    let mut lines = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            continue;
+        }
+        let Some((name, values)) = declaration(content) else {
+            lines.push(content.to_string());
+            continue;
+        };
+        if params.iter().any(|param| param.name == name) {
+            return Err(format!("{} line {}: parameter '{}' is declared twice", path, i + 1, name));
+        }
+        let values = parse_values(values).map_err(|msg| format!("{} line {}: {}", path, i + 1, msg))?;
+        params.push(Parameter { name: name.to_string(), values });
+    }
+
+    if lines.is_empty() {
+        return Err(format!("Spec {} contains no operations", path));
+    }
+    let template = Recipe {
+        name: path.to_string(),
+        params: params.iter().map(|param| param.name.clone()).collect(),
+        lines,
+    };
+    Ok(Spec { params, template })
+}
+
+/// The name and values of a `name = values` line. Plan lines carry `=` too
+/// (`af=0.5`, `layer=tumour`), so only a bare identifier before the `=`,
+/// on a line that does not start with an operation or plan keyword,
+/// declares a parameter.
+fn declaration(content: &str) -> Option<(&str, &str)> {
+    let (name, values) = content.split_once('=')?;
+    let name = name.trim();
+    let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let first_word = content.split(|c: char| c.is_whitespace() || c == '=').next().unwrap_or("");
+    let keyword = registry::is_operation(first_word) || matches!(first_word, "layer" | "from" | "schema_version");
+    (identifier && !keyword).then_some((name, values))
+}
+
+/// Parses `500..5000 step 100, 6000` into the listed values, in order.
+fn parse_values(text: &str) -> Result<Vec<usize>, String> {
+    let number = |value: &str| units::parse_bases(value.trim());
+    let mut values = Vec::new();
+    for item in text.split(',') {
+        let (range, step) = match item.split_once("step") {
+            Some((range, step)) => (range, number(step)?),
+            None => (item, 1),
+        };
+        match range.split_once("..") {
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                if step == 0 {
+                    return Err("step must be at least 1".to_string());
+                }
+                if start > end {
+                    return Err(format!("range {}..{} is empty", start, end));
+                }
+                values.extend((start..=end).step_by(step));
+            },
+            None if item.contains("step") => return Err(format!("'{}': step only applies to a range", item.trim())),
+            None => values.push(number(range)?),
+        }
+    }
+    Ok(values)
+}
+
+impl Spec {
+    /// Every combination of parameter values, varying the last parameter
+    /// fastest.
+    pub fn combinations(&self) -> Vec<Combination> {
+        let mut combinations = vec![Vec::new()];
+        for param in &self.params {
+            combinations = combinations.into_iter()
+                .flat_map(|combination: Combination| param.values.iter().map(move |&value| {
+                    let mut extended = combination.clone();
+                    extended.push((param.name.clone(), value));
+                    extended
+                }))
+                .collect();
+        }
+        combinations
+    }
+}
+
+/// Parses the DVG:full-length ratios of a dilution series, such as
+/// `100..0.01 fold 10, 0.005`: comma-separated ratios and geometric series
+/// from one ratio towards another by a constant fold (10 by default).
+pub fn parse_ratios(text: &str) -> Result<Vec<f64>, String> {
+    let number = |value: &str| value.trim().parse::<f64>().ok().filter(|r| r.is_finite() && *r > 0.0)
+        .ok_or_else(|| format!("'{}' is not a positive ratio", value.trim()));
+    let mut ratios = Vec::new();
+    for item in text.split(',') {
+        let (series, fold) = match item.split_once("fold") {
+            Some((series, fold)) => (series, number(fold)?),
+            None => (item, 10.0),
+        };
+        match series.split_once("..") {
+            Some((from, to)) => {
+                let (from, to) = (number(from)?, number(to)?);
+                if fold <= 1.0 {
+                    return Err(format!("fold {} must be greater than 1", fold));
+                }
+                // Dilute or concentrate towards `to`, stopping at it within
+                // rounding error
+                let factor = if to < from { 1.0 / fold } else { fold };
+                let steps = ((to / from).ln() / factor.ln() + 1e-9).floor() as i32;
+                // Rounded to ten significant digits, so 10-fold steps stay
+                // 0.1 and 0.01 rather than picking up float noise
+                ratios.extend((0..=steps).map(|step| format!("{:.9e}", from * factor.powi(step))
+                    .parse::<f64>().expect("a formatted float parses")));
+            },
+            None if item.contains("fold") => return Err(format!("'{}': fold only applies to a series", item.trim())),
+            None => ratios.push(number(series)?),
+        }
+    }
+    Ok(ratios)
+}
+
+/// Writes the abundance of every genome of a DVG pool at each ratio of a
+/// dilution series: the full-length genome at one copy, and `ratio` copies
+/// of DVG shared equally between the DVGs. Abundances are fractions of
+/// each dilution's pool, as read simulators take them.
+pub fn write_dilution_manifest(path: &str, full_length: &str, dvgs: &[String], ratios: &[f64]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "dilution\tratio\tid\tabundance")?;
+    for (n, &ratio) in ratios.iter().enumerate() {
+        let total = 1.0 + ratio;
+        writeln!(out, "{}\t{}\t{}\t{:.6e}", n + 1, ratio, full_length, 1.0 / total)?;
+        for id in dvgs {
+            writeln!(out, "{}\t{}\t{}\t{:.6e}", n + 1, ratio, id, ratio / dvgs.len() as f64 / total)?;
+        }
+    }
+    out.flush()
+}
+
+/// Marks the bases (0-based) of `sequence` covered by an occurrence of any
+/// of `motifs`, treating T and U as the same base.
+pub fn hotspot_mask(sequence: &str, motifs: &[String]) -> Vec<bool> {
+    let mut mask = vec![false; sequence.len()];
+    for motif in motifs {
+        for start in sgrna::find_motif(sequence, motif) {
+            mask[start - 1..start - 1 + motif.len()].fill(true);
+        }
+    }
+    mask
+}
+
+/// Writes the parameter values behind each generated genome.
+pub fn write_generation_manifest(path: &str, params: &[Parameter],
+                                 generated: &[(String, Combination, usize)]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    write!(out, "id")?;
+    for param in params {
+        write!(out, "\t{}", param.name)?;
+    }
+    writeln!(out, "\tlength")?;
+    for (id, values, length) in generated {
+        write!(out, "{}", id)?;
+        for (_, value) in values {
+            write!(out, "\t{}", value)?;
+        }
+        writeln!(out, "\t{}", length)?;
+    }
+    out.flush()
+}
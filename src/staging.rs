@@ -0,0 +1,132 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Whether outputs are staged. A program turns it on with `enable`; until
+/// then `create` and `append` write to the paths they are given, so a
+/// library caller finds its files where it asked without committing.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stages every output written from now on, to be published by `commit`.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// An output file being written under a temporary name next to its final
+/// path, so the rename into place cannot cross filesystems.
+struct Staged {
+    path: PathBuf,
+    temporary: PathBuf,
+}
+
+/// Every output file of the run so far. They are moved into place together
+/// by `commit` once the run has succeeded; until then a failed run leaves
+/// whatever was at their paths untouched.
+static STAGED: Mutex<Vec<Staged>> = Mutex::new(Vec::new());
+
+/// Text to append to files that are only ever appended to, such as
+/// journals, held back like the staged files until the run has succeeded.
+static APPENDS: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+fn appends() -> std::sync::MutexGuard<'static, Vec<(PathBuf, String)>> {
+    APPENDS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn staged() -> std::sync::MutexGuard<'static, Vec<Staged>> {
+    STAGED.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The temporary file `path` is written to, staging it if it is not yet.
+fn temporary_for(path: &Path) -> PathBuf {
+    let mut staged = staged();
+    if let Some(file) = staged.iter().find(|file| file.path == path) {
+        return file.temporary.clone();
+    }
+    let name = path.file_name().map_or_else(|| "output".into(), |name| name.to_string_lossy());
+    let temporary = path.with_file_name(format!(".{}.sv_maker-{}.partial", name, std::process::id()));
+    staged.push(Staged { path: path.to_path_buf(), temporary: temporary.clone() });
+    temporary
+}
+
+/// Creates (or truncates) the staged file for output `path`, or `path`
+/// itself when staging is off.
+pub fn create(path: impl AsRef<Path>) -> io::Result<File> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return File::create(path);
+    }
+    File::create(temporary_for(path.as_ref()))
+}
+
+/// Where output `path` currently is: its staged file while the run is
+/// writing it, `path` itself otherwise.
+pub fn current_path(path: &str) -> PathBuf {
+    match staged().iter().find(|file| file.path == Path::new(path)) {
+        Some(file) => file.temporary.clone(),
+        None => PathBuf::from(path),
+    }
+}
+
+/// The absolute path of `path`. Staged outputs do not exist there yet, so
+/// theirs is the path they will have once committed.
+pub fn canonical(path: &str) -> io::Result<PathBuf> {
+    let path = Path::new(path);
+    if !staged().iter().any(|file| file.path == path) {
+        return fs::canonicalize(path);
+    }
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    Ok(match path.file_name() {
+        Some(name) => directory.join(name),
+        None => directory,
+    })
+}
+
+/// Queues `text` to be appended to `path` once the run commits, or appends
+/// it now when staging is off.
+pub fn append(path: impl AsRef<Path>, text: String) -> io::Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return OpenOptions::new().create(true).append(true).open(path)?.write_all(text.as_bytes());
+    }
+    appends().push((path.as_ref().to_path_buf(), text));
+    Ok(())
+}
+
+/// Moves every staged file into place, then makes the queued appends.
+/// Should a rename fail, the files not yet moved are removed rather than
+/// left half-published, and nothing is appended.
+pub fn commit() -> io::Result<()> {
+    let files: Vec<Staged> = staged().drain(..).collect();
+    for (i, file) in files.iter().enumerate() {
+        if let Err(error) = fs::rename(&file.temporary, &file.path) {
+            for unmoved in &files[i..] {
+                let _ = fs::remove_file(&unmoved.temporary);
+            }
+            appends().clear();
+            return Err(io::Error::new(error.kind(), format!("could not move {} into place: {}",
+                                                            file.path.display(), error)));
+        }
+    }
+    for (path, text) in appends().drain(..) {
+        OpenOptions::new().create(true).append(true).open(&path)?.write_all(text.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Removes every staged file and drops the queued appends, leaving the
+/// output paths as they were.
+pub fn discard() {
+    for file in staged().drain(..) {
+        let _ = fs::remove_file(&file.temporary);
+    }
+    appends().clear();
+}
+
+/// Exits with `code`, first discarding the run's staged outputs.
+pub fn exit(code: i32) -> ! {
+    discard();
+    std::process::exit(code)
+}
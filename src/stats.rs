@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// Number of equal-width bins in each histogram.
+const BINS: usize = 20;
+
+/// Counts of values in one bin, 1-based and inclusive.
+#[derive(Debug, Serialize)]
+pub struct Bin {
+    pub start: usize,
+    pub end: usize,
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LengthStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub histogram: Vec<Bin>,
+}
+
+/// Aggregate statistics of a batch of generated genomes, for plotting
+/// without parsing every header.
+#[derive(Debug, Serialize)]
+pub struct BatchStats {
+    pub combinations: usize,
+    pub generated: usize,
+    pub skipped: usize,
+    pub filtered: usize,
+    /// Steps applied across all generated genomes, by operation
+    pub operations: BTreeMap<String, usize>,
+    pub lengths: Option<LengthStats>,
+    /// Breakend positions of every junction on the input genome
+    pub junctions: usize,
+    pub junction_positions: Vec<Bin>,
+}
+
+impl BatchStats {
+    /// Rolls up the generated genomes: the operations their steps used, their
+    /// lengths, and the input positions of their junctions' breakends.
+    pub fn new(operations: &[&str], lengths: &[usize], breakends: &[usize], genome_length: usize) -> BatchStats {
+        let mut counts = BTreeMap::new();
+        for &operation in operations {
+            *counts.entry(operation.to_string()).or_insert(0) += 1;
+        }
+        BatchStats {
+            combinations: lengths.len(),
+            generated: lengths.len(),
+            skipped: 0,
+            filtered: 0,
+            operations: counts,
+            lengths: length_stats(lengths),
+            junctions: breakends.len(),
+            junction_positions: histogram(breakends, 1, genome_length),
+        }
+    }
+}
+
+fn length_stats(lengths: &[usize]) -> Option<LengthStats> {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable();
+    let (&min, &max) = (sorted.first()?, sorted.last()?);
+    let middle = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) as f64 / 2.0
+    } else {
+        sorted[middle] as f64
+    };
+    Some(LengthStats {
+        min,
+        max,
+        mean: sorted.iter().sum::<usize>() as f64 / sorted.len() as f64,
+        median,
+        histogram: histogram(&sorted, min, max),
+    })
+}
+
+/// Counts `values` in up to `BINS` bins of equal whole-number width covering
+/// `low..=high`.
+fn histogram(values: &[usize], low: usize, high: usize) -> Vec<Bin> {
+    if high < low {
+        return Vec::new();
+    }
+    let width = (high - low + 1).div_ceil(BINS);
+    let mut bins: Vec<Bin> = (low..=high).step_by(width)
+        .map(|start| Bin { start, end: (start + width - 1).min(high), count: 0 })
+        .collect();
+    for &value in values.iter().filter(|&&value| value >= low && value <= high) {
+        bins[(value - low) / width].count += 1;
+    }
+    bins
+}
@@ -0,0 +1,91 @@
+use serde::Serialize;
+
+use crate::alphabet::Alphabet;
+
+/// Whether two bases form a Watson-Crick pair. Ambiguous bases never pair.
+fn pairs(a: char, b: char, alphabet: Alphabet) -> bool {
+    let (a, b) = (a.to_ascii_uppercase(), b.to_ascii_uppercase());
+    matches!(a, 'A' | 'C' | 'G' | 'T' | 'U') && alphabet.complement(a) == b
+}
+
+/// Length of the perfectly complementary stem formed by the two termini of
+/// `sequence`: the number of bases from the 5' end that pair with the bases
+/// read back from the 3' end.
+pub fn terminal_stem(sequence: &str, alphabet: Alphabet) -> usize {
+    let bases = sequence.as_bytes();
+    let mut length = 0;
+    while length < bases.len() / 2 && pairs(bases[length] as char, bases[bases.len() - 1 - length] as char, alphabet) {
+        length += 1;
+    }
+    length
+}
+
+/// Nearest-neighbour stacking free energies at 37°C (kcal/mol), keyed by the
+/// top-strand dinucleotide read 5'->3'. Each stack equals that of its
+/// reverse complement, so only one of the pair is listed.
+const DNA_STACKS: [(&str, f64); 10] = [
+    ("AA", -1.00), ("AT", -0.88), ("TA", -0.58), ("CA", -1.45), ("GT", -1.44),
+    ("CT", -1.28), ("GA", -1.30), ("CG", -2.17), ("GC", -2.24), ("GG", -1.84),
+];
+/// Initiation and per-terminal-AT-pair penalty (SantaLucia 1998).
+const DNA_INITIATION: f64 = 1.96;
+const DNA_TERMINAL_AT: f64 = 0.05;
+
+const RNA_STACKS: [(&str, f64); 10] = [
+    ("AA", -0.93), ("AU", -1.10), ("UA", -1.33), ("CU", -2.08), ("CA", -2.11),
+    ("GU", -2.24), ("GA", -2.35), ("CG", -2.36), ("GG", -3.26), ("GC", -3.42),
+];
+/// Initiation and per-terminal-AU-pair penalty (Xia et al. 1998).
+const RNA_INITIATION: f64 = 4.09;
+const RNA_TERMINAL_AU: f64 = 0.45;
+
+/// Estimated free energy of the terminal stem of `sequence`, or `None` when
+/// the stem is shorter than two pairs. Only the helix is scored: loop and
+/// dangling-end terms are ignored, which is enough to rank stems.
+pub fn stem_delta_g(sequence: &str, alphabet: Alphabet) -> Option<f64> {
+    let length = terminal_stem(sequence, alphabet);
+    if length < 2 {
+        return None;
+    }
+    let rna = alphabet == Alphabet::Rna;
+    let (stacks, initiation, terminal) = if rna {
+        (&RNA_STACKS, RNA_INITIATION, RNA_TERMINAL_AU)
+    } else {
+        (&DNA_STACKS, DNA_INITIATION, DNA_TERMINAL_AT)
+    };
+    // Score RNA stacks on U and DNA stacks on T, whatever the input used
+    let stem: String = sequence[..length].chars()
+        .map(|b| match (b, rna) { ('T', true) => 'U', ('U', false) => 'T', _ => b })
+        .collect();
+
+    let mut delta_g = initiation;
+    for i in 0..length - 1 {
+        let step = &stem[i..i + 2];
+        let reverse: String = step.chars().rev().map(|b| alphabet.complement(b)).collect();
+        let reverse = if rna { reverse.replace('T', "U") } else { reverse.replace('U', "T") };
+        let energy = stacks.iter()
+            .find(|(key, _)| *key == step || *key == reverse)
+            .map(|(_, energy)| *energy)?;
+        delta_g += energy;
+    }
+    for end in [stem.chars().next(), stem.chars().last()].into_iter().flatten() {
+        if matches!(end, 'A' | 'T' | 'U') {
+            delta_g += terminal;
+        }
+    }
+    Some((delta_g * 100.0).round() / 100.0)
+}
+
+/// Length and estimated free energy of a molecule's terminal stem, as
+/// reported in the run summary.
+#[derive(Debug, Serialize)]
+pub struct StemEnergy {
+    pub length: usize,
+    pub delta_g: Option<f64>,
+}
+
+impl StemEnergy {
+    pub fn of(sequence: &str, alphabet: Alphabet) -> StemEnergy {
+        StemEnergy { length: terminal_stem(sequence, alphabet), delta_g: stem_delta_g(sequence, alphabet) }
+    }
+}
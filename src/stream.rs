@@ -0,0 +1,228 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use crate::alphabet::Alphabet;
+use crate::fai::{self, FaiEntry, Region};
+use crate::fasta;
+use crate::layout::Segment;
+use crate::staging;
+use crate::summary;
+
+/// Bases read from the input at a time when streaming.
+const CHUNK: usize = 1 << 20;
+
+/// Bytes in the MB of `--max-memory`.
+pub const MEGABYTE: u64 = 1 << 20;
+
+/// Memory used regardless of the input: code, buffers and bookkeeping.
+const BASELINE_BYTES: u64 = 16 * MEGABYTE;
+
+/// Memory an in-memory run needs per input base: the input as read
+/// (including the slack of a growing buffer), the copy kept for side
+/// outputs, and the edited sequence.
+const IN_MEMORY_BYTES_PER_BASE: u64 = 4;
+
+/// Estimated peak memory of loading `bases` bases and editing them in memory.
+pub fn in_memory_estimate(bases: u64) -> u64 {
+    BASELINE_BYTES + bases * IN_MEMORY_BYTES_PER_BASE
+}
+
+/// Estimated peak memory of streaming an edit with the given layout: a chunk
+/// of input and its decoded bases, plus any novel sequence, which is held in
+/// full.
+pub fn streaming_estimate(segments: &[Segment]) -> u64 {
+    let novel: usize = segments.iter()
+        .map(|segment| if let Segment::Novel(bases) = segment { bases.len() } else { 0 })
+        .sum();
+    BASELINE_BYTES + 3 * CHUNK as u64 + novel as u64
+}
+
+/// Upper bound on the bases a run loads from `input`: the length of the
+/// selected region when the FASTA is indexed, and the file's size otherwise.
+/// `None` for stdin and compressed files, whose size is not known until
+/// they have been read.
+pub fn input_bases(input: &str, region: Option<&Region>) -> io::Result<Option<u64>> {
+    if input == "-" || fasta::is_compressed(input) {
+        return Ok(None);
+    }
+    if let (Some(region), Some(_)) = (region, fai::index_path(input)) {
+        if let Ok(source) = Source::open(input, region) {
+            return Ok(Some(source.length as u64));
+        }
+    }
+    Ok(Some(fs::metadata(input)?.len()))
+}
+
+/// A record, or a range of one, of an indexed FASTA, read a chunk at a time.
+pub struct Source<'a> {
+    path: &'a str,
+    entry: FaiEntry,
+    /// 0-based start of the selected range within the record
+    offset: usize,
+    pub length: usize,
+    pub header: String,
+}
+
+impl<'a> Source<'a> {
+    /// Selects `region` of `path` through its `.fai` index.
+    pub fn open(path: &'a str, region: &Region) -> Result<Source<'a>, String> {
+        let index = fai::index_path(path).ok_or_else(|| format!("{} has no .fai index", path))?;
+        let entries = fai::read_fai(&index).map_err(|e| format!("Cannot read index {}: {}", index, e))?;
+        let entry = entries.into_iter().find(|entry| entry.name == region.name)
+            .ok_or_else(|| format!("Sequence '{}' not found in index {}", region.name, index))?;
+        let (start, end) = region.bounds(entry.length)?;
+        let header = fai::fetch_header(path, &entry).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        Ok(Source { path, offset: start, length: end - start, header: region.header(&header), entry })
+    }
+
+    /// The only record of an indexed FASTA.
+    pub fn only(path: &'a str) -> Result<Source<'a>, String> {
+        let index = fai::index_path(path).ok_or_else(|| format!("{} has no .fai index", path))?;
+        let entries = fai::read_fai(&index).map_err(|e| format!("Cannot read index {}: {}", index, e))?;
+        match entries.as_slice() {
+            [entry] => Source::open(path, &Region { name: entry.name.clone(), range: None }),
+            _ => Err(format!("Input contains {} sequences. Use --region to select one.", entries.len())),
+        }
+    }
+
+    /// Bases per line of the input record.
+    pub fn line_bases(&self) -> usize {
+        self.entry.line_bases
+    }
+
+    /// Calls `each` with consecutive chunks of bases `start..end` (0-based,
+    /// half-open, within the selected range), last chunk first when
+    /// `reverse`.
+    fn chunks(&self, start: usize, end: usize, reverse: bool,
+              mut each: impl FnMut(&str) -> io::Result<()>) -> io::Result<()> {
+        let mut bounds: Vec<(usize, usize)> = (start..end).step_by(CHUNK)
+            .map(|from| (from, (from + CHUNK).min(end)))
+            .collect();
+        if reverse {
+            bounds.reverse();
+        }
+        for (from, to) in bounds {
+            each(&fai::fetch(self.path, &self.entry, self.offset + from, self.offset + to)?)?;
+        }
+        Ok(())
+    }
+
+    /// Lengths of the runs of N at the start and end of the selected range,
+    /// reading only as far as they go.
+    pub fn terminal_ns(&self) -> io::Result<(usize, usize)> {
+        let is_n = |base: &u8| base.eq_ignore_ascii_case(&b'N');
+        let mut leading = 0;
+        while leading < self.length {
+            let end = (leading + CHUNK).min(self.length);
+            let chunk = fai::fetch(self.path, &self.entry, self.offset + leading, self.offset + end)?;
+            let run = chunk.bytes().take_while(is_n).count();
+            leading += run;
+            if run < chunk.len() {
+                break;
+            }
+        }
+        let mut trailing = 0;
+        while leading + trailing < self.length {
+            let start = self.length.saturating_sub(trailing + CHUNK).max(leading);
+            let chunk = fai::fetch(self.path, &self.entry, self.offset + start, self.offset + self.length - trailing)?;
+            let run = chunk.bytes().rev().take_while(is_n).count();
+            trailing += run;
+            if run < chunk.len() {
+                break;
+            }
+        }
+        Ok((leading, trailing))
+    }
+
+    /// Checks every base against `alphabet` and returns the MD5 of the
+    /// selected bases, which is of them uppercased whatever their case.
+    pub fn check(&self, alphabet: Alphabet) -> io::Result<Result<String, String>> {
+        let mut context = md5::Context::new();
+        let mut invalid = None;
+        let mut position = 0;
+        self.chunks(0, self.length, false, |chunk| {
+            if invalid.is_none() {
+                if let Some((i, base)) = chunk.chars().enumerate().find(|&(_, base)| !alphabet.allows(base)) {
+                    invalid = Some(format!("Input sequence contains '{}' at position {}, outside the {} alphabet",
+                                           base, position + i + 1, alphabet.name()));
+                }
+            }
+            position += chunk.len();
+            summary::consume_uppercase(&mut context, chunk);
+            Ok(())
+        })?;
+        Ok(match invalid {
+            Some(msg) => Err(msg),
+            None => Ok(format!("{:x}", context.finalize())),
+        })
+    }
+}
+
+/// Writes `header` and the bases `segments` lay out from `source` as FASTA,
+/// one chunk at a time, and returns the length and MD5 of the written
+/// sequence. Bases are uppercased unless `preserve_case`; the MD5 is of
+/// them uppercased either way.
+pub fn write_streamed(source: &Source, segments: &[Segment], header: &str, line_width: Option<usize>,
+                      preserve_case: bool, alphabet: Alphabet, output: Option<&str>) -> io::Result<(usize, String)> {
+    let out: Box<dyn Write> = match output {
+        Some(path) => Box::new(staging::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut out = Wrapped { out: BufWriter::new(out), width: line_width, column: 0, length: 0, md5: md5::Context::new() };
+    writeln!(out.out, "{}", header)?;
+    for segment in segments {
+        match segment {
+            Segment::Source { start, end, reverse, complement } => {
+                source.chunks(*start, *end, *reverse, |chunk| {
+                    let chunk = if preserve_case { chunk.to_string() } else { chunk.to_ascii_uppercase() };
+                    let bases: String = match (reverse, complement) {
+                        (false, false) => chunk,
+                        (false, true) => chunk.chars().map(|base| alphabet.complement(base)).collect(),
+                        (true, false) => chunk.chars().rev().collect(),
+                        (true, true) => chunk.chars().rev().map(|base| alphabet.complement(base)).collect(),
+                    };
+                    out.push(&bases)
+                })?;
+            },
+            Segment::Novel(bases) => out.push(bases)?,
+        }
+    }
+    // Unwrapped output always ends its one sequence line, even when empty
+    if out.column > 0 || (out.width.is_none() && out.length == 0) {
+        writeln!(out.out)?;
+    }
+    out.out.flush()?;
+    Ok((out.length, format!("{:x}", out.md5.finalize())))
+}
+
+/// FASTA sequence lines written as bases arrive, wrapped at `width`.
+struct Wrapped<W: Write> {
+    out: W,
+    width: Option<usize>,
+    column: usize,
+    length: usize,
+    md5: md5::Context,
+}
+
+impl<W: Write> Wrapped<W> {
+    fn push(&mut self, bases: &str) -> io::Result<()> {
+        summary::consume_uppercase(&mut self.md5, bases);
+        self.length += bases.len();
+        let Some(width) = self.width else {
+            self.column += bases.len();
+            return self.out.write_all(bases.as_bytes());
+        };
+        let mut rest = bases.as_bytes();
+        while !rest.is_empty() {
+            let take = (width - self.column).min(rest.len());
+            self.out.write_all(&rest[..take])?;
+            self.column += take;
+            rest = &rest[take..];
+            if self.column == width {
+                self.out.write_all(b"\n")?;
+                self.column = 0;
+            }
+        }
+        Ok(())
+    }
+}
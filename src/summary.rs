@@ -0,0 +1,192 @@
+use std::io::{BufWriter, Write};
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::fasta::Record;
+use crate::schema;
+use crate::staging;
+use crate::stem::StemEnergy;
+use crate::telemetry::Telemetry;
+use crate::vcf::Allele;
+use crate::Operation;
+
+/// Machine-readable description of a single run, written with `--summary`.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub schema_version: u32,
+    pub operation: Operation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intended_operation: Option<Operation>,
+    pub input: String,
+    pub input_length: usize,
+    pub input_md5: String,
+    pub output_length: usize,
+    pub output_md5: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminal_stem: Option<StemEnergy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<Telemetry>,
+}
+
+impl RunSummary {
+    pub fn new(operation: Operation, input: &str, input_sequence: &str, output_sequence: &str) -> Self {
+        RunSummary {
+            schema_version: schema::SCHEMA_VERSION,
+            operation,
+            intended_operation: None,
+            input: input.to_string(),
+            input_length: input_sequence.len(),
+            input_md5: sequence_md5(input_sequence),
+            output_length: output_sequence.len(),
+            output_md5: sequence_md5(output_sequence),
+            terminal_stem: None,
+            telemetry: None,
+        }
+    }
+}
+
+/// Per-record outcome of an `--all` run.
+#[derive(Debug, Serialize)]
+pub struct RecordSummary {
+    pub id: String,
+    pub input_length: usize,
+    pub output_length: usize,
+    pub output_md5: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+impl RecordSummary {
+    pub fn applied(input: &Record, output: &Record) -> Self {
+        RecordSummary {
+            id: input.id().to_string(),
+            input_length: input.sequence.len(),
+            output_length: output.sequence.len(),
+            output_md5: sequence_md5(&output.sequence),
+            seed: None,
+            skipped: None,
+        }
+    }
+
+    pub fn skipped(input: &Record, reason: String) -> Self {
+        RecordSummary {
+            id: input.id().to_string(),
+            input_length: input.sequence.len(),
+            output_length: input.sequence.len(),
+            output_md5: sequence_md5(&input.sequence),
+            seed: None,
+            skipped: Some(reason),
+        }
+    }
+}
+
+impl fmt::Display for RecordSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.skipped {
+            Some(reason) => write!(f, "{}: skipped ({})", self.id, reason),
+            None => {
+                write!(f, "{}: {}bp -> {}bp", self.id, self.input_length, self.output_length)?;
+                match self.seed {
+                    Some(seed) => write!(f, " (seed {})", seed),
+                    None => Ok(()),
+                }
+            },
+        }
+    }
+}
+
+/// Summary of an `--all` run: the shared operation and one entry per record.
+#[derive(Debug, Serialize)]
+pub struct BroadcastSummary {
+    pub schema_version: u32,
+    pub operation: Operation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intended_operation: Option<Operation>,
+    pub input: String,
+    pub records: Vec<RecordSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<Telemetry>,
+}
+
+/// One step of a plan run.
+#[derive(Debug, Serialize)]
+pub struct PlanStepSummary {
+    pub line: usize,
+    pub operation: Operation,
+    #[serde(flatten, skip_serializing_if = "Allele::is_empty")]
+    pub allele: Allele,
+    pub records: Vec<RecordSummary>,
+}
+
+/// Summary of a plan run: one entry per plan step, in order.
+#[derive(Debug, Serialize)]
+pub struct PlanSummary {
+    pub schema_version: u32,
+    pub input: String,
+    pub plan: String,
+    pub steps: Vec<PlanStepSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<Telemetry>,
+}
+
+/// MD5 of the bare sequence (no header, no line breaks), matching the M5
+/// field samtools writes into @SQ lines.
+pub fn sequence_md5(sequence: &str) -> String {
+    let mut context = md5::Context::new();
+    consume_uppercase(&mut context, sequence);
+    format!("{:x}", context.finalize())
+}
+
+/// Adds `bases` to an MD5 uppercased, as samtools hashes them for M5, so
+/// that the case kept by `--preserve-case` does not change the digest.
+pub fn consume_uppercase(context: &mut md5::Context, bases: &str) {
+    for chunk in bases.as_bytes().chunks(1 << 16) {
+        context.consume(chunk.to_ascii_uppercase());
+    }
+}
+
+/// Fails if the input sequences, concatenated in order, do not hash to
+/// `expected`.
+pub fn check_input_md5(records: &[Record], expected: &str) -> Result<(), String> {
+    let mut context = md5::Context::new();
+    for record in records {
+        consume_uppercase(&mut context, &record.sequence);
+    }
+    expect_md5(&format!("{:x}", context.finalize()), expected)
+}
+
+/// Fails if the input sequence MD5 `actual` is not `expected`.
+pub fn expect_md5(actual: &str, expected: &str) -> Result<(), String> {
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(format!("Input sequence MD5 {} does not match expected {}", actual, expected));
+    }
+    Ok(())
+}
+
+/// Writes SAM header lines for `records` as `samtools dict` would: an @HD
+/// line and one @SQ line per record with its name, length and MD5, plus the
+/// URI of the FASTA they were written to when there is one.
+pub fn write_sq_header(path: &str, records: &[Record], uri: Option<&str>) -> std::io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    writeln!(out, "@HD\tVN:1.6\tSO:unsorted")?;
+    for record in records {
+        write!(out, "@SQ\tSN:{}\tLN:{}\tM5:{}", record.id(), record.sequence.len(),
+               sequence_md5(&record.sequence))?;
+        if let Some(uri) = uri {
+            write!(out, "\tUR:{}", uri)?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+pub fn write_summary<T: Serialize>(path: &str, summary: &T) -> std::io::Result<()> {
+    let mut file = staging::create(path)?;
+    let json = serde_json::to_string_pretty(summary)?;
+    writeln!(file, "{}", json)?;
+    Ok(())
+}
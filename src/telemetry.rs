@@ -0,0 +1,81 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::staging;
+
+/// Phases of a run timed for the `--summary` telemetry.
+#[derive(Debug, Clone, Copy)]
+pub enum Phase {
+    /// Reading and checking the input
+    Read,
+    /// Applying the edits
+    Apply,
+}
+
+/// Wall-clock time spent in each phase of a run so far.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    lap: Instant,
+    read: Duration,
+    apply: Duration,
+}
+
+impl Clock {
+    pub fn start() -> Clock {
+        Clock { lap: Instant::now(), read: Duration::ZERO, apply: Duration::ZERO }
+    }
+
+    /// Charges the time since the last lap to `phase`.
+    pub fn lap(&mut self, phase: Phase) {
+        let now = Instant::now();
+        let elapsed = now - self.lap;
+        match phase {
+            Phase::Read => self.read += elapsed,
+            Phase::Apply => self.apply += elapsed,
+        }
+        self.lap = now;
+    }
+
+    /// A copy of the clock that charges nothing for the time until now, for a
+    /// run that shares the input read with others (the layers of a plan).
+    pub fn resumed(&self) -> Clock {
+        Clock { lap: Instant::now(), ..*self }
+    }
+
+    /// The telemetry of a run whose outputs have been written since the last
+    /// lap. File sizes are only known for files: reading stdin or writing to
+    /// stdout gives `null`.
+    pub fn telemetry(&self, input: &str, output: Option<&str>) -> Telemetry {
+        let file_size = |path: &str| fs::metadata(staging::current_path(path)).ok().map(|metadata| metadata.len());
+        Telemetry {
+            read_seconds: self.read.as_secs_f64(),
+            apply_seconds: self.apply.as_secs_f64(),
+            write_seconds: self.lap.elapsed().as_secs_f64(),
+            peak_rss_bytes: peak_rss_bytes(),
+            input_bytes: if input == "-" { None } else { file_size(input) },
+            output_bytes: output.and_then(file_size),
+        }
+    }
+}
+
+/// Resource use of a run, in the `telemetry` entry of the `--summary` JSON.
+#[derive(Debug, Serialize)]
+pub struct Telemetry {
+    pub read_seconds: f64,
+    pub apply_seconds: f64,
+    pub write_seconds: f64,
+    pub peak_rss_bytes: Option<u64>,
+    pub input_bytes: Option<u64>,
+    pub output_bytes: Option<u64>,
+}
+
+/// Peak resident set size of the process so far, from the `VmHWM` line of
+/// `/proc/self/status`; `None` where that is not available (non-Linux).
+fn peak_rss_bytes() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line["VmHWM:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::alphabet::Alphabet;
+use crate::fasta::Record;
+use crate::layout::{self, Segment};
+use crate::liftover;
+use crate::Operation;
+
+/// The exons of one transcript, in transcript order (5' to 3').
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub id: String,
+    pub seqid: String,
+    pub reverse: bool,
+    /// 0-based, half-open genomic bounds
+    exons: Vec<(usize, usize)>,
+}
+
+/// Reads the transcripts of a GTF file from its `exon` lines, in the order
+/// they first appear.
+pub fn read_gtf(path: &str) -> Result<Vec<Transcript>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read GTF file {}: {}", path, e))?;
+    let mut transcripts: Vec<Transcript> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return Err(format!("{} line {}: expected 9 tab-separated columns", path, i + 1));
+        }
+        if fields[2] != "exon" {
+            continue;
+        }
+        // Attributes are `key "value";` pairs
+        let id = fields[8].split(';')
+            .filter_map(|pair| pair.trim().split_once(' '))
+            .find(|(key, _)| *key == "transcript_id")
+            .map(|(_, value)| value.trim().trim_matches('"'))
+            .ok_or_else(|| format!("{} line {}: exon has no transcript_id", path, i + 1))?;
+        let coordinate = |value: &str| value.trim().parse::<usize>()
+            .map_err(|_| format!("{} line {}: '{}' is not a valid coordinate", path, i + 1, value));
+        let (start, end) = (coordinate(fields[3])?, coordinate(fields[4])?);
+        if start == 0 || start > end {
+            return Err(format!("{} line {}: {}-{} is not a 1-based range", path, i + 1, start, end));
+        }
+        let reverse = fields[6] == "-";
+        let n = *index.entry(id.to_string()).or_insert_with(|| {
+            transcripts.push(Transcript { id: id.to_string(), seqid: fields[0].to_string(), reverse, exons: Vec::new() });
+            transcripts.len() - 1
+        });
+        let transcript = &mut transcripts[n];
+        if transcript.seqid != fields[0] || transcript.reverse != reverse {
+            return Err(format!("{} line {}: exons of transcript {} are on more than one sequence or strand",
+                               path, i + 1, id));
+        }
+        transcript.exons.push((start - 1, end));
+    }
+
+    for transcript in transcripts.iter_mut() {
+        transcript.exons.sort_unstable();
+        if let Some(pair) = transcript.exons.windows(2).find(|pair| pair[1].0 < pair[0].1) {
+            return Err(format!("Exons {}-{} and {}-{} of transcript {} overlap",
+                               pair[0].0 + 1, pair[0].1, pair[1].0 + 1, pair[1].1, transcript.id));
+        }
+        if transcript.reverse {
+            transcript.exons.reverse();
+        }
+    }
+    Ok(transcripts)
+}
+
+/// The transcript `transcript_id` of a GTF file.
+pub fn read_transcript(path: &str, transcript_id: &str) -> Result<Transcript, String> {
+    read_gtf(path)?.into_iter().find(|transcript| transcript.id == transcript_id)
+        .ok_or_else(|| format!("Transcript '{}' has no exons in {}", transcript_id, path))
+}
+
+impl Transcript {
+    /// Length of the spliced transcript.
+    pub fn length(&self) -> usize {
+        self.exons.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// The spliced transcript (cDNA) from its genomic record, as a record
+    /// named after the transcript.
+    pub fn cdna(&self, record: &Record, alphabet: Alphabet, insert_qual: char) -> Result<Record, String> {
+        let last = self.exons.iter().map(|&(_, end)| end).max().unwrap_or(0);
+        if last > record.sequence.len() {
+            return Err(format!("Transcript {} ends at {}, beyond sequence '{}' of length {}",
+                               self.id, last, record.id(), record.sequence.len()));
+        }
+        let identity = layout::identity(record.sequence.len());
+        Ok(self.lifted(record, &identity, alphabet, insert_qual).expect("an unedited transcript keeps its exons"))
+    }
+
+    /// The transcript spliced from `derived`, its record after the edits
+    /// laid out by `segments`: the bases its exons were copied to, and any
+    /// inserted inside or next to an exon, read in order along the derived
+    /// record on the transcript's strand. `None` when every exon was deleted.
+    pub fn lifted(&self, derived: &Record, segments: &[Segment], alphabet: Alphabet, insert_qual: char) -> Option<Record> {
+        let pieces: Vec<liftover::Mapped> = self.exons.iter()
+            .flat_map(|&(start, end)| liftover::map_interval(segments, start, end))
+            .collect();
+        // A transcript inverted as a whole is now read on the other strand
+        let reverse = self.reverse != (!pieces.is_empty() && pieces.iter().all(|piece| piece.reverse));
+        let mut exonic: Vec<(usize, usize)> = pieces.iter().map(|piece| (piece.start, piece.end)).collect();
+        let covered = |position: usize| exonic.iter().any(|&(start, end)| start <= position && position < end);
+        let mut offset = 0;
+        let mut inserted = Vec::new();
+        for segment in segments {
+            // Novel bases next to an exon are part of it, like the insertions
+            // `--transcript` projects onto exon boundaries
+            if let Segment::Novel(_) = segment {
+                if (offset > 0 && covered(offset - 1)) || covered(offset + segment.len()) {
+                    inserted.push((offset, offset + segment.len()));
+                }
+            }
+            offset += segment.len();
+        }
+        exonic.extend(inserted);
+        exonic.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in exonic {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        if merged.is_empty() {
+            return None;
+        }
+
+        let mut spliced: Vec<Segment> = merged.iter()
+            .map(|&(start, end)| Segment::Source { start, end, reverse, complement: reverse })
+            .collect();
+        if reverse {
+            spliced.reverse();
+        }
+        let strand = if reverse { '-' } else { '+' };
+        Some(Record {
+            header: format!(">{} [spliced from {} exons of {} ({} strand)]", self.id, merged.len(), self.seqid, strand),
+            sequence: layout::render_sequence(&derived.sequence, &spliced, alphabet),
+            quality: derived.quality.as_ref().map(|quality| layout::render_quality(quality, &spliced, insert_qual)),
+            line_width: derived.line_width,
+        })
+    }
+
+    /// The exon (index in transcript order) and genomic position (1-based)
+    /// of transcript position `position` (1-based).
+    fn base(&self, position: usize) -> Result<(usize, usize), String> {
+        let mut offset = position.checked_sub(1)
+            .ok_or_else(|| "Positions must be 1-based (starting from 1)".to_string())?;
+        for (exon, &(start, end)) in self.exons.iter().enumerate() {
+            if offset < end - start {
+                return Ok((exon, if self.reverse { end - offset } else { start + offset + 1 }));
+            }
+            offset -= end - start;
+        }
+        Err(format!("Position {} is beyond transcript {} of length {}", position, self.id, self.length()))
+    }
+
+    /// Genomic bounds (1-based, inclusive) of transcript positions
+    /// `start..=end`. Unless `across_introns`, both must be in one exon.
+    fn span(&self, start: usize, end: usize, across_introns: bool) -> Result<(usize, usize), String> {
+        let ((first_exon, first), (last_exon, last)) = (self.base(start)?, self.base(end)?);
+        if !across_introns && first_exon != last_exon {
+            return Err(format!("Transcript positions {}-{} cross a splice junction, so on the genome the edit would take \
+                                the intron with it; use --cdna to edit the spliced transcript", start, end));
+        }
+        Ok(if self.reverse { (last, first) } else { (first, last) })
+    }
+
+    /// Genomic insertion position for inserting before transcript position
+    /// `position` (one past the end appends to the transcript).
+    fn site(&self, position: usize) -> Result<usize, String> {
+        if position == self.length() + 1 {
+            let &(start, end) = self.exons.last().expect("a transcript has at least one exon");
+            return Ok(if self.reverse { start + 1 } else { end + 1 });
+        }
+        let (_, genomic) = self.base(position)?;
+        Ok(if self.reverse { genomic + 1 } else { genomic })
+    }
+
+    /// Inserted bases as written on the genome's forward strand.
+    fn sense(&self, sequence: &str, alphabet: Alphabet) -> String {
+        if self.reverse {
+            sequence.chars().rev().map(|base| alphabet.complement(base)).collect()
+        } else {
+            sequence.to_string()
+        }
+    }
+
+    /// `operation`, given in transcript coordinates, as the operation on the
+    /// genomic record that makes the same change to the spliced transcript.
+    /// Deletions and replacements may cross introns, which are removed with
+    /// the bases around them; every other range must lie within one exon.
+    pub fn project(&self, operation: &Operation, alphabet: Alphabet) -> Result<Operation, String> {
+        let operation = operation.resolve_end(self.length());
+        Ok(match operation {
+            Operation::Delete { start, end } => {
+                let (start, end) = self.span(start, end, true)?;
+                Operation::Delete { start, end }
+            },
+            Operation::Replace { start, end, sequence } => {
+                let (start, end) = self.span(start, end, true)?;
+                Operation::Replace { start, end, sequence: self.sense(&sequence, alphabet) }
+            },
+            Operation::Insert { position, sequence } => {
+                Operation::Insert { position: self.site(position)?, sequence: self.sense(&sequence, alphabet) }
+            },
+            Operation::Invert { start, end, complement } => {
+                let (start, end) = self.span(start, end, false)?;
+                Operation::Invert { start, end, complement }
+            },
+            Operation::Duplicate { start, end, position } => {
+                let (start, end) = self.span(start, end, false)?;
+                Operation::Duplicate { start, end, position: self.site(position)? }
+            },
+            Operation::TandemDuplicate { start, end } => {
+                let (start, end) = self.span(start, end, false)?;
+                Operation::TandemDuplicate { start, end }
+            },
+            Operation::Keep { start, end } => {
+                let (start, end) = self.span(start, end, false)?;
+                Operation::Keep { start, end }
+            },
+            Operation::Case { start, end, to } => {
+                let (start, end) = self.span(start, end, false)?;
+                Operation::Case { start, end, to }
+            },
+            _ => return Err("Only delete, replace, insert, invert, duplicate, keep and case take transcript \
+                             coordinates; use --cdna to apply other operations to the spliced transcript".to_string()),
+        })
+    }
+}
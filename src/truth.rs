@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::alphabet::Alphabet;
+use crate::layout::{Breakend, Junction};
+
+/// One reading of a truth VCF junction. A single breakend's ALT does not say
+/// whether the molecule leaves or arrives at it, so it has two readings, and
+/// the walk uses whichever fits; `group` ties them together so only one is
+/// used.
+#[derive(Debug, Clone)]
+struct Reading {
+    junction: Junction,
+    group: usize,
+}
+
+/// The junctions of a truth VCF, per chromosome, in file order.
+pub struct TruthJunctions {
+    chroms: Vec<(String, Vec<Reading>, usize)>,
+    /// Junctions from `chrom` to a breakend on `mate_chrom`, until
+    /// `join_copies` pairs them up
+    transfers: Vec<Transfer>,
+}
+
+/// A junction whose `left` is on `chrom` and whose `right` is on `mate_chrom`.
+struct Transfer {
+    chrom: String,
+    mate_chrom: String,
+    junction: Junction,
+}
+
+fn reverse_complement(sequence: &str, alphabet: Alphabet) -> String {
+    sequence.chars().rev().map(|base| alphabet.complement(base)).collect()
+}
+
+impl TruthJunctions {
+    /// Reads the BND records `--truth-vcf` writes: each `_1` record of a pair
+    /// gives a whole junction (its `_2` mate repeats it), and each single
+    /// breakend an insertion at an end of the molecule.
+    pub fn read(path: &str, alphabet: Alphabet) -> Result<TruthJunctions, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read truth VCF {}: {}", path, e))?;
+        let mut truth = TruthJunctions { chroms: Vec::new(), transfers: Vec::new() };
+        for (n, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let invalid = |what: &str| format!("{} line {}: {}", path, n + 1, what);
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 8 {
+                return Err(invalid("expected at least 8 tab-separated columns"));
+            }
+            let (chrom, id, alt) = (fields[0], fields[2], fields[4]);
+            let position: usize = fields[1].parse().map_err(|_| invalid("POS is not a number"))?;
+            if !fields[7].split(';').any(|entry| entry == "SVTYPE=BND") {
+                return Err(invalid("only BND records can be checked"));
+            }
+            // Junctions the edit broke off again are not part of the molecule
+            if id.ends_with("_2") || !matches!(fields[6], "PASS" | ".") {
+                continue;
+            }
+            let readings = if id.ends_with("_1") {
+                let (junction, mate_chrom) = paired_junction(position, alt, alphabet).ok_or_else(|| invalid("unrecognised BND ALT"))?;
+                if mate_chrom != chrom {
+                    truth.transfers.push(Transfer { chrom: chrom.to_string(), mate_chrom, junction });
+                    continue;
+                }
+                vec![junction]
+            } else {
+                single_breakend(position, alt, alphabet).ok_or_else(|| invalid("unrecognised single breakend ALT"))?
+            };
+            truth.add(chrom, readings);
+        }
+        Ok(truth)
+    }
+
+    fn add(&mut self, chrom: &str, junctions: Vec<Junction>) {
+        let index = match self.chroms.iter().position(|(name, _, _)| name == chrom) {
+            Some(index) => index,
+            None => {
+                self.chroms.push((chrom.to_string(), Vec::new(), 0));
+                self.chroms.len() - 1
+            },
+        };
+        let (_, readings, groups) = &mut self.chroms[index];
+        readings.extend(junctions.into_iter().map(|junction| Reading { junction, group: *groups }));
+        *groups += 1;
+    }
+
+    /// Joins each breakend leaving a record for bases of another with the
+    /// one returning after them, into one junction whose inserted bases are
+    /// those copied, read from `sequence` of the other record. Breakends
+    /// that do not pair up this way are an error.
+    pub fn join_copies<'a>(&mut self, sequence: impl Fn(&str) -> Option<&'a str>) -> Result<(), String> {
+        let transfers = std::mem::take(&mut self.transfers);
+        let mut joined = vec![false; transfers.len()];
+        for (i, out) in transfers.iter().enumerate() {
+            let (Some(left), Some(first)) = (out.junction.left, out.junction.right) else { continue };
+            if joined[i] {
+                continue;
+            }
+            // The copy reads forward from `first` to the nearest breakend back
+            let back = transfers.iter().enumerate()
+                .filter(|(j, back)| !joined[*j] && back.chrom == out.mate_chrom && back.mate_chrom == out.chrom)
+                .filter_map(|(j, back)| Some((j, back.junction.left?, back)))
+                .filter(|(_, last, _)| !first.reverse && !last.reverse && last.position >= first.position)
+                .min_by_key(|(_, last, _)| last.position);
+            let Some((j, last, back)) = back else { continue };
+            let donor = sequence(&out.mate_chrom)
+                .ok_or_else(|| format!("The truth VCF copies bases of {}, which is not in the reference", out.mate_chrom))?;
+            let copied = donor.get(first.position - 1..last.position)
+                .ok_or_else(|| format!("{}:{}-{} is beyond the end of {}", out.mate_chrom, first.position, last.position, out.mate_chrom))?;
+            let inserted = format!("{}{}{}", out.junction.inserted, copied, back.junction.inserted);
+            self.add(&out.chrom, vec![Junction { left: Some(left), right: back.junction.right, inserted }]);
+            joined[i] = true;
+            joined[j] = true;
+        }
+        match transfers.iter().zip(&joined).find(|(_, joined)| !**joined) {
+            Some((transfer, _)) => Err(format!(
+                "The breakend at {}:{} joins {}, but no breakend returns from there to {} after a forward copy",
+                transfer.chrom, transfer.junction.left.map_or(0, |end| end.position), transfer.mate_chrom, transfer.chrom)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn chroms(&self) -> impl Iterator<Item = &str> {
+        self.chroms.iter().map(|(name, _, _)| name.as_str())
+    }
+
+    /// Number of junctions the VCF records on `chrom`.
+    pub fn count(&self, chrom: &str) -> usize {
+        self.chroms.iter().find(|(name, _, _)| name == chrom).map_or(0, |(_, _, groups)| *groups)
+    }
+
+    /// Checks that walking `reference` through every junction on `chrom`
+    /// exactly once spells `derived`. Branches are tried against the derived
+    /// sequence, so repeated breakends are resolved by what the genome says.
+    /// On failure, returns how many bases of `derived` the best walk matched.
+    pub fn reproduces(&self, chrom: &str, reference: &str, derived: &str, alphabet: Alphabet) -> Result<(), usize> {
+        let (readings, groups) = self.chroms.iter()
+            .find(|(name, _, _)| name == chrom)
+            .map_or((&[][..], 0), |(_, readings, groups)| (readings.as_slice(), *groups));
+        let mut walk = Walk {
+            reference: reference.as_bytes(),
+            derived: derived.as_bytes(),
+            readings,
+            leaving: HashMap::new(),
+            used: vec![false; groups],
+            alphabet,
+            matched: 0,
+        };
+        for (i, reading) in readings.iter().enumerate() {
+            if let Some(left) = reading.junction.left {
+                walk.leaving.entry((left.position, left.reverse)).or_default().push(i);
+            }
+        }
+        let length = reference.len();
+        let mut starts: Vec<(usize, Breakend, &str)> = vec![
+            (usize::MAX, Breakend { position: 1, reverse: false }, ""),
+            (usize::MAX, Breakend { position: length, reverse: true }, ""),
+        ];
+        for reading in readings {
+            if let (None, Some(right)) = (reading.junction.left, reading.junction.right) {
+                starts.push((reading.group, right, &reading.junction.inserted));
+            }
+        }
+        for (group, start, inserted) in starts {
+            if length == 0 {
+                break;
+            }
+            if group != usize::MAX {
+                if !walk.matches(0, inserted.as_bytes()) {
+                    continue;
+                }
+                walk.used[group] = true;
+            }
+            if walk.read(inserted.len(), start) {
+                return Ok(());
+            }
+            if group != usize::MAX {
+                walk.used[group] = false;
+            }
+        }
+        Err(walk.matched)
+    }
+}
+
+/// The junction of a `_1` BND record, which the molecule leaves through,
+/// and the chromosome of its mate.
+fn paired_junction(position: usize, alt: &str, alphabet: Alphabet) -> Option<(Junction, String)> {
+    let open = alt.find(['[', ']'])?;
+    let close = open + 1 + alt[open + 1..].find(['[', ']'])?;
+    let bracket = alt.as_bytes()[open];
+    let (mate_chrom, mate) = alt[open + 1..close].rsplit_once(':')?;
+    let mate = Breakend { position: mate.parse().ok()?, reverse: bracket == b']' };
+    // The reference base comes first when the join is after this position
+    let (reverse, inserted) = if open > 0 {
+        (false, alt[1..open].to_string())
+    } else {
+        let inserted = &alt[close + 1..alt.len().checked_sub(1)?];
+        (true, reverse_complement(inserted, alphabet))
+    };
+    Some((Junction { left: Some(Breakend { position, reverse }), right: Some(mate), inserted }, mate_chrom.to_string()))
+}
+
+/// Both readings of a single breakend: the molecule leaving it into novel
+/// bases that end the molecule, or starting with novel bases and arriving.
+fn single_breakend(position: usize, alt: &str, alphabet: Alphabet) -> Option<Vec<Junction>> {
+    let (join_after, inserted) = if let Some(rest) = alt.strip_suffix('.') {
+        (true, rest.get(1..)?)
+    } else {
+        let rest = alt.strip_prefix('.')?;
+        (false, rest.get(..rest.len().checked_sub(1)?)?)
+    };
+    let at = |reverse: bool| Some(Breakend { position, reverse });
+    let flipped = reverse_complement(inserted, alphabet);
+    Some(if join_after {
+        vec![
+            Junction { left: at(false), right: None, inserted: inserted.to_string() },
+            Junction { left: None, right: at(true), inserted: flipped },
+        ]
+    } else {
+        vec![
+            Junction { left: at(true), right: None, inserted: flipped },
+            Junction { left: None, right: at(false), inserted: inserted.to_string() },
+        ]
+    })
+}
+
+struct Walk<'a> {
+    reference: &'a [u8],
+    derived: &'a [u8],
+    readings: &'a [Reading],
+    /// Readings by the breakend they leave from
+    leaving: HashMap<(usize, bool), Vec<usize>>,
+    used: Vec<bool>,
+    alphabet: Alphabet,
+    /// Most derived bases any walk has matched
+    matched: usize,
+}
+
+impl Walk<'_> {
+    fn matches(&self, at: usize, bases: &[u8]) -> bool {
+        self.derived.get(at..at + bases.len()) == Some(bases)
+    }
+
+    fn base(&self, end: Breakend) -> u8 {
+        let base = self.reference[end.position - 1];
+        if end.reverse { self.alphabet.complement(base as char) as u8 } else { base }
+    }
+
+    fn complete(&self, at: usize) -> bool {
+        at == self.derived.len() && self.used.iter().all(|&used| used)
+    }
+
+    /// Reads the reference from `at` (in derived coordinates) starting with
+    /// the base at `end`, trying each unused junction that leaves from every
+    /// base read as well as reading on.
+    fn read(&mut self, mut at: usize, mut end: Breakend) -> bool {
+        loop {
+            if at >= self.derived.len() || self.base(end) != self.derived[at] {
+                self.matched = self.matched.max(at);
+                return false;
+            }
+            at += 1;
+            self.matched = self.matched.max(at);
+            let leaving = self.leaving.get(&(end.position, end.reverse)).cloned().unwrap_or_default();
+            for i in leaving {
+                let reading = &self.readings[i];
+                let group = reading.group;
+                if self.used[group] || !self.matches(at, reading.junction.inserted.as_bytes()) {
+                    continue;
+                }
+                let after = at + reading.junction.inserted.len();
+                self.used[group] = true;
+                let found = match reading.junction.right {
+                    Some(right) => self.read(after, right),
+                    None => self.complete(after),
+                };
+                if found {
+                    return true;
+                }
+                self.used[group] = false;
+            }
+            let next = if end.reverse { end.position.checked_sub(1) } else { Some(end.position + 1) };
+            match next.filter(|&position| position >= 1 && position <= self.reference.len()) {
+                Some(position) => end.position = position,
+                None => return self.complete(at),
+            }
+        }
+    }
+}
@@ -0,0 +1,31 @@
+/// Parses a base-pair position or length written for humans: digits with
+/// optional `_` or `,` separators, optionally scaled by a `k`, `M` or `G`
+/// suffix (with or without a trailing `b`), e.g. `12_345`, `1.5k`, `2Mb`.
+/// Decimal values must come out as a whole number of bases.
+pub fn parse_bases(value: &str) -> Result<usize, String> {
+    let invalid = || format!("'{}' is not a valid number of bases", value);
+    let cleaned: String = value.trim().chars().filter(|c| *c != '_' && *c != ',').collect();
+    let lower = cleaned.to_ascii_lowercase();
+    let number = lower.strip_suffix("bp").or_else(|| lower.strip_suffix('b')).unwrap_or(&lower);
+
+    let (digits, scale) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 1_000u128),
+        Some('m') => (&number[..number.len() - 1], 1_000_000),
+        Some('g') => (&number[..number.len() - 1], 1_000_000_000),
+        _ => (number, 1),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) || fraction.len() > 9 {
+        return Err(invalid());
+    }
+
+    let denominator = 10u128.pow(fraction.len() as u32);
+    let numerator = format!("{}{}", whole, fraction).parse::<u128>().map_err(|_| invalid())? * scale;
+    if numerator % denominator != 0 {
+        return Err(format!("'{}' is not a whole number of bases", value));
+    }
+    usize::try_from(numerator / denominator).map_err(|_| invalid())
+}
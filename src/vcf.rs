@@ -0,0 +1,701 @@
+use std::io::{self, BufWriter, Write};
+
+use serde::Serialize;
+
+use crate::alphabet::Alphabet;
+use crate::gff::Feature;
+use crate::layout::{self, Breakend, Junction, Segment};
+use crate::staging;
+
+/// Allele fraction and genotype a variant is simulated at, reported in the
+/// FORMAT fields of the truth VCF, and the confidence intervals declared for
+/// its breakpoints, reported as CIPOS.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Allele {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allele_fraction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genotype: Option<String>,
+    /// Interval around the lower breakpoint, as offsets (`-10, 10`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cipos: Option<(i64, i64)>,
+    /// Interval around the higher breakpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ciend: Option<(i64, i64)>,
+}
+
+impl Allele {
+    pub fn is_empty(&self) -> bool {
+        !self.has_sample_fields() && self.cipos.is_none() && self.ciend.is_none()
+    }
+
+    /// Whether there is a genotype or allele fraction for the sample column.
+    pub fn has_sample_fields(&self) -> bool {
+        self.allele_fraction.is_some() || self.genotype.is_some()
+    }
+
+    /// This allele, with any value it leaves unset taken from `default`.
+    pub fn or(&self, default: &Allele) -> Allele {
+        Allele {
+            allele_fraction: self.allele_fraction.or(default.allele_fraction),
+            genotype: self.genotype.clone().or_else(|| default.genotype.clone()),
+            cipos: self.cipos.or(default.cipos),
+            ciend: self.ciend.or(default.ciend),
+        }
+    }
+
+    /// The confidence interval of breakend `this` of `junction`: `cipos` for
+    /// the lower of its two positions (or its only one), `ciend` for the
+    /// higher, and otherwise `default` bases either side.
+    pub fn interval(&self, junction: &Junction, this: Breakend, default: Option<usize>) -> Option<(i64, i64)> {
+        let lower = [junction.left, junction.right].iter().flatten().map(|end| end.position).min();
+        let declared = if lower == Some(this.position) { self.cipos } else { self.ciend };
+        declared.or(default.map(|n| (-(n as i64), n as i64)))
+    }
+}
+
+/// Parses a breakpoint confidence interval: `-<before>,<after>` offsets as
+/// in CIPOS, or one number of bases either side.
+pub fn parse_interval(value: &str) -> Result<(i64, i64), String> {
+    let interval = match value.split_once(',') {
+        Some((before, after)) => before.trim().parse::<i64>().ok().zip(after.trim().parse::<i64>().ok()),
+        None => value.trim().parse::<i64>().ok().filter(|&n| n >= 0).map(|n| (-n, n)),
+    };
+    match interval {
+        Some((before, after)) if before <= 0 && after >= 0 => Ok((before, after)),
+        _ => Err(format!("Confidence interval '{}' must be written like -10,20 (offsets around the breakpoint) or 10", value)),
+    }
+}
+
+/// Parses an allele fraction, which must lie in (0, 1].
+pub fn parse_allele_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        _ => Err(format!("Allele fraction '{}' must be a number greater than 0 and at most 1", value)),
+    }
+}
+
+/// Parses a VCF genotype such as `0/1`, `1|0` or `1`.
+pub fn parse_genotype(value: &str) -> Result<String, String> {
+    let valid = value.split(['/', '|']).all(|allele| !allele.is_empty() && allele.chars().all(|c| c.is_ascii_digit()))
+        && value.chars().any(|c| c != '0' && c.is_ascii_digit());
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(format!("Genotype '{}' must be written like 0/1, 1|0 or 1/1 and carry the variant", value))
+    }
+}
+
+/// Junctions found in one record, with the input sequence they refer to and
+/// the allele each was simulated at.
+pub struct RecordJunctions<'a> {
+    pub chrom: &'a str,
+    pub sequence: &'a str,
+    pub junctions: Vec<(Junction, Allele)>,
+    /// Junctions whose inserted bases are a copy of another record, written
+    /// as breakends joined to that record instead
+    pub donors: Vec<(Junction, Donor<'a>)>,
+    /// Junctions the edit made and then cut off the molecule again, written
+    /// with FILTER `LOST` so they are on record without being part of it
+    pub lost: Vec<Junction>,
+}
+
+/// Bases `start`-`end` (1-based, inclusive) of record `chrom`, copied
+/// forward into another record.
+#[derive(Debug, Clone, Copy)]
+pub struct Donor<'a> {
+    pub chrom: &'a str,
+    pub sequence: &'a str,
+    pub start: usize,
+    pub end: usize,
+}
+
+struct VcfLine {
+    chrom_index: usize,
+    position: usize,
+    text: String,
+}
+
+/// One breakend of a joined pair, with its INFO fields beyond SVTYPE and
+/// MATEID.
+struct Side<'a> {
+    chrom: &'a str,
+    chrom_index: usize,
+    base: char,
+    end: Breakend,
+    filter: &'static str,
+    info: String,
+}
+
+/// The `<id>_1` and `<id>_2` BND lines of a junction where the molecule
+/// leaves `leaving` for `arriving` through `inserted`.
+fn joined(id: &str, leaving: Side, arriving: Side, inserted: &str, sample: &str) -> [VcfLine; 2] {
+    let leaving_alt = bnd_alt(leaving.base, leaving.end, arriving.end, true, inserted, arriving.chrom);
+    let arriving_alt = bnd_alt(arriving.base, arriving.end, leaving.end, false, inserted, leaving.chrom);
+    [
+        VcfLine {
+            chrom_index: leaving.chrom_index,
+            position: leaving.end.position,
+            text: format!("{}\t{}\t{}_1\t{}\t{}\t.\t{}\tSVTYPE=BND;MATEID={}_2{}{}", leaving.chrom,
+                          leaving.end.position, id, leaving.base, leaving_alt, leaving.filter, id, leaving.info, sample),
+        },
+        VcfLine {
+            chrom_index: arriving.chrom_index,
+            position: arriving.end.position,
+            text: format!("{}\t{}\t{}_2\t{}\t{}\t.\t{}\tSVTYPE=BND;MATEID={}_1{}{}", arriving.chrom,
+                          arriving.end.position, id, arriving.base, arriving_alt, arriving.filter, id, arriving.info, sample),
+        },
+    ]
+}
+
+/// The BND line of a single breakend at `this`, where the molecule leaves
+/// (`outgoing`) or arrives at it through the novel bases `inserted` and
+/// ends there.
+fn single(this: Side, outgoing: bool, inserted: &str, id: &str, info: &str, sample: &str) -> VcfLine {
+    let inserted = if this.end.reverse { crate::reverse_complement(inserted) } else { inserted.to_string() };
+    let alt = if outgoing != this.end.reverse {
+        format!("{}{}.", this.base, inserted)
+    } else {
+        format!(".{}{}", inserted, this.base)
+    };
+    VcfLine {
+        chrom_index: this.chrom_index,
+        position: this.end.position,
+        text: format!("{}\t{}\t{}\t{}\t{}\t.\t{}\tSVTYPE=BND{}{}", this.chrom, this.end.position, id, this.base, alt,
+                      this.filter, info, sample),
+    }
+}
+
+/// ALT allele for the breakend at `this`, joined to `mate` on `mate_chrom`
+/// through the novel bases `inserted` (in edited-sequence order).
+/// `outgoing` is true when the edited sequence leaves `this` for the mate,
+/// false when it arrives at `this` from the mate.
+fn bnd_alt(reference_base: char, this: Breakend, mate: Breakend, outgoing: bool, inserted: &str, mate_chrom: &str) -> String {
+    // Which side of `this` the join is on in reference orientation
+    let join_after = outgoing != this.reverse;
+    // Whether the mate's piece continues to the right of the mate position
+    let mate_extends_right = outgoing != mate.reverse;
+    let inserted = if this.reverse { crate::reverse_complement(inserted) } else { inserted.to_string() };
+    let bracket = if mate_extends_right { '[' } else { ']' };
+    let mate_text = format!("{}{}:{}{}", bracket, mate_chrom, mate.position, bracket);
+
+    if join_after {
+        format!("{}{}{}", reference_base, inserted, mate_text)
+    } else {
+        format!("{}{}{}", mate_text, inserted, reference_base)
+    }
+}
+
+/// Consequence INFO fields of the breakend at `this` (joined on the side
+/// `join_after` says) of a junction: the genes it falls in, whether it
+/// breaks a CDS, and the genes the junction skips or repeats in full.
+fn consequences(chrom: &str, junction: &Junction, this: Breakend, join_after: bool, features: &[Feature]) -> String {
+    let on_chrom = |kind: &'static str| features.iter().filter(move |f| f.seqid == chrom && f.kind == kind);
+    let ids = |genes: Vec<&Feature>| genes.iter().map(|gene| gene.id.as_str()).collect::<Vec<_>>().join(",");
+    // 1-based features contain the break when bases on both sides of it do
+    let (before, after) = if join_after { (this.position, this.position + 1) } else { (this.position - 1, this.position) };
+    let breaks = |f: &Feature| f.start < before && after <= f.end;
+
+    let mut info = String::new();
+    let genes: Vec<&Feature> = on_chrom("gene").filter(|f| f.start < this.position && this.position <= f.end).collect();
+    if !genes.is_empty() {
+        info.push_str(&format!(";GENE={}", ids(genes)));
+    }
+    if on_chrom("CDS").any(breaks) {
+        info.push_str(";CDS_DISRUPTED");
+    }
+    if let (Some(left), Some(right)) = (junction.left, junction.right) {
+        if !left.reverse && !right.reverse {
+            // Reading on past the bases in between deletes them; jumping back
+            // repeats the bases from there on
+            let (key, from, to) = if right.position > left.position {
+                ("GENE_DEL", left.position + 1, right.position - 1)
+            } else {
+                ("GENE_DUP", right.position, left.position)
+            };
+            let whole: Vec<&Feature> = on_chrom("gene").filter(|f| from <= f.start + 1 && f.end <= to).collect();
+            if !whole.is_empty() {
+                info.push_str(&format!(";{}={}", key, ids(whole)));
+            }
+        }
+    }
+    info
+}
+
+/// Writes the breakend VCF of `records` to the file `path`.
+pub fn write_breakend_vcf(path: &str, records: &[RecordJunctions], confidence: Option<usize>,
+                          features: &[Feature]) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    emit_breakend_vcf(&mut out, records, confidence, features)?;
+    out.flush()
+}
+
+/// Writes a VCF 4.3 file with a BND record for each side of every junction.
+/// Junctions at the ends of the molecule become single breakends, and the
+/// bases copied from a donor record are joined to it on both sides. When any
+/// junction carries an allele fraction or genotype, a sample column gives
+/// them in the GT and AF FORMAT fields. Breakends with a confidence interval
+/// declared for their variant, or every breakend given a `confidence` of n
+/// bases, are marked IMPRECISE with CIPOS (-n,n). Given GFF `features`, each
+/// breakend is annotated with the consequences of its junction.
+pub fn emit_breakend_vcf(out: &mut dyn Write, records: &[RecordJunctions], confidence: Option<usize>,
+                         features: &[Feature]) -> io::Result<()> {
+    let mut lines = Vec::new();
+    let mut counter = 0;
+    let with_sample = records.iter().flat_map(|record| &record.junctions).any(|(_, allele)| allele.has_sample_fields());
+    let sample_column = |allele: &Allele| {
+        if !with_sample {
+            return String::new();
+        }
+        let fraction = allele.allele_fraction.map_or(".".to_string(), |fraction| fraction.to_string());
+        format!("\tGT:AF\t{}:{}", allele.genotype.as_deref().unwrap_or("."), fraction)
+    };
+
+    for (chrom_index, record) in records.iter().enumerate() {
+        let base_at = |position: usize| record.sequence.as_bytes()[position - 1] as char;
+        let chrom = record.chrom;
+
+        let unannotated = Allele::default();
+        let kept = record.junctions.iter().map(|(junction, allele)| (junction, allele, "PASS"));
+        for (junction, allele, filter) in kept.chain(record.lost.iter().map(|junction| (junction, &unannotated, "LOST"))) {
+            counter += 1;
+            let sample = sample_column(allele);
+            let imprecision = |this: Breakend| allele.interval(junction, this, confidence)
+                .map_or(String::new(), |(before, after)| format!(";IMPRECISE;CIPOS={},{}", before, after));
+            let id = format!("bnd{}", counter);
+            let side = |end: Breakend, join_after: bool| Side {
+                chrom,
+                chrom_index,
+                base: base_at(end.position),
+                end,
+                filter,
+                info: imprecision(end) + &consequences(chrom, junction, end, join_after, features),
+            };
+            if let Some((_, donor)) = record.donors.iter().find(|(copied, _)| copied == junction) {
+                // The molecule leaves this record for the copy's first base
+                // and comes back after its last
+                let donor_index = records.iter().position(|r| r.chrom == donor.chrom).unwrap_or(chrom_index);
+                let donor_side = |position: usize, join_after: bool| {
+                    let end = Breakend { position, reverse: false };
+                    let unjoined = Junction { left: None, right: None, inserted: String::new() };
+                    Side {
+                        chrom: donor.chrom,
+                        chrom_index: donor_index,
+                        base: donor.sequence.as_bytes()[position - 1] as char,
+                        end,
+                        filter,
+                        info: imprecision(end) + &consequences(donor.chrom, &unjoined, end, join_after, features),
+                    }
+                };
+                if let Some(left) = junction.left {
+                    lines.extend(joined(&format!("{}a", id), side(left, true), donor_side(donor.start, false), "", &sample));
+                }
+                if let Some(right) = junction.right {
+                    lines.extend(joined(&format!("{}b", id), donor_side(donor.end, true), side(right, false), "", &sample));
+                }
+                continue;
+            }
+            match (junction.left, junction.right) {
+                (Some(left), Some(right)) => {
+                    lines.extend(joined(&id, side(left, !left.reverse), side(right, right.reverse),
+                                        &junction.inserted, &sample));
+                },
+                (Some(end), None) | (None, Some(end)) => {
+                    let outgoing = junction.left.is_some();
+                    let end_consequences = consequences(chrom, junction, end, outgoing != end.reverse, features);
+                    lines.push(single(side(end, outgoing != end.reverse), outgoing, &junction.inserted, &id,
+                                      &(imprecision(end) + &end_consequences), &sample));
+                },
+                (None, None) => {},
+            }
+        }
+    }
+
+    lines.sort_by_key(|line| (line.chrom_index, line.position));
+
+    writeln!(out, "##fileformat=VCFv4.3")?;
+    writeln!(out, "##source=sv_maker")?;
+    for record in records {
+        writeln!(out, "##contig=<ID={},length={}>", record.chrom, record.sequence.len())?;
+    }
+    if records.iter().any(|record| !record.lost.is_empty()) {
+        writeln!(out, "##FILTER=<ID=LOST,Description=\"Junction the edit made that a later step broke off the molecule\">")?;
+    }
+    writeln!(out, "##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of structural variant\">")?;
+    writeln!(out, "##INFO=<ID=MATEID,Number=.,Type=String,Description=\"ID of mate breakend\">")?;
+    let declared = records.iter().flat_map(|record| &record.junctions)
+        .any(|(_, allele)| allele.cipos.is_some() || allele.ciend.is_some());
+    if confidence.is_some() || declared {
+        writeln!(out, "##INFO=<ID=IMPRECISE,Number=0,Type=Flag,Description=\"Imprecise structural variation\">")?;
+        writeln!(out, "##INFO=<ID=CIPOS,Number=2,Type=Integer,Description=\"Confidence interval around POS\">")?;
+    }
+    if !features.is_empty() {
+        writeln!(out, "##INFO=<ID=GENE,Number=.,Type=String,Description=\"Genes the breakend falls in\">")?;
+        writeln!(out, "##INFO=<ID=CDS_DISRUPTED,Number=0,Type=Flag,Description=\"The junction breaks a CDS\">")?;
+        writeln!(out, "##INFO=<ID=GENE_DEL,Number=.,Type=String,Description=\"Genes the junction skips in full\">")?;
+        writeln!(out, "##INFO=<ID=GENE_DUP,Number=.,Type=String,Description=\"Genes the junction repeats in full\">")?;
+    }
+    if with_sample {
+        writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+        writeln!(out, "##FORMAT=<ID=AF,Number=A,Type=Float,Description=\"Allele fraction the variant was simulated at\">")?;
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE")?;
+    } else {
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    }
+    for line in lines {
+        writeln!(out, "{}", line.text)?;
+    }
+    Ok(())
+}
+
+/// How `--vcf` writes each change: spelled out, or as a symbolic allele.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VcfStyle {
+    /// REF and ALT give every base
+    Resolved,
+    /// `<DEL>`, `<DUP>`, `<INV>` and `<INS>` alleles with END, and BND pairs
+    /// for rearrangements none of those describe
+    Symbolic,
+}
+
+impl VcfStyle {
+    pub fn parse(value: &str) -> Result<VcfStyle, String> {
+        match value {
+            "resolved" => Ok(VcfStyle::Resolved),
+            "symbolic" => Ok(VcfStyle::Symbolic),
+            _ => Err(format!("Unknown --vcf-style '{}'. Use 'resolved' or 'symbolic'", value)),
+        }
+    }
+}
+
+/// What a change to the input is, when it is one of the simple kinds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Deletion,
+    Insertion,
+    /// The inserted bases repeat input bases `start..end` (0-based,
+    /// half-open) right beside them
+    Duplication { start: usize, end: usize },
+    Inversion,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Deletion => "DEL",
+            Kind::Insertion => "INS",
+            Kind::Duplication { .. } => "DUP",
+            Kind::Inversion => "INV",
+        }
+    }
+}
+
+/// Input bases `start..end` (0-based, half-open) replaced by `alternate`,
+/// which spans `derived` of the edited sequence. `source` is the segment
+/// `alternate` reads, when it reads a single stretch of the input.
+struct Change {
+    start: usize,
+    end: usize,
+    alternate: String,
+    derived: (usize, usize),
+    source: Option<Segment>,
+}
+
+impl Change {
+    fn kind(&self, sequence: &str, alphabet: Alphabet) -> Option<Kind> {
+        let (reference, alternate) = (&sequence[self.start..self.end], self.alternate.as_str());
+        if alternate.is_empty() {
+            return Some(Kind::Deletion);
+        }
+        match self.source {
+            // A copy of input bases elsewhere, as a dispersed duplicate pastes
+            Some(Segment::Source { start, end, reverse: false, complement: false }) if reference.is_empty() => {
+                return Some(Kind::Duplication { start, end });
+            },
+            // The replaced bases read backwards, complemented or not
+            Some(Segment::Source { start, end, reverse: true, .. }) if (start, end) == (self.start, self.end) => {
+                return Some(Kind::Inversion);
+            },
+            _ => {},
+        }
+        if reference.is_empty() {
+            let length = alternate.len();
+            let repeats = |from: usize| sequence.get(from..from + length) == Some(alternate);
+            return Some(if self.start >= length && repeats(self.start - length) {
+                Kind::Duplication { start: self.start - length, end: self.start }
+            } else if repeats(self.start) {
+                Kind::Duplication { start: self.start, end: self.start + length }
+            } else {
+                Kind::Insertion
+            });
+        }
+        if reference.chars().rev().map(|base| alphabet.complement(base)).eq(alternate.chars()) {
+            return Some(Kind::Inversion);
+        }
+        None
+    }
+}
+
+/// A variant spelled out in full, on the input coordinates: `reference`
+/// replaced by `alternate` at `position`, both with the padding base VCF
+/// requires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceVariant {
+    pub position: usize,
+    pub reference: String,
+    pub alternate: String,
+    /// DEL, INS, DUP or INV when the change is one of those, `None` for
+    /// other substitutions
+    pub kind: Option<&'static str>,
+}
+
+/// One input record and the layout of its edited version.
+pub struct RecordVariants<'a> {
+    pub chrom: &'a str,
+    pub sequence: &'a str,
+    pub segments: &'a [Segment],
+}
+
+/// The variants that turn `sequence` into its edited version `segments`.
+pub fn sequence_variants(sequence: &str, segments: &[Segment], alphabet: Alphabet) -> Vec<SequenceVariant> {
+    changes(sequence, segments, alphabet).iter().map(|change| resolved_variant(sequence, change, alphabet)).collect()
+}
+
+/// The changes between `sequence` and its edited version `segments`. The
+/// edited sequence is aligned to the input through the forward stretches it
+/// keeps in input order (the chain covering the most input bases), and
+/// whatever lies between two of them becomes one change: the input bases
+/// they skip, replaced by the edited bases in between. A stretch that reads
+/// bases again, as a tandem duplication does, is anchored after the bases
+/// already read, so the repeat becomes an insertion.
+fn changes(sequence: &str, segments: &[Segment], alphabet: Alphabet) -> Vec<Change> {
+    let anchors = anchor_chain(segments);
+    let mut changes = Vec::new();
+    let mut read = 0;
+    let mut derived = 0;
+    let mut inserted = String::new();
+    // The segments the inserted bases read
+    let mut pieces: Vec<Segment> = Vec::new();
+    let mut flush = |start: usize, end: usize, inserted: &mut String, pieces: &mut Vec<Segment>, derived: usize| {
+        if sequence[start..end] != **inserted {
+            let alternate = std::mem::take(inserted);
+            let derived = (derived - alternate.len(), derived);
+            let source = match pieces[..] {
+                [ref piece @ Segment::Source { .. }] => Some(piece.clone()),
+                _ => None,
+            };
+            changes.push(Change { start, end, alternate, derived, source });
+        }
+        inserted.clear();
+        pieces.clear();
+    };
+    for (i, segment) in segments.iter().enumerate() {
+        match *segment {
+            Segment::Source { start, end, .. } if anchors.contains(&i) => {
+                let kept = start.max(read);
+                if kept > start {
+                    pieces.push(Segment::Source { start, end: kept, reverse: false, complement: false });
+                }
+                inserted.push_str(&sequence[start..kept]);
+                derived += kept - start;
+                flush(read, kept, &mut inserted, &mut pieces, derived);
+                read = end;
+                derived += end - kept;
+            },
+            _ => {
+                if !segment.is_empty() {
+                    pieces.push(segment.clone());
+                }
+                inserted.push_str(&layout::render_sequence(sequence, std::slice::from_ref(segment), alphabet));
+                derived += segment.len();
+            },
+        }
+    }
+    flush(read, sequence.len(), &mut inserted, &mut pieces, derived);
+    changes
+}
+
+/// Indices of the forward, uncomplemented segments that make up the anchor
+/// chain: each ends further into the input than the one before, and
+/// together they cover the most input bases.
+fn anchor_chain(segments: &[Segment]) -> Vec<usize> {
+    let forward: Vec<(usize, usize, usize)> = segments.iter().enumerate()
+        .filter_map(|(i, segment)| match *segment {
+            Segment::Source { start, end, reverse: false, complement: false } if end > start => Some((i, start, end)),
+            _ => None,
+        })
+        .collect();
+    // Bases covered by the best chain ending with each segment, and the
+    // segment before it in that chain
+    let mut best: Vec<(usize, Option<usize>)> = Vec::with_capacity(forward.len());
+    for (k, &(_, start, end)) in forward.iter().enumerate() {
+        let mut chain = (end - start, None);
+        for (j, &(_, _, previous_end)) in forward[..k].iter().enumerate() {
+            if previous_end < end {
+                let covered = best[j].0 + end - start.max(previous_end);
+                if covered > chain.0 {
+                    chain = (covered, Some(j));
+                }
+            }
+        }
+        best.push(chain);
+    }
+    let mut chain = Vec::new();
+    let mut next = (0..best.len()).max_by_key(|&k| (best[k].0, std::cmp::Reverse(k)));
+    while let Some(k) = next {
+        chain.push(forward[k].0);
+        next = best[k].1;
+    }
+    chain
+}
+
+/// `change` as REF and ALT, padded with the base before it, or after it at
+/// the start of the molecule.
+fn resolved_variant(sequence: &str, change: &Change, alphabet: Alphabet) -> SequenceVariant {
+    let (start, end) = (change.start, change.end);
+    let (reference, alternate) = (&sequence[start..end], change.alternate.as_str());
+    let kind = change.kind(sequence, alphabet).map(Kind::name);
+    let (position, reference, alternate) = if start > 0 {
+        let pad = &sequence[start - 1..start];
+        (start, format!("{}{}", pad, reference), format!("{}{}", pad, alternate))
+    } else if end < sequence.len() {
+        let pad = &sequence[end..end + 1];
+        (1, format!("{}{}", reference, pad), format!("{}{}", alternate, pad))
+    } else {
+        // The whole molecule changed, with no base left to pad with
+        let alternate = if alternate.is_empty() { "<DEL>".to_string() } else { alternate.to_string() };
+        (1, reference.to_string(), alternate)
+    };
+    SequenceVariant { position, reference, alternate, kind }
+}
+
+/// Writes the `--vcf` file of `records` to the file `path`.
+pub fn write_sequence_vcf(path: &str, records: &[RecordVariants], style: VcfStyle, alphabet: Alphabet,
+                          allele: &Allele) -> io::Result<()> {
+    let mut out = BufWriter::new(staging::create(path)?);
+    emit_sequence_vcf(&mut out, records, style, alphabet, allele)?;
+    out.flush()
+}
+
+/// Writes a VCF 4.3 file with a record for each change the edits made to
+/// the input. In the resolved style, REF and ALT are spelled out with the
+/// padding base, so callers' output can be compared allele by allele; INFO
+/// gives SVLEN, the change in length, and SVTYPE where the change is a
+/// deletion, insertion, duplication or inversion. In the symbolic style,
+/// those four are written as `<DEL>`, `<INS>`, `<DUP>` and `<INV>` with END
+/// and SVLEN, and every other change (a copyback, a rearrangement) as the
+/// BND records of its junctions. The allele's genotype and allele fraction,
+/// when given, fill a sample column.
+pub fn emit_sequence_vcf(out: &mut dyn Write, records: &[RecordVariants], style: VcfStyle, alphabet: Alphabet,
+                         allele: &Allele) -> io::Result<()> {
+    let sample = if allele.has_sample_fields() {
+        let fraction = allele.allele_fraction.map_or(".".to_string(), |fraction| fraction.to_string());
+        format!("\tGT:AF\t{}:{}", allele.genotype.as_deref().unwrap_or("."), fraction)
+    } else {
+        String::new()
+    };
+    let mut lines = Vec::new();
+    let (mut counter, mut breakends) = (0, 0);
+    for (chrom_index, record) in records.iter().enumerate() {
+        let (chrom, sequence) = (record.chrom, record.sequence);
+        let junctions = layout::located_junctions(record.segments);
+        for change in changes(sequence, record.segments, alphabet) {
+            counter += 1;
+            let kind = change.kind(sequence, alphabet);
+            let symbolic = match (style, kind) {
+                (VcfStyle::Resolved, _) => None,
+                (VcfStyle::Symbolic, Some(kind)) => Some(kind),
+                (VcfStyle::Symbolic, None) => {
+                    // Junctions at either edge of the change, or within it
+                    let within: Vec<&Junction> = junctions.iter()
+                        .filter(|(offset, _)| (change.derived.0..=change.derived.1).contains(offset))
+                        .map(|(_, junction)| junction)
+                        .collect();
+                    if !within.is_empty() {
+                        for junction in within {
+                            breakends += 1;
+                            let id = format!("bnd{}", breakends);
+                            let side = |end: Breakend| Side {
+                                chrom,
+                                chrom_index,
+                                base: sequence.as_bytes()[end.position - 1] as char,
+                                end,
+                                filter: "PASS",
+                                info: String::new(),
+                            };
+                            match (junction.left, junction.right) {
+                                (Some(left), Some(right)) => {
+                                    lines.extend(joined(&id, side(left), side(right), &junction.inserted, &sample));
+                                },
+                                (Some(end), None) | (None, Some(end)) => {
+                                    lines.push(single(side(end), junction.left.is_some(), &junction.inserted, &id, "", &sample));
+                                },
+                                (None, None) => {},
+                            }
+                        }
+                        continue;
+                    }
+                    None
+                },
+            };
+            let id = format!("var{}", counter);
+            let (position, text) = match symbolic {
+                Some(kind) => symbolic_line(chrom, sequence, &change, kind, &id, &sample),
+                None => {
+                    let variant = resolved_variant(sequence, &change, alphabet);
+                    let length = if variant.alternate.starts_with('<') { 0 } else { variant.alternate.len() };
+                    let kind = variant.kind.map_or(String::new(), |kind| format!("SVTYPE={};", kind));
+                    (variant.position, format!("{}\t{}\t{}\t{}\t{}\t.\tPASS\t{}SVLEN={}{}", chrom, variant.position, id,
+                                               variant.reference, variant.alternate, kind,
+                                               length as i64 - variant.reference.len() as i64, sample))
+                },
+            };
+            lines.push(VcfLine { chrom_index, position, text });
+        }
+    }
+    lines.sort_by_key(|line| (line.chrom_index, line.position));
+
+    writeln!(out, "##fileformat=VCFv4.3")?;
+    writeln!(out, "##source=sv_maker")?;
+    for record in records {
+        writeln!(out, "##contig=<ID={},length={}>", record.chrom, record.sequence.len())?;
+    }
+    writeln!(out, "##INFO=<ID=SVTYPE,Number=1,Type=String,Description=\"Type of structural variant\">")?;
+    writeln!(out, "##INFO=<ID=SVLEN,Number=1,Type=Integer,Description=\"Length of ALT minus length of REF\">")?;
+    if style == VcfStyle::Symbolic {
+        writeln!(out, "##INFO=<ID=END,Number=1,Type=Integer,Description=\"End position of the variant\">")?;
+        writeln!(out, "##INFO=<ID=MATEID,Number=.,Type=String,Description=\"ID of mate breakend\">")?;
+        writeln!(out, "##ALT=<ID=DEL,Description=\"Deletion\">")?;
+        writeln!(out, "##ALT=<ID=INS,Description=\"Insertion\">")?;
+        writeln!(out, "##ALT=<ID=DUP,Description=\"Duplication\">")?;
+        writeln!(out, "##ALT=<ID=INV,Description=\"Inversion\">")?;
+    }
+    if allele.has_sample_fields() {
+        writeln!(out, "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">")?;
+        writeln!(out, "##FORMAT=<ID=AF,Number=A,Type=Float,Description=\"Allele fraction the variant was simulated at\">")?;
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE")?;
+    } else {
+        writeln!(out, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    }
+    for line in lines {
+        writeln!(out, "{}", line.text)?;
+    }
+    Ok(())
+}
+
+/// The symbolic record of `change`: POS is the padding base before the
+/// affected input bases, and END the last of them, or POS itself for an
+/// insertion. At the start of the molecule there is no base before, so POS
+/// is 0, the telomere, with `N` for REF. A duplication is written at the
+/// bases it copies, wherever the copy went.
+fn symbolic_line(chrom: &str, sequence: &str, change: &Change, kind: Kind, id: &str, sample: &str) -> (usize, String) {
+    let (start, end, length) = match kind {
+        Kind::Duplication { start, end } => (start, end, (end - start) as i64),
+        Kind::Insertion => (change.start, change.start, change.alternate.len() as i64),
+        Kind::Deletion => (change.start, change.end, -((change.end - change.start) as i64)),
+        Kind::Inversion => (change.start, change.end, (change.end - change.start) as i64),
+    };
+    let reference = if start == 0 { "N" } else { &sequence[start - 1..start] };
+    (start, format!("{}\t{}\t{}\t{}\t<{}>\t.\tPASS\tSVTYPE={};END={};SVLEN={}{}", chrom, start, id,
+                    reference, kind.name(), kind.name(), end, length, sample))
+}
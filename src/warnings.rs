@@ -0,0 +1,179 @@
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::alphabet::Alphabet;
+use crate::fasta::Record;
+use crate::layout::{self, Segment};
+use crate::staging;
+
+/// How much a warning matters for the data; `--fail-on-warn <level>` fails
+/// the run on any warning at or above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing, but the output is what was asked for
+    Low,
+    /// The output may not behave as intended downstream
+    Medium,
+    /// The output is likely wrong for its purpose
+    High,
+}
+
+impl Severity {
+    pub fn parse(value: &str) -> Result<Severity, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            _ => Err(format!("--fail-on-warn must be low, medium or high, got '{}'", value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        }
+    }
+}
+
+/// One warning of a run. `code` names the kind of issue, stably, for
+/// scripts to filter on.
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub code: &'static str,
+    pub severity: Severity,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record: Option<String>,
+    /// 1-based position on the input record, when the issue has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+    pub message: String,
+}
+
+/// Warnings raised so far. Issues turn up deep inside operations and
+/// output writers, so they are collected here rather than passed back.
+static RAISED: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Prints a warning to stderr and records it for `--warnings` and
+/// `--fail-on-warn`.
+pub fn warn(warning: Warning) {
+    eprintln!("Warning ({}, {}): {}", warning.severity.name(), warning.code, warning.message);
+    RAISED.lock().expect("warning list poisoned").push(warning);
+}
+
+/// Writes the `--warnings` JSON, staged with the run's other outputs.
+pub fn write(path: Option<&str>) -> io::Result<()> {
+    let raised = RAISED.lock().expect("warning list poisoned");
+    if let Some(path) = path {
+        let mut out = BufWriter::new(staging::create(path)?);
+        writeln!(out, "{}", serde_json::to_string_pretty(&*raised)?)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Fails the run when a warning reaches the `--fail-on-warn` level. Called
+/// before the outputs are committed, so a failing run publishes none.
+pub fn check(fail_on: Option<Severity>) -> Result<(), String> {
+    let raised = RAISED.lock().expect("warning list poisoned");
+    if let Some(level) = fail_on {
+        let failing = raised.iter().filter(|warning| warning.severity >= level).count();
+        if failing > 0 {
+            return Err(format!("{} warning{} at {} severity or above (--fail-on-warn {})",
+                               failing, if failing == 1 { "" } else { "s" }, level.name(), level.name()));
+        }
+    }
+    Ok(())
+}
+
+/// Bases either side of a junction that reads must match to place it.
+const JUNCTION_FLANK: usize = 25;
+
+/// Warns of junctions a read simulation cannot represent cleanly, and of
+/// bases complemented without a well-defined complement, for one record
+/// edited as laid out by `segments` into `output`.
+pub fn check_edit(input: &Record, segments: &[Segment], output: &Record, alphabet: Alphabet) {
+    let is_gap = |position: usize| input.sequence.as_bytes()[position - 1].eq_ignore_ascii_case(&b'N');
+    for (offset, junction) in layout::located_junctions(segments) {
+        let ends = [junction.left, junction.right];
+        if let Some(end) = ends.iter().flatten().find(|end| is_gap(end.position)) {
+            warn(Warning {
+                code: "breakpoint_in_gap",
+                severity: Severity::Medium,
+                record: Some(input.id().to_string()),
+                position: Some(end.position),
+                message: format!("A junction of {} breaks inside an N gap at {}, so reads cannot anchor on that side",
+                                 input.id(), end.position),
+            });
+        }
+        let start = offset.saturating_sub(JUNCTION_FLANK);
+        let end = (offset + junction.inserted.len() + JUNCTION_FLANK).min(output.sequence.len());
+        let window = &output.sequence[start..end];
+        if window.len() == 2 * JUNCTION_FLANK + junction.inserted.len() && occurrences(&output.sequence, window, alphabet) > 1 {
+            let position = junction.left.or(junction.right).map(|end| end.position);
+            warn(Warning {
+                code: "junction_not_unique",
+                severity: Severity::Low,
+                record: Some(input.id().to_string()),
+                position,
+                message: format!("The {}bp spanning the junction after {}:{} of the output occur more than once in it, \
+                                  so reads across it cannot be placed uniquely", window.len(), output.id(), offset),
+            });
+        }
+    }
+
+    // Degenerate codes complement to a code for the complementary set, and
+    // other characters are copied unchanged
+    let mut degenerate = 0;
+    let mut uncomplemented = 0;
+    for segment in segments {
+        if let Segment::Source { start, end, complement: true, .. } = segment {
+            for base in input.sequence[*start..*end].chars().map(|base| base.to_ascii_uppercase()) {
+                match base {
+                    'A' | 'C' | 'G' | 'T' | 'U' | 'N' => {},
+                    _ if alphabet.complement(base) != base || "SW".contains(base) => degenerate += 1,
+                    _ => uncomplemented += 1,
+                }
+            }
+        }
+    }
+    if degenerate > 0 {
+        warn(Warning {
+            code: "degenerate_complemented",
+            severity: Severity::Low,
+            record: Some(input.id().to_string()),
+            position: None,
+            message: format!("{} IUPAC ambiguity code{} of {} complemented to the code of the complementary set",
+                             degenerate, if degenerate == 1 { " was" } else { "s were" }, input.id()),
+        });
+    }
+    if uncomplemented > 0 {
+        warn(Warning {
+            code: "degenerate_complemented",
+            severity: Severity::High,
+            record: Some(input.id().to_string()),
+            position: None,
+            message: format!("{} character{} of {} with no complement copied unchanged into reverse-complemented bases",
+                             uncomplemented, if uncomplemented == 1 { " was" } else { "s were" }, input.id()),
+        });
+    }
+}
+
+/// Number of places `window` occurs in `sequence`, on either strand.
+fn occurrences(sequence: &str, window: &str, alphabet: Alphabet) -> usize {
+    let forward = sequence.match_indices(window).count();
+    if alphabet.is_protein() {
+        return forward;
+    }
+    let reverse: String = window.chars().rev().map(|base| alphabet.complement(base)).collect();
+    // A palindromic window is the same occurrence on both strands
+    if reverse == window {
+        forward
+    } else {
+        forward + sequence.match_indices(&reverse).count()
+    }
+}
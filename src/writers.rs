@@ -0,0 +1,226 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::compress::{self, Compression};
+use crate::engineered::Engineered;
+use crate::fasta::{self, Record};
+use crate::genbank::{self, Construct};
+use crate::gfa::{self, GraphRecord};
+use crate::gff::Feature;
+use crate::header;
+use crate::layout::Segment;
+use crate::staging;
+use crate::vcf::{self, RecordJunctions};
+
+/// What a run writes: the output records, and what its edits left known
+/// about them for formats that describe more than the sequence.
+pub struct Output<'a> {
+    pub records: &'a [Record],
+    /// "DNA" or "RNA", for formats that declare the molecule.
+    pub molecule: &'static str,
+    /// The input records and the layout of each relative to it.
+    pub edit: Option<(&'a [Record], &'a [Vec<Segment>])>,
+    /// The engineered segments of each output record.
+    pub engineered: Option<&'a [Vec<Engineered>]>,
+    /// The junctions the edits created.
+    pub junctions: Option<Junctions<'a>>,
+}
+
+/// Junctions to write as breakends, with the VCF's confidence interval and
+/// the features to annotate them with.
+pub struct Junctions<'a> {
+    pub records: &'a [RecordJunctions<'a>],
+    pub confidence: Option<usize>,
+    pub features: &'a [Feature],
+}
+
+impl<'a> Output<'a> {
+    /// Records with nothing known about how they were made.
+    pub fn new(records: &'a [Record], molecule: &'static str) -> Output<'a> {
+        Output { records, molecule, edit: None, engineered: None, junctions: None }
+    }
+}
+
+/// An output format. Each one writes a whole run's output, so adding a
+/// format means adding a writer here rather than changing the edit paths.
+pub trait RecordWriter: Sync {
+    /// The name `--format` takes.
+    fn name(&self) -> &'static str;
+
+    /// File extensions that select the format without `--format`.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Why `output` cannot be written in this format, checked before the
+    /// output file is created.
+    fn check(&self, _output: &Output) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()>;
+}
+
+struct Fasta;
+struct Fastq;
+struct GenBank;
+struct Gfa;
+struct Vcf;
+
+/// Every output format, in the order `--format` lists them.
+pub const WRITERS: &[&dyn RecordWriter] = &[&Fasta, &Fastq, &GenBank, &Gfa, &Vcf];
+
+impl RecordWriter for Fasta {
+    fn name(&self) -> &'static str {
+        "fasta"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["fa", "fasta", "fna", "fas"]
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()> {
+        for record in output.records {
+            fasta::write_fasta_record(out, record)?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordWriter for Fastq {
+    fn name(&self) -> &'static str {
+        "fastq"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["fq", "fastq"]
+    }
+
+    fn check(&self, output: &Output) -> Result<(), String> {
+        match output.records.iter().find(|record| record.quality.is_none()) {
+            Some(record) => Err(format!("{} has no base qualities to write as FASTQ", record.id())),
+            None => Ok(()),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()> {
+        for record in output.records {
+            fasta::write_fastq_record(out, record, record.quality.as_deref().unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordWriter for GenBank {
+    fn name(&self) -> &'static str {
+        "genbank"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gb", "gbk", "genbank"]
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()> {
+        // Records of a run without engineered segments are written bare
+        let constructs: Vec<Construct> = output.records.iter().enumerate()
+            .map(|(i, record)| Construct {
+                id: record.id(),
+                description: header::split_header(&record.header).2,
+                sequence: &record.sequence,
+                molecule: output.molecule,
+                features: output.engineered.and_then(|features| features.get(i)).map_or(&[], Vec::as_slice),
+            })
+            .collect();
+        genbank::emit_genbank(out, &constructs)
+    }
+}
+
+impl RecordWriter for Gfa {
+    fn name(&self) -> &'static str {
+        "gfa"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gfa"]
+    }
+
+    fn check(&self, output: &Output) -> Result<(), String> {
+        match output.edit {
+            Some(_) => Ok(()),
+            None => Err("a GFA graph needs the layout of an edit of the input, which this command does not make".to_string()),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()> {
+        let (input_records, layouts) = output.edit.unwrap_or_default();
+        let graph: Vec<GraphRecord> = input_records.iter().zip(layouts)
+            .map(|(record, segments)| GraphRecord {
+                id: record.id(),
+                sequence: &record.sequence,
+                segments: segments.clone(),
+            })
+            .collect();
+        gfa::emit_gfa(out, &graph)
+    }
+}
+
+impl RecordWriter for Vcf {
+    fn name(&self) -> &'static str {
+        "vcf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["vcf"]
+    }
+
+    fn check(&self, output: &Output) -> Result<(), String> {
+        match output.junctions {
+            Some(_) => Ok(()),
+            None => Err("a breakend VCF needs the junctions of an edit of the input, which this command does not make".to_string()),
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, output: &Output) -> io::Result<()> {
+        match &output.junctions {
+            Some(junctions) => vcf::emit_breakend_vcf(out, junctions.records, junctions.confidence, junctions.features),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The writer `--format <name>` selects.
+pub fn by_name(name: &str) -> Result<&'static dyn RecordWriter, String> {
+    WRITERS.iter().copied().find(|writer| writer.name() == name).ok_or_else(|| format!(
+        "Unknown output format '{}' (expected {})",
+        name, WRITERS.iter().map(|writer| writer.name()).collect::<Vec<_>>().join(", ")))
+}
+
+/// The writer whose extension `path` ends in, ignoring case and any
+/// compression extension after it.
+pub fn by_extension(path: &str) -> Option<&'static dyn RecordWriter> {
+    let (_, extension) = compress::strip_extension(path).rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    WRITERS.iter().copied().find(|writer| writer.extensions().contains(&extension.as_str()))
+}
+
+/// The format of records given neither `--format` nor a known extension:
+/// FASTQ when they carry qualities, FASTA otherwise.
+pub fn default_for(records: &[Record]) -> &'static dyn RecordWriter {
+    if !records.is_empty() && records.iter().all(|record| record.quality.is_some()) {
+        &Fastq
+    } else {
+        &Fasta
+    }
+}
+
+/// Writes `output` with `writer` to `path`, or to stdout when `None`,
+/// compressed when `compression` is given.
+pub fn write(writer: &dyn RecordWriter, output: &Output, path: Option<&str>,
+             compression: Option<Compression>) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        Some(path) => Box::new(BufWriter::new(staging::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    match compression {
+        Some(compression) => compression.write_to(&mut out, |out| writer.write(out, output))?,
+        None => writer.write(&mut out, output)?,
+    }
+    out.flush()
+}
@@ -0,0 +1,30 @@
+use sv_maker::{check_operation, operation_from_json, parse_operation, Alphabet};
+
+fn check(json: &str) -> Result<(), String> {
+    check_operation(operation_from_json(json)?, Alphabet::Dna).map(|_| ())
+}
+
+#[test]
+fn json_operations_get_the_command_line_checks() {
+    for (json, error) in [
+        (r#"{"op":"chromothripsis","start":1,"end":3,"fragments":10,"seed":1}"#, "shorter than the number of fragments"),
+        (r#"{"op":"chromothripsis","start":1,"end":300,"fragments":10,"keep":11,"seed":1}"#, "--keep must be between"),
+        (r#"{"op":"mei","element":"ACGT","tsd":5,"truncate":4,"polya":3,"reverse":false,"seed":1}"#, "--truncate must be shorter"),
+        (r#"{"op":"telomere","repeat":"","length":10,"end":"both"}"#, "must not be empty"),
+        (r#"{"op":"bfb","cycles":0,"seed":1}"#, "at least one cycle"),
+        (r#"{"op":"integrate","virus":"ACGT","sites":0,"partial":false,"rearrange":false,"microhomology":0,"seed":1}"#, "--sites"),
+        (r#"{"op":"copyback","gend":5,"breakpoint":10,"backstart":20}"#, "backstart must be less than breakpoint"),
+    ] {
+        let message = check(json).expect_err(json);
+        assert!(message.contains(error), "{}: {}", json, message);
+    }
+}
+
+#[test]
+fn checked_operations_still_parse() {
+    for words in ["copyback -sb 5 40", "copyback 3 100 40", "mei ACGTACGT --truncate 4 --position 10", "telomere --repeat ttaggg --length 30"] {
+        let words: Vec<String> = words.split(' ').map(String::from).collect();
+        assert!(parse_operation(&words, Alphabet::Dna, 1).is_ok(), "{:?}", words);
+    }
+    assert!(check(r#"{"op":"copyback","gend":5,"breakpoint":40,"backstart":40}"#).is_ok(), "a snapback");
+}
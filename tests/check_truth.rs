@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/NC_045512_2.fasta");
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sv_maker-check-truth-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Applies `operation` to the reference with `--truth-vcf`, then checks the
+/// output against that truth set.
+fn round_trip(name: &str, operation: &[&str]) {
+    let dir = scratch(name);
+    let (output, truth) = (dir.join("edited.fa"), dir.join("truth.vcf"));
+    let edit = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .args(["--seed", "7", "--truth-vcf"]).arg(&truth).arg("-o").arg(&output).arg(REFERENCE)
+        .args(operation)
+        .output().unwrap();
+    assert!(edit.status.success(), "{:?}: {}", operation, String::from_utf8_lossy(&edit.stderr));
+    let check = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("check-truth").arg(REFERENCE).arg(&output).arg(&truth)
+        .output().unwrap();
+    assert!(check.status.success(), "check-truth rejected {:?}: {}{}", operation,
+            String::from_utf8_lossy(&check.stdout), String::from_utf8_lossy(&check.stderr));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn delete_from_the_start() {
+    round_trip("delete-start", &["delete", "1", "100"]);
+}
+
+#[test]
+fn delete_to_the_end() {
+    round_trip("delete-end", &["delete", "20000", "end"]);
+}
+
+#[test]
+fn keep_an_interval() {
+    round_trip("keep", &["keep", "100", "20000"]);
+}
+
+#[test]
+fn internal_deletion() {
+    round_trip("delete-internal", &["delete", "50", "100"]);
+}
+
+#[test]
+fn insertion_and_replacement() {
+    round_trip("insert", &["insert", "100", "ACGTACGT"]);
+    round_trip("replace", &["replace", "100", "120", "GGCC"]);
+    round_trip("add-leader", &["add-leader", "ACGTACGT"]);
+}
+
+#[test]
+fn duplications() {
+    round_trip("duplicate", &["duplicate", "100", "200", "5000"]);
+    round_trip("duplicate-tandem", &["duplicate", "-td", "100", "200"]);
+}
+
+#[test]
+fn copybacks() {
+    round_trip("copyback-5", &["copyback", "5", "200", "100"]);
+    round_trip("copyback-3", &["copyback", "3", "800", "500"]);
+    round_trip("snapback", &["copyback", "-sb", "5", "300"]);
+}
+
+#[test]
+fn insertions_at_sites() {
+    round_trip("mei", &["mei", "ACGTTGCAAGGCTTACCGGA", "--position", "1000", "--tsd", "10", "--polya", "15"]);
+    round_trip("mei-minus", &["mei", "ACGTTGCAAGGCTTACCGGA", "--position", "1000", "--strand", "-"]);
+    round_trip("integrate", &["integrate", "GGGCCCAAATTTGGGCCCAAATTT", "--position", "2000", "--position", "9000"]);
+}
+
+#[test]
+fn terminal_additions() {
+    round_trip("polya", &["polya", "--length", "20"]);
+    round_trip("telomere", &["telomere", "--length", "60", "--end", "both"]);
+}
+
+#[test]
+fn altered_output_is_rejected() {
+    let dir = scratch("altered");
+    let (output, truth) = (dir.join("edited.fa"), dir.join("truth.vcf"));
+    let edit = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("--truth-vcf").arg(&truth).arg("-o").arg(&output).arg(REFERENCE)
+        .args(["insert", "100", "ACGT"])
+        .output().unwrap();
+    assert!(edit.status.success(), "{}", String::from_utf8_lossy(&edit.stderr));
+    let text = std::fs::read_to_string(&output).unwrap();
+    let (header, sequence) = text.split_once('\n').unwrap();
+    std::fs::write(&output, format!("{}\nN{}", header, &sequence[1..])).unwrap();
+    let check = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("check-truth").arg(REFERENCE).arg(&output).arg(&truth)
+        .output().unwrap();
+    assert!(!check.status.success());
+    assert!(String::from_utf8_lossy(&check.stdout).contains("mismatch"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn inversion() {
+    round_trip("invert", &["invert", "100", "200", "--complement"]);
+}
+
+#[test]
+fn reversal_without_complement_is_refused() {
+    let dir = scratch("reverse");
+    let edit = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("--truth-vcf").arg(dir.join("truth.vcf")).arg("-o").arg(dir.join("edited.fa")).arg(REFERENCE)
+        .args(["invert", "100", "200"])
+        .output().unwrap();
+    assert!(!edit.status.success());
+    assert!(String::from_utf8_lossy(&edit.stderr).contains("reversed without being complemented"));
+    assert!(!dir.join("truth.vcf").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn breakage_fusion_bridge() {
+    for cycles in ["1", "2", "3"] {
+        round_trip(&format!("bfb-{}", cycles), &["bfb", "--cycles", cycles]);
+    }
+}
+
+#[test]
+fn breakage_fusion_bridge_manifest_matches_truth() {
+    let dir = scratch("bfb-manifest");
+    let (output, truth, manifest) = (dir.join("edited.fa"), dir.join("truth.vcf"), dir.join("cycles.tsv"));
+    let edit = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .args(["--seed", "7", "--manifest"]).arg(&manifest).arg("--truth-vcf").arg(&truth).arg("-o").arg(&output)
+        .arg(REFERENCE).args(["bfb", "--cycles", "3"])
+        .output().unwrap();
+    assert!(edit.status.success(), "{}", String::from_utf8_lossy(&edit.stderr));
+    let truth = std::fs::read_to_string(&truth).unwrap();
+    let positions: Vec<&str> = truth.lines().filter(|line| !line.starts_with('#'))
+        .map(|line| line.split('\t').nth(1).unwrap())
+        .collect();
+    let manifest = std::fs::read_to_string(&manifest).unwrap();
+    for row in manifest.lines().skip(1) {
+        let fields: Vec<&str> = row.split('\t').collect();
+        for breakend in &fields[7..9] {
+            let position = breakend.trim_end_matches(['+', '-']);
+            assert!(positions.contains(&position), "{} of cycle {} is not in the truth VCF", breakend, fields[2]);
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn chromothripsis() {
+    round_trip("chromothripsis", &["chromothripsis", "1000", "5000", "8"]);
+    round_trip("chromothripsis-keep", &["chromothripsis", "1000", "5000", "8", "--keep", "5"]);
+}
@@ -0,0 +1,54 @@
+use std::process::Command;
+
+use sv_maker::fasta;
+
+const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/NC_045512_2.fasta");
+
+fn reverse_complement(sequence: &str) -> String {
+    sequence.chars().rev().map(|base| match base {
+        'A' => 'T',
+        'C' => 'G',
+        'G' => 'C',
+        'T' => 'A',
+        other => other,
+    }).collect()
+}
+
+#[test]
+fn manifest_describes_the_derived_sequence() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-chromothripsis-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (output, manifest) = (dir.join("edited.fa"), dir.join("fragments.tsv"));
+    let run = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .args(["--seed", "7", "--manifest"]).arg(&manifest).arg("-o").arg(&output).arg(REFERENCE)
+        .args(["chromothripsis", "1000", "5000", "8", "--keep", "5"])
+        .output().unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+
+    let original = &fasta::read_records(REFERENCE, false).unwrap()[0].sequence;
+    let derived = &fasta::read_records(output.to_str().unwrap(), false).unwrap()[0].sequence;
+    assert_eq!(derived[..999], original[..999]);
+
+    let manifest = std::fs::read_to_string(&manifest).unwrap();
+    let rows: Vec<Vec<&str>> = manifest.lines().skip(1).map(|row| row.split('\t').collect()).collect();
+    assert_eq!(rows.len(), 8);
+    let (mut kept, mut covered, mut derived_end) = (0, 0, 999);
+    for row in &rows {
+        let (start, end): (usize, usize) = (row[3].parse().unwrap(), row[4].parse().unwrap());
+        covered += end - start + 1;
+        if row[6] == "lost" {
+            continue;
+        }
+        kept += 1;
+        let fragment = &original[start - 1..end];
+        let expected = if row[6] == "-" { reverse_complement(fragment) } else { fragment.to_string() };
+        let (from, to): (usize, usize) = (row[7].parse().unwrap(), row[8].parse().unwrap());
+        assert_eq!(from, derived_end + 1, "fragment {} does not follow the one before it", row[2]);
+        assert_eq!(derived[from - 1..to], expected, "fragment {}", row[2]);
+        derived_end = to;
+    }
+    assert_eq!(kept, 5);
+    assert_eq!(covered, 4001, "the fragments do not tile the region");
+    assert_eq!(derived[derived_end..], original[5000..]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
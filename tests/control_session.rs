@@ -0,0 +1,43 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const REFERENCE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/NC_045512_2.fasta");
+
+#[test]
+fn bad_lines_are_reported_and_the_session_continues() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-control-session-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("edited.fa");
+    let mut session = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .args(["--control-fd", "0", "-o"]).arg(&output).arg(REFERENCE)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().unwrap();
+    let lines = [
+        r#"{"op":"delete","start":10,"end":20}"#,
+        "not json",
+        r#"{"op":"delete","start":50000,"end":50010}"#,
+        r#"{"op":"delete","start":1,"end":9}"#,
+    ];
+    session.stdin.take().unwrap().write_all(format!("{}\n", lines.join("\n")).as_bytes()).unwrap();
+    let finished = session.wait_with_output().unwrap();
+    assert!(finished.status.success(), "{}", String::from_utf8_lossy(&finished.stderr));
+
+    let replies: Vec<serde_json::Value> = String::from_utf8(finished.stdout).unwrap().lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(replies.len(), 4);
+    for (reply, line) in replies.iter().zip(1..) {
+        assert_eq!(reply["line"], line);
+    }
+    assert!(replies[0]["error"].is_null() && replies[3]["error"].is_null());
+    for reply in &replies[1..3] {
+        let error = reply["error"].as_str().unwrap();
+        assert!(error.starts_with(&format!("Control line {}: ", reply["line"])), "{}", error);
+    }
+
+    // Both deletions were applied, and nothing of the failed lines
+    let edited = std::fs::read_to_string(&output).unwrap();
+    let bases: usize = edited.lines().filter(|line| !line.starts_with('>')).map(str::len).sum();
+    assert_eq!(bases, 29903 - 11 - 9);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
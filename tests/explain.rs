@@ -0,0 +1,19 @@
+use std::process::Command;
+
+fn explain(words: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_sv_maker")).arg("explain").args(words).output().unwrap()
+}
+
+#[test]
+fn genome_scale_lengths_are_explained_from_the_length() {
+    let run = explain(&["delete", "1", "1000", "--length", "100G"]);
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(String::from_utf8(run.stdout).unwrap().contains("Output length: 99999999000bp (-1000bp)"));
+}
+
+#[test]
+fn placeholders_are_capped() {
+    let run = explain(&["mei", "ACGTACGTAA", "--length", "100G"]);
+    assert!(!run.status.success());
+    assert!(String::from_utf8(run.stderr).unwrap().contains("--length can be at most"));
+}
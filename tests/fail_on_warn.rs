@@ -0,0 +1,31 @@
+use std::process::Command;
+
+#[test]
+fn failing_run_publishes_only_the_warnings() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-fail-on-warn-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output, journal, report) =
+        (dir.join("gap.fa"), dir.join("edited.fa"), dir.join("journal.jsonl"), dir.join("warnings.json"));
+    std::fs::write(&input, format!(">c\n{}{}{}\n", "ACGT".repeat(25), "N".repeat(20), "ACGT".repeat(25))).unwrap();
+
+    // The deletion starts inside the N gap
+    let run = |fail_on_warn: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_sv_maker"));
+        if fail_on_warn {
+            command.args(["--fail-on-warn", "medium"]);
+        }
+        command.arg("--warnings").arg(&report).arg("--journal").arg(&journal).arg("-o").arg(&output)
+            .arg(&input).args(["delete", "105", "150"])
+            .output().unwrap()
+    };
+    let failed = run(true);
+    assert!(!failed.status.success());
+    assert!(report.exists(), "the warnings report is written");
+    assert!(!output.exists(), "the output is not published");
+    assert!(!journal.exists(), "nothing is journaled");
+
+    assert!(run(false).status.success());
+    assert!(output.exists());
+    assert_eq!(std::fs::read_to_string(&journal).unwrap().lines().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,40 @@
+use std::io::ErrorKind;
+
+use sv_maker::{fasta, parse_operation_noted, Alphabet, ParseNote};
+
+#[test]
+fn malformed_fasta_is_an_error() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-library-errors-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, text) in [("empty.fa", ""), ("headless.fa", "ACGT\n"), ("bare.fa", ">a\n>b\nACGT\n"), ("short.fq", "@a\nACGT\n+\nII\n")] {
+        let path = dir.join(name);
+        std::fs::write(&path, text).unwrap();
+        let error = fasta::read_records(path.to_str().unwrap(), false).expect_err(name);
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{}: {}", name, error);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copyback_rewrites_are_returned() {
+    let words: Vec<String> = "copyback --keep-leader 30 --reanneal 10".split(' ').map(String::from).collect();
+    let (_, notes) = parse_operation_noted(&words, Alphabet::Dna, 1).unwrap();
+    assert_eq!(notes, vec![ParseNote::KeptEnd { gend: 5, kept: 30, copied: 10 }]);
+
+    let words: Vec<String> = "copyback 5 20 5 --min-stem 10".split(' ').map(String::from).collect();
+    let (_, notes) = parse_operation_noted(&words, Alphabet::Dna, 1).unwrap();
+    assert_eq!(notes, vec![ParseNote::StemAdjusted { breakpoint: 20, backstart: 10, min_stem: 10 }]);
+}
+
+#[test]
+fn writers_write_to_the_path_given() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-library-writers-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("out.fa");
+    let records = vec![fasta::Record { header: ">c".to_string(), sequence: "ACGT".to_string(), quality: None, line_width: None }];
+    fasta::write_records(&records, path.to_str()).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), ">c\nACGT\n");
+    let names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(names, vec![std::ffi::OsString::from("out.fa")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,78 @@
+use std::path::Path;
+use std::process::Command;
+
+const SEQUENCE: &str = "ACGTTGCATGGCATTACGGATCCAGTTACGACGTAGCATCGGATTACAGGCT";
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        other => other,
+    }
+}
+
+/// Runs `map` for one position and returns its answers, without the query.
+fn map(journal: &Path, position: usize, inverse: bool) -> Vec<String> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sv_maker"));
+    command.arg("map").arg(format!("c:{}", position)).arg("--edits").arg(journal);
+    if inverse {
+        command.arg("--inverse");
+    }
+    let run = command.output().unwrap();
+    assert!(run.status.success(), "map c:{}: {}", position, String::from_utf8_lossy(&run.stderr));
+    String::from_utf8(run.stdout).unwrap().lines().map(|line| line.split('\t').nth(1).unwrap().to_string()).collect()
+}
+
+/// Splits an answer such as `c:12(-)` into its position and strand.
+fn located(answer: &str) -> (usize, char) {
+    let (position, strand) = answer.trim_start_matches("c:").split_once('(').unwrap();
+    (position.parse().unwrap(), strand.chars().next().unwrap())
+}
+
+#[test]
+fn positions_map_through_a_journal_and_back() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-map-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let journal = dir.join("ops.jsonl");
+    let mut input = dir.join("0.fa");
+    std::fs::write(&input, format!(">c\n{}\n", SEQUENCE)).unwrap();
+    let edits: [&[&str]; 4] = [&["delete", "5", "10"], &["invert", "11", "20", "--complement"],
+                               &["duplicate", "-td", "1", "3"], &["insert", "30", "GGGG"]];
+    for (step, edit) in edits.iter().enumerate() {
+        let output = dir.join(format!("{}.fa", step + 1));
+        let run = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+            .arg("--journal").arg(&journal).arg("-o").arg(&output).arg(&input).args(*edit)
+            .output().unwrap();
+        assert!(run.status.success(), "{:?}: {}", edit, String::from_utf8_lossy(&run.stderr));
+        input = output;
+    }
+    let derived = std::fs::read_to_string(&input).unwrap().lines().skip(1).collect::<String>().into_bytes();
+    let original = SEQUENCE.as_bytes();
+
+    let mut reached = vec![false; derived.len()];
+    for position in 1..=original.len() {
+        let answers = map(&journal, position, false);
+        if (5..=10).contains(&position) {
+            assert_eq!(answers, ["deleted"], "c:{}", position);
+            continue;
+        }
+        assert_eq!(answers.len(), if position <= 3 { 2 } else { 1 }, "c:{}: {:?}", position, answers);
+        for answer in &answers {
+            let (target, strand) = located(answer);
+            let base = if strand == '-' { complement(original[position - 1]) } else { original[position - 1] };
+            assert_eq!(derived[target - 1], base, "c:{} -> {}", position, answer);
+            reached[target - 1] = true;
+            assert_eq!(map(&journal, target, true), [format!("c:{}({})", position, strand)], "{} back", answer);
+        }
+    }
+
+    let novel: Vec<usize> = (1..=derived.len()).filter(|&target| !reached[target - 1]).collect();
+    assert_eq!(novel.len(), 4);
+    for target in novel {
+        assert_eq!(map(&journal, target, true), ["novel"], "c:{}", target);
+        assert_eq!(derived[target - 1], b'G');
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,33 @@
+use std::process::Command;
+
+use sv_maker::summary;
+
+/// MD5 of "ACGTACGTACGGCCTTAA", the bases of the test input uppercased,
+/// as `samtools dict` gives it for M5
+const UPPERCASE_MD5: &str = "baeb48fa196b0c9d180470a0ac6c239d";
+
+#[test]
+fn digest_ignores_case() {
+    assert_eq!(summary::sequence_md5("acgtACGTac"), summary::sequence_md5("ACGTACGTAC"));
+}
+
+#[test]
+fn preserved_lowercase_input_has_the_samtools_digest() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-md5-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, summary_path) = (dir.join("lower.fa"), dir.join("run.json"));
+    std::fs::write(&input, ">lower\nacgtacgtacGGCCttaa\n").unwrap();
+    assert_eq!(UPPERCASE_MD5, summary::sequence_md5("ACGTACGTACGGCCTTAA"));
+
+    let run = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .args(["--preserve-case", "--expect-input-md5", UPPERCASE_MD5, "--summary"]).arg(&summary_path)
+        .arg(&input).args(["delete", "1", "2"])
+        .output().unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    assert!(String::from_utf8(run.stdout).unwrap().contains("gtacgtacGGCCttaa"), "case is kept");
+
+    let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&summary_path).unwrap()).unwrap();
+    assert_eq!(summary["input_md5"], UPPERCASE_MD5);
+    assert_eq!(summary["output_md5"].as_str().unwrap(), summary::sequence_md5("GTACGTACGGCCTTAA"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,56 @@
+use sv_maker::plan::{self, PlanInput, PlanStep};
+use sv_maker::{Alphabet, Operation, Record, Target};
+
+fn parse(text: &str, diploid: bool) -> Result<Vec<PlanStep>, String> {
+    let records = vec![Record { header: ">c".to_string(), sequence: "ACGT".repeat(10), quality: None, line_width: None }];
+    let input = PlanInput { records: &records, target: Target::Only, diploid };
+    plan::parse_plan(text, "test.plan", Alphabet::Dna, 1, Some(&input))
+}
+
+#[test]
+fn length_follows_earlier_steps() {
+    let steps = parse("delete 1 10\ndelete {L - 2} end\n", false).unwrap();
+    assert!(matches!(steps[1].operation, Operation::Delete { start: 28, .. }), "{:?}", steps[1].operation);
+}
+
+#[test]
+fn layers_start_from_their_parent() {
+    let steps = parse("layer=a\ninsert 1 GGGG\nlayer=b\ninsert {L} T\nlayer=c from=a\ninsert {L} T\n", false).unwrap();
+    let positions: Vec<usize> = steps.iter().filter_map(|step| match step.operation {
+        Operation::Insert { position, .. } => Some(position),
+        _ => None,
+    }).collect();
+    assert_eq!(positions, [1, 44, 44]);
+
+    let steps = parse("layer=a\ndelete 1 10\nlayer=b\ninsert 1 G\nlayer=c from=a\ninsert {L} T\n", false).unwrap();
+    assert!(matches!(steps[2].operation, Operation::Insert { position: 30, .. }), "{:?}", steps[2].operation);
+}
+
+#[test]
+fn unknown_lengths_are_errors() {
+    let error = parse("mei ACGTAC --tsd 2\ndelete {L - 2} end\n", false).unwrap_err();
+    assert!(error.contains("plan line 1"), "{}", error);
+    let error = parse("delete 1 10 gt=0|1\ndelete {L - 2} end\n", true).unwrap_err();
+    assert!(error.contains("one haplotype"), "{}", error);
+    assert!(parse("delete 1 10 gt=1|1\ndelete {L - 2} end\n", true).is_ok());
+}
+
+#[test]
+fn expressions_are_evaluated_per_record() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-plan-expressions-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, plan, output) = (dir.join("input.fa"), dir.join("edits.tsv"), dir.join("edited.fa"));
+    std::fs::write(&input, ">a\nAAAACCCCGGGGTTTT\n>b\nACGTACGTAC\n").unwrap();
+    std::fs::write(&plan, "size = 2\n*\tdelete {L - size + 1} end\n*\tinsert {L / 2} TT\n").unwrap();
+    let run = std::process::Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("-o").arg(&output).arg(&input).arg("plan").arg(&plan)
+        .output().unwrap();
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    let text = std::fs::read_to_string(&output).unwrap();
+    let headers: Vec<&str> = text.lines().step_by(2).collect();
+    assert_eq!(headers, [">a [deleted 2bp at positions 15-16] [inserted 2bp 'TT' at position 7]",
+                         ">b [deleted 2bp at positions 9-10] [inserted 2bp 'TT' at position 4]"]);
+    let sequences: Vec<&str> = text.lines().skip(1).step_by(2).collect();
+    assert_eq!(sequences, ["AAAACCTTCCGGGGTT", "ACGTTTACGT"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
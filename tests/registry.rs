@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+use sv_maker::layout::Segment;
+use sv_maker::registry::{self, CustomOperation};
+use sv_maker::Alphabet;
+
+/// An operation that leaves the sequence as it is, under any name.
+struct Named(&'static str);
+
+impl CustomOperation for Named {
+    fn name(&self) -> &str {
+        self.0
+    }
+
+    fn parse(&self, _args: &[String], _alphabet: Alphabet, _seed: u64) -> Result<Value, String> {
+        Ok(Value::Null)
+    }
+
+    fn layout(&self, _params: &Value, sequence: &str) -> Result<Vec<Segment>, String> {
+        Ok(vec![Segment::Source { start: 0, end: sequence.len(), reverse: false, complement: false }])
+    }
+
+    fn describe(&self, _params: &Value, _sequence: &str, _unit: &str) -> String {
+        String::new()
+    }
+}
+
+#[test]
+fn command_words_cannot_be_registered() {
+    let operations = [
+        "delete", "keep", "insert", "replace", "invert", "duplicate", "copyback", "chromothripsis", "repeat-family",
+        "bfb", "mei", "integrate", "polya", "telomere", "add-leader", "case", "custom",
+    ];
+    for name in operations.iter().chain(registry::SUBCOMMANDS) {
+        assert!(registry::register(Arc::new(Named(name))).is_err(), "{} was registered", name);
+    }
+    assert!(registry::register(Arc::new(Named("hairpin"))).is_ok());
+}
@@ -0,0 +1,10 @@
+use sv_maker::rng;
+
+#[test]
+fn named_streams_are_distinct() {
+    let streams = [rng::CONTROL_STREAM, rng::FUZZ_STREAM, rng::PLACE_STREAM, rng::INSERT_STREAM, rng::SCRAMBLE_STREAM,
+                   rng::METHYLATION_STREAM];
+    for (i, stream) in streams.iter().enumerate() {
+        assert!(!streams[i + 1..].contains(stream), "stream {} is used twice", stream);
+    }
+}
@@ -0,0 +1,23 @@
+use sv_maker::spec;
+
+#[test]
+fn template_annotations_are_not_parameters() {
+    let dir = std::env::temp_dir().join(format!("sv_maker-spec-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dvgs.spec");
+    std::fs::write(&path, "\
+# copybacks at two allele fractions
+breakpoint = 500..700 step 100
+stem_length=50, 100
+copyback 5 {breakpoint} {stem_length} af=0.25 gt=0/1
+delete 2000 2100 cipos=-5,5 ciend=-5,5
+").unwrap();
+    let spec = spec::read_spec(path.to_str().unwrap()).unwrap();
+    let params: Vec<(&str, &[usize])> = spec.params.iter().map(|param| (param.name.as_str(), param.values.as_slice())).collect();
+    assert_eq!(params, vec![("breakpoint", &[500, 600, 700][..]), ("stem_length", &[50, 100][..])]);
+    assert_eq!(spec.template.lines, vec![
+        "copyback 5 {breakpoint} {stem_length} af=0.25 gt=0/1",
+        "delete 2000 2100 cipos=-5,5 ciend=-5,5",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,66 @@
+use std::process::Command;
+
+const SEQUENCE: &str = "ACGTTGCATGGCATTACGGATCCAGTTACGACGTAGCATCGGATTACAGGCT";
+
+/// The edited sequence and the `--vcf` records, split into fields, for
+/// `operation` on `SEQUENCE`.
+fn resolved(test: &str, operation: &[&str]) -> (String, Vec<Vec<String>>) {
+    let dir = std::env::temp_dir().join(format!("sv_maker-vcf-{}-{}-{}", std::process::id(), test, operation.join("_")));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output, vcf) = (dir.join("input.fa"), dir.join("edited.fa"), dir.join("changes.vcf"));
+    std::fs::write(&input, format!(">c\n{}\n", SEQUENCE)).unwrap();
+    let run = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("--vcf").arg(&vcf).arg("-o").arg(&output).arg(&input)
+        .args(operation)
+        .output().unwrap();
+    assert!(run.status.success(), "{:?}: {}", operation, String::from_utf8_lossy(&run.stderr));
+    let edited = std::fs::read_to_string(&output).unwrap().lines().skip(1).collect();
+    let records = std::fs::read_to_string(&vcf).unwrap().lines().filter(|line| !line.starts_with('#'))
+        .map(|line| line.split('\t').map(String::from).collect())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (edited, records)
+}
+
+/// Applies the records to `SEQUENCE`, checking each REF against it.
+fn apply(records: &[Vec<String>]) -> String {
+    let mut sequence = SEQUENCE.to_string();
+    let mut records: Vec<&Vec<String>> = records.iter().collect();
+    records.sort_by_key(|record| std::cmp::Reverse(record[1].parse::<usize>().unwrap()));
+    for record in records {
+        let start = record[1].parse::<usize>().unwrap() - 1;
+        let end = start + record[3].len();
+        assert_eq!(sequence[start..end], record[3], "REF of {:?}", record);
+        sequence.replace_range(start..end, &record[4]);
+    }
+    sequence
+}
+
+#[test]
+fn records_reproduce_the_edit() {
+    let operations: [&[&str]; 9] = [
+        &["delete", "6", "13"],
+        &["delete", "1", "10"],
+        &["delete", "45", "end"],
+        &["insert", "11", "GGG"],
+        &["replace", "11", "14", "AA"],
+        &["invert", "11", "20", "--complement"],
+        &["duplicate", "-td", "11", "14"],
+        &["duplicate", "5", "10", "30"],
+        &["keep", "5", "40"],
+    ];
+    for operation in operations {
+        let (edited, records) = resolved("apply", operation);
+        assert!(!records.is_empty(), "{:?}", operation);
+        assert_eq!(apply(&records), edited, "{:?}: {:?}", operation, records);
+    }
+}
+
+#[test]
+fn records_carry_a_flanking_base() {
+    let line = |operation: &[&str]| resolved("line", operation).1.remove(0)[1..].join(" ");
+    assert_eq!(line(&["delete", "6", "13"]), "5 var1 TGCATGGCA T . PASS SVTYPE=DEL;SVLEN=-8");
+    assert_eq!(line(&["delete", "1", "10"]), "1 var1 ACGTTGCATGG G . PASS SVTYPE=DEL;SVLEN=-10");
+    assert_eq!(line(&["insert", "11", "GGG"]), "10 var1 G GGGG . PASS SVTYPE=INS;SVLEN=3");
+    assert_eq!(line(&["invert", "11", "20", "--complement"]), "10 var1 GGCATTACGGA GTCCGTAATGC . PASS SVTYPE=INV;SVLEN=0");
+}
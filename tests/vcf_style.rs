@@ -0,0 +1,64 @@
+use std::process::Command;
+
+const SEQUENCE: &str = "ACGTTGCATGGCATTACGGATCCAGTTACGACGTAGCATCGGATTACAGGCT";
+
+/// The records of the symbolic `--vcf` for `operation` on `SEQUENCE`.
+fn symbolic(name: &str, operation: &[&str]) -> Vec<String> {
+    let dir = std::env::temp_dir().join(format!("sv_maker-vcf-style-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, vcf) = (dir.join("input.fa"), dir.join("changes.vcf"));
+    std::fs::write(&input, format!(">c\n{}\n", SEQUENCE)).unwrap();
+    let run = Command::new(env!("CARGO_BIN_EXE_sv_maker"))
+        .arg("--vcf").arg(&vcf).args(["--vcf-style", "symbolic", "-o"]).arg(dir.join("edited.fa")).arg(&input)
+        .args(operation)
+        .output().unwrap();
+    assert!(run.status.success(), "{:?}: {}", operation, String::from_utf8_lossy(&run.stderr));
+    let records = std::fs::read_to_string(&vcf).unwrap().lines().filter(|line| !line.starts_with('#'))
+        .map(|line| line.split('\t').skip(1).collect::<Vec<_>>().join(" "))
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    records
+}
+
+#[test]
+fn inversions() {
+    let expected = ["10 var1 G <INV> . PASS SVTYPE=INV;END=20;SVLEN=10"];
+    assert_eq!(symbolic("invert", &["invert", "11", "20"]), expected);
+    assert_eq!(symbolic("invert-complement", &["invert", "11", "20", "--complement"]), expected);
+}
+
+#[test]
+fn duplications() {
+    assert_eq!(symbolic("dispersed", &["duplicate", "5", "10", "30"]),
+               ["4 var1 T <DUP> . PASS SVTYPE=DUP;END=10;SVLEN=6"]);
+    assert_eq!(symbolic("tandem", &["duplicate", "-td", "11", "14"]),
+               ["10 var1 G <DUP> . PASS SVTYPE=DUP;END=14;SVLEN=4"]);
+}
+
+#[test]
+fn deletions() {
+    assert_eq!(symbolic("delete", &["delete", "6", "13"]), ["5 var1 T <DEL> . PASS SVTYPE=DEL;END=13;SVLEN=-8"]);
+    assert_eq!(symbolic("delete-start", &["delete", "1", "10"]), ["0 var1 N <DEL> . PASS SVTYPE=DEL;END=10;SVLEN=-10"]);
+}
+
+#[test]
+fn insertions() {
+    assert_eq!(symbolic("insert", &["insert", "11", "GGG"]), ["10 var1 G <INS> . PASS SVTYPE=INS;END=10;SVLEN=3"]);
+}
+
+#[test]
+fn copyback_junctions_are_breakend_pairs() {
+    assert_eq!(symbolic("copyback", &["copyback", "5", "30", "10"]),
+               ["10 bnd1_2 G G]c:30] . PASS SVTYPE=BND;MATEID=bnd1_1",
+                "30 bnd1_1 G G]c:10] . PASS SVTYPE=BND;MATEID=bnd1_2"]);
+    assert_eq!(symbolic("snapback", &["copyback", "-sb", "5", "30"]),
+               ["30 bnd1_1 G G]c:30] . PASS SVTYPE=BND;MATEID=bnd1_2",
+                "30 bnd1_2 G G]c:30] . PASS SVTYPE=BND;MATEID=bnd1_1"]);
+}
+
+#[test]
+fn keeping_an_interval_deletes_both_ends() {
+    assert_eq!(symbolic("keep", &["keep", "5", "40"]),
+               ["0 var1 N <DEL> . PASS SVTYPE=DEL;END=4;SVLEN=-4",
+                "40 var2 C <DEL> . PASS SVTYPE=DEL;END=52;SVLEN=-12"]);
+}